@@ -2,6 +2,7 @@ use riichi::algo::agari::{self, AgariCalculator};
 use riichi::algo::shanten;
 use riichi::algo::sp::{InitState, SPCalculator};
 use riichi::hand::hand;
+use riichi::rule::RuleSet;
 use riichi::state::PlayerState;
 use riichi::{t, tu8};
 use std::hint::black_box;
@@ -36,6 +37,7 @@ fn agari(c: &mut Criterion) {
                 jikaze: tu8!(N),
                 winning_tile: tu8!(9m),
                 is_ron: true,
+                rule: RuleSet::tenhou(),
             };
             black_box(calc.search_yakus().unwrap());
         });
@@ -55,6 +57,7 @@ fn sp(c: &mut Criterion) {
         is_menzen: true,
         num_doras_in_fuuro: 0,
         dora_indicators: &[t!(6m)],
+        rule: RuleSet::tenhou(),
         calc_double_riichi: false,
         calc_haitei: false,
         sort_result: true,
@@ -100,6 +103,7 @@ fn sp(c: &mut Criterion) {
         is_menzen: true,
         num_doras_in_fuuro: 0,
         dora_indicators: &[t!(6m)],
+        rule: RuleSet::tenhou(),
         calc_double_riichi: false,
         calc_haitei: false,
         sort_result: true,
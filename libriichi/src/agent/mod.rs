@@ -1,15 +1,21 @@
-mod akochan;
 mod batchify;
 mod defs;
 mod mjai_log;
 mod mortal;
 mod py_agent;
+mod random;
+mod rule_based;
+mod search;
+mod subprocess;
 mod tsumogiri;
 
-pub use akochan::AkochanAgent;
 pub use batchify::BatchifiedAgent;
 pub use defs::{Agent, BatchAgent, InvisibleState};
 pub use mjai_log::MjaiLogBatchAgent;
 pub use mortal::MortalBatchAgent;
 pub use py_agent::new_py_agent;
+pub use random::{ActionWeights, RandomAgent};
+pub use rule_based::{RuleBasedAgent, RuleBasedConfig};
+pub use search::{SearchAgent, SearchConfig};
+pub use subprocess::{SubprocessConfig, SubprocessMjaiAgent};
 pub use tsumogiri::Tsumogiri;
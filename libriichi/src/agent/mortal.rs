@@ -2,7 +2,7 @@ use super::{BatchAgent, InvisibleState};
 use crate::consts::ACTION_SPACE;
 use crate::mjai::{Event, EventExt, Metadata};
 use crate::state::PlayerState;
-use crate::{must_tile, tu8};
+use crate::{must_tile, t, tu8};
 use std::mem;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -296,10 +296,10 @@ impl BatchAgent for MortalBatchAgent {
         state: &PlayerState,
         _: Option<InvisibleState>,
     ) -> Result<EventExt> {
-        if self.enable_quick_eval {
-            if let Some(ev) = self.quick_eval_reactions[index].take() {
-                return Ok(EventExt::no_meta(ev));
-            }
+        if self.enable_quick_eval
+            && let Some(ev) = self.quick_eval_reactions[index].take()
+        {
+            return Ok(EventExt::no_meta(ev));
         }
 
         if !self.evaluated {
@@ -362,6 +362,7 @@ impl BatchAgent for MortalBatchAgent {
                 Event::Reach { actor }
             }
 
+            38 if cans.can_nukidora => Event::Nukidora { actor, pai: t!(N) },
             38 => {
                 ensure!(
                     cans.can_chi_low,
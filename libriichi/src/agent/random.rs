@@ -0,0 +1,226 @@
+use super::{Agent, BatchifiedAgent, InvisibleState, Tsumogiri};
+use crate::mjai::{Event, EventExt};
+use crate::state::PlayerState;
+
+use anyhow::Result;
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+
+/// Relative weights of each kind of reaction for `RandomAgent`. Every legal
+/// reaction is weighted by its kind, so a kind with weight 0 is never chosen.
+#[derive(Debug, Clone, Copy)]
+pub struct ActionWeights {
+    pub discard: f32,
+    pub riichi: f32,
+    pub chi: f32,
+    pub pon: f32,
+    /// Daiminkan, kakan and ankan.
+    pub kan: f32,
+    pub agari: f32,
+    pub ryukyoku: f32,
+    pub nukidora: f32,
+    /// Skip a chi, pon, daiminkan or ron.
+    pub pass: f32,
+}
+
+/// `RandomAgent` picks a random legal reaction, which is useful for fuzzing
+/// `BatchGame` and `PlayerState`, as it exercises calls, kans and other
+/// actions that `Tsumogiri` never emits.
+pub struct RandomAgent {
+    weights: ActionWeights,
+    rng: ChaCha12Rng,
+}
+
+impl Default for ActionWeights {
+    /// Uniform over all the legal reactions.
+    fn default() -> Self {
+        Self {
+            discard: 1.,
+            riichi: 1.,
+            chi: 1.,
+            pon: 1.,
+            kan: 1.,
+            agari: 1.,
+            ryukyoku: 1.,
+            nukidora: 1.,
+            pass: 1.,
+        }
+    }
+}
+
+impl RandomAgent {
+    #[must_use]
+    pub fn new(seed: u64, weights: ActionWeights) -> Self {
+        Self {
+            weights,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Each player gets a different seed derived from `seed`.
+    pub fn new_batched(
+        player_ids: &[u8],
+        seed: u64,
+        weights: ActionWeights,
+    ) -> Result<BatchifiedAgent<Self>> {
+        BatchifiedAgent::new(
+            |id| Ok(Self::new(seed.wrapping_add(id as u64), weights)),
+            player_ids,
+        )
+    }
+
+    /// Returns every legal reaction with its weight.
+    #[must_use]
+    pub fn reactions(&self, state: &PlayerState) -> Vec<(Event, f32)> {
+        let w = &self.weights;
+        state
+            .legal_reactions()
+            .into_iter()
+            .map(|ev| {
+                let weight = match ev {
+                    Event::Dahai { .. } => w.discard,
+                    Event::Reach { .. } => w.riichi,
+                    Event::Chi { .. } => w.chi,
+                    Event::Pon { .. } => w.pon,
+                    Event::Daiminkan { .. } | Event::Kakan { .. } | Event::Ankan { .. } => w.kan,
+                    Event::Hora { .. } => w.agari,
+                    Event::Ryukyoku { .. } => w.ryukyoku,
+                    Event::Nukidora { .. } => w.nukidora,
+                    _ => w.pass,
+                };
+                (ev, weight)
+            })
+            .collect()
+    }
+
+    /// Falls back to `Tsumogiri::reaction` if all the legal reactions have
+    /// weight 0.
+    pub fn reaction(&mut self, state: &PlayerState) -> Event {
+        let reactions = self.reactions(state);
+        reactions
+            .choose_weighted(&mut self.rng, |(_, w)| *w)
+            .map_or_else(|_| Tsumogiri::reaction(state), |(ev, _)| ev.clone())
+    }
+}
+
+impl Agent for RandomAgent {
+    fn name(&self) -> String {
+        "random".to_owned()
+    }
+
+    fn react(
+        &mut self,
+        _: &[EventExt],
+        state: &PlayerState,
+        _: Option<InvisibleState>,
+    ) -> Result<EventExt> {
+        Ok(EventExt::no_meta(self.reaction(state)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::{BatchGame, Index};
+    use crate::rule::RuleSet;
+    use crate::t;
+    use std::array;
+
+    /// Returns the number of calls and kans made.
+    fn fuzz(g: &BatchGame, weights: ActionWeights) -> usize {
+        let n = g.rule.player_count() as usize;
+        let games = 4;
+        let player_ids: Vec<_> = (0..games).flat_map(|_| 0..n as u8).collect();
+        let mut agents =
+            [Box::new(RandomAgent::new_batched(&player_ids, 0, weights).unwrap()) as _];
+        let indexes: Vec<_> = (0..games)
+            .map(|game| {
+                array::from_fn(|i| Index {
+                    agent_idx: 0,
+                    player_id_idx: game * n + i % n,
+                })
+            })
+            .collect();
+        let seeds: Vec<_> = (0..games as u64).map(|i| (i, 0)).collect();
+        let results = g.run(&mut agents, &indexes, &seeds).unwrap();
+        assert_eq!(results.len(), games);
+        results
+            .iter()
+            .flat_map(|r| r.game_log.iter().flatten())
+            .filter(|ev| {
+                matches!(
+                    ev.event,
+                    Event::Chi { .. }
+                        | Event::Pon { .. }
+                        | Event::Daiminkan { .. }
+                        | Event::Kakan { .. }
+                        | Event::Ankan { .. }
+                )
+            })
+            .count()
+    }
+
+    #[test]
+    fn fuzz_games() {
+        // Make calls and kans frequent and riichi less so, otherwise most hands
+        // would be locked by riichi early.
+        let weights = ActionWeights {
+            chi: 5.,
+            pon: 5.,
+            kan: 10.,
+            riichi: 0.5,
+            ryukyoku: 0.1,
+            ..Default::default()
+        };
+        assert!(fuzz(&BatchGame::tenhou_hanchan(true), weights) > 0);
+        let sanma = BatchGame {
+            rule: RuleSet::tenhou_sanma(),
+            init_scores: [35000, 35000, 35000, 0],
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        assert!(fuzz(&sanma, weights) > 0);
+        let no_aka = BatchGame {
+            rule: RuleSet {
+                aka_count: 0,
+                ..RuleSet::m_league()
+            },
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        fuzz(&no_aka, ActionWeights::default());
+    }
+
+    #[test]
+    fn weights() {
+        let log = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["3m","4m","5mr","5m","5m","6p","7p","8p","1s","1s","E","E","W"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"?"}
+            {"type":"dahai","actor":0,"pai":"5m","tsumogiri":true}
+        "#;
+        let mut state = PlayerState::new(1);
+        for line in log.trim().lines() {
+            state.update(&serde_json::from_str(line).unwrap()).unwrap();
+        }
+
+        let agent = RandomAgent::new(0, ActionWeights::default());
+        let reactions = agent.reactions(&state);
+        // Chi high, pon, daiminkan and pass.
+        assert_eq!(reactions.len(), 4);
+        for (ev, _) in &reactions {
+            state.validate_reaction(ev).unwrap();
+        }
+
+        let weights = ActionWeights {
+            chi: 0.,
+            pon: 0.,
+            pass: 0.,
+            ..Default::default()
+        };
+        let mut agent = RandomAgent::new(0, weights);
+        for _ in 0..10 {
+            let Event::Daiminkan { consumed, .. } = agent.reaction(&state) else {
+                panic!("should daiminkan");
+            };
+            assert_eq!(consumed, [t!(5mr), t!(5m), t!(5m)]);
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use super::{Agent, BatchifiedAgent, InvisibleState};
+use crate::algo::shanten;
+use crate::mjai::{Event, EventExt};
+use crate::state::PlayerState;
+use crate::tile::Tile;
+use crate::{matches_tu8, must_tile, t};
+
+use anyhow::{Context, Result};
+use pyo3::prelude::*;
+
+/// Knobs of `RuleBasedAgent`.
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct RuleBasedConfig {
+    pub name: String,
+    /// Fold against riichi when the hand is at least this many shanten after
+    /// the discard.
+    pub fold_shanten: i8,
+    /// Whether to call yakuhai pons and the chis and pons that follow them.
+    pub open_hand: bool,
+}
+
+/// A fast rule-based agent without any external dependency, which is useful
+/// as a baseline and for sanity checks.
+///
+/// - Agari and ryukyoku follow `PlayerState::rule_based_agari` and
+///   `PlayerState::rule_based_ryukyoku`.
+/// - Discards follow the max EV table of `SPCalculator`.
+/// - Riichi whenever possible, and ankan only after riichi.
+/// - Pon yakuhai pairs, after which chi and pon are called when they reduce
+///   the shanten.
+/// - Against riichi, fold with the tile of the lowest estimated deal-in
+///   probability unless the hand is close enough to tenpai.
+pub struct RuleBasedAgent {
+    config: RuleBasedConfig,
+    player_id: u8,
+}
+
+impl Default for RuleBasedConfig {
+    fn default() -> Self {
+        Self {
+            name: "rule-based".to_owned(),
+            fold_shanten: 1,
+            open_hand: true,
+        }
+    }
+}
+
+#[pymethods]
+impl RuleBasedConfig {
+    #[new]
+    #[pyo3(signature = (*, name=None, fold_shanten=1, open_hand=true))]
+    fn new_py(name: Option<String>, fold_shanten: i8, open_hand: bool) -> Self {
+        let default = Self::default();
+        Self {
+            name: name.unwrap_or(default.name),
+            fold_shanten,
+            open_hand,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl RuleBasedAgent {
+    #[must_use]
+    pub const fn new(config: RuleBasedConfig, player_id: u8) -> Self {
+        Self { config, player_id }
+    }
+
+    pub fn new_batched(
+        config: &RuleBasedConfig,
+        player_ids: &[u8],
+    ) -> Result<BatchifiedAgent<Self>> {
+        BatchifiedAgent::new(|id| Ok(Self::new(config.clone(), id)), player_ids)
+    }
+
+    pub fn reaction(&self, state: &PlayerState) -> Result<Event> {
+        let actor = self.player_id;
+        let cans = state.last_cans();
+
+        if state.rule_based_agari() {
+            return Ok(Event::Hora {
+                actor,
+                target: cans.target_actor,
+                deltas: None,
+                ura_markers: None,
+            });
+        }
+        if state.rule_based_ryukyoku() {
+            return Ok(Event::Ryukyoku { deltas: None });
+        }
+
+        if cans.can_discard {
+            if cans.can_nukidora {
+                return Ok(Event::Nukidora { actor, pai: t!(N) });
+            }
+            if state.self_riichi_accepted() {
+                if cans.can_ankan
+                    && let Some(&tile) = state.ankan_candidates().first()
+                {
+                    return Ok(Event::Ankan {
+                        actor,
+                        consumed: [state.akaize_if_in_hand(tile), tile, tile, tile],
+                    });
+                }
+            } else if cans.can_riichi {
+                return Ok(Event::Reach { actor });
+            }
+            return self.discard(state);
+        }
+
+        if self.config.open_hand && !self.should_fold(state, state.shanten()) {
+            let pai = state
+                .last_kawa_tile()
+                .context("invalid state: no last kawa tile")?;
+            if let Some(event) = self.call(state, pai) {
+                return Ok(event);
+            }
+        }
+        Ok(Event::None)
+    }
+
+    fn discard(&self, state: &PlayerState) -> Result<Event> {
+        let candidates = state.discard_candidates();
+
+        // Tiles ordered by preference, where the best one goes first.
+        let mut ranking: Vec<_> = match state.single_player_tables() {
+            Ok(tables) => tables
+                .max_ev_table
+                .iter()
+                .map(|c| c.tile.deaka())
+                .filter(|t| candidates[t.as_usize()])
+                .collect(),
+            Err(_) => vec![],
+        };
+        if ranking.is_empty() {
+            ranking = (0..34)
+                .filter(|&tid| candidates[tid])
+                .map(|tid| must_tile!(tid))
+                .collect();
+            ranking.sort_by(|l, r| r.cmp_discard_priority(*l));
+        }
+
+        let mut tile = *ranking.first().context("no discard candidate")?;
+        if self.should_fold(state, state.real_time_shanten()) {
+            let threats: Vec<_> = (1..4)
+                .filter(|&s| state.riichi_accepted()[s as usize])
+                .map(|s| state.danger(s))
+                .collect::<Result<Vec<_>>>()?;
+            let deal_in =
+                |t: Tile| -> f32 { threats.iter().map(|d| d.wait_probs[t.as_usize()]).sum() };
+            // `min_by` keeps the first one on ties, which is the preferred one.
+            tile = ranking
+                .iter()
+                .copied()
+                .min_by(|&l, &r| deal_in(l).total_cmp(&deal_in(r)))
+                .unwrap_or(tile);
+        }
+
+        // Keep the aka dora whenever possible.
+        let candidates_aka = state.discard_candidates_aka();
+        let pai = if candidates_aka[tile.as_usize()] {
+            tile
+        } else {
+            tile.akaize()
+        };
+        let tsumogiri = state.last_self_tsumo().is_some_and(|t| t == pai);
+        Ok(Event::Dahai {
+            actor: self.player_id,
+            pai,
+            tsumogiri,
+        })
+    }
+
+    /// `shanten` is the one of the hand after the discard.
+    fn should_fold(&self, state: &PlayerState, shanten: i8) -> bool {
+        !state.self_riichi_declared()
+            && state.riichi_accepted()[1..].iter().any(|&b| b)
+            && shanten >= self.config.fold_shanten
+    }
+
+    fn call(&self, state: &PlayerState, pai: Tile) -> Option<Event> {
+        let actor = self.player_id;
+        let cans = state.last_cans();
+        let tile = pai.deaka();
+        let tid = tile.as_usize();
+        let tehai = state.tehai();
+        let len_div3 = tehai.iter().sum::<u8>() / 3;
+
+        let is_yakuhai = |t: Tile| {
+            matches_tu8!(t.as_u8(), P | F | C) || t == state.bakaze() || t == state.jikaze()
+        };
+        let has_yakuhai_pon = state
+            .pons()
+            .iter()
+            .chain(state.minkans())
+            .any(|&t| is_yakuhai(must_tile!(t)));
+        // Shanten of the 3n+2 hand after the call, which is the one after the
+        // best discard.
+        let shanten_after = |consumed: [Tile; 2]| {
+            let mut tehai = tehai;
+            for t in consumed {
+                tehai[t.deaka().as_usize()] -= 1;
+            }
+            shanten::calc_all(&tehai, len_div3 - 1)
+        };
+        let current = state.shanten();
+
+        if cans.can_pon {
+            let consumed = [tile, tile];
+            let worth = if is_yakuhai(tile) {
+                tehai[tid] == 2 && shanten_after(consumed) <= current
+            } else {
+                has_yakuhai_pon && shanten_after(consumed) < current
+            };
+            if worth {
+                let consumed = [state.akaize_if_in_hand(tile), tile];
+                return Some(Event::Pon {
+                    actor,
+                    target: cans.target_actor,
+                    pai,
+                    consumed,
+                });
+            }
+        }
+
+        if !has_yakuhai_pon {
+            return None;
+        }
+        [
+            (cans.can_chi_low, [tile.next(), tile.next().next()]),
+            (cans.can_chi_mid, [tile.prev(), tile.next()]),
+            (cans.can_chi_high, [tile.prev().prev(), tile.prev()]),
+        ]
+        .into_iter()
+        .filter(|&(can, consumed)| can && shanten_after(consumed) < current)
+        .min_by_key(|&(_, consumed)| shanten_after(consumed))
+        .map(|(_, consumed)| Event::Chi {
+            actor,
+            target: cans.target_actor,
+            pai,
+            consumed: consumed.map(|t| state.akaize_if_in_hand(t)),
+        })
+    }
+}
+
+impl Agent for RuleBasedAgent {
+    fn name(&self) -> String {
+        self.config.name.clone()
+    }
+
+    fn react(
+        &mut self,
+        _: &[EventExt],
+        state: &PlayerState,
+        _: Option<InvisibleState>,
+    ) -> Result<EventExt> {
+        self.reaction(state).map(EventExt::no_meta)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::BatchAgent;
+    use crate::arena::{Arena, NewAgent, Seating};
+    use serde_json as json;
+
+    #[test]
+    fn reactions() {
+        let log = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["1m","2m","3m","4p","5p","6p","7s","8s","9s","P","P","E","9m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"1s"}
+        "#;
+        let mut state = PlayerState::new(0);
+        for line in log.trim().lines() {
+            state.update(&json::from_str(line).unwrap()).unwrap();
+        }
+        let agent = RuleBasedAgent::new(RuleBasedConfig::default(), 0);
+        let Event::Dahai { pai, .. } = agent.reaction(&state).unwrap() else {
+            panic!("should discard");
+        };
+        assert!(matches_tu8!(pai.as_u8(), E | 9m | 1s), "{pai}");
+
+        let log = r#"
+            {"type":"dahai","actor":0,"pai":"9m","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"?"}
+            {"type":"dahai","actor":1,"pai":"P","tsumogiri":true}
+        "#;
+        for line in log.trim().lines() {
+            state.update(&json::from_str(line).unwrap()).unwrap();
+        }
+        let Event::Pon { consumed, .. } = agent.reaction(&state).unwrap() else {
+            panic!("should pon the yakuhai");
+        };
+        assert_eq!(consumed, [t!(P), t!(P)]);
+    }
+
+    #[test]
+    fn fold_against_riichi() {
+        let log = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","4m","7m","2p","5p","8p","2s","6s","9s","E","S","W","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"reach","actor":3}
+            {"type":"dahai","actor":3,"pai":"2s","tsumogiri":false}
+            {"type":"reach_accepted","actor":3}
+            {"type":"tsumo","actor":0,"pai":"5s"}
+        "#;
+        let mut state = PlayerState::new(0);
+        for line in log.trim().lines() {
+            state.update(&json::from_str(line).unwrap()).unwrap();
+        }
+        let agent = RuleBasedAgent::new(RuleBasedConfig::default(), 0);
+        let Event::Dahai { pai, .. } = agent.reaction(&state).unwrap() else {
+            panic!("should discard");
+        };
+        // The genbutsu is the only safe tile, since even the honors can be
+        // tanki or shanpon.
+        assert_eq!(pai, t!(2s));
+    }
+
+    #[test]
+    fn arena() {
+        let arena = Arena {
+            disable_progress_bar: true,
+            game_length: 1,
+            west_round: false,
+            ..Arena::new(Seating::FourWay)
+        };
+        let new_agents = (0..4)
+            .map(|_| -> NewAgent<'_> {
+                Box::new(|player_ids| {
+                    let agent =
+                        RuleBasedAgent::new_batched(&RuleBasedConfig::default(), player_ids)?;
+                    assert_eq!(agent.name(), "rule-based");
+                    Ok(Box::new(agent) as _)
+                })
+            })
+            .collect();
+        let results = arena.run_batch(new_agents, (0, 0), 1).unwrap();
+        assert_eq!(results.len(), 4);
+    }
+}
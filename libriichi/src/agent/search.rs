@@ -0,0 +1,347 @@
+use super::BatchAgent;
+use crate::algo::danger::WaitWeights;
+use crate::arena::{BoardState, Poll, Sampler};
+use crate::mjai::{Event, EventExt};
+use crate::state::PlayerState;
+
+use anyhow::{Context, Result, ensure};
+use ndarray::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+
+/// Knobs of `SearchAgent`.
+#[derive(Debug, Clone)]
+pub struct SearchConfig {
+    pub name: String,
+    /// Number of worlds sampled for each decision.
+    pub samples: usize,
+    /// Value of each placement by the scores at the end of the kyoku. In
+    /// sanma, only the first 3 are used.
+    pub placement_points: [f32; 4],
+    /// Prior of the waits of the opponents in riichi, see `Sampler`.
+    pub danger_prior: Option<WaitWeights>,
+    pub seed: u64,
+}
+
+/// `SearchAgent` is a determinized Monte-Carlo search on top of a fast
+/// policy.
+///
+/// For each decision with more than one legal reaction, it samples boards
+/// consistent with what the player has seen, tries every legal reaction on
+/// each of them, and rolls the kyoku out to the end with `policy` playing
+/// every seat. The reaction with the highest mean value of the placement by
+/// the scores at the end of the kyoku is taken. The rollouts never go beyond
+/// the kyoku, so the value does not tell how the rest of the game would go.
+///
+/// `policy` is given the seat as the index, so it should be created with
+/// player IDs `[0, 1, 2, 3]`, or `[0, 1, 2]` in sanma. It is also used for
+/// the decision itself when no world can be sampled.
+pub struct SearchAgent {
+    config: SearchConfig,
+    policy: Box<dyn BatchAgent>,
+    rng: ChaCha12Rng,
+    last_actions: Vec<Option<EventExt>>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            name: "search".to_owned(),
+            samples: 16,
+            placement_points: [90., 45., 0., -135.],
+            danger_prior: Some(WaitWeights::default()),
+            seed: 0,
+        }
+    }
+}
+
+impl SearchAgent {
+    pub fn new(
+        config: SearchConfig,
+        player_ids: &[u8],
+        policy: Box<dyn BatchAgent>,
+    ) -> Result<Self> {
+        ensure!(!player_ids.is_empty());
+        Ok(Self {
+            rng: ChaCha12Rng::seed_from_u64(config.seed),
+            config,
+            policy,
+            last_actions: vec![None; player_ids.len()],
+        })
+    }
+
+    /// Returns every legal reaction with its mean value over the sampled
+    /// worlds, which can also serve as a teacher signal.
+    ///
+    /// Fails if none of the sampled worlds is consistent with `log`.
+    pub fn search(&mut self, log: &[EventExt], state: &PlayerState) -> Result<Vec<(Event, f32)>> {
+        let sampler = Sampler::new(
+            log,
+            state.player_id(),
+            state.rule(),
+            self.config.danger_prior,
+        )?;
+        self.search_worlds(&sampler, state)?
+            .context("failed to sample any world consistent with the log")
+    }
+
+    pub fn reaction(&mut self, log: &[EventExt], state: &PlayerState) -> Result<EventExt> {
+        let candidates = state.legal_reactions();
+        if let [only] = candidates.as_slice() {
+            return Ok(EventExt::no_meta(only.clone()));
+        }
+        // Only a failure to sample falls back to the policy, errors of the
+        // rollouts are returned as is.
+        let values = match Sampler::new(
+            log,
+            state.player_id(),
+            state.rule(),
+            self.config.danger_prior,
+        ) {
+            Ok(sampler) => self.search_worlds(&sampler, state)?,
+            Err(_) => None,
+        };
+        let Some(values) = values else {
+            let seat = state.player_id() as usize;
+            self.policy.set_scene(seat, log, state, None)?;
+            return self.policy.get_reaction(seat, log, state, None);
+        };
+        // `max_by` keeps the last one on ties, so reverse it to prefer the
+        // first.
+        let (ev, _) = values
+            .into_iter()
+            .rev()
+            .max_by(|(_, l), (_, r)| l.total_cmp(r))
+            .unwrap();
+        Ok(EventExt::no_meta(ev))
+    }
+
+    /// Returns `None` if no world can be sampled.
+    fn search_worlds(
+        &mut self,
+        sampler: &Sampler,
+        state: &PlayerState,
+    ) -> Result<Option<Vec<(Event, f32)>>> {
+        let candidates = state.legal_reactions();
+        let seat = state.player_id();
+        let mut values = vec![0.; candidates.len()];
+        let mut sampled = 0;
+        for _ in 0..self.config.samples {
+            let Ok(board) = sampler.sample_state(&mut self.rng) else {
+                continue;
+            };
+            for (value, action) in values.iter_mut().zip(&candidates) {
+                let scores = self.rollout_kyoku(board.clone(), seat, action)?;
+                *value += self.kyoku_end_value(scores, state);
+            }
+            sampled += 1;
+        }
+        if sampled == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(
+            candidates
+                .into_iter()
+                .zip(values)
+                .map(|(ev, v)| (ev, v / sampled as f32))
+                .collect(),
+        ))
+    }
+
+    /// Plays `action` for `seat` on `board`, and the rest of the kyoku with
+    /// `policy`. Returns the scores at the end of the kyoku.
+    fn rollout_kyoku(
+        &mut self,
+        mut board: BoardState,
+        seat: u8,
+        action: &Event,
+    ) -> Result<[i32; 4]> {
+        let mut reactions = self.policy_reactions(&board, Some(seat))?;
+        reactions[seat as usize] = EventExt::no_meta(action.clone());
+        loop {
+            if matches!(board.poll(reactions)?, Poll::End) {
+                return Ok(board.end().scores);
+            }
+            reactions = self.policy_reactions(&board, None)?;
+        }
+    }
+
+    fn policy_reactions(&mut self, board: &BoardState, skip: Option<u8>) -> Result<[EventExt; 4]> {
+        let ctx = board.agent_context();
+        let version = self.policy.oracle_obs_version();
+        let mut acting = vec![];
+        for (i, state) in ctx.player_states.iter().enumerate() {
+            if skip == Some(i as u8) || !state.last_cans().can_act() {
+                continue;
+            }
+            let invisible_state = version.map(|ver| board.encode_oracle_obs(i as u8, ver));
+            self.policy
+                .set_scene(i, ctx.log, state, invisible_state.clone())?;
+            acting.push((i, invisible_state));
+        }
+
+        let mut reactions: [EventExt; 4] = Default::default();
+        for (i, invisible_state) in acting {
+            reactions[i] =
+                self.policy
+                    .get_reaction(i, ctx.log, &ctx.player_states[i], invisible_state)?;
+        }
+        Ok(reactions)
+    }
+
+    /// Value of the placement of the player of `state` by `scores`, the
+    /// absolute scores at the end of the kyoku, with the ties broken from
+    /// chiicha as in the game.
+    fn kyoku_end_value(&self, mut scores: [i32; 4], state: &PlayerState) -> f32 {
+        let n = state.rule().player_count() as usize;
+        scores[..n].rotate_left(state.player_id() as usize);
+        self.config.placement_points[state.get_rank(scores) as usize]
+    }
+}
+
+impl BatchAgent for SearchAgent {
+    fn name(&self) -> String {
+        self.config.name.clone()
+    }
+
+    fn set_scene(
+        &mut self,
+        index: usize,
+        log: &[EventExt],
+        state: &PlayerState,
+        _: Option<Array2<f32>>,
+    ) -> Result<()> {
+        self.last_actions[index] = Some(self.reaction(log, state)?);
+        Ok(())
+    }
+
+    fn get_reaction(
+        &mut self,
+        index: usize,
+        _: &[EventExt],
+        _: &PlayerState,
+        _: Option<Array2<f32>>,
+    ) -> Result<EventExt> {
+        self.last_actions[index]
+            .take()
+            .context("`get_reaction` without `set_scene`")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Agent, BatchifiedAgent, InvisibleState, Tsumogiri};
+    use anyhow::bail;
+    use serde_json as json;
+
+    struct Broken;
+
+    impl Agent for Broken {
+        fn name(&self) -> String {
+            "broken".to_owned()
+        }
+
+        fn react(
+            &mut self,
+            _: &[EventExt],
+            _: &PlayerState,
+            _: Option<InvisibleState>,
+        ) -> Result<EventExt> {
+            bail!("broken policy");
+        }
+    }
+
+    fn new_agent() -> SearchAgent {
+        let config = SearchConfig {
+            samples: 2,
+            ..Default::default()
+        };
+        let policy = Box::new(Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap());
+        SearchAgent::new(config, &[0], policy).unwrap()
+    }
+
+    fn replay(log: &str) -> (Vec<EventExt>, PlayerState) {
+        let log: Vec<EventExt> = log
+            .trim()
+            .lines()
+            .map(|line| json::from_str(line).unwrap())
+            .collect();
+        let mut state = PlayerState::new(0);
+        for ev in &log {
+            state.update(&ev.event).unwrap();
+        }
+        (log, state)
+    }
+
+    #[test]
+    fn tsumo_agari() {
+        // Tsumo is the only way to get to the top, as tenpai at the ryukyoku
+        // gives at most 3000.
+        let (log, state) = replay(
+            r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,30000,25000,20000],"tehais":[["1m","2m","3m","4m","5m","6m","7m","8m","9m","2p","3p","4p","5p"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":0,"pai":"5p"}
+            "#,
+        );
+        let mut agent = new_agent();
+        let values = agent.search(&log, &state).unwrap();
+        assert_eq!(values.len(), state.legal_reactions().len());
+        let (_, agari) = values
+            .iter()
+            .find(|(ev, _)| matches!(ev, Event::Hora { .. }))
+            .unwrap();
+        assert!((agari - 90.).abs() < f32::EPSILON);
+        assert!(
+            values
+                .iter()
+                .filter(|(ev, _)| !matches!(ev, Event::Hora { .. }))
+                .all(|(_, v)| v < agari)
+        );
+
+        let reaction = agent.reaction(&log, &state).unwrap();
+        assert!(matches!(reaction.event, Event::Hora { .. }));
+    }
+
+    #[test]
+    fn against_riichi() {
+        let (log, state) = replay(
+            r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1s","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","4m","7m","2p","5p","8p","2s","6s","9s","E","S","W","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"reach","actor":3}
+            {"type":"dahai","actor":3,"pai":"2s","tsumogiri":false}
+            {"type":"reach_accepted","actor":3}
+            {"type":"tsumo","actor":0,"pai":"5s"}
+            "#,
+        );
+        let mut agent = new_agent();
+        let values = agent.search(&log, &state).unwrap();
+        assert_eq!(values.len(), state.legal_reactions().len());
+        let reaction = agent.reaction(&log, &state).unwrap();
+        state.validate_reaction(&reaction.event).unwrap();
+
+        // Errors of the policy in the rollouts are not hidden by the
+        // fallback.
+        let policy = Box::new(BatchifiedAgent::new(|_| Ok(Broken), &[0, 1, 2, 3]).unwrap());
+        let mut agent = SearchAgent::new(SearchConfig::default(), &[0], policy).unwrap();
+        let err = agent.reaction(&log, &state).unwrap_err();
+        assert!(format!("{err:#}").contains("broken policy"));
+    }
+    #[test]
+    fn ties_from_chiicha() {
+        // Seat 2 is the oya of E2, so chiicha is seat 1 and seat 0 loses
+        // every tie.
+        let (_, state) = replay(
+            r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1s","kyoku":2,"honba":0,"kyotaku":0,"oya":2,"scores":[25000,25000,25000,25000],"tehais":[["1m","4m","7m","2p","5p","8p","2s","6s","9s","E","S","W","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            "#,
+        );
+        let agent = new_agent();
+        let value = agent.kyoku_end_value([25000; 4], &state);
+        assert!((value + 135.).abs() < f32::EPSILON);
+        let value = agent.kyoku_end_value([25000, 24000, 25000, 26000], &state);
+        assert!(value.abs() < f32::EPSILON);
+    }
+}
@@ -0,0 +1,472 @@
+use super::{Agent, BatchifiedAgent, InvisibleState, Tsumogiri};
+use crate::arena::GameResult;
+use crate::mjai::{Event, EventExt, EventWithCanAct, Metadata};
+use crate::state::PlayerState;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::io::BufReader;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail, ensure};
+use crossbeam::channel::{self, Receiver, RecvTimeoutError};
+use pyo3::prelude::*;
+use serde::Serialize;
+use serde_json::{self as json, Value};
+
+/// Describes how to spawn a mjai bot as a subprocess.
+///
+/// The bot reads one event per line from stdin, and writes one line to stdout
+/// for each event with `can_act: true`. The line can be either a single event
+/// or an array of events, where the second one is the discard following the
+/// chi, pon or riichi, as akochan does.
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct SubprocessConfig {
+    pub name: String,
+    pub program: OsString,
+    /// Occurrences of `{id}` are replaced with the player ID.
+    pub args: Vec<OsString>,
+    pub current_dir: Option<PathBuf>,
+    pub envs: Vec<(String, String)>,
+    /// Time limit for each response, `None` for no limit.
+    pub timeout: Option<Duration>,
+    /// Extra fields to be sent along with `start_game`.
+    pub start_game_extras: json::Map<String, Value>,
+}
+
+/// Runs a mjai bot as a subprocess. The bot only sees what its player is
+/// supposed to see.
+///
+/// If the bot crashes, times out or responds with an invalid reaction, it
+/// gets killed and `Tsumogiri::reaction` is used for that move. The bot is
+/// then restarted and resynced with the current kyoku at the next move.
+pub struct SubprocessMjaiAgent {
+    config: SubprocessConfig,
+    player_id: u8,
+    process: Option<Process>,
+
+    event_idx: usize,
+    naki_tx: Option<Event>,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout_rx: Receiver<String>,
+}
+
+#[pymethods]
+impl SubprocessConfig {
+    /// `cmd` is the command line, where occurrences of `{id}` are replaced
+    /// with the player ID. `timeout` is in seconds.
+    #[new]
+    #[pyo3(signature = (cmd, *, name=None, cwd=None, env=None, timeout=None))]
+    fn new_py(
+        cmd: Vec<String>,
+        name: Option<String>,
+        cwd: Option<PathBuf>,
+        env: Option<HashMap<String, String>>,
+        timeout: Option<f64>,
+    ) -> Result<Self> {
+        let Some((program, args)) = cmd.split_first() else {
+            bail!("cmd is empty");
+        };
+        let name = name.unwrap_or_else(|| {
+            Path::new(program)
+                .file_stem()
+                .map_or_else(|| program.clone(), |s| s.to_string_lossy().into_owned())
+        });
+        let timeout = timeout.map(Duration::try_from_secs_f64).transpose()?;
+
+        Ok(Self {
+            name,
+            program: program.into(),
+            args: args.iter().map(OsString::from).collect(),
+            current_dir: cwd,
+            envs: env.into_iter().flatten().collect(),
+            timeout,
+            ..Default::default()
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl SubprocessConfig {
+    /// akochan in pipe mode, located by the `AKOCHAN_DIR` and
+    /// `AKOCHAN_TACTICS` env vars.
+    pub fn akochan() -> Self {
+        let akochan_dir = env::var_os("AKOCHAN_DIR").unwrap_or_else(|| OsString::from("akochan"));
+        let akochan_exe = [&akochan_dir, &OsString::from("system.exe")]
+            .iter()
+            .collect::<PathBuf>();
+        let akochan_tactics =
+            env::var_os("AKOCHAN_TACTICS").unwrap_or_else(|| OsString::from("tactics.json"));
+
+        let mut start_game_extras = json::Map::new();
+        start_game_extras.insert("kyoku_first".to_owned(), 0.into());
+        start_game_extras.insert("aka_flag".to_owned(), true.into());
+
+        Self {
+            name: "akochan".to_owned(),
+            program: akochan_exe.into(),
+            args: vec!["pipe".into(), akochan_tactics, "{id}".into()],
+            current_dir: Some(akochan_dir.into()),
+            start_game_extras,
+            ..Default::default()
+        }
+    }
+}
+
+impl SubprocessMjaiAgent {
+    pub fn new(config: SubprocessConfig, player_id: u8) -> Result<Self> {
+        ensure!(matches!(player_id, 0..=3));
+
+        let process = Process::spawn(&config, player_id)?;
+        Ok(Self {
+            config,
+            player_id,
+            process: Some(process),
+
+            event_idx: 0,
+            naki_tx: None,
+        })
+    }
+
+    pub fn new_batched(
+        config: &SubprocessConfig,
+        player_ids: &[u8],
+    ) -> Result<BatchifiedAgent<Self>> {
+        BatchifiedAgent::new(|id| Self::new(config.clone(), id), player_ids)
+    }
+
+    fn start_game_event(&self) -> Value {
+        let start_game = Event::StartGame {
+            id: Some(self.player_id),
+            names: Default::default(),
+            seed: None,
+        };
+        let mut value = json::to_value(start_game).unwrap_or_default();
+        if let Value::Object(map) = &mut value {
+            map.extend(self.config.start_game_extras.clone());
+        }
+        value
+    }
+
+    /// Sends the events not yet sent to the bot, and reads its reaction.
+    fn query(&mut self, events: &[EventExt], state: &PlayerState) -> Result<Event> {
+        let process = self
+            .process
+            .as_mut()
+            .context("the bot has not been restarted")?;
+
+        for (i, ev) in events.iter().enumerate().skip(self.event_idx) {
+            let v = EventWithCanAct {
+                event: ev.event.masked(self.player_id),
+                can_act: Some(i == events.len() - 1),
+            };
+            process.send(&v)?;
+        }
+        self.event_idx = events.len();
+
+        let line = process.recv(self.config.timeout)?;
+        let ev = match json::from_str(&line)
+            .with_context(|| format!("failed to parse response {line}"))?
+        {
+            Value::Array(actions) => {
+                let mut actions = actions.into_iter().map(json::from_value::<Event>);
+                let ev = actions.next().context("response is empty")??;
+                self.naki_tx = actions.next().transpose()?;
+                ev
+            }
+            value => json::from_value(value)?,
+        };
+        state
+            .validate_reaction(&ev)
+            .with_context(|| format!("invalid reaction {ev:?}"))?;
+
+        Ok(ev)
+    }
+
+    /// Restarts the bot if it has been killed, and resyncs it with the
+    /// current kyoku.
+    fn ensure_process(&mut self) -> Result<()> {
+        if self.process.is_none() {
+            let mut process = Process::spawn(&self.config, self.player_id)?;
+            process.send(&self.start_game_event())?;
+            self.process = Some(process);
+            self.event_idx = 0;
+        }
+        Ok(())
+    }
+
+    /// Sends `value` to the bot if it is alive, killing it on error.
+    fn notify<T: Serialize>(&mut self, value: &T) {
+        if let Some(process) = &mut self.process
+            && let Err(err) = process.send(value)
+        {
+            log::warn!(
+                "{} (player {}): {err:#}, restarting",
+                self.config.name,
+                self.player_id,
+            );
+            self.process = None;
+        }
+    }
+}
+
+impl Agent for SubprocessMjaiAgent {
+    fn name(&self) -> String {
+        self.config.name.clone()
+    }
+
+    fn react(
+        &mut self,
+        events: &[EventExt],
+        state: &PlayerState,
+        _: Option<InvisibleState>,
+    ) -> Result<EventExt> {
+        // handle two-phase actions like Chi, Pon and Riichi
+        if let Some(dahai) = self.naki_tx.take() {
+            let last = events.last().context("events is empty")?;
+            match last.event {
+                Event::Chi { actor, .. }
+                | Event::Pon { actor, .. }
+                | Event::Daiminkan { actor, .. }
+                | Event::Reach { actor, .. }
+                    if actor == self.player_id && state.validate_reaction(&dahai).is_ok() =>
+                {
+                    return Ok(EventExt::no_meta(dahai));
+                }
+                _ => (),
+            };
+        }
+
+        self.ensure_process()?;
+        let start = Instant::now();
+        let ev = self.query(events, state).unwrap_or_else(|err| {
+            log::warn!(
+                "{} (player {}): {err:#}, restarting",
+                self.config.name,
+                self.player_id,
+            );
+            self.process = None;
+            self.naki_tx = None;
+            Tsumogiri::reaction(state)
+        });
+
+        let eval_time_ns = Instant::now()
+            .checked_duration_since(start)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos()
+            .try_into()
+            .unwrap_or(u64::MAX);
+        Ok(EventExt {
+            event: ev,
+            meta: Some(Metadata {
+                eval_time_ns: Some(eval_time_ns),
+                shanten: Some(state.shanten()),
+                ..Default::default()
+            }),
+        })
+    }
+
+    fn start_game(&mut self) -> Result<()> {
+        if self.process.is_some() {
+            let start_game = self.start_game_event();
+            self.notify(&start_game);
+        } else {
+            self.ensure_process()?;
+        }
+        Ok(())
+    }
+
+    fn end_kyoku(&mut self) -> Result<()> {
+        self.notify(&Event::EndKyoku);
+        self.event_idx = 0;
+        self.naki_tx = None;
+        Ok(())
+    }
+
+    fn end_game(&mut self, _: &GameResult) -> Result<()> {
+        self.notify(&Event::EndGame);
+        Ok(())
+    }
+}
+
+impl Process {
+    fn spawn(config: &SubprocessConfig, player_id: u8) -> Result<Self> {
+        let id = player_id.to_string();
+        let mut cmd = Command::new(&config.program);
+        cmd.args(config.args.iter().map(|a| match a.to_str() {
+            Some(a) => a.replace("{id}", &id).into(),
+            None => a.clone(),
+        }))
+        .envs(config.envs.iter().cloned())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+        if let Some(dir) = &config.current_dir {
+            cmd.current_dir(dir);
+        }
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("failed to spawn {}", config.name))?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .with_context(|| format!("failed to get stdin of {}", config.name))?;
+        let stdout = child
+            .stdout
+            .take()
+            .with_context(|| format!("failed to get stdout of {}", config.name))?;
+        let stderr = child
+            .stderr
+            .take()
+            .with_context(|| format!("failed to get stderr of {}", config.name))?;
+
+        // The channel gets disconnected when stdout is closed, which is how a
+        // crash is noticed.
+        let (stdout_tx, stdout_rx) = channel::unbounded();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if stdout_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let prefix = format!("{} (player {player_id})", config.name);
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                log::info!("{prefix}: {line}");
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout_rx,
+        })
+    }
+
+    fn send<T: Serialize>(&mut self, value: &T) -> Result<()> {
+        json::to_writer(&mut self.stdin, value)?;
+        self.stdin.write_all(b"\n")?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    fn recv(&self, timeout: Option<Duration>) -> Result<String> {
+        let Some(timeout) = timeout else {
+            return self.stdout_rx.recv().context("unexpected EOF");
+        };
+        self.stdout_rx
+            .recv_timeout(timeout)
+            .map_err(|err| match err {
+                RecvTimeoutError::Timeout => anyhow::anyhow!("timed out"),
+                RecvTimeoutError::Disconnected => anyhow::anyhow!("unexpected EOF"),
+            })
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        if let Err(err) = self.child.kill() {
+            log::error!("failed to kill subprocess: {err}");
+        }
+        if let Err(err) = self.child.wait() {
+            log::error!("failed to wait subprocess: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::BatchAgent;
+    use crate::arena::{BatchGame, Index};
+    use crate::rule::RuleSet;
+    use std::{array, fs};
+
+    /// A stand-in bot which plays tsumogiri. It crashes in its first run and
+    /// hangs in its second run, counting the runs in the file at `argv[1]`.
+    const STAND_IN: &str = r#"
+import json, os, sys, time
+path = sys.argv[1]
+runs = int(open(path).read()) if os.path.exists(path) else 0
+open(path, 'w').write(str(runs + 1))
+me = None
+lines = 0
+while line := sys.stdin.readline():
+    lines += 1
+    if lines == 40 and runs == 0:
+        sys.exit(1)
+    if lines == 40 and runs == 1:
+        time.sleep(2)
+    ev = json.loads(line)
+    if ev['type'] == 'start_game':
+        me = ev['id']
+    if ev.get('can_act'):
+        if ev['type'] == 'tsumo' and ev['actor'] == me:
+            ret = {'type': 'dahai', 'actor': me, 'pai': ev['pai'], 'tsumogiri': True}
+        else:
+            ret = {'type': 'none'}
+        print(json.dumps(ret), flush=True)
+"#;
+
+    #[test]
+    fn restart() {
+        // The stand-in is a Python script, so skip where there is no python3.
+        if Command::new("python3").arg("--version").output().is_err() {
+            return;
+        }
+        let runs_file = env::temp_dir().join(format!("riichi-subprocess-{}", std::process::id()));
+        fs::remove_file(&runs_file).ok();
+
+        let config = SubprocessConfig {
+            name: "stand-in".to_owned(),
+            program: "python3".into(),
+            args: vec![
+                "-c".into(),
+                STAND_IN.into(),
+                runs_file.as_os_str().to_owned(),
+            ],
+            timeout: Some(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let mut agents: [Box<dyn BatchAgent>; 2] = [
+            Box::new(SubprocessMjaiAgent::new_batched(&config, &[0]).unwrap()),
+            Box::new(Tsumogiri::new_batched(&[1, 2, 3]).unwrap()),
+        ];
+        let indexes = &[array::from_fn(|i| Index {
+            agent_idx: (i > 0) as usize,
+            player_id_idx: i.saturating_sub(1),
+        })];
+
+        let g = BatchGame {
+            rule: RuleSet {
+                game_length: 4,
+                ..RuleSet::tenhou()
+            },
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let results = g.run(&mut agents, indexes, &[(1009, 0)]).unwrap();
+        assert_eq!(results[0].names[0], "stand-in");
+
+        let runs: u32 = fs::read_to_string(&runs_file).unwrap().parse().unwrap();
+        fs::remove_file(&runs_file).ok();
+        assert_eq!(runs, 3);
+    }
+}
@@ -1,8 +1,9 @@
 use super::{Agent, BatchifiedAgent, InvisibleState};
 use crate::mjai::{Event, EventExt};
+use crate::must_tile;
 use crate::state::PlayerState;
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 
 /// `Tsumogiri` always performs tsumogiri in all case and will not emit any
 /// action other than discard.
@@ -12,6 +13,30 @@ impl Tsumogiri {
     pub fn new_batched(player_ids: &[u8]) -> Result<BatchifiedAgent<Self>> {
         BatchifiedAgent::new(|id| Ok(Self(id)), player_ids)
     }
+
+    /// Returns tsumogiri if possible, or the first valid discard after a
+    /// call, or pass if it cannot discard. It is also used as the fallback
+    /// for players that fail to respond.
+    #[must_use]
+    pub fn reaction(state: &PlayerState) -> Event {
+        if !state.last_cans().can_discard {
+            return Event::None;
+        }
+
+        let candidates = state.discard_candidates_aka();
+        let (pai, tsumogiri) = match state.last_self_tsumo() {
+            Some(tile) if candidates[tile.as_usize()] => (tile, true),
+            _ => {
+                let idx = candidates.iter().position(|&b| b).unwrap_or_default();
+                (must_tile!(idx), false)
+            }
+        };
+        Event::Dahai {
+            actor: state.player_id(),
+            pai,
+            tsumogiri,
+        }
+    }
 }
 
 impl Agent for Tsumogiri {
@@ -25,15 +50,6 @@ impl Agent for Tsumogiri {
         state: &PlayerState,
         _: Option<InvisibleState>,
     ) -> Result<EventExt> {
-        let ev = if state.last_cans().can_discard {
-            Event::Dahai {
-                actor: self.0,
-                pai: state.last_self_tsumo().context("last tsumo is empty")?,
-                tsumogiri: true,
-            }
-        } else {
-            Event::None
-        };
-        Ok(EventExt::no_meta(ev))
+        Ok(EventExt::no_meta(Self::reaction(state)))
     }
 }
@@ -8,6 +8,7 @@
 
 use super::point::Point;
 use super::shanten;
+use crate::rule::RuleSet;
 use crate::tile::Tile;
 use crate::{matches_tu8, must_tile, tu8};
 use std::cmp::Ordering;
@@ -73,6 +74,151 @@ pub enum Agari {
     Yakuman(u8),
 }
 
+/// A yaku, named in the Hepburn romanization of its Japanese name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Yaku {
+    /// 立直
+    Riichi,
+    /// 両立直
+    DoubleRiichi,
+    /// 一発
+    Ippatsu,
+    /// 門前清自摸和
+    MenzenTsumo,
+    /// 海底摸月
+    Haitei,
+    /// 河底撈魚
+    Houtei,
+    /// 嶺上開花
+    Rinshan,
+    /// 槍槓
+    Chankan,
+    /// 平和
+    Pinfu,
+    /// 七対子
+    Chiitoitsu,
+    /// 断幺九
+    Tanyao,
+    /// 一盃口
+    Iipeikou,
+    /// 二盃口
+    Ryanpeikou,
+    /// 対々和
+    Toitoi,
+    /// 三暗刻
+    Sanankou,
+    /// 三槓子
+    Sankantsu,
+    /// 三色同順
+    SanshokuDoujun,
+    /// 三色同刻
+    SanshokuDoukou,
+    /// 一気通貫
+    Ittsuu,
+    /// 混全帯幺九
+    Chanta,
+    /// 純全帯幺九
+    Junchan,
+    /// 混老頭
+    Honroutou,
+    /// 小三元
+    Shousangen,
+    /// 混一色
+    Honitsu,
+    /// 清一色
+    Chinitsu,
+    /// 役牌:場風牌
+    Bakaze,
+    /// 役牌:自風牌
+    Jikaze,
+    /// 役牌:白
+    Haku,
+    /// 役牌:發
+    Hatsu,
+    /// 役牌:中
+    Chun,
+    /// 天和
+    Tenhou,
+    /// 地和
+    Chihou,
+    /// 国士無双
+    KokushiMusou,
+    /// 九蓮宝燈
+    ChuurenPoutou,
+    /// 四暗刻
+    Suuankou,
+    /// 四槓子
+    Suukantsu,
+    /// 大三元
+    Daisangen,
+    /// 小四喜
+    Shousuushii,
+    /// 大四喜
+    Daisuushii,
+    /// 字一色
+    Tsuuiisou,
+    /// 緑一色
+    Ryuuiisou,
+    /// 清老頭
+    Chinroutou,
+    /// 人和, with `RuleSet::renhou`.
+    Renhou,
+    /// 十三不塔, with `RuleSet::shiisanpuutaa`.
+    Shiisanpuutaa,
+    /// 国士無双十三面, with `RuleSet::double_yakuman`.
+    KokushiMusouJuusanmen,
+    /// 純正九蓮宝燈, ditto.
+    JunseiChuurenPoutou,
+    /// 四暗刻単騎, ditto.
+    SuuankouTanki,
+    /// 三連刻, with `RuleSet::sanrenkou`.
+    Sanrenkou,
+}
+
+/// A component of fu before rounding up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fu {
+    /// 副底, always 20.
+    Futei,
+    /// 七対子, always 25 in place of everything else.
+    Chiitoitsu,
+    /// 門前加符
+    MenzenRon,
+    /// ツモ符
+    Tsumo,
+    /// 喰い平和, which makes 30 fu.
+    OpenPinfu,
+    Minkou(Tile),
+    Ankou(Tile),
+    Minkan(Tile),
+    Ankan(Tile),
+    /// 雀頭 of 役牌, counted once for each of 場風 and 自風.
+    Jantou(Tile),
+    Kanchan,
+    Penchan,
+    Tanki,
+}
+
+/// How a hand scores, as returned by `AgariCalculator::agari_detail`.
+#[derive(Debug, Clone)]
+pub struct AgariDetail {
+    pub agari: Agari,
+    /// Each yaku with its han, or with its multiplier if `agari` is yakuman.
+    pub yakus: Vec<(Yaku, u8)>,
+    /// Components of the fu, empty if `agari` has 0 fu.
+    pub fu: Vec<(Fu, u8)>,
+    /// Including nukidora.
+    pub dora: u8,
+    pub aka_dora: u8,
+    pub ura_dora: u8,
+}
+
+#[derive(Default)]
+struct Breakdown {
+    yakus: Vec<(Yaku, u8)>,
+    fu: Vec<(Fu, u8)>,
+}
+
 #[derive(Debug)]
 pub struct AgariCalculator<'a> {
     /// Must include the winning tile (i.e. must be 3n+2)
@@ -93,6 +239,9 @@ pub struct AgariCalculator<'a> {
     /// ankou/ankan-related yakus like 三/四暗刻. It will not be used to
     /// determine 門前清自摸和.
     pub is_ron: bool,
+    /// Only the yaku related switches are used, which are `kuitan`,
+    /// `double_yakuman`, `kazoe_yakuman` and `sanrenkou`.
+    pub rule: RuleSet,
 }
 
 struct DivWorker<'a> {
@@ -200,6 +349,33 @@ impl Agari {
     }
 }
 
+impl Yaku {
+    #[inline]
+    #[must_use]
+    pub const fn is_yakuman(self) -> bool {
+        matches!(
+            self,
+            Self::Tenhou
+                | Self::Chihou
+                | Self::KokushiMusou
+                | Self::ChuurenPoutou
+                | Self::Suuankou
+                | Self::Suukantsu
+                | Self::Daisangen
+                | Self::Shousuushii
+                | Self::Daisuushii
+                | Self::Tsuuiisou
+                | Self::Ryuuiisou
+                | Self::Chinroutou
+                | Self::Renhou
+                | Self::Shiisanpuutaa
+                | Self::KokushiMusouJuusanmen
+                | Self::JunseiChuurenPoutou
+                | Self::SuuankouTanki
+        )
+    }
+}
+
 impl AgariCalculator<'_> {
     #[inline]
     #[must_use]
@@ -227,7 +403,7 @@ impl AgariCalculator<'_> {
             Some(match agari {
                 Agari::Normal { fu, han } => Agari::Normal {
                     fu,
-                    han: han + additional_hans + doras,
+                    han: self.kazoe(han + additional_hans + doras),
                 },
                 _ => agari,
             })
@@ -236,7 +412,7 @@ impl AgariCalculator<'_> {
         } else if additional_hans + doras >= 5 {
             Some(Agari::Normal {
                 fu: 0,
-                han: additional_hans + doras,
+                han: self.kazoe(additional_hans + doras),
             })
         } else {
             let (tile14, key) = get_tile14_and_key(self.tehai);
@@ -245,7 +421,7 @@ impl AgariCalculator<'_> {
             let fu = divs
                 .iter()
                 .map(|div| DivWorker::new(self, &tile14, div))
-                .map(|w| w.calc_fu(false))
+                .map(|w| w.calc_fu::<false>(false, &mut vec![]))
                 .max()?;
             Some(Agari::Normal {
                 fu,
@@ -254,6 +430,68 @@ impl AgariCalculator<'_> {
         }
     }
 
+    /// Same as `agari`, but also tells how the hand scores. `additional_yakus`
+    /// are the yakus counted in `additional_hans` of `agari`, along with their
+    /// hans.
+    ///
+    /// Doras are not counted in `Agari::Yakuman`, but are still reported. The
+    /// han of the result is capped at 12 if 数え役満 is disabled, so it may be
+    /// less than the sum of `yakus` and doras.
+    #[must_use]
+    pub fn agari_detail(
+        &self,
+        additional_yakus: &[(Yaku, u8)],
+        dora: u8,
+        aka_dora: u8,
+        ura_dora: u8,
+    ) -> Option<AgariDetail> {
+        let additional_hans = additional_yakus.iter().map(|&(_, n)| n).sum::<u8>();
+        let doras = dora + aka_dora + ura_dora;
+        let detail = |agari, yakus, fu| AgariDetail {
+            agari,
+            yakus,
+            fu,
+            dora,
+            aka_dora,
+            ura_dora,
+        };
+
+        if let Some((agari, breakdown)) = self.search_yakus_detail() {
+            return Some(match agari {
+                Agari::Normal { fu, han } => {
+                    let mut yakus = additional_yakus.to_vec();
+                    yakus.extend(breakdown.yakus);
+                    let agari = Agari::Normal {
+                        fu,
+                        han: self.kazoe(han + additional_hans + doras),
+                    };
+                    detail(agari, yakus, breakdown.fu)
+                }
+                _ => detail(agari, breakdown.yakus, vec![]),
+            });
+        }
+        if additional_hans == 0 {
+            return None;
+        }
+
+        let han = self.kazoe(additional_hans + doras);
+        let yakus = additional_yakus.to_vec();
+        if han >= 5 {
+            return Some(detail(Agari::Normal { fu: 0, han }, yakus, vec![]));
+        }
+        let (tile14, key) = get_tile14_and_key(self.tehai);
+        let divs = AGARI_TABLE.get(&key)?;
+        let (fu, fus) = divs
+            .iter()
+            .map(|div| {
+                let mut fus = vec![];
+                let fu = DivWorker::new(self, &tile14, div).calc_fu::<true>(false, &mut fus);
+                (fu, fus)
+            })
+            .max_by_key(|&(fu, _)| fu)?;
+        Some(detail(Agari::Normal { fu, han }, yakus, fus))
+    }
+
     fn search_yakus_impl(&self, return_if_any: bool) -> Option<Agari> {
         assert_eq!(
             self.is_menzen,
@@ -262,26 +500,71 @@ impl AgariCalculator<'_> {
 
         // Kokushi has a special pattern and cannot be combined with other
         // pattern-based yakus.
-        if self.is_menzen && shanten::calc_kokushi(self.tehai) == -1 {
-            // 国士無双
-            return Some(Agari::Yakuman(1));
+        if let Some((_, n)) = self.kokushi() {
+            return Some(Agari::Yakuman(n));
         }
 
         let (tile14, key) = get_tile14_and_key(self.tehai);
         let divs = AGARI_TABLE.get(&key)?;
 
+        let mut breakdown = Breakdown::default();
         if return_if_any {
             // Benchmark result indicates it is too trivial to use rayon here.
             divs.iter()
                 .map(|div| DivWorker::new(self, &tile14, div))
-                .find_map(|w| w.search_yakus::<true>())
+                .find_map(|w| w.search_yakus::<true, false>(&mut breakdown))
         } else {
             divs.iter()
                 .map(|div| DivWorker::new(self, &tile14, div))
-                .filter_map(|w| w.search_yakus::<false>())
+                .filter_map(|w| w.search_yakus::<false, false>(&mut breakdown))
                 .max()
         }
     }
+
+    fn kokushi(&self) -> Option<(Yaku, u8)> {
+        if !self.is_menzen || shanten::calc_kokushi(self.tehai) != -1 {
+            return None;
+        }
+        if self.rule.double_yakuman && self.tehai[self.winning_tile as usize] == 2 {
+            // 国士無双十三面
+            Some((Yaku::KokushiMusouJuusanmen, 2))
+        } else {
+            // 国士無双
+            Some((Yaku::KokushiMusou, 1))
+        }
+    }
+
+    /// Returns `han` capped at 三倍満 if 数え役満 is disabled.
+    const fn kazoe(&self, han: u8) -> u8 {
+        if !self.rule.kazoe_yakuman && han >= 13 {
+            12
+        } else {
+            han
+        }
+    }
+
+    /// Same as `search_yakus`, along with the breakdown of the division that
+    /// gives the result.
+    fn search_yakus_detail(&self) -> Option<(Agari, Breakdown)> {
+        if let Some((yaku, n)) = self.kokushi() {
+            let breakdown = Breakdown {
+                yakus: vec![(yaku, n)],
+                fu: vec![],
+            };
+            return Some((Agari::Yakuman(n), breakdown));
+        }
+
+        let (tile14, key) = get_tile14_and_key(self.tehai);
+        let divs = AGARI_TABLE.get(&key)?;
+        divs.iter()
+            .filter_map(|div| {
+                let mut breakdown = Breakdown::default();
+                DivWorker::new(self, &tile14, div)
+                    .search_yakus::<false, true>(&mut breakdown)
+                    .map(|agari| (agari, breakdown))
+            })
+            .max_by_key(|&(agari, _)| agari)
+    }
 }
 
 impl<'a> DivWorker<'a> {
@@ -359,89 +642,106 @@ impl<'a> DivWorker<'a> {
         self.all_kotsu_and_kantsu().chain(self.all_shuntsu())
     }
 
-    fn calc_fu(&self, has_pinfu: bool) -> u8 {
+    /// If `RECORD` holds, each component of the fu before rounding up is
+    /// recorded into `fus`.
+    fn calc_fu<const RECORD: bool>(&self, has_pinfu: bool, fus: &mut Vec<(Fu, u8)>) -> u8 {
         if self.div.has_chitoi {
+            if RECORD {
+                fus.push((Fu::Chiitoitsu, 25));
+            }
             return 25;
         }
         let mut fu = 20;
-
-        fu += self
-            .menzen_kotsu
-            .iter()
-            .map(|&t| {
-                // `menzen_kotsu` are usually ankou, except when the winning
-                // tile makes a minkou and the tile is the winning tile.
-                let is_minkou = self.winning_tile_makes_minkou && t == self.sup.winning_tile;
-                match (is_minkou, must_tile!(t).is_yaokyuu()) {
-                    (false, true) => 8,
-                    (false, false) | (true, true) => 4,
-                    (true, false) => 2,
+        if RECORD {
+            fus.push((Fu::Futei, 20));
+        }
+        macro_rules! add_fu {
+            ($kind:expr, $n:expr) => {{
+                let n = $n;
+                fu += n;
+                if RECORD {
+                    fus.push(($kind, n));
                 }
-            })
-            .sum::<u8>();
-        fu += self
-            .sup
-            .pons
-            .iter()
-            .map(|&t| if must_tile!(t).is_yaokyuu() { 4 } else { 2 })
-            .sum::<u8>();
-        fu += self
-            .sup
-            .ankans
-            .iter()
-            .map(|&t| if must_tile!(t).is_yaokyuu() { 32 } else { 16 })
-            .sum::<u8>();
-        fu += self
-            .sup
-            .minkans
-            .iter()
-            .map(|&t| if must_tile!(t).is_yaokyuu() { 16 } else { 8 })
-            .sum::<u8>();
+            }};
+        }
+
+        for &t in &self.menzen_kotsu {
+            // `menzen_kotsu` are usually ankou, except when the winning tile
+            // makes a minkou and the tile is the winning tile.
+            let is_minkou = self.winning_tile_makes_minkou && t == self.sup.winning_tile;
+            let tile = must_tile!(t);
+            match (is_minkou, tile.is_yaokyuu()) {
+                (false, true) => add_fu!(Fu::Ankou(tile), 8),
+                (false, false) => add_fu!(Fu::Ankou(tile), 4),
+                (true, true) => add_fu!(Fu::Minkou(tile), 4),
+                (true, false) => add_fu!(Fu::Minkou(tile), 2),
+            }
+        }
+        for &t in self.sup.pons {
+            let tile = must_tile!(t);
+            add_fu!(Fu::Minkou(tile), if tile.is_yaokyuu() { 4 } else { 2 });
+        }
+        for &t in self.sup.ankans {
+            let tile = must_tile!(t);
+            add_fu!(Fu::Ankan(tile), if tile.is_yaokyuu() { 32 } else { 16 });
+        }
+        for &t in self.sup.minkans {
+            let tile = must_tile!(t);
+            add_fu!(Fu::Minkan(tile), if tile.is_yaokyuu() { 16 } else { 8 });
+        }
 
+        let pair = must_tile!(self.pair_tile);
         if matches_tu8!(self.pair_tile, P | F | C) {
-            fu += 2;
+            add_fu!(Fu::Jantou(pair), 2);
         } else {
             // As per [Tenhou's rule](https://tenhou.net/man/#RULE):
             //
             // > 連風牌は4符
             if self.pair_tile == self.sup.bakaze {
-                fu += 2;
+                add_fu!(Fu::Jantou(pair), 2);
             }
             if self.pair_tile == self.sup.jikaze {
-                fu += 2;
+                add_fu!(Fu::Jantou(pair), 2);
             }
         }
 
         if fu == 20 {
-            return if !self.sup.is_menzen {
-                30
-            } else if has_pinfu {
-                if self.sup.is_ron { 30 } else { 20 }
-            } else if self.sup.is_ron {
-                40
-            } else {
-                30
-            };
+            if !self.sup.is_menzen {
+                // 喰い平和
+                add_fu!(Fu::OpenPinfu, 10);
+                return fu;
+            }
+            if has_pinfu {
+                if self.sup.is_ron {
+                    add_fu!(Fu::MenzenRon, 10);
+                    return fu;
+                }
+                return 20;
+            }
+            // Otherwise the wait must be kanchan, penchan or tanki, which
+            // ends up with 40 for ron and 30 for tsumo below.
         }
 
         if !self.sup.is_ron {
-            fu += 2;
+            add_fu!(Fu::Tsumo, 2);
         } else if self.sup.is_menzen {
-            fu += 10;
+            add_fu!(Fu::MenzenRon, 10);
         }
 
         if !self.winning_tile_makes_minkou {
             if self.pair_tile == self.sup.winning_tile {
-                // tanki wait
-                fu += 2;
+                add_fu!(Fu::Tanki, 2);
             } else {
-                let is_kanchan_penchan = self.menzen_shuntsu.iter().any(|&s| {
-                    s + 1 == self.sup.winning_tile
-                        || s % 9 == 0 && s + 2 == self.sup.winning_tile
-                        || s % 9 == 6 && s == self.sup.winning_tile
-                });
-                if is_kanchan_penchan {
-                    fu += 2;
+                let w = self.sup.winning_tile;
+                let is_kanchan = self.menzen_shuntsu.iter().any(|&s| s + 1 == w);
+                let is_penchan = self
+                    .menzen_shuntsu
+                    .iter()
+                    .any(|&s| s % 9 == 0 && s + 2 == w || s % 9 == 6 && s == w);
+                if is_kanchan {
+                    add_fu!(Fu::Kanchan, 2);
+                } else if is_penchan {
+                    add_fu!(Fu::Penchan, 2);
                 }
             }
         }
@@ -449,7 +749,12 @@ impl<'a> DivWorker<'a> {
         ((fu - 1) / 10 + 1) * 10
     }
 
-    fn search_yakus<const RETURN_IF_ANY: bool>(&self) -> Option<Agari> {
+    /// If `RECORD` holds, the yakus and fu making up the result are recorded
+    /// into `breakdown`.
+    fn search_yakus<const RETURN_IF_ANY: bool, const RECORD: bool>(
+        &self,
+        breakdown: &mut Breakdown,
+    ) -> Option<Agari> {
         let mut han = 0;
         let mut yakuman = 0;
 
@@ -465,12 +770,15 @@ impl<'a> DivWorker<'a> {
         macro_rules! make_return {
             () => {
                 return if yakuman > 0 {
+                    if RECORD {
+                        breakdown.yakus.retain(|(yaku, _)| yaku.is_yakuman());
+                    }
                     Some(Agari::Yakuman(yakuman))
                 } else if han > 0 {
                     let fu = if RETURN_IF_ANY || han >= 5 {
                         0
                     } else {
-                        self.calc_fu(has_pinfu)
+                        self.calc_fu::<RECORD>(has_pinfu, &mut breakdown.fu)
                     };
                     Some(Agari::Normal { fu, han })
                 } else {
@@ -478,9 +786,28 @@ impl<'a> DivWorker<'a> {
                 };
             };
         }
-        macro_rules! check_early_return {
-            ($($block:tt)*) => {{
-                $($block)*;
+        macro_rules! han {
+            ($yaku:expr, $n:expr) => {{
+                let n = $n;
+                han += n;
+                if RECORD {
+                    breakdown.yakus.push(($yaku, n));
+                }
+                if RETURN_IF_ANY {
+                    make_return!();
+                }
+            }};
+        }
+        macro_rules! yakuman {
+            ($yaku:expr) => {
+                yakuman!($yaku, 1)
+            };
+            ($yaku:expr, $n:expr) => {{
+                let n = $n;
+                yakuman += n;
+                if RECORD {
+                    breakdown.yakus.push(($yaku, n));
+                }
                 if RETURN_IF_ANY {
                     make_return!();
                 }
@@ -489,50 +816,60 @@ impl<'a> DivWorker<'a> {
 
         if has_pinfu {
             // 平和
-            check_early_return! { han += 1 };
+            han!(Yaku::Pinfu, 1);
         }
         if self.div.has_chitoi {
             // 七対子
-            check_early_return! { han += 2 };
+            han!(Yaku::Chiitoitsu, 2);
         }
         if self.div.has_ryanpeikou {
             // 二盃口
-            check_early_return! { han += 3 };
+            han!(Yaku::Ryanpeikou, 3);
         }
         if self.div.has_chuuren {
-            // 九蓮宝燈
-            check_early_return! { yakuman += 1 };
+            // The winning tile is the extra one of 1112345678999 iff it is
+            // 純正.
+            let w = self.sup.winning_tile;
+            let extra = if matches!(w % 9, 0 | 8) { 4 } else { 2 };
+            if self.sup.rule.double_yakuman && self.sup.tehai[w as usize] == extra {
+                // 純正九蓮宝燈
+                yakuman!(Yaku::JunseiChuurenPoutou, 2);
+            } else {
+                // 九蓮宝燈
+                yakuman!(Yaku::ChuurenPoutou);
+            }
         }
 
-        let has_tanyao = if self.div.has_chitoi {
-            self.chitoi_pairs().all(|t| {
-                let kind = t / 9;
-                let num = t % 9;
-                kind < 3 && num > 0 && num < 8
-            })
-        } else {
-            self.all_shuntsu().all(|s| {
-                let num = s % 9;
-                num > 0 && num < 6
-            }) && self
-                .all_kotsu_and_kantsu()
-                .chain(iter::once(self.pair_tile))
-                .all(|k| {
-                    let kind = k / 9;
-                    let num = k % 9;
+        let has_tanyao = (self.sup.is_menzen || self.sup.rule.kuitan)
+            && if self.div.has_chitoi {
+                self.chitoi_pairs().all(|t| {
+                    let kind = t / 9;
+                    let num = t % 9;
                     kind < 3 && num > 0 && num < 8
                 })
-        };
+            } else {
+                self.all_shuntsu().all(|s| {
+                    let num = s % 9;
+                    num > 0 && num < 6
+                }) && self
+                    .all_kotsu_and_kantsu()
+                    .chain(iter::once(self.pair_tile))
+                    .all(|k| {
+                        let kind = k / 9;
+                        let num = k % 9;
+                        kind < 3 && num > 0 && num < 8
+                    })
+            };
         if has_tanyao {
             // 断幺九
-            check_early_return! { han += 1 };
+            han!(Yaku::Tanyao, 1);
         }
 
         let has_toitoi =
             !self.div.has_chitoi && self.menzen_shuntsu.is_empty() && self.sup.chis.is_empty();
         if has_toitoi {
             // 対々和
-            check_early_return! { han += 2 };
+            han!(Yaku::Toitoi, 2);
         }
 
         let mut isou_kind = None;
@@ -564,17 +901,21 @@ impl<'a> DivWorker<'a> {
         }
         if isou_kind.is_none() {
             // 字一色
-            check_early_return! { yakuman += 1 };
+            yakuman!(Yaku::Tsuuiisou);
         } else if is_chinitsu_or_honitsu {
             // 混一色, 清一色
-            let n = if has_jihai { 2 } else { 5 } + self.sup.is_menzen as u8;
-            check_early_return! { han += n };
+            let (yaku, n) = if has_jihai {
+                (Yaku::Honitsu, 2)
+            } else {
+                (Yaku::Chinitsu, 5)
+            };
+            han!(yaku, n + self.sup.is_menzen as u8);
         }
 
         if !self.div.has_chitoi {
             // 一盃口
             if self.div.has_ipeikou {
-                check_early_return! { han += 1 };
+                han!(Yaku::Iipeikou, 1);
             } else if !self.sup.ankans.is_empty()
                 && self.sup.is_menzen
                 && self.menzen_shuntsu.len() >= 2
@@ -592,15 +933,15 @@ impl<'a> DivWorker<'a> {
                     }
                 });
                 if has_ipeikou {
-                    check_early_return! { han += 1 };
+                    han!(Yaku::Iipeikou, 1);
                 }
             }
 
             // 一気通貫
             if self.sup.is_menzen && self.div.has_ittsuu {
-                check_early_return! { han += 2 };
+                han!(Yaku::Ittsuu, 2);
             } else if self.sup.chis.is_empty() && self.div.has_ittsuu {
-                check_early_return! { han += 1 };
+                han!(Yaku::Ittsuu, 1);
             } else if self.menzen_shuntsu.len() + self.sup.chis.len() >= 3 {
                 let mut kinds = [0; 3];
                 for s in self.all_shuntsu() {
@@ -614,7 +955,7 @@ impl<'a> DivWorker<'a> {
                     };
                 }
                 if kinds.contains(&0b111) {
-                    check_early_return! { han += 1 };
+                    han!(Yaku::Ittsuu, 1);
                 }
             }
 
@@ -627,7 +968,7 @@ impl<'a> DivWorker<'a> {
             if s_counter.contains(&0b111) {
                 // 三色同順
                 let n = if self.sup.is_menzen { 2 } else { 1 };
-                check_early_return! { han += n };
+                han!(Yaku::SanshokuDoujun, n);
             } else {
                 let mut k_counter = [0; 9];
                 for k in self.all_kotsu_and_kantsu() {
@@ -639,26 +980,44 @@ impl<'a> DivWorker<'a> {
                 }
                 if k_counter.contains(&0b111) {
                     // 三色同刻
-                    check_early_return! { han += 2 };
+                    han!(Yaku::SanshokuDoukou, 2);
+                }
+            }
+
+            if self.sup.rule.sanrenkou {
+                let mut k_marks = [0_u16; 3];
+                for k in self.all_kotsu_and_kantsu().filter(|&k| k < 3 * 9) {
+                    k_marks[k as usize / 9] |= 1 << (k % 9);
+                }
+                if k_marks
+                    .iter()
+                    .any(|&m| (0..7).any(|i| (m >> i) & 0b111 == 0b111))
+                {
+                    // 三連刻
+                    han!(Yaku::Sanrenkou, 2);
                 }
             }
 
             let ankous_count = self.sup.ankans.len() + self.menzen_kotsu.len()
                 - self.winning_tile_makes_minkou as usize;
             match ankous_count {
+                4 if self.sup.rule.double_yakuman && self.pair_tile == self.sup.winning_tile => {
+                    // 四暗刻単騎
+                    yakuman!(Yaku::SuuankouTanki, 2);
+                }
                 // 四暗刻
-                4 => check_early_return! { yakuman += 1 },
+                4 => yakuman!(Yaku::Suuankou),
                 // 三暗刻
-                3 => check_early_return! { han += 2 },
+                3 => han!(Yaku::Sanankou, 2),
                 _ => (),
             };
 
             let kans_count = self.sup.ankans.len() + self.sup.minkans.len();
             match kans_count {
                 // 四槓子
-                4 => check_early_return! { yakuman += 1 },
+                4 => yakuman!(Yaku::Suukantsu),
                 // 三槓子
-                3 => check_early_return! { han += 2 },
+                3 => han!(Yaku::Sankantsu, 2),
                 _ => (),
             };
 
@@ -669,7 +1028,7 @@ impl<'a> DivWorker<'a> {
                 && self.all_shuntsu().all(|s| s == tu8!(2s)); // only 234s is possible for shuntsu in ryuisou
             if has_ryuisou {
                 // 緑一色
-                check_early_return! { yakuman += 1 };
+                yakuman!(Yaku::Ryuuiisou);
             }
 
             if !has_tanyao {
@@ -681,35 +1040,37 @@ impl<'a> DivWorker<'a> {
                     }
                 }
                 if has_jihai[self.sup.bakaze as usize - 3 * 9] {
-                    // 役牌:門風牌
-                    check_early_return! { han += 1 };
+                    // 役牌:場風牌
+                    han!(Yaku::Bakaze, 1);
                 }
                 if has_jihai[self.sup.jikaze as usize - 3 * 9] {
-                    // 役牌:場風牌
-                    check_early_return! { han += 1 };
+                    // 役牌:門風牌
+                    han!(Yaku::Jikaze, 1);
                 }
 
-                let saneins = (4..7).filter(|&i| has_jihai[i]).count() as u8;
-                if saneins > 0 {
-                    // 役牌:三元牌
-                    check_early_return! { han += saneins };
-                    if saneins == 3 {
-                        // 大三元
-                        check_early_return! { yakuman += 1 };
-                    } else if saneins == 2 && matches_tu8!(self.pair_tile, P | F | C) {
-                        // 小三元
-                        check_early_return! { han += 2 };
+                // 役牌:三元牌
+                for (i, yaku) in [(4, Yaku::Haku), (5, Yaku::Hatsu), (6, Yaku::Chun)] {
+                    if has_jihai[i] {
+                        han!(yaku, 1);
                     }
                 }
+                let saneins = (4..7).filter(|&i| has_jihai[i]).count();
+                if saneins == 3 {
+                    // 大三元
+                    yakuman!(Yaku::Daisangen);
+                } else if saneins == 2 && matches_tu8!(self.pair_tile, P | F | C) {
+                    // 小三元
+                    han!(Yaku::Shousangen, 2);
+                }
 
                 let winds = (0..4).filter(|&i| has_jihai[i]).count();
                 #[allow(clippy::if_same_then_else)]
                 if winds == 4 {
                     // 大四喜
-                    check_early_return! { yakuman += 1 };
+                    yakuman!(Yaku::Daisuushii, 1 + self.sup.rule.double_yakuman as u8);
                 } else if winds == 3 && matches_tu8!(self.pair_tile, E | S | W | N) {
                     // 小四喜
-                    check_early_return! { yakuman += 1 };
+                    yakuman!(Yaku::Shousuushii);
                 }
             }
         }
@@ -737,10 +1098,10 @@ impl<'a> DivWorker<'a> {
                 if self.div.has_chitoi || has_toitoi {
                     if has_jihai {
                         // 混老頭
-                        check_early_return! { han += 2 };
+                        han!(Yaku::Honroutou, 2);
                     } else {
                         // 清老頭
-                        check_early_return! { yakuman += 1 };
+                        yakuman!(Yaku::Chinroutou);
                     }
                 } else {
                     let is_junchan_or_chanta = self.all_shuntsu().all(|s| {
@@ -749,8 +1110,12 @@ impl<'a> DivWorker<'a> {
                     });
                     if is_junchan_or_chanta {
                         // 混全帯幺九, 純全帯幺九
-                        let n = if has_jihai { 1 } else { 2 } + self.sup.is_menzen as u8;
-                        check_early_return! { han += n };
+                        let (yaku, n) = if has_jihai {
+                            (Yaku::Chanta, 1)
+                        } else {
+                            (Yaku::Junchan, 2)
+                        };
+                        han!(yaku, n + self.sup.is_menzen as u8);
                     }
                 }
             }
@@ -837,6 +1202,30 @@ fn get_tile14_and_key(tiles: &[u8; 34]) -> ([u8; 14], u32) {
     (tile14, key)
 }
 
+/// 十三不塔, where the 14 tiles of `tehai` have exactly one pair and no other
+/// mentsu or taatsu. Whether it is at the first draw is not checked here.
+#[must_use]
+pub fn is_shiisanpuutaa(tehai: &[u8; 34]) -> bool {
+    if tehai.iter().sum::<u8>() != 14
+        || tehai.iter().any(|&c| c > 2)
+        || tehai.iter().filter(|&&c| c > 1).count() != 1
+    {
+        return false;
+    }
+    tehai[..3 * 9].chunks_exact(9).all(|suit| {
+        // No two tiles of the same suit within a distance of 2.
+        let mut last = None;
+        suit.iter()
+            .enumerate()
+            .filter(|&(_, &c)| c > 0)
+            .all(|(num, _)| {
+                let ok = last.is_none_or(|l| num - l > 2);
+                last = Some(num);
+                ok
+            })
+    })
+}
+
 /// `tehai` must already contain `tile`. `true` is returned if making an ankan
 /// with the tile is legal under the riichi'd `tehai`.
 ///
@@ -915,6 +1304,7 @@ pub fn check_ankan_after_riichi(tehai: &[u8; 34], len_div3: u8, tile: Tile, stri
 mod test {
     use super::*;
     use crate::hand::hand;
+    use crate::t;
 
     #[test]
     fn ankan_after_riichi() {
@@ -956,6 +1346,175 @@ mod test {
         test_one("1113445678999m", "9m", 4, true, false);
     }
 
+    #[test]
+    fn agari_detail() {
+        let calc_of = |tehai, pons: &'static [u8], winning_tile, is_ron| AgariCalculator {
+            tehai,
+            is_menzen: pons.is_empty(),
+            chis: &[],
+            pons,
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile,
+            is_ron,
+            rule: RuleSet::tenhou(),
+        };
+
+        let tehai = hand("2234455m 234p 234s 3m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(3m), true);
+        let detail = calc.agari_detail(&[(Yaku::Riichi, 1)], 1, 0, 0).unwrap();
+        assert_eq!(detail.agari, calc.agari(1, 1).unwrap());
+        assert_eq!(detail.agari, Agari::Normal { fu: 40, han: 6 });
+        assert_eq!(
+            detail.yakus,
+            [
+                (Yaku::Riichi, 1),
+                (Yaku::Tanyao, 1),
+                (Yaku::Iipeikou, 1),
+                (Yaku::SanshokuDoujun, 2),
+            ],
+        );
+        assert_eq!(
+            detail.fu,
+            [(Fu::Futei, 20), (Fu::MenzenRon, 10), (Fu::Kanchan, 2)],
+        );
+
+        let tehai = hand("234m 567p 999s 11s").unwrap();
+        let calc = calc_of(&tehai, &[tu8!(P)], tu8!(1s), false);
+        let detail = calc.agari_detail(&[], 0, 0, 0).unwrap();
+        assert_eq!(detail.agari, Agari::Normal { fu: 40, han: 1 });
+        assert_eq!(detail.yakus, [(Yaku::Haku, 1)]);
+        assert_eq!(
+            detail.fu,
+            [
+                (Fu::Futei, 20),
+                (Fu::Ankou(t!(9s)), 8),
+                (Fu::Minkou(t!(P)), 4),
+                (Fu::Tsumo, 2),
+                (Fu::Tanki, 2),
+            ],
+        );
+
+        // No yaku other than riichi.
+        let tehai = hand("123m 456p 789s 99s 24m 3m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(3m), true);
+        assert!(calc.agari_detail(&[], 0, 0, 0).is_none());
+        let detail = calc.agari_detail(&[(Yaku::Riichi, 1)], 0, 1, 0).unwrap();
+        assert_eq!(detail.agari, calc.agari(1, 1).unwrap());
+        assert_eq!(detail.yakus, [(Yaku::Riichi, 1)]);
+        assert_eq!(
+            detail.fu,
+            [(Fu::Futei, 20), (Fu::MenzenRon, 10), (Fu::Kanchan, 2)],
+        );
+
+        // Yakuhai is dropped in yakuman.
+        let tehai = hand("555666777z 11m 23m 4m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(4m), false);
+        let detail = calc
+            .agari_detail(&[(Yaku::MenzenTsumo, 1)], 2, 0, 0)
+            .unwrap();
+        assert_eq!(detail.agari, Agari::Yakuman(1));
+        assert_eq!(detail.yakus, [(Yaku::Daisangen, 1)]);
+        assert!(detail.fu.is_empty());
+
+        let tehai = hand("19m 19p 19s 1234567z 1m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(1m), true);
+        let detail = calc.agari_detail(&[], 0, 0, 0).unwrap();
+        assert_eq!(detail.yakus, [(Yaku::KokushiMusou, 1)]);
+    }
+
+    #[test]
+    fn local_yakus() {
+        let calc_of = |tehai, pons: &'static [u8], winning_tile, is_ron, rule| AgariCalculator {
+            tehai,
+            is_menzen: pons.is_empty(),
+            chis: &[],
+            pons,
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile,
+            is_ron,
+            rule,
+        };
+        let tenhou = RuleSet::tenhou();
+        let double = RuleSet {
+            double_yakuman: true,
+            ..tenhou
+        };
+        let yakus_of = |calc: &AgariCalculator<'_>| calc.agari_detail(&[], 0, 0, 0).unwrap().yakus;
+
+        let tehai = hand("19m 19p 19s 1234567z 1m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(1m), true, tenhou);
+        assert_eq!(calc.agari(0, 0).unwrap(), Agari::Yakuman(1));
+        let calc = calc_of(&tehai, &[], tu8!(1m), true, double);
+        assert_eq!(calc.agari(0, 0).unwrap(), Agari::Yakuman(2));
+        assert_eq!(yakus_of(&calc), [(Yaku::KokushiMusouJuusanmen, 2)]);
+        let calc = calc_of(&tehai, &[], tu8!(9m), true, double);
+        assert_eq!(calc.agari(0, 0).unwrap(), Agari::Yakuman(1));
+
+        let tehai = hand("1112345678999m 5m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(5m), true, tenhou);
+        assert_eq!(yakus_of(&calc), [(Yaku::ChuurenPoutou, 1)]);
+        let calc = calc_of(&tehai, &[], tu8!(5m), true, double);
+        assert_eq!(calc.agari(0, 0).unwrap(), Agari::Yakuman(2));
+        assert_eq!(yakus_of(&calc), [(Yaku::JunseiChuurenPoutou, 2)]);
+        let calc = calc_of(&tehai, &[], tu8!(2m), true, double);
+        assert_eq!(yakus_of(&calc), [(Yaku::ChuurenPoutou, 1)]);
+
+        let tehai = hand("111m 222p 333s 444z 5z 5z").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(P), false, tenhou);
+        assert_eq!(yakus_of(&calc), [(Yaku::Suuankou, 1)]);
+        let calc = calc_of(&tehai, &[], tu8!(P), false, double);
+        assert_eq!(yakus_of(&calc), [(Yaku::SuuankouTanki, 2)]);
+        let calc = calc_of(&tehai, &[], tu8!(N), false, double);
+        assert_eq!(yakus_of(&calc), [(Yaku::Suuankou, 1)]);
+
+        let tehai = hand("333z 44z 55m 4z").unwrap();
+        let calc = calc_of(&tehai, &tu8![E, S], tu8!(N), true, tenhou);
+        assert_eq!(calc.agari(0, 0).unwrap(), Agari::Yakuman(1));
+        let calc = calc_of(&tehai, &tu8![E, S], tu8!(N), true, double);
+        assert_eq!(calc.agari(0, 0).unwrap(), Agari::Yakuman(2));
+        assert_eq!(yakus_of(&calc), [(Yaku::Daisuushii, 2)]);
+
+        let tehai = hand("222333444p 678s 9m 9m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(9m), false, tenhou);
+        assert!(!yakus_of(&calc).contains(&(Yaku::Sanrenkou, 2)));
+        let rule = RuleSet {
+            sanrenkou: true,
+            ..tenhou
+        };
+        let calc = calc_of(&tehai, &[], tu8!(9m), false, rule);
+        assert_eq!(yakus_of(&calc), [(Yaku::Sanrenkou, 2), (Yaku::Sanankou, 2)],);
+
+        let tehai = hand("2234455m 234p 234s 3m").unwrap();
+        let calc = calc_of(&tehai, &[], tu8!(3m), true, tenhou);
+        assert_eq!(calc.agari(1, 9).unwrap(), Agari::Normal { fu: 40, han: 14 });
+        let rule = RuleSet {
+            kazoe_yakuman: false,
+            ..tenhou
+        };
+        let calc = calc_of(&tehai, &[], tu8!(3m), true, rule);
+        assert_eq!(calc.agari(1, 9).unwrap(), Agari::Normal { fu: 40, han: 12 });
+        let detail = calc.agari_detail(&[(Yaku::Riichi, 1)], 9, 0, 0).unwrap();
+        assert_eq!(detail.agari, Agari::Normal { fu: 40, han: 12 });
+    }
+
+    #[test]
+    fn shiisanpuutaa() {
+        assert!(is_shiisanpuutaa(&hand("147m 258p 369s 11234z").unwrap()));
+        assert!(is_shiisanpuutaa(&hand("147m 258p 369s 1234z 4z").unwrap()));
+        // 6s and 8s are too close.
+        assert!(!is_shiisanpuutaa(&hand("147m 258p 368s 11234z").unwrap()));
+        // Two pairs.
+        assert!(!is_shiisanpuutaa(&hand("147m 258p 39s 112234z").unwrap()));
+        // Only 13 tiles.
+        assert!(!is_shiisanpuutaa(&hand("147m 258p 369s 1234z").unwrap()));
+    }
+
     #[test]
     fn agari_calc() {
         let tehai = hand("2234455m 234p 234s 3m").unwrap();
@@ -970,6 +1529,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(3m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 40, han: 4 });
@@ -986,6 +1546,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(3m),
             is_ron: false,
+            rule: RuleSet::tenhou(),
         };
         let points = calc.agari(2, 0).unwrap().point(true);
         // 立直, 門前清自摸和
@@ -1010,6 +1571,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(5p),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 25, han: 3 });
@@ -1027,6 +1589,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(4m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 30, han: 1 });
@@ -1043,6 +1606,7 @@ mod test {
             jikaze: tu8!(N),
             winning_tile: tu8!(3m),
             is_ron: false,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Normal { fu: 30, han: 4 });
@@ -1059,6 +1623,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         assert_eq!(calc.search_yakus(), None);
 
@@ -1074,6 +1639,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 一盃口 (without ankan)
@@ -1091,6 +1657,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 一盃口 (with ankan)
@@ -1108,6 +1675,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(7m),
             is_ron: false,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 四暗刻
@@ -1130,6 +1698,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 平和, 二盃口
@@ -1152,6 +1721,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 一気通貫
@@ -1169,6 +1739,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 一気通貫
@@ -1186,6 +1757,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(8p),
             is_ron: false,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 門前清自摸和 is not accounted.
@@ -1203,6 +1775,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(C),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         assert_eq!(yaku, Agari::Yakuman(3));
@@ -1219,6 +1792,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1m),
             is_ron: false,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 純全, 三色
@@ -1236,6 +1810,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(5s),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 三暗刻 (5s is ankou)
@@ -1255,6 +1830,7 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(E),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 混全帯幺九, 役牌*1
@@ -1273,6 +1849,7 @@ mod test {
             jikaze: tu8!(N),
             winning_tile: tu8!(9m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 混一色, 混老頭, 役牌*3, 対々和
@@ -1282,7 +1859,7 @@ mod test {
         let fu = divs
             .iter()
             .map(|div| DivWorker::new(&calc, &tile14, div))
-            .map(|w| w.calc_fu(false))
+            .map(|w| w.calc_fu::<false>(false, &mut vec![]))
             .max()
             .unwrap();
         // 20 + tanki(2) + 1m(8) + 2z(4) + 7z(4) + 4z(32) = 70
@@ -1301,6 +1878,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(9m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1319,6 +1897,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(5p),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 断么九
@@ -1337,6 +1916,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1s),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1355,6 +1935,7 @@ mod test {
             jikaze: tu8!(E),
             winning_tile: tu8!(1m),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 清一色, 一気通貫
@@ -1372,9 +1953,31 @@ mod test {
             jikaze: tu8!(S),
             winning_tile: tu8!(C),
             is_ron: true,
+            rule: RuleSet::tenhou(),
         };
         let yaku = calc.search_yakus().unwrap();
         // 三暗刻, 対々和, 混一色, 混老頭, 小三元, double 南, 白, 中
         assert!(matches!(yaku, Agari::Normal { han: 15, .. }));
+
+        let tehai = hand("234m 55p 45667s 8s").unwrap();
+        let mut calc = AgariCalculator {
+            tehai: &tehai,
+            is_menzen: false,
+            chis: &tu8![3p,],
+            pons: &[],
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            winning_tile: tu8!(8s),
+            is_ron: true,
+            rule: RuleSet::tenhou(),
+        };
+        let yaku = calc.search_yakus().unwrap();
+        // 断幺九
+        assert_eq!(yaku, Agari::Normal { fu: 30, han: 1 });
+
+        calc.rule.kuitan = false;
+        assert!(!calc.has_yaku());
     }
 }
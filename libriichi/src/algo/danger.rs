@@ -0,0 +1,240 @@
+//! Rule-based estimation of how dangerous each tile is to discard against an
+//! opponent, usually one in riichi.
+//!
+//! `tiles_seen` in this mod has the same meaning as `PlayerState::tiles_seen`,
+//! that is, the number of each tile visible to the player, including those in
+//! the hand itself.
+
+use crate::tile::Tile;
+
+/// Number of discards that are considered early (一段目).
+pub const EARLY_DISCARDS: usize = 6;
+
+/// What is known about an opponent.
+#[derive(Debug, Clone, Copy)]
+pub struct Opponent<'a> {
+    /// Tiles discarded by the opponent in order, including the called ones.
+    pub discards: &'a [Tile],
+    /// Index of the riichi declaration in `discards`.
+    pub riichi_idx: Option<usize>,
+    /// Tiles that can never deal in to the opponent due to furiten, which are
+    /// their own discards plus the ones discarded by anyone after their
+    /// riichi.
+    pub genbutsu: &'a [bool; 34],
+}
+
+/// Prior weights of each kind of wait, which are multiplied by the number of
+/// combinations of unseen tiles that can make the shape.
+#[derive(Debug, Clone, Copy)]
+pub struct WaitWeights {
+    pub ryanmen: f32,
+    pub kanchan: f32,
+    pub penchan: f32,
+    pub shanpon: f32,
+    pub tanki: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Danger {
+    /// 現物
+    pub genbutsu: [bool; 34],
+    /// 筋, which for 4 to 6 requires both sides (中筋) to be genbutsu.
+    pub suji: [bool; 34],
+    /// The tile cannot be a ryanmen wait because the tiles to make every such
+    /// ryanmen are all seen (壁, ノーチャンス). Always `false` for jihais.
+    pub no_chance: [bool; 34],
+    /// Same as `no_chance`, but with only one of them left (ワンチャンス).
+    pub one_chance: [bool; 34],
+    /// The tile is on the outer side of an early discard in the same suit
+    /// (早外し), such as 1m and 2m for an early 3m.
+    pub early_outside: [bool; 34],
+    /// Estimated probability of each tile being a winning tile of the
+    /// opponent, assuming they are tenpai. Since a wait may have two winning
+    /// tiles, the sum is usually greater than 1.
+    pub wait_probs: [f32; 34],
+}
+
+impl Default for WaitWeights {
+    fn default() -> Self {
+        Self {
+            ryanmen: 1.,
+            kanchan: 0.3,
+            penchan: 0.3,
+            shanpon: 0.5,
+            tanki: 0.2,
+        }
+    }
+}
+
+impl Danger {
+    #[must_use]
+    pub fn new(opponent: &Opponent<'_>, tiles_seen: &[u8; 34], weights: &WaitWeights) -> Self {
+        let genbutsu = *opponent.genbutsu;
+        let left = |tid: usize| 4_u8.saturating_sub(tiles_seen[tid]) as f32;
+
+        let mut suji = [false; 34];
+        let mut no_chance = [false; 34];
+        let mut one_chance = [false; 34];
+        for suit in 0..3 {
+            let base = suit * 9;
+            for num in 0..9 {
+                let tid = base + num;
+                let lower = num >= 3 && genbutsu[tid - 3];
+                let upper = num <= 5 && genbutsu[tid + 3];
+                suji[tid] = match num {
+                    0..=2 => upper,
+                    6..=8 => lower,
+                    _ => lower && upper,
+                };
+
+                // The ryanmens waiting on this tile are made of the 2 tiles
+                // right above or right below. 12 and 89 are penchans, which
+                // do not count.
+                let chance = [(num <= 5).then(|| tid + 1), (num >= 3).then(|| tid - 2)]
+                    .into_iter()
+                    .flatten()
+                    .map(|lo| left(lo).min(left(lo + 1)) as u8)
+                    .max()
+                    .unwrap_or(0);
+                no_chance[tid] = chance == 0;
+                one_chance[tid] = chance == 1;
+            }
+        }
+
+        let mut early_outside = [false; 34];
+        let early = opponent
+            .riichi_idx
+            .map_or(EARLY_DISCARDS, |idx| idx.min(EARLY_DISCARDS));
+        for tile in opponent.discards.iter().take(early) {
+            let tid = tile.deaka().as_usize();
+            if tid >= 27 {
+                continue;
+            }
+            let (base, num) = (tid / 9 * 9, tid % 9);
+            match num {
+                1..=3 => early_outside[base..tid].fill(true),
+                5..=7 => early_outside[tid + 1..base + 9].fill(true),
+                _ => (),
+            }
+        }
+
+        let wait_probs = wait_probs(&genbutsu, tiles_seen, weights);
+        Self {
+            genbutsu,
+            suji,
+            no_chance,
+            one_chance,
+            early_outside,
+            wait_probs,
+        }
+    }
+}
+
+/// Enumerates the waits of a tenpai hand that are not furiten, weighted by
+/// `weights` and the number of combinations of the unseen tiles that make
+/// them, and returns the probability of each tile being a winning tile.
+fn wait_probs(genbutsu: &[bool; 34], tiles_seen: &[u8; 34], weights: &WaitWeights) -> [f32; 34] {
+    let left = |tid: usize| 4_u8.saturating_sub(tiles_seen[tid]) as f32;
+    let mut waits = [0.; 34];
+    let mut total = 0.;
+    let mut add = |winning: &[usize], weight: f32| {
+        if weight <= 0. || winning.iter().any(|&tid| genbutsu[tid]) {
+            return;
+        }
+        for &tid in winning {
+            waits[tid] += weight;
+        }
+        total += weight;
+    };
+
+    for suit in 0..3 {
+        let base = suit * 9;
+        for num in 0..8 {
+            let tid = base + num;
+            let pair = left(tid) * left(tid + 1);
+            match num {
+                // 12 waiting on 3
+                0 => add(&[tid + 2], weights.penchan * pair),
+                // 89 waiting on 7
+                7 => add(&[tid - 1], weights.penchan * pair),
+                _ => add(&[tid - 1, tid + 2], weights.ryanmen * pair),
+            }
+            if num < 7 {
+                add(&[tid + 1], weights.kanchan * left(tid) * left(tid + 2));
+            }
+        }
+    }
+    for tid in 0..34 {
+        let n = left(tid);
+        add(&[tid], weights.shanpon * n * (n - 1.) / 2.);
+        add(&[tid], weights.tanki * n);
+    }
+
+    if total > 0. {
+        for w in &mut waits {
+            *w /= total;
+        }
+    }
+    waits
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{t, tuz};
+
+    #[test]
+    fn danger() {
+        let discards = [t!(2m), t!(E), t!(4p), t!(6s), t!(9m), t!(5m)];
+        let mut genbutsu = [false; 34];
+        for t in discards {
+            genbutsu[t.as_usize()] = true;
+        }
+        // Passed after the riichi.
+        genbutsu[tuz!(1p)] = true;
+        let opponent = Opponent {
+            discards: &discards,
+            riichi_idx: Some(5),
+            genbutsu: &genbutsu,
+        };
+        let mut tiles_seen = [0; 34];
+        tiles_seen[tuz!(8p)] = 4;
+        tiles_seen[tuz!(7p)] = 3;
+        tiles_seen[tuz!(4s)] = 4;
+        tiles_seen[tuz!(6s)] = 4;
+        let danger = Danger::new(&opponent, &tiles_seen, &WaitWeights::default());
+
+        assert!(danger.genbutsu[tuz!(1p)]);
+        assert!(danger.suji[tuz!(1p)]);
+        assert!(danger.suji[tuz!(7p)]);
+        assert!(danger.suji[tuz!(3s)] && danger.suji[tuz!(9s)]);
+        assert!(danger.suji[tuz!(2m)] && danger.suji[tuz!(8m)]);
+        // Only one side of 4p is genbutsu.
+        assert!(!danger.suji[tuz!(4p)] && !danger.suji[tuz!(4s)]);
+
+        // 89p is impossible as 8p are all seen.
+        assert!(danger.no_chance[tuz!(9p)]);
+        assert!(!danger.one_chance[tuz!(9p)]);
+        assert!(danger.one_chance[tuz!(8p)]);
+        assert!(!danger.no_chance[tuz!(8p)]);
+        assert!(!danger.no_chance[tuz!(E)]);
+        // 12s and 89s are penchans, so 3s and 7s are walled as well.
+        assert!(danger.no_chance[tuz!(3s)] && danger.no_chance[tuz!(7s)]);
+        assert!(!danger.no_chance[tuz!(1s)] && !danger.no_chance[tuz!(9s)]);
+
+        assert!(danger.early_outside[tuz!(1m)]);
+        assert!(danger.early_outside[tuz!(3p)]);
+        assert!(danger.early_outside[tuz!(7s)] && danger.early_outside[tuz!(9s)]);
+        assert!(!danger.early_outside[tuz!(5s)]);
+        // 5m was the riichi tile, so it is not early.
+        assert!(!danger.early_outside[tuz!(4m)]);
+
+        assert!(danger.wait_probs[tuz!(2m)] < f32::EPSILON);
+        assert!(danger.wait_probs[tuz!(E)] < f32::EPSILON);
+        // Suji tiles can still be kanchan or shanpon.
+        assert!(danger.wait_probs[tuz!(8m)] > 0.);
+        assert!(danger.wait_probs[tuz!(3m)] > danger.wait_probs[tuz!(8m)]);
+        assert!(danger.wait_probs[tuz!(5p)] > danger.wait_probs[tuz!(9p)]);
+        assert!(danger.wait_probs[tuz!(S)] < danger.wait_probs[tuz!(3m)]);
+    }
+}
@@ -1,7 +1,13 @@
 //! This module includes essential mahjong algorithms including agari, shanten,
-//! single-player calculators and score lookups.
+//! ukeire, danger estimation, single-player calculators and score lookups.
 
 pub mod agari;
+pub mod danger;
 pub mod point;
 pub mod shanten;
 pub mod sp;
+pub mod ukeire;
+
+mod py;
+
+pub(crate) use py::register_module;
@@ -110,6 +110,43 @@ impl Point {
             self.tsumo_ko * 2 + self.tsumo_oya
         }
     }
+
+    /// Converts a point of four-player mahjong into that of sanma. `ron` is
+    /// always unchanged.
+    ///
+    /// With ツモ損 the tsumo payments are unchanged as well, and the share of
+    /// the absent ko is simply lost. Otherwise it is split between the two
+    /// payers, rounded up to 100 (北家折半).
+    #[must_use]
+    pub const fn sanma(self, is_oya: bool, tsumo_son: bool) -> Self {
+        if tsumo_son {
+            return self;
+        }
+        let half = (self.tsumo_ko / 2 + 99) / 100 * 100;
+        if is_oya {
+            Self {
+                tsumo_ko: self.tsumo_ko + half,
+                ..self
+            }
+        } else {
+            Self {
+                tsumo_ko: self.tsumo_ko + half,
+                tsumo_oya: self.tsumo_oya + half,
+                ..self
+            }
+        }
+    }
+
+    /// The sanma version of `tsumo_total`.
+    #[inline]
+    #[must_use]
+    pub const fn tsumo_total_sanma(self, is_oya: bool) -> i32 {
+        if is_oya {
+            self.tsumo_ko * 2
+        } else {
+            self.tsumo_ko + self.tsumo_oya
+        }
+    }
 }
 
 #[cfg(test)]
@@ -151,4 +188,20 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn sanma() {
+        let ko = Point::calc(false, 30, 4);
+        assert_eq!(ko.sanma(false, true), ko);
+        assert_eq!(ko.sanma(false, true).tsumo_total_sanma(false), 5900);
+        let ko = ko.sanma(false, false);
+        assert_eq!((ko.ron, ko.tsumo_ko, ko.tsumo_oya), (7700, 3000, 4900));
+        assert_eq!(ko.tsumo_total_sanma(false), 7900);
+
+        let oya = Point::calc(true, 30, 1).sanma(true, false);
+        assert_eq!((oya.ron, oya.tsumo_ko), (1500, 800));
+        assert_eq!(oya.tsumo_total_sanma(true), 1600);
+        let oya = Point::yakuman(true, 1).sanma(true, false);
+        assert_eq!(oya.tsumo_total_sanma(true), 48000);
+    }
 }
@@ -0,0 +1,691 @@
+//! Python bindings of the hand analysis algorithms.
+//!
+//! Hands are given in tenhou.net/2 format (like `123m 0p 11z`, see
+//! [`crate::hand`]), while single tiles, including those of melds, are given
+//! in mjai format (like `5mr` and `E`). Tiles in the returned dicts are in mjai
+//! format as well.
+
+use super::agari::{Agari, AgariCalculator, AgariDetail, Yaku};
+use super::point::Point;
+use super::shanten;
+use super::sp::{Candidate, InitState, SPCalculator};
+use super::ukeire::{self, Improvement, Shape, Ukeire};
+use crate::hand::hand_with_aka;
+use crate::py_helper::add_submodule;
+use crate::rule::RuleSet;
+use crate::tile::Tile;
+use crate::{must_tile, t, tu8, tuz};
+use std::array;
+
+use anyhow::{Context, Result, bail, ensure};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use tinyvec::ArrayVec;
+
+/// A hand parsed from a hand string.
+#[derive(Debug, Clone)]
+struct Hand {
+    tehai: [u8; 34],
+    akas: [bool; 3],
+}
+
+/// The melds of a hand, each of which is identified by its tile, or the
+/// smallest tile for a chi.
+#[derive(Debug, Default)]
+struct Melds {
+    chis: Vec<u8>,
+    pons: Vec<u8>,
+    minkans: Vec<u8>,
+    ankans: Vec<u8>,
+    akas: [bool; 3],
+}
+
+struct AgariQuery<'a> {
+    /// Including the winning tile.
+    hand: Hand,
+    melds: &'a Melds,
+    winning_tile: Tile,
+    is_ron: bool,
+    bakaze: Tile,
+    jikaze: Tile,
+    dora_indicators: &'a [Tile],
+    ura_indicators: &'a [Tile],
+    /// Situational yakus only.
+    yakus: Vec<(Yaku, u8)>,
+    rule: RuleSet,
+}
+
+impl Hand {
+    fn parse(s: &str) -> Result<Self> {
+        let tiles = hand_with_aka(s)?;
+        let mut tehai = [0; 34];
+        tehai.copy_from_slice(&tiles[..34]);
+        let mut akas = [false; 3];
+        for (aka_id, &count) in tiles[34..].iter().enumerate() {
+            ensure!(count <= 1, "hand {s} has more than one aka of a kind");
+            tehai[tuz!(5m) + 9 * aka_id] += count;
+            akas[aka_id] = count == 1;
+        }
+        ensure!(
+            tehai.iter().all(|&c| c <= 4),
+            "hand {s} has more than 4 tiles of a kind",
+        );
+        Ok(Self { tehai, akas })
+    }
+
+    fn len(&self) -> u8 {
+        self.tehai.iter().sum()
+    }
+
+    /// The hand must be 3n+1 or 3n+2.
+    fn len_div3(&self) -> Result<u8> {
+        let len = self.len();
+        ensure!(
+            !len.is_multiple_of(3) && len <= 14,
+            "a hand of {len} tiles is neither 3n+1 nor 3n+2",
+        );
+        Ok(len / 3)
+    }
+
+    fn add(&mut self, tile: Tile) -> Result<()> {
+        let tid = tile.deaka().as_usize();
+        ensure!(self.tehai[tid] < 4, "more than 4 of {tile}");
+        self.tehai[tid] += 1;
+        if tile.is_aka() {
+            let aka_id = tile.as_usize() - tuz!(5mr);
+            ensure!(!self.akas[aka_id], "more than one {tile}");
+            self.akas[aka_id] = true;
+        }
+        Ok(())
+    }
+}
+
+impl Melds {
+    fn parse(
+        chis: &[String],
+        pons: &[String],
+        minkans: &[String],
+        ankans: &[String],
+    ) -> Result<Self> {
+        let mut akas = [false; 3];
+        let mut parse = |tiles: &[String], is_chi: bool| {
+            tiles
+                .iter()
+                .map(|s| {
+                    let tile = parse_tile(s)?;
+                    if tile.is_aka() {
+                        akas[tile.as_usize() - tuz!(5mr)] = true;
+                    }
+                    let tid = tile.deaka().as_u8();
+                    ensure!(
+                        !is_chi || tid < tu8!(E) && tid % 9 < 7,
+                        "{s} cannot be the smallest tile of a chi",
+                    );
+                    Ok(tid)
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        let chis = parse(chis, true)?;
+        let pons = parse(pons, false)?;
+        let minkans = parse(minkans, false)?;
+        let ankans = parse(ankans, false)?;
+        Ok(Self {
+            chis,
+            pons,
+            minkans,
+            ankans,
+            akas,
+        })
+    }
+
+    const fn len(&self) -> u8 {
+        (self.chis.len() + self.pons.len() + self.minkans.len() + self.ankans.len()) as u8
+    }
+
+    const fn is_menzen(&self) -> bool {
+        self.chis.is_empty() && self.pons.is_empty() && self.minkans.is_empty()
+    }
+
+    fn tiles(&self) -> [u8; 34] {
+        let mut tiles = [0; 34];
+        for &tid in &self.chis {
+            for t in &mut tiles[tid as usize..tid as usize + 3] {
+                *t += 1;
+            }
+        }
+        for &tid in &self.pons {
+            tiles[tid as usize] += 3;
+        }
+        for &tid in self.minkans.iter().chain(&self.ankans) {
+            tiles[tid as usize] += 4;
+        }
+        tiles
+    }
+}
+
+impl AgariQuery<'_> {
+    /// `None` is returned if the hand is not a hora hand or has no yaku.
+    fn detail(&self) -> Result<Option<AgariDetail>> {
+        let len_div3 = self.hand.len_div3()?;
+        ensure!(
+            self.hand.len() % 3 == 2 && len_div3 + self.melds.len() == 4,
+            "the hand and the melds do not make 14 tiles",
+        );
+
+        let mut all_tiles = self.melds.tiles();
+        for (t, &c) in all_tiles.iter_mut().zip(&self.hand.tehai) {
+            *t += c;
+        }
+        let dora = count_doras(&self.rule, self.dora_indicators, &all_tiles);
+        let ura_dora = count_doras(&self.rule, self.ura_indicators, &all_tiles);
+        let aka_dora = self
+            .hand
+            .akas
+            .iter()
+            .zip(&self.melds.akas)
+            .filter(|&(&a, &b)| a || b)
+            .count() as u8;
+
+        let is_menzen = self.melds.is_menzen();
+        let mut yakus = self.yakus.clone();
+        if is_menzen && !self.is_ron {
+            yakus.push((Yaku::MenzenTsumo, 1));
+        }
+        let calc = AgariCalculator {
+            tehai: &self.hand.tehai,
+            is_menzen,
+            chis: &self.melds.chis,
+            pons: &self.melds.pons,
+            minkans: &self.melds.minkans,
+            ankans: &self.melds.ankans,
+            bakaze: self.bakaze.as_u8(),
+            jikaze: self.jikaze.as_u8(),
+            winning_tile: self.winning_tile.deaka().as_u8(),
+            is_ron: self.is_ron,
+            rule: self.rule,
+        };
+        Ok(calc.agari_detail(&yakus, dora, aka_dora, ura_dora))
+    }
+}
+
+fn parse_tile(s: &str) -> Result<Tile> {
+    s.parse()
+        .with_context(|| format!("invalid tile {s}, expected mjai format like 5mr or E"))
+}
+
+fn parse_tiles(tiles: &[String]) -> Result<Vec<Tile>> {
+    tiles.iter().map(|s| parse_tile(s)).collect()
+}
+
+/// Yakus that are beyond the hand itself, along with their hans.
+fn parse_situational_yaku(name: &str) -> Result<(Yaku, u8)> {
+    let yaku = match name {
+        "Riichi" => (Yaku::Riichi, 1),
+        "DoubleRiichi" => (Yaku::DoubleRiichi, 2),
+        "Ippatsu" => (Yaku::Ippatsu, 1),
+        "Haitei" => (Yaku::Haitei, 1),
+        "Houtei" => (Yaku::Houtei, 1),
+        "Rinshan" => (Yaku::Rinshan, 1),
+        "Chankan" => (Yaku::Chankan, 1),
+        _ => bail!(
+            "unknown situational yaku {name}, expected one of Riichi, DoubleRiichi, \
+            Ippatsu, Haitei, Houtei, Rinshan and Chankan"
+        ),
+    };
+    Ok(yaku)
+}
+
+fn count_doras(rule: &RuleSet, indicators: &[Tile], tiles: &[u8; 34]) -> u8 {
+    indicators
+        .iter()
+        .map(|&ind| tiles[rule.dora_of(ind).as_usize()])
+        .sum()
+}
+
+fn parse_shape(name: &str) -> Result<Shape> {
+    let shape = match name {
+        "all" => Shape::All,
+        "normal" => Shape::Normal,
+        "chiitoitsu" => Shape::Chiitoitsu,
+        "kokushi" => Shape::Kokushi,
+        _ => bail!("unknown shape {name}, expected one of all, normal, chiitoitsu and kokushi"),
+    };
+    Ok(shape)
+}
+
+fn ukeire_dict<'py>(
+    py: Python<'py>,
+    ukeire: &Ukeire,
+    improvements: &[Improvement],
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let tiles = PyDict::new(py);
+    for r in &ukeire.tiles {
+        tiles.set_item(r.tile.to_string(), r.count)?;
+    }
+    let improvements = improvements
+        .iter()
+        .map(|i| {
+            let dict = PyDict::new(py);
+            dict.set_item("tile", i.tile.to_string())?;
+            dict.set_item("count", i.count)?;
+            dict.set_item("discard", i.discard.to_string())?;
+            dict.set_item("total", i.total)?;
+            Ok(dict)
+        })
+        .collect::<PyResult<Vec<_>>>()?;
+    dict.set_item("shanten", ukeire.shanten)?;
+    dict.set_item("tiles", tiles)?;
+    dict.set_item("total", ukeire.total)?;
+    dict.set_item("improvements", improvements)?;
+    Ok(dict)
+}
+
+fn point_dict(py: Python<'_>, point: Point) -> PyResult<Bound<'_, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("ron", point.ron)?;
+    dict.set_item("tsumo_oya", point.tsumo_oya)?;
+    dict.set_item("tsumo_ko", point.tsumo_ko)?;
+    Ok(dict)
+}
+
+fn agari_dict<'py>(
+    py: Python<'py>,
+    detail: &AgariDetail,
+    is_oya: bool,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let (han, fu, yakuman) = match detail.agari {
+        Agari::Normal { fu, han } => (han, fu, 0),
+        Agari::Yakuman(n) => (0, 0, n),
+    };
+    let yakus: Vec<_> = detail
+        .yakus
+        .iter()
+        .map(|(yaku, n)| (format!("{yaku:?}"), n))
+        .collect();
+    let fus: Vec<_> = detail
+        .fu
+        .iter()
+        .map(|(fu, n)| (format!("{fu:?}"), n))
+        .collect();
+    dict.set_item("han", han)?;
+    dict.set_item("fu", fu)?;
+    dict.set_item("yakuman", yakuman)?;
+    dict.set_item("yakus", yakus)?;
+    dict.set_item("fu_detail", fus)?;
+    dict.set_item("dora", detail.dora)?;
+    dict.set_item("aka_dora", detail.aka_dora)?;
+    dict.set_item("ura_dora", detail.ura_dora)?;
+    dict.set_item("point", point_dict(py, detail.agari.point(is_oya))?)?;
+    Ok(dict)
+}
+
+fn candidate_dict<'py>(
+    py: Python<'py>,
+    candidate: &Candidate,
+    can_discard: bool,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    let required_tiles = PyDict::new(py);
+    for r in &candidate.required_tiles {
+        required_tiles.set_item(r.tile.to_string(), r.count)?;
+    }
+    let tile = can_discard.then(|| candidate.tile.to_string());
+    dict.set_item("tile", tile)?;
+    dict.set_item("exp_values", candidate.exp_values.to_vec())?;
+    dict.set_item("win_probs", candidate.win_probs.to_vec())?;
+    dict.set_item("tenpai_probs", candidate.tenpai_probs.to_vec())?;
+    dict.set_item("required_tiles", required_tiles)?;
+    dict.set_item("num_required_tiles", candidate.num_required_tiles)?;
+    dict.set_item("shanten_down", candidate.shanten_down)?;
+    Ok(dict)
+}
+
+/// Returns the shanten of a 3n+1 or 3n+2 hand, where -1 means agari. `shape`
+/// is one of `all`, `normal`, `chiitoitsu` and `kokushi`.
+#[pyfunction]
+#[pyo3(name = "shanten", signature = (hand, shape = "all"))]
+pub fn shanten_py(hand: &str, shape: &str) -> Result<i8> {
+    let hand = Hand::parse(hand)?;
+    Ok(parse_shape(shape)?.shanten(&hand.tehai, hand.len_div3()?))
+}
+
+/// Returns the effective tiles and improvements of a hand, where `seen` is a
+/// hand string of the other visible tiles, such as those in kawa and dora
+/// indicators. `shape` is one of `all`, `normal`, `chiitoitsu` and `kokushi`.
+///
+/// For a 3n+1 hand, the result is `{"shanten": int, "tiles": {tile: left},
+/// "total": int, "improvements": [dict]}`, where each improvement has keys
+/// `tile`, `count`, `discard` and `total`. For a 3n+2 hand, the result maps
+/// each discard to such a dict of the hand after the discard, best first.
+#[pyfunction]
+#[pyo3(name = "ukeire", signature = (hand, seen = "", shape = "all"))]
+pub fn ukeire_py<'py>(
+    py: Python<'py>,
+    hand: &str,
+    seen: &str,
+    shape: &str,
+) -> Result<Bound<'py, PyDict>> {
+    let hand = Hand::parse(hand)?;
+    let len_div3 = hand.len_div3()?;
+    let shape = parse_shape(shape)?;
+    let mut tiles_seen = Hand::parse(seen)?.tehai;
+    for (s, &c) in tiles_seen.iter_mut().zip(&hand.tehai) {
+        *s += c;
+    }
+
+    if hand.len() % 3 == 1 {
+        let u = ukeire::ukeire(&hand.tehai, len_div3, &tiles_seen, shape);
+        let imps = ukeire::improvements(&hand.tehai, len_div3, &tiles_seen, shape);
+        return Ok(ukeire_dict(py, &u, &imps)?);
+    }
+    let dict = PyDict::new(py);
+    for d in ukeire::discard_ukeire(&hand.tehai, len_div3, &tiles_seen, shape) {
+        dict.set_item(
+            d.discard.to_string(),
+            ukeire_dict(py, &d.ukeire, &d.improvements)?,
+        )?;
+    }
+    Ok(dict)
+}
+
+/// Evaluates the agari of `hand` plus `winning_tile`.
+///
+/// Melds are given by their tiles, which are the smallest ones for `chis`.
+/// `yakus` lists the situational yakus among `Riichi`, `DoubleRiichi`,
+/// `Ippatsu`, `Haitei`, `Houtei`, `Rinshan` and `Chankan`, while `MenzenTsumo`
+/// is added automatically. Tenhou, chihou and the like are not considered.
+/// Akas are counted from both the hand and the melds. The player is oya iff
+/// `jikaze` is `E`.
+///
+/// `rule` is one of `tenhou`, `tenhou-tonpuu`, `tenhou-sanma`, `mahjong-soul`,
+/// `mahjong-soul-sanma` and `m-league`, which controls the yaku switches and
+/// the dora of 1m in sanma. The points are always calculated as in yonma.
+///
+/// Returns `None` if the hand is not a hora hand or has no yaku, otherwise a
+/// dict with keys `han`, `fu`, `yakuman`, `yakus`, `fu_detail`, `dora`,
+/// `aka_dora`, `ura_dora` and `point`.
+#[pyfunction]
+#[pyo3(signature = (
+    hand,
+    winning_tile,
+    *,
+    is_ron = true,
+    chis = vec![],
+    pons = vec![],
+    minkans = vec![],
+    ankans = vec![],
+    bakaze = "E",
+    jikaze = "E",
+    dora_indicators = vec![],
+    ura_indicators = vec![],
+    yakus = vec![],
+    rule = "tenhou",
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn agari<'py>(
+    py: Python<'py>,
+    hand: &str,
+    winning_tile: &str,
+    is_ron: bool,
+    chis: Vec<String>,
+    pons: Vec<String>,
+    minkans: Vec<String>,
+    ankans: Vec<String>,
+    bakaze: &str,
+    jikaze: &str,
+    dora_indicators: Vec<String>,
+    ura_indicators: Vec<String>,
+    yakus: Vec<String>,
+    rule: &str,
+) -> Result<Option<Bound<'py, PyDict>>> {
+    let winning_tile = parse_tile(winning_tile)?;
+    let mut hand = Hand::parse(hand)?;
+    hand.add(winning_tile)?;
+    let jikaze = parse_tile(jikaze)?;
+    let query = AgariQuery {
+        hand,
+        melds: &Melds::parse(&chis, &pons, &minkans, &ankans)?,
+        winning_tile,
+        is_ron,
+        bakaze: parse_tile(bakaze)?,
+        jikaze,
+        dora_indicators: &parse_tiles(&dora_indicators)?,
+        ura_indicators: &parse_tiles(&ura_indicators)?,
+        yakus: yakus
+            .iter()
+            .map(|name| parse_situational_yaku(name))
+            .collect::<Result<_>>()?,
+        rule: RuleSet::from_name(rule)?,
+    };
+    let Some(detail) = query.detail()? else {
+        return Ok(None);
+    };
+    Ok(Some(agari_dict(py, &detail, jikaze == t!(E))?))
+}
+
+/// Returns the points of a hand of `fu` and `han` as a dict with keys `ron`,
+/// `tsumo_oya` and `tsumo_ko`. `fu` is ignored if `han` is greater than 4.
+#[pyfunction]
+#[pyo3(signature = (fu, han, *, is_oya = false))]
+pub fn point(py: Python<'_>, fu: u8, han: u8, is_oya: bool) -> Result<Bound<'_, PyDict>> {
+    let valid_fu = fu == 25 || (20..=110).contains(&fu) && fu.is_multiple_of(10);
+    ensure!(
+        han >= 5 || han >= 1 && valid_fu && !matches!((fu, han), (20 | 25, 1)),
+        "impossible combination of {fu} fu and {han} han",
+    );
+    Ok(point_dict(py, Point::calc(is_oya, fu, han))?)
+}
+
+/// Runs the single-player calculator on a 3n+1 or 3n+2 hand, which must not
+/// be agari yet, and returns the candidate table as a list of dicts, best
+/// first. Each dict has keys `tile` (the discard, or `None` for a 3n+1 hand),
+/// `exp_values`, `win_probs`, `tenpai_probs` (indexed by the number of tsumos
+/// done from now), `required_tiles`, `num_required_tiles` and `shanten_down`.
+///
+/// `seen` is a hand string of the visible tiles other than those in the hand,
+/// the melds and the dora indicators. `tsumos_left` must be within [1, 17].
+#[pyfunction]
+#[pyo3(signature = (
+    hand,
+    *,
+    chis = vec![],
+    pons = vec![],
+    minkans = vec![],
+    ankans = vec![],
+    bakaze = "E",
+    jikaze = "E",
+    dora_indicators = vec![],
+    seen = "",
+    tsumos_left = 17,
+    prefer_riichi = true,
+    calc_double_riichi = false,
+    calc_haitei = true,
+    rule = "tenhou",
+))]
+#[allow(clippy::too_many_arguments)]
+pub fn sp_table<'py>(
+    py: Python<'py>,
+    hand: &str,
+    chis: Vec<String>,
+    pons: Vec<String>,
+    minkans: Vec<String>,
+    ankans: Vec<String>,
+    bakaze: &str,
+    jikaze: &str,
+    dora_indicators: Vec<String>,
+    seen: &str,
+    tsumos_left: u8,
+    prefer_riichi: bool,
+    calc_double_riichi: bool,
+    calc_haitei: bool,
+    rule: &str,
+) -> Result<Vec<Bound<'py, PyDict>>> {
+    let hand = Hand::parse(hand)?;
+    let len_div3 = hand.len_div3()?;
+    let melds = Melds::parse(&chis, &pons, &minkans, &ankans)?;
+    let dora_indicators = parse_tiles(&dora_indicators)?;
+    ensure!(
+        dora_indicators.len() <= 5,
+        "at most 5 dora indicators are allowed, got {}",
+        dora_indicators.len(),
+    );
+    let seen = Hand::parse(seen)?;
+    let rule = RuleSet::from_name(rule)?;
+
+    let meld_tiles = melds.tiles();
+    let mut tiles_seen: [u8; 34] =
+        array::from_fn(|tid| hand.tehai[tid] + meld_tiles[tid] + seen.tehai[tid]);
+    let mut akas_seen: [bool; 3] =
+        array::from_fn(|i| hand.akas[i] || melds.akas[i] || seen.akas[i]);
+    for &ind in &dora_indicators {
+        tiles_seen[ind.deaka().as_usize()] += 1;
+        if ind.is_aka() {
+            akas_seen[ind.as_usize() - tuz!(5mr)] = true;
+        }
+    }
+    // Tiles that do not exist in the rule are treated as all seen, as in
+    // `PlayerState`.
+    for (tid, count) in tiles_seen.iter_mut().enumerate() {
+        if !rule.has_tile(must_tile!(tid)) {
+            *count = 4;
+        }
+        ensure!(*count <= 4, "more than 4 of {} are seen", must_tile!(tid));
+    }
+    for (aka_id, seen) in akas_seen.iter_mut().enumerate() {
+        if aka_id as u8 >= rule.aka_count {
+            *seen = true;
+        }
+    }
+
+    let num_doras_in_fuuro = count_doras(&rule, &dora_indicators, &meld_tiles)
+        + melds.akas.iter().filter(|&&b| b).count() as u8;
+    let mapped_indicators: ArrayVec<[Tile; 5]> = dora_indicators
+        .iter()
+        .map(|&ind| rule.dora_of(ind).prev())
+        .collect();
+    let cur_shanten = shanten::calc_all(&hand.tehai, len_div3);
+    let init_state = InitState {
+        tehai: hand.tehai,
+        akas_in_hand: hand.akas,
+        tiles_seen,
+        akas_seen,
+    };
+    let sp_calc = SPCalculator {
+        tehai_len_div3: len_div3,
+        chis: &melds.chis,
+        pons: &melds.pons,
+        minkans: &melds.minkans,
+        ankans: &melds.ankans,
+        bakaze: parse_tile(bakaze)?.as_u8(),
+        jikaze: parse_tile(jikaze)?.as_u8(),
+        is_menzen: melds.is_menzen(),
+        num_doras_in_fuuro,
+        dora_indicators: &mapped_indicators,
+        rule,
+        calc_double_riichi,
+        calc_haitei,
+        prefer_riichi,
+        sort_result: true,
+        maximize_win_prob: false,
+        calc_tegawari: false,
+        calc_shanten_down: false,
+    };
+    let can_discard = hand.len() % 3 == 2;
+    let candidates =
+        py.allow_threads(|| sp_calc.calc(init_state, can_discard, tsumos_left, cur_shanten))?;
+    let table = candidates
+        .iter()
+        .map(|c| candidate_dict(py, c, can_discard))
+        .collect::<PyResult<_>>()?;
+    Ok(table)
+}
+
+pub(crate) fn register_module(
+    py: Python<'_>,
+    prefix: &str,
+    super_mod: &Bound<'_, PyModule>,
+) -> PyResult<()> {
+    let m = PyModule::new(py, "algo")?;
+    m.add_function(wrap_pyfunction!(shanten_py, &m)?)?;
+    m.add_function(wrap_pyfunction!(ukeire_py, &m)?)?;
+    m.add_function(wrap_pyfunction!(agari, &m)?)?;
+    m.add_function(wrap_pyfunction!(point, &m)?)?;
+    m.add_function(wrap_pyfunction!(sp_table, &m)?)?;
+    add_submodule(py, prefix, super_mod, &m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::algo::agari::Fu;
+    use crate::hand::hand;
+
+    #[test]
+    fn parse_hand() {
+        let h = Hand::parse("123m 0p 067s 111z").unwrap();
+        assert_eq!(h.tehai, hand("123m 5p 567s 111z").unwrap());
+        assert_eq!(h.akas, [false, true, true]);
+        assert_eq!(h.len_div3().unwrap(), 3);
+
+        Hand::parse("00m").unwrap_err();
+        Hand::parse("55550m").unwrap_err();
+        Hand::parse("123m").unwrap().len_div3().unwrap_err();
+
+        let melds = Melds::parse(&["3m".to_owned()], &["5pr".to_owned()], &[], &[]).unwrap();
+        assert_eq!(melds.len(), 2);
+        assert!(!melds.is_menzen());
+        assert_eq!(melds.akas, [false, true, false]);
+        assert_eq!(melds.tiles(), hand("345m 555p").unwrap());
+        Melds::parse(&["8m".to_owned()], &[], &[], &[]).unwrap_err();
+        Melds::parse(&["E".to_owned()], &[], &[], &[]).unwrap_err();
+    }
+
+    #[test]
+    fn agari_query() {
+        let melds = Melds::default();
+        let mut hand = Hand::parse("2234405m 234p 234s").unwrap();
+        hand.add(t!(3m)).unwrap();
+        let query = AgariQuery {
+            hand,
+            melds: &melds,
+            winning_tile: t!(3m),
+            is_ron: true,
+            bakaze: t!(E),
+            jikaze: t!(S),
+            dora_indicators: &[t!(2m)],
+            ura_indicators: &[t!(1s)],
+            yakus: vec![parse_situational_yaku("Riichi").unwrap()],
+            rule: RuleSet::tenhou(),
+        };
+        let detail = query.detail().unwrap().unwrap();
+        // 立直, 断幺九, 一盃口, 三色同順, dora 2, aka 1, ura 1
+        assert_eq!(detail.agari, Agari::Normal { fu: 40, han: 9 });
+        assert_eq!((detail.dora, detail.aka_dora, detail.ura_dora), (2, 1, 1));
+        assert_eq!(
+            detail.fu,
+            [(Fu::Futei, 20), (Fu::MenzenRon, 10), (Fu::Kanchan, 2)],
+        );
+
+        let melds = Melds::parse(&[], &["P".to_owned()], &[], &[]).unwrap();
+        let mut hand = Hand::parse("234m 567p 999s 1s").unwrap();
+        hand.add(t!(1s)).unwrap();
+        let query = AgariQuery {
+            melds: &melds,
+            winning_tile: t!(1s),
+            is_ron: false,
+            dora_indicators: &[t!(C)],
+            ura_indicators: &[],
+            yakus: vec![],
+            hand,
+            ..query
+        };
+        let detail = query.detail().unwrap().unwrap();
+        assert_eq!(detail.agari, Agari::Normal { fu: 40, han: 4 });
+        assert_eq!(detail.yakus, [(Yaku::Haku, 1)]);
+        assert_eq!(detail.dora, 3);
+
+        parse_situational_yaku("Pinfu").unwrap_err();
+    }
+}
@@ -3,6 +3,7 @@ use super::state::{InitState, State};
 use super::tile::{DiscardTile, DrawTile};
 use super::{Candidate, CandidateColumn, MAX_TSUMOS_LEFT};
 use crate::algo::agari::{Agari, AgariCalculator};
+use crate::rule::RuleSet;
 use crate::tile::Tile;
 use crate::{must_tile, t, tu8};
 use std::rc::Rc;
@@ -47,6 +48,7 @@ pub struct SPCalculator<'a> {
     /// Unlike others, fuuro here includes ankan.
     pub num_doras_in_fuuro: u8,
     pub dora_indicators: &'a [Tile],
+    pub rule: RuleSet,
     pub calc_double_riichi: bool,
     pub calc_haitei: bool,
     pub prefer_riichi: bool,
@@ -649,6 +651,7 @@ impl<const MAX_TSUMO: usize> SPCalculatorState<'_, MAX_TSUMO> {
             jikaze: self.sup.jikaze,
             winning_tile: win_tile.deaka().as_u8(),
             is_ron: false,
+            rule: self.sup.rule,
         };
         let is_oya = self.sup.jikaze == tu8!(E);
 
@@ -783,6 +786,7 @@ mod test {
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![P,],
+            rule: RuleSet::tenhou(),
             calc_double_riichi: false,
             calc_haitei: false,
             sort_result: true,
@@ -855,6 +859,7 @@ mod test {
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![6m,],
+            rule: RuleSet::tenhou(),
             calc_double_riichi: true,
             calc_haitei: true,
             sort_result: true,
@@ -913,6 +918,7 @@ mod test {
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![1m,],
+            rule: RuleSet::tenhou(),
             calc_double_riichi: false,
             calc_haitei: false,
             sort_result: true,
@@ -949,6 +955,52 @@ mod test {
         }
     }
 
+    #[test]
+    fn kuitan() {
+        let pons = [tu8!(5s)];
+        let mut calc = SPCalculator {
+            tehai_len_div3: 3,
+            chis: &[],
+            pons: &pons,
+            minkans: &[],
+            ankans: &[],
+            bakaze: tu8!(E),
+            jikaze: tu8!(S),
+            prefer_riichi: false,
+            is_menzen: false,
+            num_doras_in_fuuro: 0,
+            dora_indicators: &t![E,],
+            rule: RuleSet::tenhou(),
+            calc_double_riichi: false,
+            calc_haitei: false,
+            sort_result: true,
+            maximize_win_prob: false,
+            calc_tegawari: false,
+            calc_shanten_down: false,
+        };
+        let tehai = hand("234m 567p 44s 66p").unwrap();
+        let mut tiles_seen = tehai;
+        tiles_seen[tu8!(5s) as usize] += 3;
+        for ind in calc.dora_indicators {
+            tiles_seen[ind.deaka().as_usize()] += 1;
+        }
+        let state = InitState {
+            tehai,
+            akas_in_hand: [false; 3],
+            tiles_seen,
+            akas_seen: [false; 3],
+        };
+        let cur_shanten = CALC_SHANTEN_FN(&tehai, calc.tehai_len_div3);
+        assert_eq!(cur_shanten, 0);
+
+        // The open tanyao is the only yaku.
+        let candidates = calc.calc(state.clone(), false, 10, cur_shanten).unwrap();
+        assert!(candidates[0].win_probs[0] > 0.);
+        calc.rule.kuitan = false;
+        let candidates = calc.calc(state, false, 10, cur_shanten).unwrap();
+        assert!(feq(candidates[0].win_probs[0], 0.));
+    }
+
     #[test]
     fn tsumo_only() {
         let calc = SPCalculator {
@@ -963,6 +1015,7 @@ mod test {
             is_menzen: true,
             num_doras_in_fuuro: 0,
             dora_indicators: &t![6m,],
+            rule: RuleSet::tenhou(),
             calc_double_riichi: true,
             calc_haitei: true,
             sort_result: true,
@@ -41,8 +41,12 @@ impl From<InitState> for State {
     ) -> Self {
         let mut tiles_in_wall = tiles_seen;
         let mut akas_in_wall = akas_seen;
-        tiles_in_wall.iter_mut().for_each(|v| *v = 4 - *v);
-        akas_in_wall.iter_mut().for_each(|v| *v = !*v);
+        for v in &mut tiles_in_wall {
+            *v = 4 - *v;
+        }
+        for v in &mut akas_in_wall {
+            *v = !*v;
+        }
         Self {
             tehai,
             akas_in_hand,
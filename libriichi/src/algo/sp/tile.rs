@@ -13,7 +13,7 @@ pub(super) struct DrawTile {
     pub(super) shanten_diff: i8,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct RequiredTile {
     pub tile: Tile,
     pub count: u8,
@@ -0,0 +1,282 @@
+//! Effective tiles (受け入れ) and improvements (改良) of a hand.
+//!
+//! `tiles_seen` in this mod has the same meaning as `PlayerState::tiles_seen`,
+//! that is, the number of each tile visible to the player, including those in
+//! the hand itself. The number of a tile left is `4 - tiles_seen`.
+
+use super::shanten;
+use super::sp::RequiredTile;
+use crate::must_tile;
+use crate::tile::Tile;
+use std::cmp::Ordering;
+
+/// The shapes to consider when calculating shanten. `Chiitoitsu` and
+/// `Kokushi` only make sense for a closed hand, i.e. `len_div3 == 4`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Shape {
+    /// The minimum of all the others, as in `shanten::calc_all`.
+    #[default]
+    All,
+    Normal,
+    Chiitoitsu,
+    Kokushi,
+}
+
+/// Effective tiles of a 3n+1 hand.
+#[derive(Debug, Clone, Default)]
+pub struct Ukeire {
+    pub shanten: i8,
+    /// Tiles that reduce the shanten, along with the number of each left.
+    pub tiles: Vec<RequiredTile>,
+    /// Sum of the counts in `tiles`.
+    pub total: u8,
+}
+
+/// A tile that does not reduce the shanten, but gives more effective tiles
+/// with a discard afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Improvement {
+    pub tile: Tile,
+    /// Number of `tile` left.
+    pub count: u8,
+    /// The discard after drawing `tile` that gives the most effective tiles.
+    pub discard: Tile,
+    /// Total effective tiles after the discard.
+    pub total: u8,
+}
+
+/// Effective tiles and improvements after discarding `discard` from a 3n+2
+/// hand.
+#[derive(Debug, Clone)]
+pub struct DiscardUkeire {
+    pub discard: Tile,
+    pub ukeire: Ukeire,
+    pub improvements: Vec<Improvement>,
+}
+
+impl Shape {
+    #[must_use]
+    pub fn shanten(self, tehai: &[u8; 34], len_div3: u8) -> i8 {
+        match self {
+            Self::All => shanten::calc_all(tehai, len_div3),
+            Self::Normal => shanten::calc_normal(tehai, len_div3),
+            Self::Chiitoitsu => shanten::calc_chitoi(tehai),
+            Self::Kokushi => shanten::calc_kokushi(tehai),
+        }
+    }
+}
+
+impl DiscardUkeire {
+    /// Lower shanten first, then more effective tiles, then more total tiles of
+    /// improvements, and finally the discard priority.
+    #[must_use]
+    pub fn cmp_preference(&self, other: &Self) -> Ordering {
+        let improvements = |d: &Self| d.improvements.iter().map(|i| i.count as u32).sum::<u32>();
+        self.ukeire
+            .shanten
+            .cmp(&other.ukeire.shanten)
+            .then_with(|| other.ukeire.total.cmp(&self.ukeire.total))
+            .then_with(|| improvements(other).cmp(&improvements(self)))
+            .then_with(|| other.discard.cmp_discard_priority(self.discard))
+    }
+}
+
+/// Returns the effective tiles of the 3n+1 `tehai`.
+#[must_use]
+pub fn ukeire(tehai: &[u8; 34], len_div3: u8, tiles_seen: &[u8; 34], shape: Shape) -> Ukeire {
+    let shanten = shape.shanten(tehai, len_div3);
+    let mut tehai = *tehai;
+    let mut tiles = vec![];
+    for tid in 0..34 {
+        if tehai[tid] == 4 {
+            continue;
+        }
+        tehai[tid] += 1;
+        let advanced = shape.shanten(&tehai, len_div3) < shanten;
+        tehai[tid] -= 1;
+        if advanced {
+            tiles.push(RequiredTile {
+                tile: must_tile!(tid),
+                count: tiles_left(&tehai, tiles_seen, tid),
+            });
+        }
+    }
+    let total = tiles.iter().map(|r| r.count).sum();
+    Ukeire {
+        shanten,
+        tiles,
+        total,
+    }
+}
+
+/// Returns the improvements of the 3n+1 `tehai`, which are the tiles that keep
+/// the shanten but give more effective tiles than the current ones after the
+/// best discard. Tiles with none left are not included.
+#[must_use]
+pub fn improvements(
+    tehai: &[u8; 34],
+    len_div3: u8,
+    tiles_seen: &[u8; 34],
+    shape: Shape,
+) -> Vec<Improvement> {
+    let current = ukeire(tehai, len_div3, tiles_seen, shape);
+    let mut tehai = *tehai;
+    let mut ret = vec![];
+    for tid in 0..34 {
+        let count = tiles_left(&tehai, tiles_seen, tid);
+        if count == 0 {
+            continue;
+        }
+
+        // The drawn tile is no longer left in the wall.
+        let mut seen = *tiles_seen;
+        seen[tid] = seen[tid].max(tehai[tid]) + 1;
+        tehai[tid] += 1;
+        let mut best: Option<(usize, u8)> = None;
+        if shape.shanten(&tehai, len_div3) == current.shanten {
+            for discard in 0..34 {
+                if discard == tid || tehai[discard] == 0 {
+                    continue;
+                }
+                tehai[discard] -= 1;
+                let after = ukeire(&tehai, len_div3, &seen, shape);
+                tehai[discard] += 1;
+                if after.shanten == current.shanten
+                    && after.total > best.map_or(current.total, |(_, total)| total)
+                {
+                    best = Some((discard, after.total));
+                }
+            }
+        }
+        tehai[tid] -= 1;
+
+        if let Some((discard, total)) = best {
+            ret.push(Improvement {
+                tile: must_tile!(tid),
+                count,
+                discard: must_tile!(discard),
+                total,
+            });
+        }
+    }
+    ret
+}
+
+/// Returns the effective tiles and improvements of each kind of discard of
+/// the 3n+2 `tehai`, sorted by `DiscardUkeire::cmp_preference`.
+#[must_use]
+pub fn discard_ukeire(
+    tehai: &[u8; 34],
+    len_div3: u8,
+    tiles_seen: &[u8; 34],
+    shape: Shape,
+) -> Vec<DiscardUkeire> {
+    let mut tehai = *tehai;
+    let mut ret = vec![];
+    for tid in 0..34 {
+        if tehai[tid] == 0 {
+            continue;
+        }
+        tehai[tid] -= 1;
+        ret.push(DiscardUkeire {
+            discard: must_tile!(tid),
+            ukeire: ukeire(&tehai, len_div3, tiles_seen, shape),
+            improvements: improvements(&tehai, len_div3, tiles_seen, shape),
+        });
+        tehai[tid] += 1;
+    }
+    ret.sort_by(DiscardUkeire::cmp_preference);
+    ret
+}
+
+/// Tiles in `tehai` are always counted as seen, even if `tiles_seen` misses
+/// them.
+fn tiles_left(tehai: &[u8; 34], tiles_seen: &[u8; 34], tid: usize) -> u8 {
+    4_u8.saturating_sub(tiles_seen[tid].max(tehai[tid]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hand::hand;
+    use crate::t;
+
+    fn tiles_seen(tehai: &[u8; 34], others: &str) -> [u8; 34] {
+        let mut seen = hand(others).unwrap();
+        for (s, &c) in seen.iter_mut().zip(tehai) {
+            *s += c;
+        }
+        seen
+    }
+
+    #[test]
+    fn ukeire_shapes() {
+        let tehai = hand("123m 456p 789s 11z 45m").unwrap();
+        let seen = tiles_seen(&tehai, "3m 6m 6m");
+        let u = ukeire(&tehai, 4, &seen, Shape::All);
+        assert_eq!(u.shanten, 0);
+        let tiles: Vec<_> = u.tiles.iter().map(|r| (r.tile, r.count)).collect();
+        assert_eq!(tiles, [(t!(3m), 2), (t!(6m), 2)]);
+        assert_eq!(u.total, 4);
+
+        // 一向聴 of chiitoitsu, where the normal shape is far behind.
+        let tehai = hand("1155m 1199p 33s 1z 2z 7z").unwrap();
+        let u = ukeire(&tehai, 4, &tehai, Shape::Chiitoitsu);
+        assert_eq!(u.shanten, 1);
+        assert_eq!(u.total, 9);
+        assert_eq!(ukeire(&tehai, 4, &tehai, Shape::All).total, 9);
+        assert!(ukeire(&tehai, 4, &tehai, Shape::Normal).shanten > 1);
+
+        let tehai = hand("19m 19p 19s 1234567z").unwrap();
+        let u = ukeire(&tehai, 4, &tehai, Shape::Kokushi);
+        assert_eq!(u.shanten, 0);
+        assert_eq!(u.tiles.len(), 13);
+        assert_eq!(u.total, 39);
+
+        // Works for open hands as well.
+        let tehai = hand("23m 55p").unwrap();
+        let u = ukeire(&tehai, 1, &tehai, Shape::All);
+        assert_eq!(u.shanten, 0);
+        assert_eq!(u.total, 8);
+    }
+
+    #[test]
+    fn improvements_and_discards() {
+        let tehai = hand("24m 456p 789s 123s 9p 1z").unwrap();
+        let current = ukeire(&tehai, 4, &tehai, Shape::All);
+        assert_eq!(current.shanten, 1);
+        assert_eq!(current.total, 10);
+        let imps = improvements(&tehai, 4, &tehai, Shape::All);
+        assert!(imps.iter().all(|i| i.total > current.total));
+        // Drawing 5m makes 245m, which is better than the kanchan alone.
+        let i5m = imps.iter().find(|i| i.tile == t!(5m)).unwrap();
+        assert_eq!((i5m.count, i5m.discard, i5m.total), (4, t!(9p), 17));
+
+        // With a 5m in a discard, the one drawn is the last but one.
+        let seen = tiles_seen(&tehai, "5m");
+        let imps = improvements(&tehai, 4, &seen, Shape::All);
+        let i5m = imps.iter().find(|i| i.tile == t!(5m)).unwrap();
+        let mut after = tehai;
+        after[t!(5m).as_usize()] += 1;
+        after[t!(9p).as_usize()] -= 1;
+        let u = ukeire(&after, 4, &tiles_seen(&after, "5m"), Shape::All);
+        let r5m = u.tiles.iter().find(|r| r.tile == t!(5m)).unwrap();
+        assert_eq!(r5m.count, 2);
+        assert_eq!((i5m.count, i5m.discard, i5m.total), (3, t!(9p), u.total));
+        assert_eq!(u.total, 16);
+        // Effective tiles are not improvements.
+        assert!(
+            !imps
+                .iter()
+                .any(|i| current.tiles.iter().any(|r| r.tile == i.tile))
+        );
+
+        let tehai = hand("123m 456p 789s 11z 45m 9m").unwrap();
+        let table = discard_ukeire(&tehai, 4, &tehai, Shape::All);
+        assert_eq!(table.len(), 13);
+        assert_eq!(table[0].discard, t!(9m));
+        assert_eq!(table[0].ukeire.shanten, 0);
+        assert_eq!(table[0].ukeire.total, 7);
+        assert!(table.windows(2).all(|w| w[0].cmp_preference(&w[1]).is_le()));
+    }
+}
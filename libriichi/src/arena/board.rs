@@ -1,15 +1,17 @@
 use super::result::KyokuResult;
+use crate::algo::point::Point;
 use crate::array::Simple2DArray;
 use crate::consts::oracle_obs_shape;
 use crate::mjai::{Event, EventExt};
+use crate::rule::RuleSet;
 use crate::state::PlayerState;
 use crate::tile::Tile;
 use crate::vec_ops::vec_add_assign;
-use crate::{matches_tu8, must_tile, t, tu8};
+use crate::{matches_tu8, must_tile, t, tu8, tuz};
 use std::convert::TryInto;
 use std::{array, mem};
 
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, bail, ensure};
 use derivative::Derivative;
 use ndarray::prelude::*;
 use rand::prelude::*;
@@ -20,14 +22,22 @@ use tinyvec::ArrayVec;
 /// The fields are all pub on purpose so the caller will be able to set the
 /// yama, doras, scores directly.
 ///
-/// Other than what is mentioned below, everything else is identical to Tenhou's
-/// Rule.
+/// The rule differences are controlled by `rule`. Other than those and what is
+/// mentioned below, everything else is identical to Tenhou's Rule.
 ///
-/// 1. No triple-ron ryukyoku.
-/// 2. Tenhou (the yaku) and chihou do not accumulate with other yakus; they are
-///    always 1x yakuman.
-#[derive(Debug, Default)]
+/// 1. Tenhou (the yaku), chihou and renhou (if enabled) do not accumulate with
+///    other yakus; they are always 1x yakuman. The same goes for shiisanpuutaa.
+/// 2. Pao covers the whole point of the hand even if it is a composite or
+///    double yakuman.
+///
+/// In sanma, seat 3 is vacant. Its haipai is all unknown tiles and its score
+/// stays 0. Once the 4 rinshan tiles are used up, further rinshan tsumos are
+/// taken from the last tiles of `yama`.
+#[derive(Debug, Clone, Default)]
 pub struct Board {
+    /// Tenhou's rule by default.
+    pub rule: RuleSet,
+
     /// Counts from 0
     pub kyoku: u8,
     pub honba: u8,
@@ -47,7 +57,7 @@ pub struct Board {
     pub ura_indicators: Vec<Tile>,
 }
 
-#[derive(Derivative)]
+#[derive(Clone, Derivative)]
 #[derivative(Default)]
 pub struct BoardState {
     board: Board,
@@ -60,7 +70,6 @@ pub struct BoardState {
     has_abortive_ryukyoku: bool,
     kyoku_deltas: [i32; 4],
 
-    #[derivative(Default(value = "70"))]
     tiles_left: u8,
     tsumo_actor: u8,
     // Just a fancy bool
@@ -105,11 +114,18 @@ impl Board {
             .finalize()
             .into();
         let mut rng = ChaCha12Rng::from_seed(kyoku_seed);
-        let mut seq = UNSHUFFLED;
+        let mut seq = tiles_of(self.rule);
         seq.shuffle(&mut rng);
 
-        self.haipai = array::from_fn(|i| seq[i * 13..(i + 1) * 13].try_into().unwrap());
-        let mut idx = 13 * 4;
+        let n = self.rule.player_count() as usize;
+        self.haipai = array::from_fn(|i| {
+            if i < n {
+                seq[i * 13..(i + 1) * 13].try_into().unwrap()
+            } else {
+                [t!(?); 13]
+            }
+        });
+        let mut idx = 13 * n;
 
         self.rinshan = seq[idx..idx + 4].to_vec();
         idx += 4;
@@ -117,25 +133,54 @@ impl Board {
         idx += 5;
         self.ura_indicators = seq[idx..idx + 5].to_vec();
         idx += 5;
-        self.yama = seq[idx..idx + 70].to_vec();
-        idx += 70;
+        let tiles_left = self.rule.initial_tiles_left() as usize;
+        self.yama = seq[idx..idx + tiles_left].to_vec();
+        idx += tiles_left;
         assert_eq!(idx, seq.len());
     }
 
     pub fn into_state(self) -> BoardState {
-        let oya = self.kyoku % 4;
+        let rule = self.rule;
+        let n = rule.player_count();
+        let oya = self.kyoku % n;
         let dora_indicators_full = self.dora_indicators.clone();
 
         BoardState {
             board: self,
             oya,
-            player_states: array::from_fn(|i| PlayerState::new(i as u8)),
+            // The state of the vacant seat in sanma is never updated.
+            player_states: array::from_fn(|i| {
+                if (i as u8) < n {
+                    PlayerState::new_with_rule(i as u8, rule)
+                } else {
+                    PlayerState::default()
+                }
+            }),
+            tiles_left: rule.initial_tiles_left(),
+            can_nagashi_mangan: array::from_fn(|i| (i as u8) < n),
+            // There is no 四風連打 in sanma.
+            can_four_wind: !rule.sanma,
             dora_indicators_full,
             ..Default::default()
         }
     }
 }
 
+/// Returns all the tiles used under `rule` in a fixed order.
+pub(super) fn tiles_of(rule: RuleSet) -> Vec<Tile> {
+    UNSHUFFLED
+        .into_iter()
+        .filter(|&tile| rule.has_tile(tile))
+        .map(|tile| {
+            if tile.is_aka() && tile.as_usize() - tuz!(5mr) >= rule.aka_count as usize {
+                tile.deaka()
+            } else {
+                tile
+            }
+        })
+        .collect()
+}
+
 impl BoardState {
     /// Returns iff any player on the board can act or the kyoku has ended.
     pub fn poll(&mut self, mut reactions: [EventExt; 4]) -> Result<Poll> {
@@ -196,15 +241,21 @@ impl BoardState {
         self.log.push(EventExt::no_meta(ev));
     }
 
+    #[inline]
+    const fn player_count(&self) -> usize {
+        self.board.rule.player_count() as usize
+    }
+
     #[inline]
     fn broadcast(&mut self, ev: &Event) {
-        for s in &mut self.player_states {
+        let n = self.player_count();
+        for s in &mut self.player_states[..n] {
             s.update(ev).expect("fatal internal bug in BoardState");
         }
     }
 
     fn haipai(&mut self) -> Result<()> {
-        let bakaze = must_tile!(tu8!(E) + self.board.kyoku / 4);
+        let bakaze = must_tile!(tu8!(E) + self.board.kyoku / self.board.rule.player_count());
         let start_kyoku = Event::StartKyoku {
             bakaze,
             dora_marker: self
@@ -239,47 +290,46 @@ impl BoardState {
     }
 
     fn exhaustive_ryukyoku(&mut self) {
+        let n = self.player_count();
         let mut deltas = [0; 4];
         self.can_renchan = self.player_states[self.oya as usize].shanten() == 0;
 
         let mut has_nagashi_mangan = false;
-        self.can_nagashi_mangan
-            .iter()
-            .enumerate()
-            .filter(|&(_, &b)| b)
-            .map(|(i, _)| i)
-            .for_each(|i| {
-                has_nagashi_mangan = true;
-                if i as u8 == self.oya {
-                    let mut dod = [-4000; 4];
-                    dod[i] = 12000;
-                    vec_add_assign(&mut deltas, &dod);
-                } else {
-                    let mut dod = [-2000; 4];
-                    dod[i] = 8000;
-                    dod[self.oya as usize] = -4000;
-                    vec_add_assign(&mut deltas, &dod);
-                };
-            });
+        for i in 0..n {
+            if !self.can_nagashi_mangan[i] {
+                continue;
+            }
+            has_nagashi_mangan = true;
+            // Paid as a mangan tsumo, without honba.
+            let is_oya = i as u8 == self.oya;
+            let mut point = Point::calc(is_oya, 30, 5);
+            if self.board.rule.sanma {
+                point = point.sanma(is_oya, self.board.rule.tsumo_son);
+            }
+            let dod = self.tsumo_deltas(i as u8, point, 0);
+            vec_add_assign(&mut deltas, &dod);
+        }
 
         if !has_nagashi_mangan {
-            let tenpai_actors: ArrayVec<[_; 4]> = self
-                .player_states
+            let tenpai_actors: ArrayVec<[_; 4]> = self.player_states[..n]
                 .iter()
                 .enumerate()
                 .filter(|&(_, s)| s.shanten() == 0)
                 .map(|(i, _)| i)
                 .collect();
 
-            let (plus, minus) = match tenpai_actors.len() {
-                1 => (3000, -1000),
-                2 => (1500, -1500),
-                3 => (1000, -3000),
-                // 0 | 4
+            let (plus, minus) = match (n, tenpai_actors.len()) {
+                (4, 1) => (3000, -1000),
+                (4, 2) => (1500, -1500),
+                (4, 3) => (1000, -3000),
+                (3, 1) => (2000, -1000),
+                (3, 2) => (1000, -2000),
+                // 0 or everyone
                 _ => (0, 0),
             };
             if plus > 0 {
-                let mut dod = [minus; 4];
+                let mut dod = [0; 4];
+                dod[..n].fill(minus);
                 tenpai_actors.into_iter().for_each(|i| dod[i] = plus);
                 vec_add_assign(&mut deltas, &dod);
             }
@@ -363,6 +413,36 @@ impl BoardState {
         Ok(())
     }
 
+    /// Returns the deltas of a tsumo agari of `point` by `actor`, including
+    /// honba but not kyotaku.
+    fn tsumo_deltas(&self, actor: u8, point: Point, honba: i32) -> [i32; 4] {
+        let mut deltas = [0; 4];
+        for (i, delta) in deltas.iter_mut().enumerate().take(self.player_count()) {
+            if i as u8 == actor {
+                continue;
+            }
+            let payment = if i as u8 == self.oya {
+                point.tsumo_oya
+            } else {
+                point.tsumo_ko
+            };
+            *delta = -payment - honba * 100;
+        }
+        deltas[actor as usize] = -deltas.iter().sum::<i32>();
+        deltas
+    }
+
+    fn pop_rinshan(&mut self) -> Result<Tile> {
+        if let Some(tile) = self.board.rinshan.pop() {
+            return Ok(tile);
+        }
+        ensure!(
+            self.board.rule.sanma && !self.board.yama.is_empty(),
+            "illegal kan: already 4 kans and this is the 5th",
+        );
+        Ok(self.board.yama.remove(0))
+    }
+
     fn handle_hora(
         &mut self,
         single_actor: u8,
@@ -380,83 +460,76 @@ impl BoardState {
         // indicators.
         let ura_indicators =
             self.board.ura_indicators[..5 - self.board.dora_indicators.len()].to_vec();
-        let points = reactions
-            .iter()
-            .map(|ev| match ev.event {
-                Event::Hora { actor, .. } => {
-                    self.can_renchan |= actor == self.oya;
-                    let point =
-                        self.player_states[actor as usize].agari_points(is_ron, &ura_indicators);
-                    Some(point).transpose()
-                }
-                _ => Ok(None),
-            })
-            .collect::<Result<Vec<_>>>()?;
 
         if is_ron {
-            // Multi-ron will be handled
-            points
-                .into_iter()
-                .enumerate()
-                .cycle()
-                .skip(single_target as usize + 1)
-                .take(3)
-                .filter_map(|(actor, v)| v.map(|point| (actor, point)))
-                .for_each(|(actor, point)| {
-                    let mut deltas = [0; 4];
-                    if let Some(pao_target) = self.paos[actor] {
-                        // As per [Tenhou's rule](https://tenhou.net/man/#RULE):
-                        //
-                        // > 複合役満を含む得点を、ツモ＝全額・ロン＝折半で支払
-                        // > う。積み棒は包。
-                        deltas[pao_target as usize] = -point.ron / 2 - honba_left * 300;
-                        deltas[single_target as usize] -= point.ron / 2; // they may be the same person
-                    } else {
-                        deltas[single_target as usize] = -point.ron - honba_left * 300;
-                    }
-                    deltas[actor] = point.ron + kyotaku_point + honba_left * 300;
-
-                    kyotaku_point = 0;
-                    honba_left = 0;
-
-                    vec_add_assign(&mut self.kyoku_deltas, &deltas);
-                    let ura_markers = self.player_states[actor]
-                        .self_riichi_accepted()
-                        .then(|| ura_indicators.clone())
-                        .unwrap_or_default();
-
-                    let hora = Event::Hora {
-                        actor: actor as u8,
-                        target: single_target,
-                        deltas: Some(deltas),
-                        ura_markers: Some(ura_markers),
-                    };
-                    self.add_log_no_meta(hora);
-                    // No need to broadcast
-                });
+            // Multi-ron will be handled, in turn order starting from the
+            // target, which is also the order of 頭ハネ.
+            let n = self.board.rule.player_count();
+            let mut actors: ArrayVec<[u8; 3]> = (1..n)
+                .map(|i| (single_target + i) % n)
+                .filter(|&actor| matches!(reactions[actor as usize].event, Event::Hora { .. }))
+                .collect();
+            actors.truncate(self.board.rule.accepted_rons(actors.len()));
+
+            for actor in actors {
+                self.can_renchan |= actor == self.oya;
+                let point =
+                    self.player_states[actor as usize].agari_points(true, &ura_indicators)?;
+
+                let mut deltas = [0; 4];
+                if let Some(pao_target) = self.paos[actor as usize] {
+                    // As per [Tenhou's rule](https://tenhou.net/man/#RULE):
+                    //
+                    // > 複合役満を含む得点を、ツモ＝全額・ロン＝折半で支払
+                    // > う。積み棒は包。
+                    deltas[pao_target as usize] = -point.ron / 2 - honba_left * 300;
+                    deltas[single_target as usize] -= point.ron / 2; // they may be the same person
+                } else {
+                    deltas[single_target as usize] = -point.ron - honba_left * 300;
+                }
+                deltas[actor as usize] = point.ron + kyotaku_point + honba_left * 300;
+
+                kyotaku_point = 0;
+                honba_left = 0;
+
+                vec_add_assign(&mut self.kyoku_deltas, &deltas);
+                let ura_markers = if self.player_states[actor as usize].self_riichi_accepted() {
+                    ura_indicators.clone()
+                } else {
+                    vec![]
+                };
+
+                let hora = Event::Hora {
+                    actor,
+                    target: single_target,
+                    deltas: Some(deltas),
+                    ura_markers: Some(ura_markers),
+                };
+                self.add_log_no_meta(hora);
+                // No need to broadcast
+            }
             return Ok(());
         }
 
-        let point = points[single_actor as usize].unwrap();
-        let mut deltas = [0; 4];
+        self.can_renchan |= single_actor == self.oya;
+        let point =
+            self.player_states[single_actor as usize].agari_points(false, &ura_indicators)?;
+        let mut deltas = self.tsumo_deltas(single_actor, point, honba_left);
         if let Some(pao_target) = self.paos[single_actor as usize] {
-            // For pao to happen, the agari must have at least 1 yakuman so ron
-            // point and sum of tsumo point should be equal.
-            deltas[pao_target as usize] = -point.ron - honba_left * 300;
-        } else {
-            deltas.fill(-point.tsumo_ko - honba_left * 100);
-            if single_actor != self.oya {
-                deltas[self.oya as usize] = -point.tsumo_oya - honba_left * 100;
-            }
-        };
-        deltas[single_actor as usize] =
-            point.tsumo_total(single_actor == self.oya) + kyotaku_point + honba_left * 300;
+            // The pao target pays everything on its own.
+            let total = deltas[single_actor as usize];
+            deltas = [0; 4];
+            deltas[pao_target as usize] = -total;
+            deltas[single_actor as usize] = total;
+        }
+        deltas[single_actor as usize] += kyotaku_point;
 
         vec_add_assign(&mut self.kyoku_deltas, &deltas);
-        let ura_markers = self.player_states[single_actor as usize]
-            .self_riichi_accepted()
-            .then_some(ura_indicators)
-            .unwrap_or_default();
+        let ura_markers = if self.player_states[single_actor as usize].self_riichi_accepted() {
+            ura_indicators
+        } else {
+            vec![]
+        };
 
         let hora = Event::Hora {
             actor: single_actor,
@@ -509,7 +582,7 @@ impl BoardState {
     }
 
     fn step(&mut self, reactions: &[EventExt; 4]) -> Result<Poll> {
-        if self.tiles_left == 70 {
+        if self.log.is_empty() {
             self.haipai()?;
             return Ok(Poll::InGame);
         }
@@ -559,10 +632,7 @@ impl BoardState {
                 self.check_riichi_accepted();
 
                 let tile = if self.deal_from_rinshan.take().is_some() {
-                    self.board
-                        .rinshan
-                        .pop()
-                        .context("illegal kan: already 4 kans and this is the 5th")?
+                    self.pop_rinshan()?
                 } else {
                     self.board.yama.pop().with_context(|| {
                         format!("tiles left > 0 ({}) but yama is empty", self.tiles_left)
@@ -591,7 +661,7 @@ impl BoardState {
 
                 self.broadcast(&ev.event);
                 self.add_log(ev.clone());
-                self.tsumo_actor = (actor + 1) % 4;
+                self.tsumo_actor = (actor + 1) % self.board.rule.player_count();
 
                 // 四風連打
                 if self.can_four_wind && self.check_four_wind(pai)? {
@@ -647,6 +717,14 @@ impl BoardState {
                 self.kans += 1;
             }
 
+            Event::Nukidora { actor, .. } => {
+                self.broadcast(&ev.event);
+                self.add_log(ev.clone());
+
+                self.tsumo_actor = actor;
+                self.deal_from_rinshan = Some(());
+            }
+
             Event::Reach { actor } => {
                 self.broadcast(&ev.event);
                 self.add_log(ev.clone());
@@ -654,7 +732,16 @@ impl BoardState {
             }
 
             Event::Hora { actor, target, .. } => {
-                self.handle_hora(actor, target, reactions)?;
+                let rons = reactions
+                    .iter()
+                    .filter(|ev| matches!(ev.event, Event::Hora { .. }))
+                    .count();
+                if rons == 3 && self.board.rule.triple_ron_abort {
+                    // 三家和
+                    self.abortive_ryukyoku();
+                } else {
+                    self.handle_hora(actor, target, reactions)?;
+                }
                 return Ok(Poll::End);
             }
 
@@ -682,60 +769,62 @@ impl BoardState {
         let mut arr = Simple2DArray::<34, f32>::new(shape.0);
         let mut idx = 0;
 
-        self.player_states
-            .iter()
-            .cycle()
-            .skip(perspective as usize + 1)
-            .take(3)
-            .for_each(|state| {
-                state
-                    .tehai()
-                    .iter()
-                    .enumerate()
-                    .filter(|&(_, &count)| count > 0)
-                    .for_each(|(tile_id, &count)| {
-                        arr.assign_rows(idx, tile_id, count as usize, 1.);
-                    });
-                idx += 4;
-
-                state
-                    .akas_in_hand()
-                    .iter()
-                    .enumerate()
-                    .filter(|&(_, &has_it)| has_it)
-                    .for_each(|(i, _)| arr.fill(idx + i, 1.));
-                idx += 3;
-
-                let n = state.shanten() as usize;
-                match version {
-                    1 => {
-                        arr.fill_rows(idx, n, 1.);
-                        idx += 6;
-                    }
-                    2 | 3 | 4 => {
-                        arr.fill(idx + n, 1.);
-                        idx += 7;
+        let player_count = self.board.rule.player_count();
+        for i in 1..4 {
+            if i >= player_count {
+                // The vacant seat in sanma.
+                idx += if version == 1 { 15 } else { 17 };
+                continue;
+            }
+            let state = &self.player_states[((perspective + i) % player_count) as usize];
+            state
+                .tehai()
+                .iter()
+                .enumerate()
+                .filter(|&(_, &count)| count > 0)
+                .for_each(|(tile_id, &count)| {
+                    arr.assign_rows(idx, tile_id, count as usize, 1.);
+                });
+            idx += 4;
 
-                        let v = n as f32 / 6.;
-                        arr.fill(idx, v);
-                        idx += 1;
-                    }
-                    _ => unreachable!(),
+            state
+                .akas_in_hand()
+                .iter()
+                .enumerate()
+                .filter(|&(_, &has_it)| has_it)
+                .for_each(|(i, _)| arr.fill(idx + i, 1.));
+            idx += 3;
+
+            let n = state.shanten() as usize;
+            match version {
+                1 => {
+                    arr.fill_rows(idx, n, 1.);
+                    idx += 6;
                 }
+                2..=5 => {
+                    arr.fill(idx + n, 1.);
+                    idx += 7;
 
-                state
-                    .waits()
-                    .iter()
-                    .enumerate()
-                    .filter(|&(_, &c)| c)
-                    .for_each(|(t, _)| arr.assign(idx, t, 1.));
-                idx += 1;
-
-                if state.at_furiten() {
-                    arr.fill(idx, 1.);
+                    let v = n as f32 / 6.;
+                    arr.fill(idx, v);
+                    idx += 1;
                 }
-                idx += 1;
-            });
+                _ => unreachable!(),
+            }
+
+            state
+                .waits()
+                .iter()
+                .enumerate()
+                .filter(|&(_, &c)| c)
+                .for_each(|(t, _)| arr.assign(idx, t, 1.));
+            idx += 1;
+
+            if state.at_furiten() {
+                arr.fill(idx, 1.);
+            }
+            idx += 1;
+        }
 
         let mut encode_tile = |idx: usize, tile: Tile| {
             let tile_id = tile.deaka().as_usize();
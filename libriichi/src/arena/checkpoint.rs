@@ -0,0 +1,263 @@
+use super::result::GameResult;
+use crate::mjai::{Event, EventExt};
+use crate::rule::RuleSet;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, ensure};
+use flate2::Compression;
+use flate2::read::{GzDecoder, GzEncoder};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+const MANIFEST: &str = "manifest.jsonl";
+
+/// Finished games of a batch, streamed into a log directory along with a
+/// manifest, so that an interrupted run can pick up where it left off.
+///
+/// A game is identified by its seed and split, where split is the number of
+/// games with the same seed before it in the batch. Its log is named
+/// `{seed}_{key}_{split}.json.gz`, with split named as `a`, `b`, ..., `z`,
+/// `aa`, `ab`, ...
+pub(super) struct Checkpoint {
+    dir: PathBuf,
+    manifest: File,
+    done: HashMap<String, Entry>,
+}
+
+/// A line in the manifest. A game is only recorded after its log has been
+/// fully written.
+///
+/// The split is not stored, as it is already in `file`.
+#[derive(Serialize, Deserialize)]
+pub(super) struct Entry {
+    pub(super) file: String,
+    pub(super) seed: (u64, u64),
+    pub(super) names: [String; 4],
+    pub(super) scores: [i32; 4],
+    pub(super) rule: RuleSet,
+}
+
+impl Checkpoint {
+    pub(super) fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+
+        let path = dir.join(MANIFEST);
+        let content = if path.exists() {
+            fs::read_to_string(&path)?
+        } else {
+            String::new()
+        };
+        // The last line may be incomplete if the previous run crashed while
+        // writing it, in which case it is dropped and the game is simply
+        // played again.
+        let complete = content.rfind('\n').map_or(0, |i| i + 1);
+        let done = parse_manifest(&content[..complete])
+            .with_context(|| format!("failed to read {}", path.display()))?
+            .into_iter()
+            .map(|entry| (entry.file.clone(), entry))
+            .collect();
+        let manifest = OpenOptions::new().create(true).append(true).open(&path)?;
+        if complete < content.len() {
+            manifest.set_len(complete as u64)?;
+        }
+
+        Ok(Self {
+            dir,
+            manifest,
+            done,
+        })
+    }
+
+    /// Loads the result of a game finished in a previous run, if any. Fails
+    /// if the game was played by other agents or under another rule, which
+    /// means the log directory is being reused for a different run.
+    pub(super) fn load(
+        &self,
+        seed: (u64, u64),
+        split: usize,
+        rule: RuleSet,
+        names: &[String; 4],
+    ) -> Result<Option<GameResult>> {
+        let Some(entry) = self.done.get(&log_filename(seed, split)) else {
+            return Ok(None);
+        };
+        ensure!(
+            entry.names == *names,
+            "{} in {} was played by {:?} instead of {names:?}",
+            entry.file,
+            self.dir.display(),
+            entry.names,
+        );
+        ensure!(
+            entry.rule == rule,
+            "{} in {} was played under another rule",
+            entry.file,
+            self.dir.display(),
+        );
+
+        let path = self.dir.join(&entry.file);
+        let reader = BufReader::new(GzDecoder::new(File::open(&path)?));
+        let mut game_log: Vec<Vec<EventExt>> = vec![];
+        for line in reader.lines() {
+            let ev: EventExt = json::from_str(&line?)
+                .with_context(|| format!("failed to parse {}", path.display()))?;
+            match ev.event {
+                Event::StartGame { .. } | Event::EndGame => (),
+                Event::StartKyoku { .. } => game_log.push(vec![ev]),
+                _ => game_log
+                    .last_mut()
+                    .with_context(|| format!("event before start_kyoku in {}", path.display()))?
+                    .push(ev),
+            }
+        }
+
+        Ok(Some(GameResult {
+            names: entry.names.clone(),
+            scores: entry.scores,
+            seed: entry.seed,
+            rule,
+            game_log,
+        }))
+    }
+
+    /// Writes the logs of the given finished games and records them in the
+    /// manifest.
+    pub(super) fn save<'a, I>(&mut self, finished: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (usize, &'a GameResult)>,
+    {
+        let finished: Vec<_> = finished.into_iter().collect();
+        if finished.is_empty() {
+            return Ok(());
+        }
+
+        let entries = finished
+            .into_par_iter()
+            .map(|(split, game_result)| {
+                let file = log_filename(game_result.seed, split);
+
+                // Write to a temporary file first so that a crash never
+                // leaves a truncated log behind.
+                let tmp = self.dir.join(format!("{file}.tmp"));
+                let log = game_result.dump_json_log()?;
+                let mut comp = GzEncoder::new(log.as_bytes(), Compression::best());
+                let mut f = File::create(&tmp)?;
+                io::copy(&mut comp, &mut f)?;
+                f.sync_all()?;
+                fs::rename(&tmp, self.dir.join(&file))?;
+
+                Ok(Entry {
+                    file,
+                    seed: game_result.seed,
+                    names: game_result.names.clone(),
+                    scores: game_result.scores,
+                    rule: game_result.rule,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut lines = vec![];
+        for entry in &entries {
+            json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+        self.manifest.write_all(&lines)?;
+        self.manifest.sync_data()?;
+
+        for entry in entries {
+            self.done.insert(entry.file.clone(), entry);
+        }
+        Ok(())
+    }
+}
+
+impl Entry {
+    /// Parses the split back from `file`.
+    pub(super) fn split(&self) -> Result<usize> {
+        let name = self
+            .file
+            .strip_suffix(".json.gz")
+            .and_then(|id| id.rsplit_once('_'))
+            .map(|(_, name)| name)
+            .filter(|name| !name.is_empty() && name.bytes().all(|b| b.is_ascii_lowercase()))
+            .with_context(|| format!("invalid log file name {}", self.file))?;
+        let split = name
+            .bytes()
+            .fold(0, |acc, b| acc * 26 + (b - b'a') as usize + 1);
+        Ok(split - 1)
+    }
+}
+
+/// Reads the games recorded in the manifest of `dir`, in the order they
+/// finished. The run may still be going on, so an incomplete last line is
+/// ignored.
+pub(super) fn read_manifest(dir: impl AsRef<Path>) -> Result<Vec<Entry>> {
+    let path = dir.as_ref().join(MANIFEST);
+    let content = fs::read_to_string(&path)?;
+    let complete = content.rfind('\n').map_or(0, |i| i + 1);
+    parse_manifest(&content[..complete])
+        .with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Parses complete lines of a manifest, every one of which must be valid.
+fn parse_manifest(content: &str) -> Result<Vec<Entry>> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            json::from_str(line).with_context(|| format!("invalid entry at line {}", i + 1))
+        })
+        .collect()
+}
+
+pub(super) fn log_filename(seed: (u64, u64), split: usize) -> String {
+    format!("{}.json.gz", game_id(seed, split))
+}
+
+/// Also the ID of the game in a rating ledger.
+pub(super) fn game_id((seed, key): (u64, u64), split: usize) -> String {
+    format!("{seed}_{key}_{}", split_name(split))
+}
+
+fn split_name(mut split: usize) -> String {
+    let mut name = vec![];
+    loop {
+        name.push(b'a' + (split % 26) as u8);
+        if split < 26 {
+            break;
+        }
+        split = split / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_names() {
+        assert_eq!(split_name(0), "a");
+        assert_eq!(split_name(25), "z");
+        assert_eq!(split_name(26), "aa");
+        assert_eq!(split_name(27 * 26), "aaa");
+        assert_eq!(log_filename((1009, 42), 3), "1009_42_d.json.gz");
+
+        for split in [0, 3, 25, 26, 27 * 26, 12345] {
+            let entry = Entry {
+                file: log_filename((1009, 42), split),
+                seed: (1009, 42),
+                names: Default::default(),
+                scores: [25000; 4],
+                rule: RuleSet::tenhou(),
+            };
+            assert_eq!(entry.split().unwrap(), split);
+        }
+    }
+}
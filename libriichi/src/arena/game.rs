@@ -1,7 +1,10 @@
 use super::board::{Board, BoardState, Poll};
+use super::checkpoint::Checkpoint;
 use super::result::GameResult;
 use crate::agent::BatchAgent;
 use crate::mjai::EventExt;
+use crate::rule::RuleSet;
+use std::collections::HashMap;
 use std::time::Duration;
 use std::{array, mem};
 
@@ -10,10 +13,16 @@ use indicatif::{ProgressBar, ProgressStyle};
 use ndarray::prelude::*;
 
 pub struct BatchGame {
-    /// 8 for hanchan and 4 for tonpuu
-    pub length: u8,
+    /// The game length is specified by `rule.game_length`. In sanma, the
+    /// `Index` of seat 3 is ignored.
+    pub rule: RuleSet,
     pub init_scores: [i32; 4],
     pub disable_progress_bar: bool,
+    /// If set, logs of finished games are streamed into this directory as
+    /// soon as they end, and games already recorded there by a previous run
+    /// are skipped, with their results loaded back instead. It is an error if
+    /// those were played by other agents or under another rule.
+    pub log_dir: Option<String>,
 }
 
 #[derive(Clone, Copy, Default)]
@@ -26,8 +35,6 @@ pub struct Index {
 
 #[derive(Default)]
 struct Game {
-    length: u8,
-    seed: (u64, u64),
     indexes: [Index; 4],
 
     oracle_obs_versions: [Option<u32>; 4],
@@ -35,6 +42,16 @@ struct Game {
 
     last_reactions: [EventExt; 4], // cached for poll phase
 
+    progress: GameProgress,
+}
+
+/// Drives the kyokus of a single game on a `BoardState`, independent of where
+/// the reactions come from.
+#[derive(Default)]
+pub(super) struct GameProgress {
+    rule: RuleSet,
+    seed: (u64, u64),
+
     board: BoardState,
     kyoku: u8,
     honba: u8,
@@ -44,8 +61,8 @@ struct Game {
 
     kyoku_started: bool,
     ended: bool,
-    /// Used in 西入 where the oya and another player get to 30000 at the same
-    /// time, but the game continues because oya is not the top.
+    /// Used in 西入 where the oya and another player get to the target score
+    /// at the same time, but the game continues because oya is not the top.
     ///
     /// As per [Tenhou's rule](https://tenhou.net/man/#RULE):
     ///
@@ -57,147 +74,55 @@ struct Game {
 impl Game {
     /// Returns iff any player in the game can act or the game has ended.
     fn poll(&mut self, agents: &mut [Box<dyn BatchAgent>]) -> Result<()> {
-        if self.ended {
-            return Ok(());
-        }
-
-        if !self.kyoku_started {
-            // after W4
-            // or, after all-last
-            //   and, oya is not in renchan (if oya is in renchan, it would already have been ended in the renchan owari check)
-            //   and, anyone has more than 30k
-            if self.kyoku >= self.length + 4
-                || self.kyoku >= self.length
-                    && !self.in_renchan
-                    && self.scores.iter().any(|&s| s >= 30000)
-            {
-                self.ended = true;
-                return Ok(());
-            }
-
-            let mut next_board = Board {
-                kyoku: self.kyoku,
-                honba: self.honba,
-                kyotaku: self.kyotaku,
-                scores: self.scores,
-                ..Default::default()
-            };
-            next_board.init_from_seed(self.seed);
-            self.board = next_board.into_state();
-            self.kyoku_started = true;
-        }
-
-        let reactions = mem::take(&mut self.last_reactions);
-        let poll = self.board.poll(reactions)?;
-        match poll {
-            Poll::InGame => {
-                let ctx = self.board.agent_context();
-                for (player_id, state) in ctx.player_states.iter().enumerate() {
-                    if !state.last_cans().can_act() {
-                        continue;
+        let mut reactions = mem::take(&mut self.last_reactions);
+        while !self.progress.has_ended() {
+            match self.progress.poll(mem::take(&mut reactions))? {
+                Poll::InGame => {
+                    let board = self.progress.board();
+                    let ctx = board.agent_context();
+                    for (player_id, state) in ctx.player_states.iter().enumerate() {
+                        if !state.last_cans().can_act() {
+                            continue;
+                        }
+
+                        let invisible_state = self.oracle_obs_versions[player_id]
+                            .map(|ver| board.encode_oracle_obs(player_id as u8, ver));
+                        self.invisible_state_cache[player_id].clone_from(&invisible_state);
+
+                        let idx = self.indexes[player_id];
+                        agents[idx.agent_idx].set_scene(
+                            idx.player_id_idx,
+                            ctx.log,
+                            state,
+                            invisible_state,
+                        )?;
                     }
-
-                    let invisible_state = self.oracle_obs_versions[player_id]
-                        .map(|ver| self.board.encode_oracle_obs(player_id as u8, ver));
-                    self.invisible_state_cache[player_id].clone_from(&invisible_state);
-
-                    let idx = self.indexes[player_id];
-                    agents[idx.agent_idx].set_scene(
-                        idx.player_id_idx,
-                        ctx.log,
-                        state,
-                        invisible_state,
-                    )?;
-                }
-            }
-
-            Poll::End => {
-                self.kyoku_started = false;
-                self.in_renchan = false;
-
-                for idx in &self.indexes {
-                    agents[idx.agent_idx].end_kyoku(idx.player_id_idx)?;
-                }
-
-                let kyoku_result = self.board.end();
-                self.kyotaku = kyoku_result.kyotaku_left;
-                self.scores = kyoku_result.scores;
-
-                let logs = self.board.take_log();
-                self.game_log.push(logs);
-
-                let has_tobi = self.scores.iter().any(|&s| s < 0);
-                if has_tobi {
-                    self.ended = true;
                     return Ok(());
                 }
 
-                if kyoku_result.has_abortive_ryukyoku {
-                    self.honba += 1;
-                    return self.poll(agents);
-                }
-
-                if !kyoku_result.can_renchan {
-                    self.kyoku += 1;
-                    if kyoku_result.has_hora {
-                        self.honba = 0;
-                    } else {
-                        self.honba += 1;
+                Poll::End => {
+                    for idx in self.seated_indexes() {
+                        agents[idx.agent_idx].end_kyoku(idx.player_id_idx)?;
                     }
-                    return self.poll(agents);
                 }
-
-                // renchan owari conditions:
-                // 1. can renchan
-                // 2. is at all-last
-                // 3. oya has at least 30000
-                // 4. oya is the top
-                let oya = kyoku_result.kyoku as usize % 4;
-                if kyoku_result.kyoku >= self.length - 1 && self.scores[oya] >= 30000 {
-                    let top = kyoku_result
-                        .scores
-                        .iter()
-                        .enumerate()
-                        .min_by_key(|&(_, &s)| -s)
-                        .map(|(i, _)| i)
-                        .unwrap();
-                    if top == oya {
-                        self.ended = true;
-                        return Ok(());
-                    }
-                }
-
-                // renchan
-                self.in_renchan = true;
-                self.honba += 1;
-                return self.poll(agents);
             }
-        };
+        }
 
         Ok(())
     }
 
     fn commit(&mut self, agents: &mut [Box<dyn BatchAgent>]) -> Result<Option<GameResult>> {
-        if self.ended {
-            if self.kyotaku > 0 {
-                *self.scores.iter_mut().min_by_key(|s| -**s).unwrap() += self.kyotaku as i32 * 1000;
-            }
+        if self.progress.has_ended() {
+            let names = seat_names(agents, &self.indexes, self.progress.rule);
+            let game_result = self.progress.finish(names);
 
-            let names = array::from_fn(|i| agents[self.indexes[i].agent_idx].name());
-            let game_result = GameResult {
-                names,
-                scores: self.scores,
-                seed: self.seed,
-                game_log: mem::take(&mut self.game_log),
-            };
-
-            for idx in &self.indexes {
+            for idx in self.seated_indexes() {
                 agents[idx.agent_idx].end_game(idx.player_id_idx, &game_result)?;
             }
             return Ok(Some(game_result));
         }
 
-        let ctx = self.board.agent_context();
+        let ctx = self.progress.board().agent_context();
         for (player_id, state) in ctx.player_states.iter().enumerate() {
             if !state.last_cans().can_act() {
                 continue;
@@ -216,14 +141,163 @@ impl Game {
 
         Ok(None)
     }
+
+    fn seated_indexes(&self) -> &[Index] {
+        &self.indexes[..self.progress.rule.player_count() as usize]
+    }
+}
+
+impl GameProgress {
+    pub(super) fn new(rule: RuleSet, init_scores: [i32; 4], seed: (u64, u64)) -> Self {
+        Self {
+            rule,
+            seed,
+            scores: init_scores,
+            ..Default::default()
+        }
+    }
+
+    /// Polls the board with `reactions`, starting a new kyoku if needed.
+    ///
+    /// Returns `Poll::InGame` if any player can act, or `Poll::End` if a kyoku
+    /// has just ended, after which `has_ended` tells if the game has ended
+    /// too.
+    pub(super) fn poll(&mut self, reactions: [EventExt; 4]) -> Result<Poll> {
+        if !self.kyoku_started {
+            let mut next_board = Board {
+                kyoku: self.kyoku,
+                honba: self.honba,
+                kyotaku: self.kyotaku,
+                scores: self.scores,
+                rule: self.rule,
+                ..Default::default()
+            };
+            next_board.init_from_seed(self.seed);
+            self.board = next_board.into_state();
+            self.kyoku_started = true;
+        }
+
+        let poll = self.board.poll(reactions)?;
+        if matches!(poll, Poll::End) {
+            self.end_kyoku();
+        }
+        Ok(poll)
+    }
+
+    fn end_kyoku(&mut self) {
+        self.kyoku_started = false;
+        self.in_renchan = false;
+
+        let kyoku_result = self.board.end();
+        self.kyotaku = kyoku_result.kyotaku_left;
+        self.scores = kyoku_result.scores;
+
+        let logs = self.board.take_log();
+        self.game_log.push(logs);
+
+        if self.rule.is_tobi(&self.scores) {
+            self.ended = true;
+            return;
+        }
+
+        if kyoku_result.has_abortive_ryukyoku {
+            self.honba += 1;
+        } else if !kyoku_result.can_renchan {
+            self.kyoku += 1;
+            if kyoku_result.has_hora {
+                self.honba = 0;
+            } else {
+                self.honba += 1;
+            }
+        } else {
+            // renchan owari conditions:
+            // 1. can renchan
+            // 2. 和了止め or 聴牌止め is enabled, depending on how it renchans
+            // 3. is at all-last
+            // 4. oya has at least the target score
+            // 5. oya is the top
+            let n = self.rule.player_count() as usize;
+            let oya = kyoku_result.kyoku as usize % n;
+            let yame = if kyoku_result.has_hora {
+                self.rule.agari_yame
+            } else {
+                self.rule.tenpai_yame
+            };
+            if yame
+                && kyoku_result.kyoku >= self.rule.game_length - 1
+                && self.scores[oya] >= self.rule.target_score
+            {
+                let top = kyoku_result.scores[..n]
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|&(_, &s)| -s)
+                    .map(|(i, _)| i)
+                    .unwrap();
+                if top == oya {
+                    self.ended = true;
+                    return;
+                }
+            }
+
+            // renchan
+            self.in_renchan = true;
+            self.honba += 1;
+        }
+
+        // after W4 (or all-last if there is no 西入)
+        // or, after all-last
+        //   and, oya is not in renchan (if oya is in renchan, it would already have been ended in the renchan owari check)
+        //   and, anyone has reached the target score
+        if self.kyoku >= self.rule.max_kyoku()
+            || self.kyoku >= self.rule.game_length
+                && !self.in_renchan
+                && self.scores.iter().any(|&s| s >= self.rule.target_score)
+        {
+            self.ended = true;
+        }
+    }
+
+    /// Gives the remaining kyotaku to the top and returns the result. It
+    /// should only be called once after the game has ended.
+    pub(super) fn finish(&mut self, names: [String; 4]) -> GameResult {
+        let n = self.rule.player_count() as usize;
+        if self.kyotaku > 0 {
+            *self.scores[..n].iter_mut().min_by_key(|s| -**s).unwrap() +=
+                self.kyotaku as i32 * 1000;
+        }
+
+        GameResult {
+            names,
+            scores: self.scores,
+            seed: self.seed,
+            rule: self.rule,
+            game_log: mem::take(&mut self.game_log),
+        }
+    }
+
+    #[inline]
+    pub(super) const fn has_ended(&self) -> bool {
+        self.ended
+    }
+
+    #[inline]
+    pub(super) const fn board(&self) -> &BoardState {
+        &self.board
+    }
+
+    #[inline]
+    pub(super) fn game_log(&self) -> &[Vec<EventExt>] {
+        &self.game_log
+    }
 }
 
 impl BatchGame {
     pub const fn tenhou_hanchan(disable_progress_bar: bool) -> Self {
         Self {
-            length: 8,
+            rule: RuleSet::tenhou(),
             init_scores: [25000; 4],
             disable_progress_bar,
+            log_dir: None,
         }
     }
 
@@ -241,39 +315,75 @@ impl BatchGame {
             indexes.len(),
             seeds.len(),
         );
+        // The game must end before it goes beyond the north round.
+        ensure!(
+            (1..=12).contains(&self.rule.game_length),
+            "game_length must be within [1, 12], got {}",
+            self.rule.game_length,
+        );
 
-        let mut games = indexes
+        let mut checkpoint = self.log_dir.as_ref().map(Checkpoint::open).transpose()?;
+        let mut seed_counts = HashMap::new();
+        let splits: Vec<_> = seeds
             .iter()
-            .zip(seeds)
-            .enumerate()
-            .map(|(game_idx, (idxs, &seed))| {
+            .map(|seed| {
+                let count = seed_counts.entry(seed).or_insert(0);
+                *count += 1;
+                *count - 1
+            })
+            .collect();
+
+        let mut game_results = vec![GameResult::default(); seeds.len()];
+        let mut to_play = vec![];
+        for (game_idx, (&seed, &split)) in seeds.iter().zip(&splits).enumerate() {
+            let loaded = match &checkpoint {
+                Some(checkpoint) => {
+                    let names = seat_names(agents, &indexes[game_idx], self.rule);
+                    checkpoint.load(seed, split, self.rule, &names)?
+                }
+                None => None,
+            };
+            match loaded {
+                Some(game_result) => game_results[game_idx] = game_result,
+                None => to_play.push(game_idx),
+            }
+        }
+        let skipped = seeds.len() - to_play.len();
+        if skipped > 0 {
+            log::info!("skipping {skipped} games finished in a previous run");
+        }
+
+        let mut games = to_play
+            .into_iter()
+            .map(|game_idx| {
+                let idxs = &indexes[game_idx];
+                let seed = seeds[game_idx];
                 let mut oracle_obs_versions = [None; 4];
-                for (i, idx) in idxs.iter().enumerate() {
+                let n = self.rule.player_count() as usize;
+                for (i, idx) in idxs[..n].iter().enumerate() {
                     agents[idx.agent_idx].start_game(idx.player_id_idx)?;
                     oracle_obs_versions[i] = agents[idx.agent_idx].oracle_obs_version();
                 }
 
                 let game = Box::new(Game {
-                    length: self.length,
-                    seed,
                     indexes: *idxs,
-                    scores: self.init_scores,
                     oracle_obs_versions,
+                    progress: GameProgress::new(self.rule, self.init_scores, seed),
                     ..Default::default()
                 });
                 Ok((game_idx, game))
             })
             .collect::<Result<Vec<_>>>()?;
 
-        let mut game_results = vec![GameResult::default(); games.len()];
         let mut to_remove = vec![];
+        let mut finished = vec![];
         let mut cycles = 0;
         let mut actions = 0;
 
         let bar = if self.disable_progress_bar {
             ProgressBar::hidden()
         } else {
-            ProgressBar::new(games.len() as u64)
+            ProgressBar::new(seeds.len() as u64)
         };
         const TEMPLATE: &str =
             "{spinner:.cyan} {msg}\n[{elapsed_precise}] [{wide_bar}] {pos}/{len} {percent:>3}%";
@@ -282,6 +392,7 @@ impl BatchGame {
             .progress_chars("#-");
         bar.set_style(style);
         bar.enable_steady_tick(Duration::from_millis(150));
+        bar.inc(skipped as u64);
 
         while !games.is_empty() {
             for (_, game) in &mut games {
@@ -292,9 +403,19 @@ impl BatchGame {
                 if let Some(game_result) = game.commit(agents)? {
                     game_results[*game_idx] = game_result;
                     to_remove.push(idx_for_rm);
+                    finished.push(*game_idx);
                 }
             }
 
+            if let Some(checkpoint) = &mut checkpoint {
+                checkpoint.save(
+                    finished
+                        .iter()
+                        .map(|&game_idx| (splits[game_idx], &game_results[game_idx])),
+                )?;
+            }
+            finished.clear();
+
             for idx_for_rm in to_remove.drain(..).rev() {
                 games.swap_remove(idx_for_rm);
                 bar.inc(1);
@@ -316,10 +437,26 @@ impl BatchGame {
     }
 }
 
+/// Names of the agents at each seat, which are empty for the vacant seat in
+/// sanma.
+fn seat_names(agents: &[Box<dyn BatchAgent>], indexes: &[Index; 4], rule: RuleSet) -> [String; 4] {
+    let n = rule.player_count() as usize;
+    array::from_fn(|i| {
+        if i < n {
+            agents[indexes[i].agent_idx].name()
+        } else {
+            String::new()
+        }
+    })
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::agent::Tsumogiri;
+    use crate::agent::{ActionWeights, RandomAgent, Tsumogiri};
+    use crate::mjai::Event;
+    use crate::{t, tu8};
+    use std::{env, fs};
 
     #[test]
     fn tsumogiri() {
@@ -370,4 +507,192 @@ mod test {
         g.run(&mut agents, indexes, &[(1009, 0), (1021, 0)])
             .unwrap();
     }
+
+    #[test]
+    fn m_league_rule() {
+        let g = BatchGame {
+            rule: RuleSet {
+                aka_count: 0,
+                kuitan: false,
+                atozuke: false,
+                ..RuleSet::m_league()
+            },
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let mut agents = [Box::new(Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap()) as _];
+        let indexes = &[array::from_fn(|i| Index {
+            agent_idx: 0,
+            player_id_idx: i,
+        })];
+
+        let results = g.run(&mut agents, indexes, &[(1009, 0)]).unwrap();
+        for result in &results {
+            for ev in result.game_log.iter().flatten() {
+                match &ev.event {
+                    Event::StartKyoku { bakaze, tehais, .. } => {
+                        assert_ne!(*bakaze, t!(W));
+                        assert!(tehais.iter().flatten().all(|t| !t.is_aka()));
+                    }
+                    Event::Tsumo { pai, .. } => assert!(!pai.is_aka()),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tonpuu() {
+        let g = BatchGame {
+            rule: RuleSet {
+                game_length: 4,
+                ..RuleSet::tenhou()
+            },
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let mut agents = [Box::new(Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap()) as _];
+        let indexes = &[array::from_fn(|i| Index {
+            agent_idx: 0,
+            player_id_idx: i,
+        })];
+
+        let results = g.run(&mut agents, indexes, &[(1009, 0)]).unwrap();
+        for ev in results[0].game_log.iter().flatten() {
+            if let Event::StartKyoku { bakaze, .. } = ev.event {
+                assert!(matches!(bakaze.as_u8(), tu8!(E) | tu8!(S)));
+            }
+        }
+
+        let g = BatchGame {
+            rule: RuleSet {
+                game_length: 13,
+                ..RuleSet::tenhou()
+            },
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        g.run(&mut agents, indexes, &[(1009, 0)]).unwrap_err();
+    }
+
+    #[test]
+    fn sanma() {
+        let g = BatchGame {
+            rule: RuleSet::tenhou_sanma(),
+            init_scores: [35000, 35000, 35000, 0],
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let mut agents = [Box::new(Tsumogiri::new_batched(&[0, 1, 2]).unwrap()) as _];
+        let indexes = &[array::from_fn(|i| Index {
+            agent_idx: 0,
+            player_id_idx: i % 3,
+        })];
+
+        let results = g.run(&mut agents, indexes, &[(1009, 0)]).unwrap();
+        let result = &results[0];
+        assert_eq!(result.scores[3], 0);
+        assert_eq!(result.scores.iter().sum::<i32>(), 105000);
+        assert_eq!(result.rankings().player_by_rank[3], 3);
+        for ev in result.game_log.iter().flatten() {
+            match &ev.event {
+                Event::StartKyoku { tehais, .. } => {
+                    assert!(tehais[3].iter().all(|t| t.is_unknown()));
+                    assert!(
+                        tehais[..3]
+                            .iter()
+                            .flatten()
+                            .all(|&t| RuleSet::tenhou_sanma().has_tile(t))
+                    );
+                }
+                Event::Tsumo { actor, pai } => {
+                    assert!(*actor < 3);
+                    assert!(RuleSet::tenhou_sanma().has_tile(*pai));
+                }
+                Event::Hora { deltas, .. } | Event::Ryukyoku { deltas } => {
+                    assert_eq!(deltas.unwrap()[3], 0);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn resume() {
+        let log_dir = env::temp_dir().join(format!("riichi-resume-{}", std::process::id()));
+        fs::remove_dir_all(&log_dir).ok();
+
+        let g = BatchGame {
+            rule: RuleSet {
+                game_length: 4,
+                ..RuleSet::tenhou()
+            },
+            log_dir: Some(log_dir.to_string_lossy().into_owned()),
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let indexes: [[_; 4]; 3] = array::from_fn(|game| {
+            array::from_fn(|i| Index {
+                agent_idx: 0,
+                player_id_idx: game * 4 + i,
+            })
+        });
+        let seeds = &[(1009, 0), (1009, 0), (1021, 0)];
+        let player_ids: Vec<_> = (0..4).cycle().take(12).collect();
+        let new_agents = || [Box::new(Tsumogiri::new_batched(&player_ids).unwrap()) as _];
+        let results = g.run(&mut new_agents(), &indexes, seeds).unwrap();
+
+        let manifest_path = log_dir.join("manifest.jsonl");
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        let lines: Vec<_> = manifest.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for file in ["1009_0_a.json.gz", "1009_0_b.json.gz", "1021_0_a.json.gz"] {
+            assert!(log_dir.join(file).exists());
+        }
+
+        // Pretend the run crashed while recording the last game.
+        let partial = &lines[2][..lines[2].len() / 2];
+        fs::write(
+            &manifest_path,
+            format!("{}\n{}\n{partial}", lines[0], lines[1]),
+        )
+        .unwrap();
+
+        let resumed = g.run(&mut new_agents(), &indexes, seeds).unwrap();
+        let manifest = fs::read_to_string(&manifest_path).unwrap();
+        let resumed_lines: Vec<_> = manifest.lines().collect();
+        assert_eq!(resumed_lines[..2], lines[..2]);
+        assert_eq!(resumed_lines.len(), 3);
+        for (result, resumed) in results.iter().zip(&resumed) {
+            assert_eq!(result.seed, resumed.seed);
+            assert_eq!(result.scores, resumed.scores);
+            assert_eq!(result.names, resumed.names);
+            assert_eq!(
+                result.dump_json_log().unwrap(),
+                resumed.dump_json_log().unwrap(),
+            );
+        }
+
+        // The games in the manifest were played by another lineup or under
+        // another rule.
+        let random = || {
+            let agent = RandomAgent::new_batched(&player_ids, 0, ActionWeights::default());
+            [Box::new(agent.unwrap()) as _]
+        };
+        g.run(&mut random(), &indexes, seeds).unwrap_err();
+        let no_aka = BatchGame {
+            rule: RuleSet {
+                aka_count: 0,
+                ..g.rule
+            },
+            log_dir: g.log_dir.clone(),
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        no_aka.run(&mut new_agents(), &indexes, seeds).unwrap_err();
+
+        // Only the last line may be broken.
+        fs::write(
+            &manifest_path,
+            format!("{}\n{partial}\n{}\n", lines[0], lines[2]),
+        )
+        .unwrap();
+        g.run(&mut new_agents(), &indexes, seeds).unwrap_err();
+
+        fs::remove_dir_all(&log_dir).unwrap();
+    }
 }
@@ -1,12 +1,25 @@
 mod board;
+mod checkpoint;
 mod game;
 mod one_vs_three;
+mod replay;
+mod report;
 mod result;
+mod sampler;
+mod server;
+mod tournament;
 mod two_vs_two;
 
-pub use board::Board;
+pub use board::{Board, BoardState, Poll};
+pub use game::{BatchGame, Index};
+pub use replay::{KyokuRerun, Replay};
+pub use report::{ArenaReport, Decision, Estimate, ReportConfig, Sprt};
 pub use result::GameResult;
+pub use sampler::{Sampler, World};
+pub use server::Server;
+pub use tournament::{Arena, NewAgent, Seating};
 
+use crate::agent::{RuleBasedConfig, SubprocessConfig};
 use crate::py_helper::add_submodule;
 use one_vs_three::OneVsThree;
 use two_vs_two::TwoVsTwo;
@@ -21,5 +34,11 @@ pub(crate) fn register_module(
     let m = PyModule::new(py, "arena")?;
     m.add_class::<OneVsThree>()?;
     m.add_class::<TwoVsTwo>()?;
+    m.add_class::<Arena>()?;
+    m.add_class::<ArenaReport>()?;
+    m.add_class::<Estimate>()?;
+    m.add_class::<Sprt>()?;
+    m.add_class::<SubprocessConfig>()?;
+    m.add_class::<RuleBasedConfig>()?;
     add_submodule(py, prefix, super_mod, &m)
 }
@@ -0,0 +1,540 @@
+use super::board::{Board, BoardState, Poll};
+use super::result::GameResult;
+use crate::agent::{BatchAgent, Tsumogiri};
+use crate::mjai::{Event, EventExt};
+use crate::rule::RuleSet;
+use crate::state::PlayerState;
+use crate::{matches_tu8, tu8};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail, ensure};
+use flate2::read::GzDecoder;
+use serde_json as json;
+
+/// A game log with a seed in its `start_game`, which is what `BatchGame`
+/// dumps. Every kyoku of it can be replayed on a board whose walls are
+/// regenerated from the seed.
+pub struct Replay {
+    pub rule: RuleSet,
+    pub names: [String; 4],
+    pub seed: (u64, u64),
+    /// Each kyoku starts with `start_kyoku` and ends with `end_kyoku`.
+    pub kyokus: Vec<Vec<EventExt>>,
+}
+
+/// The outcome of `Replay::rerun_kyoku`.
+#[derive(Debug)]
+pub struct KyokuRerun {
+    pub log: Vec<EventExt>,
+    /// Index in `log` of the first event that differs from the original log.
+    pub diverged_at: Option<usize>,
+    /// Scores at the end of the rerun kyoku.
+    pub scores: [i32; 4],
+    /// Scores at the end of the original kyoku.
+    pub original_scores: [i32; 4],
+}
+
+impl Replay {
+    pub fn from_log(raw_log: &str, rule: RuleSet) -> Result<Self> {
+        let mut names = None;
+        let mut kyokus: Vec<Vec<EventExt>> = vec![];
+        for (i, line) in raw_log.lines().enumerate() {
+            let ev: EventExt =
+                json::from_str(line).with_context(|| format!("failed to parse line {}", i + 1))?;
+            match ev.event {
+                Event::StartGame {
+                    names: ref n, seed, ..
+                } => {
+                    let seed = seed.context("start_game has no seed")?;
+                    names = Some((n.clone(), seed));
+                }
+                Event::EndGame => (),
+                Event::StartKyoku { .. } => kyokus.push(vec![ev]),
+                _ => kyokus
+                    .last_mut()
+                    .with_context(|| format!("event before start_kyoku at line {}", i + 1))?
+                    .push(ev),
+            }
+        }
+        let (names, seed) = names.context("missing start_game")?;
+
+        Ok(Self {
+            rule,
+            names,
+            seed,
+            kyokus,
+        })
+    }
+
+    /// Reads a log from a `*.json` or `*.json.gz` file.
+    pub fn from_path(path: impl AsRef<Path>, rule: RuleSet) -> Result<Self> {
+        let path = path.as_ref();
+        let raw_log = if path
+            .extension()
+            .is_some_and(|s| s.eq_ignore_ascii_case("gz"))
+        {
+            io::read_to_string(GzDecoder::new(File::open(path)?))?
+        } else {
+            io::read_to_string(File::open(path)?)?
+        };
+        Self::from_log(&raw_log, rule).with_context(|| format!("in log {}", path.display()))
+    }
+
+    /// Checks that every kyoku can be reproduced from the seed, including
+    /// haipai, tsumo, dora, ura and deltas.
+    pub fn verify(&self) -> Result<()> {
+        for kyoku in 0..self.kyokus.len() {
+            self.verify_kyoku(kyoku)
+                .with_context(|| format!("in kyoku {}", self.kyoku_name(kyoku)))?;
+        }
+        Ok(())
+    }
+
+    /// Replays the kyoku at index `kyoku` with the logged reactions, returning
+    /// the scores at the end of it.
+    pub fn verify_kyoku(&self, kyoku: usize) -> Result<[i32; 4]> {
+        let logged = self.kyoku_log(kyoku)?;
+        let mut board = self.board(kyoku)?;
+        let mut reactions = Default::default();
+        let mut pos = 0;
+        loop {
+            let poll = board.poll(reactions)?;
+            let log = board.agent_context().log;
+            for ev in &log[pos..] {
+                let Some(expected) = logged.get(pos) else {
+                    bail!(
+                        "event {pos} is missing from the log, should be {:?}",
+                        ev.event
+                    );
+                };
+                ensure!(
+                    expected.event == ev.event,
+                    "event {pos} is {:?} in the log, but {:?} from the seed",
+                    expected.event,
+                    ev.event,
+                );
+                pos += 1;
+            }
+
+            if matches!(poll, Poll::End) {
+                ensure!(
+                    pos == logged.len(),
+                    "unexpected event {pos} {:?} after the end of kyoku",
+                    logged[pos].event,
+                );
+                return Ok(board.end().scores);
+            }
+            let ctx = board.agent_context();
+            reactions = logged_reactions(&logged[pos..], ctx.player_states, self.rule);
+        }
+    }
+
+    /// Re-runs the kyoku at index `kyoku` on the same wall, with `seat`
+    /// played by `agent`.
+    ///
+    /// The other seats replay their logged reactions for as long as the kyoku
+    /// stays identical to the log. Once it diverges, they are played by
+    /// `others`, or by `Tsumogiri` if it is `None`.
+    ///
+    /// Both agents are given the seat as the index, so they should be created
+    /// with player IDs `[0, 1, 2, 3]`.
+    pub fn rerun_kyoku(
+        &self,
+        kyoku: usize,
+        seat: u8,
+        agent: &mut dyn BatchAgent,
+        mut others: Option<&mut dyn BatchAgent>,
+    ) -> Result<KyokuRerun> {
+        let n = self.rule.player_count();
+        ensure!(seat < n, "seat {seat} is out of range");
+        let original_scores = self.verify_kyoku(kyoku)?;
+        let logged = self.kyoku_log(kyoku)?;
+
+        let seat_idx = seat as usize;
+        agent.start_game(seat_idx)?;
+        if let Some(others) = others.as_deref_mut() {
+            for i in (0..n as usize).filter(|&i| i != seat_idx) {
+                others.start_game(i)?;
+            }
+        }
+
+        let mut board = self.board(kyoku)?;
+        let mut reactions: [EventExt; 4] = Default::default();
+        let mut pos = 0;
+        let mut diverged_at = None;
+        loop {
+            let poll = board.poll(reactions)?;
+            let ctx = board.agent_context();
+            if diverged_at.is_none() {
+                diverged_at = ctx.log[pos..]
+                    .iter()
+                    .zip(logged.get(pos..).unwrap_or_default())
+                    .position(|(ev, expected)| ev.event != expected.event)
+                    .map(|i| pos + i);
+                if diverged_at.is_none() && ctx.log.len() > logged.len() {
+                    diverged_at = Some(logged.len());
+                }
+                pos = ctx.log.len();
+            }
+            if matches!(poll, Poll::End) {
+                break;
+            }
+
+            reactions = match diverged_at {
+                None => logged_reactions(&logged[pos..], ctx.player_states, self.rule),
+                Some(_) => Default::default(),
+            };
+            let swapped_state = &ctx.player_states[seat_idx];
+            if swapped_state.last_cans().can_act() {
+                let invisible_state = agent
+                    .oracle_obs_version()
+                    .map(|ver| board.encode_oracle_obs(seat, ver));
+                agent.set_scene(seat_idx, ctx.log, swapped_state, invisible_state.clone())?;
+                reactions[seat_idx] =
+                    agent.get_reaction(seat_idx, ctx.log, swapped_state, invisible_state)?;
+            } else {
+                reactions[seat_idx] = EventExt::no_meta(Event::None);
+            }
+            if diverged_at.is_none() {
+                continue;
+            }
+
+            for (i, state) in ctx.player_states[..n as usize].iter().enumerate() {
+                if i == seat_idx || !state.last_cans().can_act() {
+                    continue;
+                }
+                reactions[i] = if let Some(others) = others.as_deref_mut() {
+                    let invisible_state = others
+                        .oracle_obs_version()
+                        .map(|ver| board.encode_oracle_obs(i as u8, ver));
+                    others.set_scene(i, ctx.log, state, invisible_state.clone())?;
+                    others.get_reaction(i, ctx.log, state, invisible_state)?
+                } else {
+                    EventExt::no_meta(Tsumogiri::reaction(state))
+                };
+            }
+        }
+
+        let scores = board.end().scores;
+        let log = board.take_log();
+        let game_result = GameResult {
+            names: self.names.clone(),
+            scores,
+            seed: self.seed,
+            rule: self.rule,
+            game_log: vec![log.clone()],
+        };
+        agent.end_kyoku(seat_idx)?;
+        agent.end_game(seat_idx, &game_result)?;
+        if let Some(others) = others {
+            for i in (0..n as usize).filter(|&i| i != seat_idx) {
+                others.end_kyoku(i)?;
+                others.end_game(i, &game_result)?;
+            }
+        }
+
+        Ok(KyokuRerun {
+            log,
+            diverged_at,
+            scores,
+            original_scores,
+        })
+    }
+
+    /// Returns the name of the kyoku at index `kyoku`, such as `E1-0` for
+    /// East 1, 0 honba.
+    #[must_use]
+    pub fn kyoku_name(&self, kyoku: usize) -> String {
+        match self.kyokus.get(kyoku).map(|log| &log[0].event) {
+            Some(&Event::StartKyoku {
+                bakaze,
+                kyoku,
+                honba,
+                ..
+            }) => format!("{bakaze}{kyoku}-{honba}"),
+            _ => format!("#{kyoku}"),
+        }
+    }
+
+    fn kyoku_log(&self, kyoku: usize) -> Result<&[EventExt]> {
+        self.kyokus
+            .get(kyoku)
+            .map(Vec::as_slice)
+            .with_context(|| format!("kyoku index {kyoku} is out of range"))
+    }
+
+    /// Sets up a board for the kyoku, with its wall derived from the seed.
+    fn board(&self, kyoku: usize) -> Result<BoardState> {
+        let Event::StartKyoku {
+            bakaze,
+            kyoku: kyoku_num,
+            honba,
+            kyotaku,
+            scores,
+            ..
+        } = self.kyoku_log(kyoku)?[0].event
+        else {
+            unreachable!();
+        };
+        ensure!(
+            matches_tu8!(bakaze.as_u8(), E | S | W | N)
+                && (1..=self.rule.player_count()).contains(&kyoku_num),
+            "invalid start_kyoku {}",
+            self.kyoku_name(kyoku),
+        );
+
+        let mut board = Board {
+            rule: self.rule,
+            kyoku: (bakaze.as_u8() - tu8!(E)) * self.rule.player_count() + kyoku_num - 1,
+            honba,
+            kyotaku,
+            scores,
+            ..Default::default()
+        };
+        board.init_from_seed(self.seed);
+        Ok(board.into_state())
+    }
+}
+
+/// Derives the reactions that lead to `upcoming`, the rest of the log.
+/// Events dealt by the board itself, such as tsumo, need no reaction.
+pub(super) fn logged_reactions(
+    upcoming: &[EventExt],
+    states: &[PlayerState; 4],
+    rule: RuleSet,
+) -> [EventExt; 4] {
+    let mut reactions: [EventExt; 4] = Default::default();
+    // The dora revealed by a daiminkan or kakan is logged right before the
+    // discard that triggers it.
+    let Some(next) = upcoming
+        .iter()
+        .find(|ev| !matches!(ev.event, Event::Dora { .. }))
+    else {
+        return reactions;
+    };
+
+    match next.event {
+        Event::Hora { .. } => {
+            // Multi-ron is logged as consecutive hora events.
+            for ev in upcoming {
+                let Event::Hora { actor, target, .. } = ev.event else {
+                    break;
+                };
+                reactions[actor as usize] = EventExt::no_meta(Event::Hora {
+                    actor,
+                    target,
+                    deltas: None,
+                    ura_markers: None,
+                });
+            }
+        }
+        Event::Ryukyoku { .. } => {
+            if let Some(actor) = states.iter().position(|s| s.last_cans().can_ryukyoku) {
+                // 九種九牌
+                reactions[actor] = EventExt::no_meta(Event::Ryukyoku { deltas: None });
+            } else {
+                // 三家和
+                let rons: Vec<_> = states
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, s)| s.last_cans().can_ron_agari)
+                    .collect();
+                if rule.triple_ron_abort && rons.len() == 3 {
+                    for (actor, s) in rons {
+                        reactions[actor] = EventExt::no_meta(Event::Hora {
+                            actor: actor as u8,
+                            target: s.last_cans().target_actor,
+                            deltas: None,
+                            ura_markers: None,
+                        });
+                    }
+                }
+            }
+        }
+        Event::Dahai { actor, .. }
+        | Event::Chi { actor, .. }
+        | Event::Pon { actor, .. }
+        | Event::Daiminkan { actor, .. }
+        | Event::Kakan { actor, .. }
+        | Event::Ankan { actor, .. }
+        | Event::Nukidora { actor, .. }
+        | Event::Reach { actor }
+            if states[actor as usize].last_cans().can_act() =>
+        {
+            reactions[actor as usize] = next.clone();
+        }
+        _ => (),
+    }
+
+    reactions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{Agent, BatchifiedAgent, InvisibleState};
+    use crate::arena::{BatchGame, Index};
+    use crate::must_tile;
+    use crate::test_util::run_arena;
+
+    /// Always discards the smallest tile.
+    struct Tedashi(u8);
+
+    impl Agent for Tedashi {
+        fn name(&self) -> String {
+            "tedashi".to_owned()
+        }
+
+        fn react(
+            &mut self,
+            _: &[EventExt],
+            state: &PlayerState,
+            _: Option<InvisibleState>,
+        ) -> Result<EventExt> {
+            if !state.last_cans().can_discard {
+                return Ok(EventExt::no_meta(Event::None));
+            }
+            let idx = state.discard_candidates_aka().iter().position(|&b| b);
+            let pai = must_tile!(idx.unwrap());
+            Ok(EventExt::no_meta(Event::Dahai {
+                actor: self.0,
+                pai,
+                tsumogiri: state.last_self_tsumo() == Some(pai),
+            }))
+        }
+    }
+
+    fn events(log: &[EventExt]) -> Vec<Event> {
+        log.iter().map(|ev| ev.event.clone()).collect()
+    }
+
+    fn tsumogiri_game() -> GameResult {
+        let mut agents: Vec<Box<dyn BatchAgent>> =
+            vec![Box::new(Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap())];
+        let indexes = [[0, 1, 2, 3].map(|player_id_idx| Index {
+            agent_idx: 0,
+            player_id_idx,
+        })];
+        let game = BatchGame {
+            rule: RuleSet::tenhou_tonpuu(),
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        game.run(&mut agents, &indexes, &[(1009, 42)])
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn verify() {
+        let game_result = tsumogiri_game();
+        let raw_log = game_result.dump_json_log().unwrap();
+        let replay = Replay::from_log(&raw_log, game_result.rule).unwrap();
+        assert!(
+            replay
+                .kyokus
+                .iter()
+                .zip(&game_result.game_log)
+                .all(|(a, b)| events(a) == events(b))
+        );
+        replay.verify().unwrap();
+        assert_eq!(
+            replay.verify_kyoku(replay.kyokus.len() - 1).unwrap(),
+            game_result.scores,
+        );
+
+        // Swap two tsumo tiles of the first kyoku.
+        let mut tampered = Replay::from_log(&raw_log, game_result.rule).unwrap();
+        let tsumos: Vec<_> = tampered.kyokus[0]
+            .iter()
+            .enumerate()
+            .filter(|(_, ev)| matches!(ev.event, Event::Tsumo { .. }))
+            .map(|(i, _)| i)
+            .collect();
+        let (a, b) = (tsumos[0], tsumos[1]);
+        let (Event::Tsumo { pai: pai_a, .. }, Event::Tsumo { pai: pai_b, .. }) =
+            (&tampered.kyokus[0][a].event, &tampered.kyokus[0][b].event)
+        else {
+            unreachable!();
+        };
+        if pai_a != pai_b {
+            let (pai_a, pai_b) = (*pai_a, *pai_b);
+            for (i, pai) in [(a, pai_b), (b, pai_a)] {
+                if let Event::Tsumo { pai: p, .. } = &mut tampered.kyokus[0][i].event {
+                    *p = pai;
+                }
+            }
+            tampered.verify().unwrap_err();
+        }
+
+        // A different seed gives different walls.
+        tampered.seed = (1009, 43);
+        tampered.verify().unwrap_err();
+    }
+
+    #[test]
+    fn verify_kans() {
+        let game = BatchGame {
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let game_result = run_arena(&game, (1, 0));
+        let kyoku_with = |f: fn(&Event) -> bool| {
+            game_result
+                .game_log
+                .iter()
+                .position(|kyoku| kyoku.iter().any(|ev| f(&ev.event)))
+                .unwrap()
+        };
+        // Both reveal a dora between the call and the next reaction.
+        let daiminkan = kyoku_with(|ev| matches!(ev, Event::Daiminkan { .. }));
+        let kakan = kyoku_with(|ev| matches!(ev, Event::Kakan { .. }));
+
+        let raw_log = game_result.dump_json_log().unwrap();
+        let replay = Replay::from_log(&raw_log, game_result.rule).unwrap();
+        replay.verify().unwrap();
+        for kyoku in [daiminkan, kakan] {
+            let scores = replay.verify_kyoku(kyoku).unwrap();
+            let next_scores = match replay.kyokus.get(kyoku + 1).map(|log| &log[0].event) {
+                Some(&Event::StartKyoku { scores, .. }) => scores,
+                _ => game_result.scores,
+            };
+            assert_eq!(scores, next_scores);
+        }
+    }
+
+    #[test]
+    fn rerun() {
+        let game_result = tsumogiri_game();
+        let raw_log = game_result.dump_json_log().unwrap();
+        let replay = Replay::from_log(&raw_log, game_result.rule).unwrap();
+
+        let mut agent = Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap();
+        let rerun = replay.rerun_kyoku(0, 2, &mut agent, None).unwrap();
+        assert_eq!(rerun.diverged_at, None);
+        assert_eq!(events(&rerun.log), events(&replay.kyokus[0]));
+        assert_eq!(rerun.scores, rerun.original_scores);
+
+        let mut agent = BatchifiedAgent::new(|id| Ok(Tedashi(id)), &[0, 1, 2, 3]).unwrap();
+        let rerun = replay.rerun_kyoku(0, 0, &mut agent, None).unwrap();
+        let diverged_at = rerun.diverged_at.unwrap();
+        assert!(matches!(
+            rerun.log[diverged_at].event,
+            Event::Dahai { actor: 0, .. },
+        ));
+        assert!(
+            rerun.log[..diverged_at]
+                .iter()
+                .zip(&replay.kyokus[0])
+                .all(|(a, b)| a.event == b.event)
+        );
+        assert_eq!(rerun.log.last().unwrap().event, Event::EndKyoku);
+
+        replay.rerun_kyoku(0, 4, &mut agent, None).unwrap_err();
+        replay
+            .rerun_kyoku(replay.kyokus.len(), 0, &mut agent, None)
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,546 @@
+use super::checkpoint;
+use super::result::GameResult;
+use crate::rankings::Rankings;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{Result, ensure};
+use pyo3::prelude::*;
+
+/// A sample mean with its standard error and confidence interval.
+#[pyclass]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Estimate {
+    #[pyo3(get)]
+    pub mean: f64,
+    #[pyo3(get)]
+    pub std_err: f64,
+    #[pyo3(get)]
+    pub low: f64,
+    #[pyo3(get)]
+    pub high: f64,
+}
+
+/// Outcome of the sequential probability ratio test on the challenger's
+/// avg rank improvement over 2.5, tested per seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Decision {
+    /// Not enough evidence yet, keep playing.
+    #[default]
+    Continue,
+    /// The challenger is not better by `delta`.
+    AcceptH0,
+    /// The challenger is better by `delta`.
+    AcceptH1,
+}
+
+#[pyclass]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sprt {
+    /// Log-likelihood ratio of H1 (improvement of `delta`) against H0 (no
+    /// improvement).
+    #[pyo3(get)]
+    pub llr: f64,
+    #[pyo3(get)]
+    pub lower: f64,
+    #[pyo3(get)]
+    pub upper: f64,
+    pub decision: Decision,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReportConfig {
+    /// Rank points of 1st to 4th.
+    pub pts: [f64; 4],
+    /// Confidence level of the intervals.
+    pub confidence: f64,
+    /// Improvement in avg rank over 2.5 that H1 of the SPRT asserts.
+    pub delta: f64,
+    /// False positive rate of the SPRT.
+    pub alpha: f64,
+    /// False negative rate of the SPRT.
+    pub beta: f64,
+}
+
+/// Statistics of the challenger in a 1v3 run.
+///
+/// Every seed is played in 4 splits with the challenger taking each seat in
+/// turn, so the same walls are mirrored between the challenger and the
+/// champion. `rank_diff`, `pt_diff` and the SPRT take the mean of each seed as
+/// one sample, which cancels most of the luck of the walls and gives much
+/// tighter intervals than treating every game as independent, as
+/// `mean_rank` and `mean_pt` do. Seeds with missing splits are left out of
+/// the paired statistics.
+#[pyclass]
+#[derive(Debug, Clone, Default)]
+pub struct ArenaReport {
+    #[pyo3(get)]
+    pub games: usize,
+    /// Number of seeds with all 4 splits finished.
+    #[pyo3(get)]
+    pub seeds: usize,
+    #[pyo3(get)]
+    pub rankings: [u32; 4],
+    #[pyo3(get)]
+    pub mean_rank: Estimate,
+    #[pyo3(get)]
+    pub mean_pt: Estimate,
+    /// Challenger's avg rank minus champion's, paired by seed.
+    #[pyo3(get)]
+    pub rank_diff: Estimate,
+    /// Challenger's avg pt minus champion's, paired by seed.
+    #[pyo3(get)]
+    pub pt_diff: Estimate,
+    #[pyo3(get)]
+    pub sprt: Sprt,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            pts: [90., 45., 0., -135.],
+            confidence: 0.95,
+            delta: 0.02,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+impl Estimate {
+    fn new(samples: &[f64], z: f64) -> Result<Self> {
+        ensure!(
+            samples.len() >= 2,
+            "at least 2 samples are required, got {}",
+            samples.len(),
+        );
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.);
+        let std_err = (var / n).sqrt();
+        Ok(Self {
+            mean,
+            std_err,
+            low: z.mul_add(-std_err, mean),
+            high: z.mul_add(std_err, mean),
+        })
+    }
+}
+
+impl Sprt {
+    fn new(samples: &[f64], config: &ReportConfig) -> Self {
+        let lower = (config.beta / (1. - config.alpha)).ln();
+        let upper = ((1. - config.beta) / config.alpha).ln();
+
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.);
+        if samples.len() < 2 || var <= 0. {
+            return Self {
+                lower,
+                upper,
+                ..Default::default()
+            };
+        }
+
+        // Normal approximation with the variance estimated from the samples.
+        let delta = config.delta;
+        let llr = n * delta * (mean - delta / 2.) / var;
+        let decision = if llr >= upper {
+            Decision::AcceptH1
+        } else if llr <= lower {
+            Decision::AcceptH0
+        } else {
+            Decision::Continue
+        };
+        Self {
+            llr,
+            lower,
+            upper,
+            decision,
+        }
+    }
+}
+
+#[pymethods]
+impl Sprt {
+    /// One of `"continue"`, `"h0"` and `"h1"`.
+    #[getter]
+    #[must_use]
+    pub const fn decision(&self) -> &'static str {
+        match self.decision {
+            Decision::Continue => "continue",
+            Decision::AcceptH0 => "h0",
+            Decision::AcceptH1 => "h1",
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+#[pymethods]
+impl Estimate {
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl ArenaReport {
+    /// `results` must be returned by `OneVsThree::run_batch`, where the
+    /// challenger sits at seat `i % 4` of the `i`-th game.
+    pub fn one_vs_three(results: &[GameResult], config: &ReportConfig) -> Result<Self> {
+        let games = results
+            .iter()
+            .enumerate()
+            .map(|(i, result)| (result.seed, i % 4, result.scores));
+        Self::new(games, config)
+    }
+
+    /// `games` yields the seed, the challenger's seat and the final scores of
+    /// each game.
+    pub fn new<I>(games: I, config: &ReportConfig) -> Result<Self>
+    where
+        I: IntoIterator<Item = ((u64, u64), usize, [i32; 4])>,
+    {
+        ensure!(
+            config.confidence > 0. && config.confidence < 1.,
+            "confidence must be within (0, 1), got {}",
+            config.confidence,
+        );
+        let z = probit(1. - (1. - config.confidence) / 2.);
+        let pt_sum: f64 = config.pts.iter().sum();
+
+        let mut rankings = [0; 4];
+        let mut ranks = vec![];
+        let mut pts = vec![];
+        let mut by_seed: BTreeMap<_, [Option<usize>; 4]> = BTreeMap::new();
+        for (seed, seat, scores) in games {
+            ensure!(seat < 4, "invalid seat {seat}");
+            let rank = Rankings::new(scores).rank_by_player[seat] as usize;
+            by_seed.entry(seed).or_default()[seat] = Some(rank);
+            rankings[rank] += 1;
+            ranks.push(rank as f64 + 1.);
+            pts.push(config.pts[rank]);
+        }
+        let mut rank_diffs = vec![];
+        let mut pt_diffs = vec![];
+        let mut improvements = vec![];
+        for splits in by_seed.values() {
+            let Some(ranks) = splits.iter().copied().collect::<Option<Vec<_>>>() else {
+                continue;
+            };
+            let rank = ranks.iter().map(|&r| r as f64 + 1.).sum::<f64>() / 4.;
+            let pt = ranks.iter().map(|&r| config.pts[r]).sum::<f64>() / 4.;
+            // The 3 champions share the rest of ranks and pts of each game.
+            let champion_rank = (10. - rank) / 3.;
+            let champion_pt = (pt_sum - pt) / 3.;
+            rank_diffs.push(rank - champion_rank);
+            pt_diffs.push(pt - champion_pt);
+            improvements.push(2.5 - rank);
+        }
+        ensure!(
+            rank_diffs.len() >= 2,
+            "at least 2 seeds with all 4 splits finished are required, got {}",
+            rank_diffs.len(),
+        );
+
+        Ok(Self {
+            games: ranks.len(),
+            seeds: rank_diffs.len(),
+            rankings,
+            mean_rank: Estimate::new(&ranks, z)?,
+            mean_pt: Estimate::new(&pts, z)?,
+            rank_diff: Estimate::new(&rank_diffs, z)?,
+            pt_diff: Estimate::new(&pt_diffs, z)?,
+            sprt: Sprt::new(&improvements, config),
+        })
+    }
+}
+
+#[pymethods]
+impl ArenaReport {
+    /// Builds the report from the manifest written by a 1v3 run with
+    /// `log_dir`, which includes resumed runs. Only the games where
+    /// `challenger` sits at the seat of the split against 3 `champion`s are
+    /// counted, and if `key` is given, only those with that seed key.
+    #[staticmethod]
+    #[pyo3(signature = (
+        log_dir,
+        challenger,
+        champion,
+        *,
+        key = None,
+        pts = [90., 45., 0., -135.],
+        confidence = 0.95,
+        delta = 0.02,
+        alpha = 0.05,
+        beta = 0.05,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_log_dir(
+        log_dir: &str,
+        challenger: &str,
+        champion: &str,
+        key: Option<u64>,
+        pts: [f64; 4],
+        confidence: f64,
+        delta: f64,
+        alpha: f64,
+        beta: f64,
+    ) -> Result<Self> {
+        let config = ReportConfig {
+            pts,
+            confidence,
+            delta,
+            alpha,
+            beta,
+        };
+        let mut games = vec![];
+        for entry in checkpoint::read_manifest(log_dir)? {
+            if key.is_some_and(|key| entry.seed.1 != key) {
+                continue;
+            }
+            let seat = entry.split()? % 4;
+            let lineup_matches = entry.names.iter().enumerate().all(|(i, name)| {
+                if i == seat {
+                    name == challenger
+                } else {
+                    name == champion
+                }
+            });
+            if lineup_matches {
+                games.push((entry.seed, seat, entry.scores));
+            }
+        }
+        Self::new(games, &config)
+    }
+
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.6} ± {:.6} [{:.6}, {:.6}]",
+            self.mean, self.std_err, self.low, self.high,
+        )
+    }
+}
+
+impl fmt::Display for ArenaReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            r#"Games            {}
+Paired seeds     {}
+Rankings         {:?}
+Avg rank         {}
+Avg pt           {}
+Rank diff        {}
+Pt diff          {}
+SPRT             {} (llr {:.4}, bounds [{:.4}, {:.4}])"#,
+            self.games,
+            self.seeds,
+            self.rankings,
+            self.mean_rank,
+            self.mean_pt,
+            self.rank_diff,
+            self.pt_diff,
+            self.sprt.decision(),
+            self.sprt.llr,
+            self.sprt.lower,
+            self.sprt.upper,
+        )
+    }
+}
+
+/// Inverse of the standard normal CDF, using Acklam's rational approximation,
+/// whose relative error is below 1.15e-9.
+fn probit(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 6] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+        1.,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 5] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+        1.,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    // Evaluates the polynomial with the coefficients from the highest order.
+    let poly = |coeffs: &[f64], x: f64| coeffs.iter().fold(0., |acc: f64, &c| acc.mul_add(x, c));
+    let tail = |q: f64| poly(&C, q) / poly(&D, q);
+    if p < P_LOW {
+        tail((-2. * p.ln()).sqrt())
+    } else if p <= 1. - P_LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        poly(&A, r) * q / poly(&B, r)
+    } else {
+        -tail((-2. * (1. - p).ln()).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::checkpoint::Entry;
+    use crate::rule::RuleSet;
+    use std::{array, env, fs};
+
+    #[test]
+    fn probit_values() {
+        assert!((probit(0.5)).abs() < 1e-9);
+        assert!((probit(0.975) - 1.959_963_985).abs() < 1e-6);
+        assert!((probit(0.005) + 2.575_829_304).abs() < 1e-6);
+    }
+
+    fn scores_with_rank(seat: usize, rank: usize) -> [i32; 4] {
+        const SCORES: [i32; 4] = [40000, 30000, 20000, 10000];
+        let mut rest = (0..4).filter(|&r| r != rank).map(|r| SCORES[r]);
+        array::from_fn(|s| {
+            if s == seat {
+                SCORES[rank]
+            } else {
+                rest.next().unwrap()
+            }
+        })
+    }
+
+    #[test]
+    fn paired_seeds() {
+        // The challenger gets 2nd in every split of the first seed, and
+        // alternates between 1st and 3rd in the others, so every game is
+        // noisy but every seed averages to exactly the same rank.
+        let mut games = vec![];
+        for seed in 0..100 {
+            for seat in 0..4 {
+                let rank = if seed == 0 {
+                    1
+                } else {
+                    [0, 2][(seed + seat) % 2]
+                };
+                games.push(((seed as u64, 0), seat, scores_with_rank(seat, rank)));
+            }
+        }
+        // Leave the last seed incomplete.
+        games.pop();
+
+        let report = ArenaReport::new(games, &ReportConfig::default()).unwrap();
+        assert_eq!(report.games, 399);
+        assert_eq!(report.seeds, 99);
+        assert_eq!(report.rankings[1], 4);
+        assert!((report.mean_rank.mean - 2.).abs() < 0.01);
+        assert!(report.mean_rank.std_err > 0.04);
+        assert!(report.rank_diff.std_err < 1e-9);
+        assert!((report.rank_diff.mean - (2. - 8. / 3.)).abs() < 1e-9);
+        // No variance between seeds, no decision.
+        assert_eq!(report.sprt.decision, Decision::Continue);
+
+        // A single seed has no variance to estimate.
+        let games = (0..4).map(|seat| ((0, 0), seat, scores_with_rank(seat, 0)));
+        ArenaReport::new(games, &ReportConfig::default()).unwrap_err();
+    }
+
+    #[test]
+    fn from_log_dir() {
+        let log_dir = env::temp_dir().join(format!("riichi-report-{}", std::process::id()));
+        fs::create_dir_all(&log_dir).unwrap();
+        let mut manifest = String::new();
+        for seed in 0..3 {
+            for split in 0..4 {
+                let rank = [0, 2][(seed + split) % 2];
+                let mut names = ["mortal", "mortal", "mortal", "mortal"].map(String::from);
+                names[split] = "challenger".to_owned();
+                let mut entry = serde_json::to_value(Entry {
+                    file: checkpoint::log_filename((seed as u64, 7), split),
+                    seed: (seed as u64, 7),
+                    names,
+                    scores: scores_with_rank(split, rank),
+                    rule: RuleSet::tenhou(),
+                })
+                .unwrap();
+                // Games of another lineup are left out.
+                if seed == 2 {
+                    entry["names"][split] = "mortal".into();
+                }
+                manifest.push_str(&entry.to_string());
+                manifest.push('\n');
+            }
+        }
+        fs::write(log_dir.join("manifest.jsonl"), manifest).unwrap();
+
+        let from_log_dir = |challenger, key| {
+            ArenaReport::from_log_dir(
+                log_dir.to_str().unwrap(),
+                challenger,
+                "mortal",
+                key,
+                [90., 45., 0., -135.],
+                0.95,
+                0.02,
+                0.05,
+                0.05,
+            )
+        };
+        let report = from_log_dir("challenger", None).unwrap();
+        assert_eq!(report.games, 8);
+        assert_eq!(report.seeds, 2);
+        from_log_dir("challenger", Some(8)).unwrap_err();
+        from_log_dir("someone", None).unwrap_err();
+
+        fs::remove_dir_all(&log_dir).unwrap();
+    }
+
+    #[test]
+    fn sprt() {
+        let config = ReportConfig::default();
+        let noise = [0.4, -0.4, 0.2, -0.2];
+        let improvements: Vec<_> = (0..4000).map(|i| 0.05 + noise[i % 4]).collect();
+        assert_eq!(
+            Sprt::new(&improvements, &config).decision,
+            Decision::AcceptH1
+        );
+        let improvements: Vec<_> = (0..4000).map(|i| -0.02 + noise[i % 4]).collect();
+        assert_eq!(
+            Sprt::new(&improvements, &config).decision,
+            Decision::AcceptH0
+        );
+        let improvements: Vec<_> = (0..10).map(|i| 0.01 + noise[i % 4]).collect();
+        assert_eq!(
+            Sprt::new(&improvements, &config).decision,
+            Decision::Continue
+        );
+    }
+}
@@ -1,5 +1,6 @@
-use crate::mjai::{Event, EventExt};
+use crate::mjai::{Event, EventExt, convert};
 use crate::rankings::Rankings;
+use crate::rule::RuleSet;
 
 use anyhow::Result;
 use serde_json as json;
@@ -20,19 +21,25 @@ pub struct GameResult {
     pub names: [String; 4],
     pub scores: [i32; 4],
     pub seed: (u64, u64),
+    pub rule: RuleSet,
     pub game_log: Vec<Vec<EventExt>>,
 }
 
 impl GameResult {
     #[inline]
     pub fn rankings(&self) -> Rankings {
-        Rankings::new(self.scores)
+        if self.rule.sanma {
+            Rankings::new_sanma(self.scores)
+        } else {
+            Rankings::new(self.scores)
+        }
     }
 
     pub fn dump_json_log(&self) -> Result<String> {
         let mut v = vec![];
 
         let start_game = Event::StartGame {
+            id: None,
             names: self.names.clone(),
             seed: Some(self.seed),
         };
@@ -49,4 +56,17 @@ impl GameResult {
 
         Ok(String::from_utf8(v)?)
     }
+
+    /// Dumps the log in the JSON format of tenhou.net/6, for Tenhou's replay
+    /// viewer. Horas and ryukyokus are recalculated under `self.rule`.
+    pub fn dump_tenhou6_log(&self) -> Result<String> {
+        let mut events = vec![Event::StartGame {
+            id: None,
+            names: self.names.clone(),
+            seed: Some(self.seed),
+        }];
+        events.extend(self.game_log.iter().flatten().map(|ev| ev.event.clone()));
+        events.push(Event::EndGame);
+        convert::to_tenhou6_with_rule(&events, self.rule)
+    }
 }
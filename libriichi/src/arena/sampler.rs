@@ -0,0 +1,680 @@
+use super::board::{Board, BoardState, Poll, tiles_of};
+use super::replay::logged_reactions;
+use crate::algo::danger::{Danger, Opponent, WaitWeights};
+use crate::algo::shanten;
+use crate::mjai::{Event, EventExt};
+use crate::rule::RuleSet;
+use crate::tile::Tile;
+use crate::{matches_tu8, t, tu8};
+use std::array;
+
+use anyhow::{Context, Result, bail, ensure};
+use derivative::Derivative;
+use rand::prelude::*;
+
+/// Number of attempts to deal a tenpai hand to an opponent in riichi.
+const MAX_RIICHI_TRIES: usize = 100;
+
+/// Samples complete worlds consistent with what a player has seen in the
+/// current kyoku, which are the concealed hands of the opponents and the
+/// tiles left in the walls.
+///
+/// The hidden tiles are dealt uniformly at random from the ones not seen,
+/// except for the opponents in riichi, who are always dealt a tenpai hand that
+/// cannot win with any of their genbutsu, as they would have won with them
+/// otherwise. Their winning tile is weighted by `Danger::wait_probs` if a
+/// danger prior is given, or uniform otherwise. As such the samples are only
+/// approximately distributed as the true posterior.
+pub struct Sampler {
+    rule: RuleSet,
+    player_id: u8,
+    /// Masked for `player_id`, starting from `start_kyoku`.
+    log: Vec<Event>,
+    tracker: Tracker,
+    danger_prior: Option<WaitWeights>,
+}
+
+/// A sampled world at the end of the log. Walls are sorted early -> late, as
+/// in `Invisible`.
+#[derive(Debug, Clone)]
+pub struct World {
+    /// Concealed hands by absolute seat, with the one of the player being the
+    /// real one. It is empty for the vacant seat in sanma.
+    pub tehais: [Vec<Tile>; 4],
+    /// Tiles left in yama, the next tsumo first.
+    pub yama: Vec<Tile>,
+    /// Rinshan tiles left, the next rinshan tsumo first.
+    pub rinshan: Vec<Tile>,
+    /// Dora indicators not revealed yet.
+    pub dora_indicators: Vec<Tile>,
+    pub ura_indicators: Vec<Tile>,
+    /// The board at the start of the kyoku that leads to this world.
+    pub board: Board,
+}
+
+/// Tracks where each tile dealt so far comes from, with the hidden ones being
+/// `None` until they leave the hand publicly.
+#[derive(Derivative)]
+#[derivative(Default)]
+struct Tracker {
+    player_id: u8,
+    /// Every haipai and tsumo dealt in the kyoku.
+    tiles: Vec<Option<Tile>>,
+    haipai: [Vec<usize>; 4],
+    /// Indexes of `tiles` that are still in each hand.
+    hands: [Vec<usize>; 4],
+    last_tsumo: [Option<usize>; 4],
+    draws: Vec<usize>,
+    rinshan_draws: Vec<usize>,
+    deal_from_rinshan: bool,
+    dora_indicators: Vec<Tile>,
+
+    discards: [Vec<Tile>; 4],
+    #[derivative(Default(value = "[[false; 34]; 4]"))]
+    genbutsu: [[bool; 34]; 4],
+    riichi_declared: [bool; 4],
+    riichi_accepted: [bool; 4],
+    /// Index of the riichi declaration in `discards`.
+    riichi_idxs: [Option<usize>; 4],
+    /// Indexes of `tiles` in the hand right after the riichi discard.
+    riichi_hands: [Option<Vec<usize>>; 4],
+}
+
+impl Sampler {
+    /// `log` must end at a point where `player_id` can act, and only its
+    /// last kyoku is taken into account.
+    pub fn new(
+        log: &[EventExt],
+        player_id: u8,
+        rule: RuleSet,
+        danger_prior: Option<WaitWeights>,
+    ) -> Result<Self> {
+        let n = rule.player_count();
+        ensure!(player_id < n, "seat {player_id} is out of range");
+        let start = log
+            .iter()
+            .rposition(|ev| matches!(ev.event, Event::StartKyoku { .. }))
+            .context("missing start_kyoku")?;
+        let log: Vec<_> = log[start..]
+            .iter()
+            .map(|ev| ev.event.masked(player_id))
+            .collect();
+
+        let Event::StartKyoku {
+            bakaze, kyoku, oya, ..
+        } = log[0]
+        else {
+            unreachable!();
+        };
+        ensure!(
+            (1..=n).contains(&kyoku)
+                && oya == kyoku - 1
+                && matches_tu8!(bakaze.as_u8(), E | S | W | N),
+            "invalid start_kyoku {bakaze}{kyoku} with oya {oya}",
+        );
+
+        let mut tracker = Tracker {
+            player_id,
+            ..Default::default()
+        };
+        for ev in &log {
+            tracker.update(ev, n)?;
+        }
+
+        Ok(Self {
+            rule,
+            player_id,
+            log,
+            tracker,
+            danger_prior,
+        })
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<World> {
+        let rule = self.rule;
+        let n = rule.player_count();
+        let tracker = &self.tracker;
+        let mut tiles = tracker.tiles.clone();
+
+        let mut pool = tiles_of(rule);
+        for &tile in tiles.iter().flatten().chain(&tracker.dora_indicators) {
+            let pos = pool
+                .iter()
+                .position(|&t| t == tile)
+                .with_context(|| format!("too many {tile} seen"))?;
+            pool.swap_remove(pos);
+        }
+
+        for seat in 0..n as usize {
+            if let Some(hand) = &tracker.riichi_hands[seat] {
+                self.deal_riichi_hand(seat, hand, &mut tiles, &mut pool, rng)?;
+            }
+        }
+        pool.shuffle(rng);
+
+        let front_rinshan_draws = tracker.rinshan_draws.len().saturating_sub(4);
+        ensure!(
+            front_rinshan_draws == 0 || rule.sanma,
+            "more than 4 rinshan tsumos",
+        );
+        let yama_len = rule.initial_tiles_left() as usize;
+        let yama_hidden = yama_len
+            .checked_sub(tracker.draws.len() + front_rinshan_draws)
+            .context("too many tsumos")?;
+        let rinshan_hidden = 4 - tracker.rinshan_draws.len().min(4);
+        let dora_hidden = 5_usize
+            .checked_sub(tracker.dora_indicators.len())
+            .context("too many dora indicators")?;
+        let hidden_tiles = tiles.iter().filter(|t| t.is_none()).count();
+        ensure!(
+            pool.len() == hidden_tiles + yama_hidden + rinshan_hidden + dora_hidden + 5,
+            "the number of unseen tiles does not add up",
+        );
+        for tile in tiles.iter_mut().filter(|t| t.is_none()) {
+            *tile = pool.pop();
+        }
+        let tiles: Vec<_> = tiles.into_iter().flatten().collect();
+
+        let mut yama: Vec<_> = tracker.rinshan_draws[4.min(tracker.rinshan_draws.len())..]
+            .iter()
+            .map(|&i| tiles[i])
+            .collect();
+        yama.extend(pool.drain(..yama_hidden));
+        yama.extend(tracker.draws.iter().rev().map(|&i| tiles[i]));
+
+        let mut rinshan: Vec<_> = pool.drain(..rinshan_hidden).collect();
+        rinshan.extend(
+            tracker
+                .rinshan_draws
+                .iter()
+                .take(4)
+                .rev()
+                .map(|&i| tiles[i]),
+        );
+
+        let mut dora_indicators: Vec<_> = pool.drain(..dora_hidden).collect();
+        dora_indicators.extend(tracker.dora_indicators.iter().rev());
+
+        let Event::StartKyoku {
+            bakaze,
+            kyoku,
+            honba,
+            kyotaku,
+            scores,
+            ..
+        } = self.log[0]
+        else {
+            unreachable!();
+        };
+        let board = Board {
+            rule,
+            kyoku: (bakaze.as_u8() - tu8!(E)) * n + kyoku - 1,
+            honba,
+            kyotaku,
+            scores,
+            haipai: array::from_fn(|seat| {
+                if (seat as u8) < n {
+                    array::from_fn(|i| tiles[tracker.haipai[seat][i]])
+                } else {
+                    [t!(?); 13]
+                }
+            }),
+            yama,
+            rinshan,
+            dora_indicators,
+            ura_indicators: pool,
+        };
+
+        let yama_left = &board.yama[front_rinshan_draws..front_rinshan_draws + yama_hidden];
+        Ok(World {
+            tehais: array::from_fn(|seat| tracker.hands[seat].iter().map(|&i| tiles[i]).collect()),
+            yama: yama_left.iter().rev().copied().collect(),
+            rinshan: board.rinshan[..rinshan_hidden]
+                .iter()
+                .rev()
+                .copied()
+                .collect(),
+            dora_indicators: board.dora_indicators[..dora_hidden]
+                .iter()
+                .rev()
+                .copied()
+                .collect(),
+            ura_indicators: board.ura_indicators.clone(),
+            board,
+        })
+    }
+
+    /// Samples a world and replays its board up to the end of the log,
+    /// checking that everything visible to the player is identical. The
+    /// returned board is waiting for the reactions to the last event.
+    pub fn sample_state<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<BoardState> {
+        let mut board = self.sample(rng)?.board.into_state();
+
+        let upcoming: Vec<_> = self.log.iter().cloned().map(EventExt::no_meta).collect();
+        let mut reactions = Default::default();
+        let mut pos = 0;
+        loop {
+            let poll = board.poll(reactions)?;
+            let ctx = board.agent_context();
+            for ev in &ctx.log[pos..] {
+                let expected = self
+                    .log
+                    .get(pos)
+                    .with_context(|| format!("unexpected event {pos} {:?}", ev.event))?;
+                ensure!(
+                    ev.event.masked(self.player_id) == *expected,
+                    "event {pos} is {expected:?} in the log, but {:?} in the sample",
+                    ev.event,
+                );
+                pos += 1;
+            }
+            ensure!(matches!(poll, Poll::InGame), "the sampled kyoku has ended");
+            if pos == self.log.len() {
+                let state = &ctx.player_states[self.player_id as usize];
+                ensure!(
+                    state.last_cans().can_act(),
+                    "player {} cannot act at the end of the log",
+                    self.player_id,
+                );
+                return Ok(board);
+            }
+            reactions = logged_reactions(&upcoming[pos..], ctx.player_states, self.rule);
+        }
+    }
+
+    /// Deals a tenpai hand to `seat` at the time of its riichi, moving the
+    /// tiles from `pool` to the hidden slots of `hand`.
+    fn deal_riichi_hand<R: Rng + ?Sized>(
+        &self,
+        seat: usize,
+        hand: &[usize],
+        tiles: &mut [Option<Tile>],
+        pool: &mut Vec<Tile>,
+        rng: &mut R,
+    ) -> Result<()> {
+        let tracker = &self.tracker;
+        let genbutsu = &tracker.genbutsu[seat];
+        // Tiles of the riichi hand that left it later by ankan.
+        let mut fixed = [0; 34];
+        for tile in hand.iter().filter_map(|&i| tiles[i]) {
+            fixed[tile.deaka().as_usize()] += 1;
+        }
+        let mut avail = fixed;
+        for tile in &*pool {
+            avail[tile.deaka().as_usize()] += 1;
+        }
+
+        let wait_weights = if let Some(weights) = &self.danger_prior {
+            let opponent = Opponent {
+                discards: &tracker.discards[seat],
+                riichi_idx: tracker.riichi_idxs[seat],
+                genbutsu,
+            };
+            Danger::new(&opponent, &tracker.tiles_seen(), weights).wait_probs
+        } else {
+            array::from_fn(|tid| if genbutsu[tid] { 0. } else { 1. })
+        };
+        let tids: Vec<_> = (0..34).filter(|&tid| avail[tid] > 0).collect();
+
+        let len_div3 = (hand.len() / 3) as u8;
+        let tehai = (0..MAX_RIICHI_TRIES)
+            .filter_map(|_| {
+                let &winning = tids.choose_weighted(rng, |&tid| wait_weights[tid]).ok()?;
+                tenpai_hand(&fixed, &avail, hand.len(), winning, rng)
+            })
+            .find(|tehai| {
+                shanten::calc_all(tehai, len_div3) == 0
+                    && (0..34).filter(|&tid| genbutsu[tid]).all(|tid| {
+                        let mut tehai = *tehai;
+                        tehai[tid] += 1;
+                        tehai[tid] > 4 || shanten::calc_all(&tehai, len_div3) > -1
+                    })
+            })
+            .with_context(|| format!("failed to deal a tenpai hand to {seat} in riichi"))?;
+
+        let mut hidden = hand.iter().filter(|&&i| tiles[i].is_none()).copied();
+        let mut dealt = vec![];
+        for tid in 0..34 {
+            for _ in fixed[tid]..tehai[tid] {
+                let candidates: Vec<_> = (0..pool.len())
+                    .filter(|&i| pool[i].deaka().as_usize() == tid)
+                    .collect();
+                let &pos = candidates.choose(rng).context("tile not in pool")?;
+                let slot = hidden.next().context("too many tiles dealt")?;
+                dealt.push((slot, pool.swap_remove(pos)));
+            }
+        }
+        ensure!(hidden.next().is_none(), "too few tiles dealt");
+        for (slot, tile) in dealt {
+            tiles[slot] = Some(tile);
+        }
+        Ok(())
+    }
+}
+
+/// Builds a random complete hand of `len + 1` tiles out of `avail` that
+/// includes `fixed` and `winning`, and returns it without `winning`.
+///
+/// Only the standard form is considered, and `fixed` must consist of
+/// triplets.
+fn tenpai_hand<R: Rng + ?Sized>(
+    fixed: &[u8; 34],
+    avail: &[u8; 34],
+    len: usize,
+    winning: usize,
+    rng: &mut R,
+) -> Option<[u8; 34]> {
+    let mut tehai = [0; 34];
+    let mut left = *avail;
+    let mut groups = (len + 1) / 3;
+    let mut take = |tehai: &mut [u8; 34], tids: &[usize]| {
+        let mut needed = [0; 34];
+        for &tid in tids {
+            needed[tid] += 1;
+        }
+        if (0..34).any(|tid| needed[tid] > left[tid]) {
+            return false;
+        }
+        for &tid in tids {
+            left[tid] -= 1;
+            tehai[tid] += 1;
+        }
+        true
+    };
+
+    for (tid, &count) in fixed.iter().enumerate() {
+        match count {
+            0 => (),
+            3 if groups > 0 => {
+                take(&mut tehai, &[tid; 3]);
+                groups -= 1;
+            }
+            _ => return None,
+        }
+    }
+
+    // The shapes that the winning tile completes, which are a group or the
+    // pair.
+    let mut shapes = vec![];
+    if groups > 0 {
+        shapes.push(vec![winning; 3]);
+        if winning < 27 {
+            let num = winning % 9;
+            for lo in winning - num.min(2)..=winning.min(winning - num + 6) {
+                shapes.push(vec![lo, lo + 1, lo + 2]);
+            }
+        }
+    }
+    let has_pair = shapes.is_empty() || rng.random_bool(0.2);
+    if has_pair {
+        shapes = vec![vec![winning; 2]];
+    } else {
+        groups -= 1;
+    }
+    let shape = shapes.choose(rng)?;
+    if !take(&mut tehai, shape) {
+        return None;
+    }
+
+    for _ in 0..groups {
+        let added = (0..10).any(|_| {
+            let tid = rng.random_range(0..34);
+            if tid < 27 && tid % 9 <= 6 && rng.random_bool(0.75) {
+                take(&mut tehai, &[tid, tid + 1, tid + 2])
+            } else {
+                take(&mut tehai, &[tid; 3])
+            }
+        });
+        if !added {
+            return None;
+        }
+    }
+    if !has_pair && !(0..10).any(|_| take(&mut tehai, &[rng.random_range(0..34); 2])) {
+        return None;
+    }
+
+    tehai[winning] -= 1;
+    Some(tehai)
+}
+
+impl Tracker {
+    fn deal(&mut self, actor: u8, pai: Tile) -> usize {
+        let idx = self.tiles.len();
+        self.tiles.push((actor == self.player_id).then_some(pai));
+        self.hands[actor as usize].push(idx);
+        idx
+    }
+
+    /// Removes a tile from the hand of `actor`, pinning the hidden slot it
+    /// takes to `pai`.
+    fn take(&mut self, actor: u8, pai: Tile, tsumogiri: Option<bool>) -> Result<()> {
+        let hand = &mut self.hands[actor as usize];
+        let last_tsumo = self.last_tsumo[actor as usize];
+        let pos = if actor == self.player_id {
+            hand.iter().position(|&i| self.tiles[i] == Some(pai))
+        } else {
+            match tsumogiri {
+                Some(true) => last_tsumo.and_then(|t| hand.iter().position(|&i| i == t)),
+                Some(false) => hand.iter().position(|&i| Some(i) != last_tsumo),
+                None => None,
+            }
+            .or_else(|| (!hand.is_empty()).then_some(0))
+        };
+        let pos = pos.with_context(|| format!("{pai} is not in the hand of {actor}"))?;
+        let idx = hand.remove(pos);
+        self.tiles[idx] = Some(pai);
+        Ok(())
+    }
+
+    /// Tiles visible to the player, in the same meaning as
+    /// `PlayerState::tiles_seen`.
+    fn tiles_seen(&self) -> [u8; 34] {
+        let mut tiles_seen = [0; 34];
+        for tile in self.tiles.iter().flatten().chain(&self.dora_indicators) {
+            tiles_seen[tile.deaka().as_usize()] += 1;
+        }
+        tiles_seen
+    }
+
+    fn update(&mut self, ev: &Event, n: u8) -> Result<()> {
+        match *ev {
+            Event::StartKyoku {
+                dora_marker,
+                tehais,
+                ..
+            } => {
+                for seat in 0..n {
+                    for pai in tehais[seat as usize] {
+                        let idx = self.deal(seat, pai);
+                        self.haipai[seat as usize].push(idx);
+                    }
+                }
+                self.dora_indicators.push(dora_marker);
+            }
+            Event::Tsumo { actor, pai } => {
+                let idx = self.deal(actor, pai);
+                if self.deal_from_rinshan {
+                    self.deal_from_rinshan = false;
+                    self.rinshan_draws.push(idx);
+                } else {
+                    self.draws.push(idx);
+                }
+                self.last_tsumo[actor as usize] = Some(idx);
+            }
+            Event::Dahai {
+                actor,
+                pai,
+                tsumogiri,
+            } => {
+                self.take(actor, pai, Some(tsumogiri))?;
+                let actor = actor as usize;
+                self.last_tsumo[actor] = None;
+                self.discards[actor].push(pai);
+                for seat in 0..4 {
+                    if seat == actor || self.riichi_accepted[seat] {
+                        self.genbutsu[seat][pai.deaka().as_usize()] = true;
+                    }
+                }
+                if self.riichi_declared[actor] && self.riichi_hands[actor].is_none() {
+                    self.riichi_idxs[actor] = Some(self.discards[actor].len() - 1);
+                    self.riichi_hands[actor] = Some(self.hands[actor].clone());
+                }
+            }
+            Event::Chi {
+                actor, consumed, ..
+            }
+            | Event::Pon {
+                actor, consumed, ..
+            } => {
+                for pai in consumed {
+                    self.take(actor, pai, None)?;
+                }
+            }
+            Event::Daiminkan {
+                actor, consumed, ..
+            } => {
+                for pai in consumed {
+                    self.take(actor, pai, None)?;
+                }
+                self.deal_from_rinshan = true;
+            }
+            Event::Ankan { actor, consumed } => {
+                for pai in consumed {
+                    self.take(actor, pai, None)?;
+                }
+                self.last_tsumo[actor as usize] = None;
+                self.deal_from_rinshan = true;
+            }
+            Event::Kakan { actor, pai, .. } | Event::Nukidora { actor, pai } => {
+                self.take(actor, pai, None)?;
+                self.last_tsumo[actor as usize] = None;
+                self.deal_from_rinshan = true;
+            }
+            Event::Dora { dora_marker } => self.dora_indicators.push(dora_marker),
+            Event::Reach { actor } => self.riichi_declared[actor as usize] = true,
+            Event::ReachAccepted { actor } => self.riichi_accepted[actor as usize] = true,
+            _ => bail!("unexpected event in the middle of a kyoku: {ev:?}"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{ActionWeights, BatchAgent, RandomAgent};
+    use crate::arena::{BatchGame, Index};
+    use crate::state::PlayerState;
+    use crate::tuz;
+    use rand_chacha::ChaCha12Rng;
+
+    fn check_samples(game: &BatchGame) {
+        let n = game.rule.player_count();
+        let weights = ActionWeights {
+            chi: 5.,
+            pon: 5.,
+            kan: 10.,
+            ..Default::default()
+        };
+        let player_ids: Vec<_> = (0..n).collect();
+        let mut agents = [
+            Box::new(RandomAgent::new_batched(&player_ids, 0, weights).unwrap())
+                as Box<dyn BatchAgent>,
+        ];
+        let indexes = [std::array::from_fn(|i| Index {
+            agent_idx: 0,
+            player_id_idx: i % n as usize,
+        })];
+        let results = game.run(&mut agents, &indexes, &[(7, 7)]).unwrap();
+
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        let mut samples = 0;
+        for log in &results[0].game_log {
+            for player_id in 0..n {
+                let mut state = PlayerState::new_with_rule(player_id, game.rule);
+                for (i, ev) in log.iter().enumerate() {
+                    state.update(&ev.event).unwrap();
+                    if !state.last_cans().can_act() {
+                        continue;
+                    }
+                    let sampler = Sampler::new(&log[..=i], player_id, game.rule, None).unwrap();
+                    let board = sampler.sample_state(&mut rng).unwrap();
+                    let sampled = &board.agent_context().player_states[player_id as usize];
+                    assert_eq!(sampled.tehai(), state.tehai());
+                    assert_eq!(sampled.legal_reactions(), state.legal_reactions());
+                    samples += 1;
+                }
+            }
+        }
+        assert!(samples > 0);
+    }
+
+    #[test]
+    fn consistent_samples() {
+        let game = BatchGame {
+            rule: RuleSet::tenhou_tonpuu(),
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        check_samples(&game);
+        let sanma = BatchGame {
+            rule: RuleSet::tenhou_sanma(),
+            init_scores: [35000, 35000, 35000, 0],
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        check_samples(&sanma);
+    }
+
+    #[test]
+    fn riichi_hands() {
+        let log = r#"
+            {"type":"start_kyoku","bakaze":"E","dora_marker":"1s","kyoku":4,"honba":0,"kyotaku":0,"oya":3,"scores":[25000,25000,25000,25000],"tehais":[["1m","4m","7m","2p","5p","8p","2s","6s","9s","E","S","W","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"reach","actor":3}
+            {"type":"dahai","actor":3,"pai":"3m","tsumogiri":false}
+            {"type":"reach_accepted","actor":3}
+            {"type":"tsumo","actor":0,"pai":"5s"}
+            {"type":"dahai","actor":0,"pai":"N","tsumogiri":false}
+            {"type":"tsumo","actor":1,"pai":"?"}
+            {"type":"dahai","actor":1,"pai":"6m","tsumogiri":true}
+            {"type":"tsumo","actor":2,"pai":"?"}
+            {"type":"dahai","actor":2,"pai":"P","tsumogiri":false}
+            {"type":"tsumo","actor":3,"pai":"?"}
+            {"type":"dahai","actor":3,"pai":"9m","tsumogiri":true}
+            {"type":"tsumo","actor":0,"pai":"1p"}
+        "#;
+        let log: Vec<EventExt> = log
+            .trim()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let rule = RuleSet::default();
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for prior in [None, Some(WaitWeights::default())] {
+            let sampler = Sampler::new(&log, 0, rule, prior).unwrap();
+            for _ in 0..20 {
+                let world = sampler.sample(&mut rng).unwrap();
+                assert_eq!(world.tehais[0].len(), 14);
+                assert_eq!(world.yama.len(), 70 - 6);
+                assert_eq!(world.rinshan.len(), 4);
+                assert_eq!(world.dora_indicators.len(), 4);
+                assert_eq!(world.ura_indicators.len(), 5);
+
+                let mut tehai = [0; 34];
+                for tile in &world.tehais[3] {
+                    tehai[tile.deaka().as_usize()] += 1;
+                }
+                assert_eq!(shanten::calc_all(&tehai, 4), 0);
+                // Genbutsu of seat 3 can never be the winning tile.
+                for tid in [tuz!(3m), tuz!(9m), tuz!(N), tuz!(6m), tuz!(P)] {
+                    let mut tehai = tehai;
+                    tehai[tid] += 1;
+                    assert!(tehai[tid] > 4 || shanten::calc_all(&tehai, 4) > -1);
+                }
+
+                sampler.sample_state(&mut rng).unwrap();
+            }
+        }
+    }
+}
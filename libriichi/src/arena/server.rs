@@ -0,0 +1,513 @@
+use super::board::Poll;
+use super::game::GameProgress;
+use super::result::GameResult;
+use crate::agent::Tsumogiri;
+use crate::mjai::{Event, EventExt, EventWithCanAct};
+use crate::rule::RuleSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{array, mem};
+
+use anyhow::{Context, Result, ensure};
+use flate2::Compression;
+use flate2::read::GzEncoder;
+use serde::Deserialize;
+use serde_json::{self as json, json};
+
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A mjai game server which runs games on `BoardState` against remote
+/// clients.
+///
+/// The protocol follows the TCP flavor of the original mjai server, where
+/// every message is a line of JSON. Upon connection, the server sends `hello`
+/// and the client replies `join` with its name. Players are seated in the
+/// order they join, and each of them receives every event of the game from
+/// its own perspective, starting from a `start_game` carrying its `id`.
+///
+/// A client that fails to respond in time, sends an invalid reaction or
+/// disconnects is dropped, and its seat falls back to tsumogiri or pass until
+/// a client of the same name joins again, which is then resynced with the
+/// current kyoku. The replayed events are marked with `can_act: false`.
+pub struct Server {
+    /// The number of clients to wait for is specified by `rule.sanma`.
+    pub rule: RuleSet,
+    pub init_scores: [i32; 4],
+    /// Time limit for each response, also applied to the handshake.
+    pub timeout: Duration,
+    /// Whether clients respond to every message as in the original mjai
+    /// server, instead of only when they can act.
+    pub reply_all: bool,
+    pub log_dir: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Join {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+}
+
+struct Client {
+    name: String,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    /// The number of responses yet to be read.
+    pending: usize,
+}
+
+struct Session<'a> {
+    server: &'a Server,
+    listener: &'a TcpListener,
+    names: [String; 4],
+    clients: [Option<Client>; 4],
+}
+
+impl Server {
+    /// Waits for the players to join on `listener` and runs one game for each
+    /// seed, with the same seating.
+    pub fn run(&self, listener: &TcpListener, seeds: &[(u64, u64)]) -> Result<Vec<GameResult>> {
+        if let Some(dir) = &self.log_dir {
+            fs::create_dir_all(dir)?;
+        }
+        listener.set_nonblocking(true)?;
+
+        let mut session = Session {
+            server: self,
+            listener,
+            names: Default::default(),
+            clients: Default::default(),
+        };
+        session.wait_for_players()?;
+
+        seeds
+            .iter()
+            .map(|&seed| {
+                let game_result = session.run_game(seed)?;
+                log::info!("game {seed:?} ended with scores {:?}", game_result.scores,);
+
+                if let Some(dir) = &self.log_dir {
+                    let (seed, key) = game_result.seed;
+                    let filename: PathBuf =
+                        [dir, &format!("{seed}_{key}.json.gz")].iter().collect();
+
+                    let log = game_result.dump_json_log()?;
+                    let mut comp = GzEncoder::new(log.as_bytes(), Compression::best());
+                    let mut f = File::create(filename)?;
+                    io::copy(&mut comp, &mut f)?;
+                }
+
+                Ok(game_result)
+            })
+            .collect()
+    }
+}
+
+impl Session<'_> {
+    const fn player_count(&self) -> usize {
+        self.server.rule.player_count() as usize
+    }
+
+    fn wait_for_players(&mut self) -> Result<()> {
+        let mut seated = 0;
+        while seated < self.player_count() {
+            let Some(client) = self.accept()? else {
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            };
+            log::info!("{} joined as player {seated}", client.name);
+            self.names[seated].clone_from(&client.name);
+            self.clients[seated] = Some(client);
+            seated += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns a client that has finished the handshake, if any is pending.
+    fn accept(&self) -> Result<Option<Client>> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, addr)) => match Client::handshake(stream, self.server.timeout) {
+                    Ok(client) => return Ok(Some(client)),
+                    Err(err) => log::warn!("handshake with {addr} failed: {err:#}"),
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Seats the clients that join again, replacing the stale connections if
+    /// any, and resyncs them with the current kyoku.
+    fn accept_rejoins(&mut self, progress: &GameProgress, sent: &mut [usize; 4]) -> Result<()> {
+        while let Some(mut client) = self.accept()? {
+            let seat = (0..self.player_count())
+                .filter(|&i| self.names[i] == client.name)
+                .min_by_key(|&i| self.clients[i].is_some());
+            let Some(seat) = seat else {
+                log::warn!("rejected {}: not a player of this game", client.name);
+                let msg = json!({"type": "error", "message": "not a player of this game"});
+                client.send_json(&msg, false).ok();
+                continue;
+            };
+
+            // The replayed events are marked with `can_act: false` so that the
+            // client will not react to a decision point which has passed.
+            let log = progress.board().agent_context().log;
+            let resync = client
+                .send(&self.start_game(seat), self.server.reply_all)
+                .and_then(|_| {
+                    log.iter().try_for_each(|ev| {
+                        let replayed = EventWithCanAct {
+                            event: ev.event.masked(seat as u8),
+                            can_act: Some(false),
+                        };
+                        client.send_json(&replayed, self.server.reply_all)
+                    })
+                });
+            if let Err(err) = resync {
+                log::warn!("failed to resync player {seat} ({}): {err:#}", client.name);
+                continue;
+            }
+
+            log::info!("{} rejoined as player {seat}", client.name);
+            self.clients[seat] = Some(client);
+            sent[seat] = log.len();
+        }
+        Ok(())
+    }
+
+    fn run_game(&mut self, seed: (u64, u64)) -> Result<GameResult> {
+        let n = self.player_count();
+        let reply_all = self.server.reply_all;
+        let mut progress = GameProgress::new(self.server.rule, self.server.init_scores, seed);
+
+        for seat in 0..n {
+            let start_game = self.start_game(seat);
+            self.with_client(seat, |c| c.send(&start_game, reply_all));
+        }
+
+        // The number of events in the current kyoku sent to each player.
+        let mut sent = [0; 4];
+        let mut reactions: [EventExt; 4] = Default::default();
+        loop {
+            self.accept_rejoins(&progress, &mut sent)?;
+
+            let poll = progress.poll(mem::take(&mut reactions))?;
+            let ctx = progress.board().agent_context();
+            let log = match poll {
+                Poll::InGame => ctx.log,
+                Poll::End => progress.game_log().last().map_or(&[][..], |l| l),
+            };
+            let can_act: [bool; 4] = array::from_fn(|seat| {
+                matches!(poll, Poll::InGame) && ctx.player_states[seat].last_cans().can_act()
+            });
+
+            for seat in 0..n {
+                let events = &log[sent[seat]..];
+                self.with_client(seat, |c| {
+                    events.iter().enumerate().try_for_each(|(i, ev)| {
+                        let is_last = i == events.len() - 1;
+                        let masked = ev.event.masked(seat as u8);
+                        c.send(&masked, reply_all || can_act[seat] && is_last)
+                    })
+                });
+                sent[seat] = log.len();
+            }
+
+            if matches!(poll, Poll::End) {
+                sent = [0; 4];
+                if progress.has_ended() {
+                    break;
+                }
+            }
+
+            let deadline = Instant::now() + self.server.timeout;
+            for seat in 0..n {
+                let response = self.with_client(seat, |c| c.recv(deadline)).flatten();
+                if !can_act[seat] {
+                    continue;
+                }
+
+                let state = &ctx.player_states[seat];
+                let reaction = response.and_then(|ev| {
+                    let validated = state
+                        .validate_reaction(&ev.event)
+                        .with_context(|| format!("invalid reaction {:?}", ev.event));
+                    self.with_client(seat, |_| validated.map(|_| ev))
+                });
+                reactions[seat] = reaction.unwrap_or_else(|| Tsumogiri::reaction(state).into());
+            }
+        }
+
+        // The responses to `end_game` do not matter.
+        for seat in 0..n {
+            self.with_client(seat, |c| c.send(&Event::EndGame, reply_all));
+        }
+        let deadline = Instant::now() + self.server.timeout;
+        for seat in 0..n {
+            self.with_client(seat, |c| c.recv(deadline));
+        }
+
+        Ok(progress.finish(self.names.clone()))
+    }
+
+    fn start_game(&self, seat: usize) -> Event {
+        Event::StartGame {
+            id: Some(seat as u8),
+            names: self.names.clone(),
+            seed: None,
+        }
+    }
+
+    /// Runs `f` on the client of `seat` if it is connected, and drops the
+    /// client on error.
+    fn with_client<T, F>(&mut self, seat: usize, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut Client) -> Result<T>,
+    {
+        let client = self.clients[seat].as_mut()?;
+        match f(client) {
+            Ok(v) => Some(v),
+            Err(err) => {
+                log::warn!("dropped player {seat} ({}): {err:#}", client.name);
+                self.clients[seat] = None;
+                None
+            }
+        }
+    }
+}
+
+impl Client {
+    fn handshake(stream: TcpStream, timeout: Duration) -> Result<Self> {
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let mut client = Self {
+            name: String::new(),
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            pending: 0,
+        };
+        let hello = json!({"type": "hello", "protocol": "mjsonp", "protocol_version": 3});
+        client.send_json(&hello, false)?;
+
+        let line = client.read_line(Instant::now() + timeout)?;
+        let join: Join =
+            json::from_str(&line).with_context(|| format!("failed to parse join {line}"))?;
+        ensure!(join.kind == "join", "expected join, got {line}");
+        client.name = join.name;
+
+        Ok(client)
+    }
+
+    fn send(&mut self, ev: &Event, expects_response: bool) -> Result<()> {
+        self.send_json(ev, expects_response)
+    }
+
+    fn send_json<T: serde::Serialize>(&mut self, value: &T, expects_response: bool) -> Result<()> {
+        let mut buf = json::to_vec(value)?;
+        buf.push(b'\n');
+        self.writer.write_all(&buf).context("failed to send")?;
+        if expects_response {
+            self.pending += 1;
+        }
+        Ok(())
+    }
+
+    /// Reads all the pending responses and returns the last one.
+    fn recv(&mut self, deadline: Instant) -> Result<Option<EventExt>> {
+        let mut ret = None;
+        while self.pending > 0 {
+            let line = self.read_line(deadline)?;
+            self.pending -= 1;
+            let ev = json::from_str(&line)
+                .with_context(|| format!("failed to parse response {}", line.trim()))?;
+            ret = Some(ev);
+        }
+        Ok(ret)
+    }
+
+    fn read_line(&mut self, deadline: Instant) -> Result<String> {
+        let timeout = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())
+            .context("timed out")?;
+        self.reader.get_ref().set_read_timeout(Some(timeout))?;
+
+        let mut line = String::new();
+        let size = self
+            .reader
+            .read_line(&mut line)
+            .context("failed to receive")?;
+        ensure!(size > 0, "connection closed");
+        Ok(line)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::Agent;
+    use crate::state::PlayerState;
+    use std::net::SocketAddr;
+
+    /// A stand-in mjai client which plays tsumogiri. It stops responding
+    /// after `silent_after` lines, or disconnects after `drop_after` lines.
+    /// Returns the events received.
+    fn stand_in(
+        addr: SocketAddr,
+        name: &str,
+        reply_all: bool,
+        silent_after: Option<usize>,
+        drop_after: Option<usize>,
+    ) -> Result<Vec<Event>> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let join = json!({"type": "join", "name": name});
+        writer.write_all(format!("{join}\n").as_bytes())?;
+
+        let mut agent = Tsumogiri(0);
+        let mut state = PlayerState::new(0);
+        let mut events = vec![];
+        loop {
+            if drop_after.is_some_and(|n| events.len() >= n) {
+                break;
+            }
+
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            let EventWithCanAct { event: ev, can_act } = json::from_str(&line)?;
+            if let Event::StartGame { id: Some(id), .. } = ev {
+                agent = Tsumogiri(id);
+                state = PlayerState::new(id);
+            }
+            let cans = state.update(&ev)?;
+            let is_end_game = matches!(ev, Event::EndGame);
+            events.push(ev);
+
+            if silent_after.is_some_and(|n| events.len() >= n) {
+                continue;
+            }
+            if reply_all || can_act != Some(false) && cans.can_act() {
+                let reaction = agent.react(&[], &state, None)?;
+                let reaction = json::to_string(&reaction)?;
+                writer.write_all(format!("{reaction}\n").as_bytes())?;
+            }
+            if is_end_game {
+                break;
+            }
+        }
+
+        Ok(events)
+    }
+
+    fn server(reply_all: bool, timeout: Duration) -> Server {
+        Server {
+            rule: RuleSet::tenhou(),
+            init_scores: [25000; 4],
+            timeout,
+            reply_all,
+            log_dir: None,
+        }
+    }
+
+    #[test]
+    fn stand_in_clients() {
+        for reply_all in [false, true] {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            let names = ["a", "b", "c", "d"];
+
+            let results = thread::scope(|s| {
+                let clients = names.map(|name| {
+                    s.spawn(move || stand_in(addr, name, reply_all, None, None).unwrap())
+                });
+                let server = server(reply_all, Duration::from_secs(10));
+                let results = server.run(&listener, &[(1009, 0), (1021, 0)]).unwrap();
+
+                for client in clients {
+                    let events = client.join().unwrap();
+                    let start_games = events
+                        .iter()
+                        .filter(|ev| matches!(ev, Event::StartGame { .. }))
+                        .count();
+                    assert_eq!(start_games, 1);
+                    assert!(matches!(events.last(), Some(Event::EndGame)));
+                }
+                results
+            });
+
+            assert_eq!(results.len(), 2);
+            for result in &results {
+                assert_eq!(result.scores.iter().sum::<i32>(), 100000);
+                let mut seated = result.names.clone();
+                seated.sort();
+                assert_eq!(seated, names.map(|s| s.to_owned()));
+            }
+        }
+    }
+
+    #[test]
+    fn timeout_and_rejoin() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (result, rejoined) = thread::scope(|s| {
+            let normal = ["a", "b"]
+                .map(|name| s.spawn(move || stand_in(addr, name, false, None, None).unwrap()));
+            let silent = s.spawn(move || stand_in(addr, "c", false, Some(20), None).unwrap());
+            let dropped = s.spawn(move || {
+                let first = stand_in(addr, "d", false, None, Some(20)).unwrap();
+                let second = stand_in(addr, "d", false, None, None).unwrap();
+                (first, second)
+            });
+
+            let server = server(false, Duration::from_millis(200));
+            let result = server.run(&listener, &[(1009, 0)]).unwrap().remove(0);
+
+            for client in normal {
+                client.join().unwrap();
+            }
+            silent.join().unwrap();
+            let (_, rejoined) = dropped.join().unwrap();
+            (result, rejoined)
+        });
+
+        assert_eq!(result.scores.iter().sum::<i32>(), 100000);
+
+        let Some(Event::StartGame { id: Some(id), .. }) = rejoined.first() else {
+            panic!("expected start_game, got {:?}", rejoined.first());
+        };
+        assert!(matches!(rejoined.last(), Some(Event::EndGame)));
+
+        let player_id = *id;
+        for ev in &rejoined {
+            match ev {
+                Event::StartKyoku { tehais, .. } => {
+                    for (i, tehai) in tehais.iter().enumerate() {
+                        let masked = tehai.iter().all(|t| t.is_unknown());
+                        assert_eq!(masked, i != player_id as usize);
+                    }
+                }
+                Event::Tsumo { actor, pai } => {
+                    assert_eq!(pai.is_unknown(), *actor != player_id);
+                }
+                _ => (),
+            }
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use super::checkpoint;
+use super::game::{BatchGame, Index};
+use super::result::GameResult;
+use crate::agent::{
+    BatchAgent, RuleBasedAgent, RuleBasedConfig, SubprocessConfig, SubprocessMjaiAgent,
+    new_py_agent,
+};
+use crate::rating::Ledger;
+use crate::rule::RuleSet;
+use std::array;
+use std::iter;
+
+use anyhow::{Result, bail, ensure};
+use pyo3::prelude::*;
+
+/// Constructs the agent that plays as all of the given player IDs, which are
+/// listed in the order of games.
+pub type NewAgent<'a> = Box<dyn FnOnce(&[u8]) -> Result<Box<dyn BatchAgent>> + 'a>;
+
+/// How agents are seated in the games of each seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Seating {
+    /// Exactly 4 agents, rotated through every seat, 4 games per seed.
+    FourWay,
+    /// Every combination of 4 out of all agents, each rotated through every
+    /// seat like `FourWay`, `C(n, 4) * 4` games per seed.
+    RoundRobin,
+    /// Exactly 4 agents in every permutation of seats, 24 games per seed.
+    Permutations,
+    /// The given lineups, each being the agent index of every seat. An agent
+    /// may take multiple seats in a lineup.
+    Custom(Vec<[usize; 4]>),
+}
+
+/// Runs games among any number of agents, according to a [`Seating`].
+#[pyclass]
+#[derive(Clone)]
+pub struct Arena {
+    pub seating: Seating,
+    pub disable_progress_bar: bool,
+    /// See `BatchGame::log_dir`. Rerunning with the same directory and seeds
+    /// resumes an interrupted run.
+    pub log_dir: Option<String>,
+    /// 8 for hanchan and 4 for tonpuu.
+    pub game_length: u8,
+    pub init_scores: [i32; 4],
+    /// Whether to extend into 西入 (南入 for tonpuu) sudden death.
+    pub west_round: bool,
+    /// Path of the rating ledger to record every finished game into.
+    pub ledger: Option<String>,
+}
+
+impl Seating {
+    /// Returns the lineups to play for each seed.
+    pub fn lineups(&self, agent_count: usize) -> Result<Vec<[usize; 4]>> {
+        let lineups: Vec<_> = match self {
+            Self::FourWay => {
+                ensure!(
+                    agent_count == 4,
+                    "four-way seating requires exactly 4 agents, got {agent_count}",
+                );
+                rotations([0, 1, 2, 3]).collect()
+            }
+            Self::RoundRobin => {
+                ensure!(
+                    agent_count >= 4,
+                    "round-robin seating requires at least 4 agents, got {agent_count}",
+                );
+                (0..agent_count)
+                    .flat_map(|a| {
+                        (a + 1..agent_count).flat_map(move |b| {
+                            (b + 1..agent_count)
+                                .flat_map(move |c| (c + 1..agent_count).map(move |d| [a, b, c, d]))
+                        })
+                    })
+                    .flat_map(rotations)
+                    .collect()
+            }
+            Self::Permutations => {
+                ensure!(
+                    agent_count == 4,
+                    "permutation seating requires exactly 4 agents, got {agent_count}",
+                );
+                (0..4)
+                    .flat_map(|a| {
+                        (0..4).flat_map(move |b| {
+                            (0..4).flat_map(move |c| (0..4).map(move |d| [a, b, c, d]))
+                        })
+                    })
+                    .filter(|l| l.iter().enumerate().all(|(i, a)| !l[..i].contains(a)))
+                    .collect()
+            }
+            Self::Custom(lineups) => {
+                ensure!(!lineups.is_empty(), "no lineup is given");
+                if let Some(&a) = lineups.iter().flatten().find(|&&a| a >= agent_count) {
+                    bail!("agent index {a} is out of range, there are {agent_count} agents");
+                }
+                lineups.clone()
+            }
+        };
+
+        if let Some(a) = (0..agent_count).find(|a| !lineups.iter().flatten().any(|b| b == a)) {
+            bail!("agent {a} is not seated in any lineup");
+        }
+        Ok(lineups)
+    }
+}
+
+/// Rotates the agents through every seat, starting from the given order.
+fn rotations(agents: [usize; 4]) -> impl Iterator<Item = [usize; 4]> {
+    (0..4).map(move |r| array::from_fn(|seat| agents[(seat + 4 - r) % 4]))
+}
+
+#[pymethods]
+impl Arena {
+    /// `seating` is one of `"four_way"`, `"round_robin"` and
+    /// `"permutations"`, or a list of lineups.
+    #[new]
+    #[pyo3(signature = (
+        seating,
+        *,
+        disable_progress_bar = false,
+        log_dir = None,
+        game_length = 8,
+        init_scores = [25000; 4],
+        west_round = true,
+        ledger = None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new_py(
+        seating: &Bound<'_, PyAny>,
+        disable_progress_bar: bool,
+        log_dir: Option<String>,
+        game_length: u8,
+        init_scores: [i32; 4],
+        west_round: bool,
+        ledger: Option<String>,
+    ) -> Result<Self> {
+        let seating = if let Ok(name) = seating.extract::<String>() {
+            match name.as_str() {
+                "four_way" => Seating::FourWay,
+                "round_robin" => Seating::RoundRobin,
+                "permutations" => Seating::Permutations,
+                _ => bail!("unknown seating {name}"),
+            }
+        } else {
+            Seating::Custom(seating.extract()?)
+        };
+        Ok(Self {
+            seating,
+            disable_progress_bar,
+            log_dir,
+            game_length,
+            init_scores,
+            west_round,
+            ledger,
+        })
+    }
+
+    /// Returns the lineups to play for each seed, each being the agent index
+    /// of every seat.
+    #[pyo3(name = "lineups")]
+    fn lineups_py(&self, agent_count: usize) -> Result<Vec<[usize; 4]>> {
+        self.seating.lineups(agent_count)
+    }
+
+    /// `agents` can be python engines, `SubprocessConfig`s or
+    /// `RuleBasedConfig`s. Returns the rankings of each agent.
+    fn run(
+        &self,
+        agents: Vec<Bound<'_, PyAny>>,
+        seed_start: (u64, u64),
+        seed_count: u64,
+        py: Python<'_>,
+    ) -> Result<Vec<[i32; 4]>> {
+        enum Spec {
+            Py(PyObject),
+            Cmd(SubprocessConfig),
+            RuleBased(RuleBasedConfig),
+        }
+        let specs: Vec<_> = agents
+            .into_iter()
+            .map(|agent| {
+                if let Ok(config) = agent.extract() {
+                    Spec::Cmd(config)
+                } else if let Ok(config) = agent.extract() {
+                    Spec::RuleBased(config)
+                } else {
+                    Spec::Py(agent.unbind())
+                }
+            })
+            .collect();
+        let agent_count = specs.len();
+
+        // `allow_threads` is required, otherwise it will block python GC to
+        // run, leading to memory leaks, since this function is doing long
+        // tasks.
+        py.allow_threads(move || {
+            let new_agents = specs
+                .into_iter()
+                .map(|spec| -> NewAgent<'_> {
+                    match spec {
+                        Spec::Py(engine) => {
+                            Box::new(move |player_ids| new_py_agent(engine, player_ids))
+                        }
+                        Spec::Cmd(config) => Box::new(move |player_ids| {
+                            SubprocessMjaiAgent::new_batched(&config, player_ids)
+                                .map(|a| Box::new(a) as _)
+                        }),
+                        Spec::RuleBased(config) => Box::new(move |player_ids| {
+                            RuleBasedAgent::new_batched(&config, player_ids)
+                                .map(|a| Box::new(a) as _)
+                        }),
+                    }
+                })
+                .collect();
+            let results = self.run_batch(new_agents, seed_start, seed_count)?;
+            self.rankings(agent_count, &results)
+        })
+    }
+}
+
+impl Arena {
+    #[must_use]
+    pub const fn new(seating: Seating) -> Self {
+        Self {
+            seating,
+            disable_progress_bar: false,
+            log_dir: None,
+            game_length: 8,
+            init_scores: [25000; 4],
+            west_round: true,
+            ledger: None,
+        }
+    }
+
+    fn batch_game(&self) -> BatchGame {
+        BatchGame {
+            rule: RuleSet {
+                game_length: self.game_length,
+                west_round: self.west_round,
+                ..RuleSet::tenhou()
+            },
+            init_scores: self.init_scores,
+            log_dir: self.log_dir.clone(),
+            ..BatchGame::tenhou_hanchan(self.disable_progress_bar)
+        }
+    }
+
+    /// Counts the rankings of each agent in `results`, which must be returned
+    /// by `run_batch` with the same number of agents.
+    pub fn rankings(&self, agent_count: usize, results: &[GameResult]) -> Result<Vec<[i32; 4]>> {
+        let lineups = self.seating.lineups(agent_count)?;
+        let mut rankings = vec![[0; 4]; agent_count];
+        for (result, lineup) in results.iter().zip(lineups.iter().cycle()) {
+            let rank_by_player = result.rankings().rank_by_player;
+            for (&agent_idx, rank) in lineup.iter().zip(rank_by_player) {
+                rankings[agent_idx][rank as usize] += 1;
+            }
+        }
+        Ok(rankings)
+    }
+
+    /// Plays every lineup of the seating on each seed. The results are
+    /// ordered by seed first and then by lineup.
+    pub fn run_batch(
+        &self,
+        new_agents: Vec<NewAgent<'_>>,
+        seed_start: (u64, u64),
+        seed_count: u64,
+    ) -> Result<Vec<GameResult>> {
+        let lineups = self.seating.lineups(new_agents.len())?;
+        let game_count = seed_count * lineups.len() as u64;
+
+        log::info!(
+            "seed: [{}, {}) w/ {:#x}, start {} sets, {} games",
+            seed_start.0,
+            seed_start.0 + seed_count,
+            seed_start.1,
+            seed_count,
+            game_count,
+        );
+
+        let seeds: Vec<_> = (seed_start.0..seed_start.0 + seed_count)
+            .flat_map(|seed| iter::repeat_n((seed, seed_start.1), lineups.len()))
+            .collect();
+
+        let mut player_ids = vec![vec![]; new_agents.len()];
+        let indexes: Vec<_> = iter::repeat_n(&lineups, seed_count as usize)
+            .flatten()
+            .map(|lineup| {
+                array::from_fn(|seat| {
+                    let agent_idx = lineup[seat];
+                    let ids = &mut player_ids[agent_idx];
+                    ids.push(seat as u8);
+                    Index {
+                        agent_idx,
+                        player_id_idx: ids.len() - 1,
+                    }
+                })
+            })
+            .collect();
+
+        let mut agents = new_agents
+            .into_iter()
+            .zip(&player_ids)
+            .map(|(new_agent, player_ids)| new_agent(player_ids))
+            .collect::<Result<Vec<_>>>()?;
+        let batch_game = self.batch_game();
+
+        let results = batch_game.run(&mut agents, &indexes, &seeds)?;
+
+        if let Some(path) = &self.ledger {
+            let games = results
+                .iter()
+                .enumerate()
+                .map(|(i, result)| (checkpoint::game_id(result.seed, i % lineups.len()), result));
+            let added = Ledger::open(path)?.record_results(games)?;
+            log::info!("recorded {added} games into {path}");
+        }
+
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::Tsumogiri;
+
+    #[test]
+    fn lineups() {
+        let four_way = Seating::FourWay.lineups(4).unwrap();
+        assert_eq!(
+            four_way,
+            [[0, 1, 2, 3], [3, 0, 1, 2], [2, 3, 0, 1], [1, 2, 3, 0]],
+        );
+        let permutations = Seating::Permutations.lineups(4).unwrap();
+        assert_eq!(permutations.len(), 24);
+        assert_eq!(permutations[0], [0, 1, 2, 3]);
+        assert_eq!(permutations[23], [3, 2, 1, 0]);
+        let round_robin = Seating::RoundRobin.lineups(6).unwrap();
+        assert_eq!(round_robin.len(), 15 * 4);
+        // Every agent takes every seat equally often.
+        for a in 0..6 {
+            for seat in 0..4 {
+                assert_eq!(round_robin.iter().filter(|l| l[seat] == a).count(), 10);
+            }
+        }
+        assert_eq!(Seating::RoundRobin.lineups(4).unwrap(), four_way);
+
+        Seating::FourWay.lineups(5).unwrap_err();
+        Seating::RoundRobin.lineups(3).unwrap_err();
+        Seating::Custom(vec![[0, 1, 1, 1]]).lineups(3).unwrap_err();
+        Seating::Custom(vec![[0, 1, 1, 4]]).lineups(3).unwrap_err();
+    }
+
+    #[test]
+    fn round_robin() {
+        let arena = Arena {
+            disable_progress_bar: true,
+            game_length: 1,
+            west_round: false,
+            ..Arena::new(Seating::RoundRobin)
+        };
+        let new_agents = (0..5)
+            .map(|_| -> NewAgent<'_> {
+                Box::new(|player_ids| Ok(Box::new(Tsumogiri::new_batched(player_ids)?) as _))
+            })
+            .collect();
+        let results = arena.run_batch(new_agents, (0, 0), 2).unwrap();
+        assert_eq!(results.len(), 2 * 5 * 4);
+
+        let rankings = arena.rankings(5, &results).unwrap();
+        for agent_rankings in rankings {
+            assert_eq!(agent_rankings.iter().sum::<i32>(), 2 * 4 * 4);
+        }
+    }
+}
@@ -1,34 +1,64 @@
 use super::game::{BatchGame, Index};
 use super::result::GameResult;
-use crate::agent::{AkochanAgent, BatchAgent, new_py_agent};
+use super::tournament::{Arena, Seating};
+use crate::agent::{BatchAgent, SubprocessConfig, SubprocessMjaiAgent, new_py_agent};
+use crate::rule::RuleSet;
 use std::fs::{self, File};
 use std::io;
-use std::iter;
 use std::path::PathBuf;
-use std::time::Duration;
 
 use anyhow::Result;
 use flate2::Compression;
 use flate2::read::GzEncoder;
-use indicatif::{ParallelProgressIterator, ProgressBar, ProgressStyle};
 use pyo3::prelude::*;
-use rayon::prelude::*;
 
 #[pyclass]
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct TwoVsTwo {
     pub disable_progress_bar: bool,
     pub log_dir: Option<String>,
+    /// 8 for hanchan and 4 for tonpuu.
+    pub game_length: u8,
+    pub init_scores: [i32; 4],
+    /// Whether to extend into 西入 (南入 for tonpuu) sudden death.
+    pub west_round: bool,
+    /// Path of the rating ledger to record every finished game into.
+    pub ledger: Option<String>,
+}
+
+impl Default for TwoVsTwo {
+    fn default() -> Self {
+        Self::new(false, None, 8, [25000; 4], true, None)
+    }
 }
 
 #[pymethods]
 impl TwoVsTwo {
     #[new]
-    #[pyo3(signature = (*, disable_progress_bar=false, log_dir=None))]
-    const fn new(disable_progress_bar: bool, log_dir: Option<String>) -> Self {
+    #[pyo3(signature = (
+        *,
+        disable_progress_bar = false,
+        log_dir = None,
+        game_length = 8,
+        init_scores = [25000; 4],
+        west_round = true,
+        ledger = None,
+    ))]
+    const fn new(
+        disable_progress_bar: bool,
+        log_dir: Option<String>,
+        game_length: u8,
+        init_scores: [i32; 4],
+        west_round: bool,
+        ledger: Option<String>,
+    ) -> Self {
         Self {
             disable_progress_bar,
             log_dir,
+            game_length,
+            init_scores,
+            west_round,
+            ledger,
         }
     }
 
@@ -63,7 +93,10 @@ impl TwoVsTwo {
     ) -> Result<()> {
         py.allow_threads(move || {
             self.run_batch(
-                |player_ids| AkochanAgent::new_batched(player_ids).map(|a| Box::new(a) as _),
+                |player_ids| {
+                    SubprocessMjaiAgent::new_batched(&SubprocessConfig::akochan(), player_ids)
+                        .map(|a| Box::new(a) as _)
+                },
                 |player_ids| new_py_agent(engine, player_ids),
                 seed_start,
                 seed_count,
@@ -82,7 +115,52 @@ impl TwoVsTwo {
         py.allow_threads(move || {
             self.run_batch(
                 |player_ids| new_py_agent(engine, player_ids),
-                |player_ids| AkochanAgent::new_batched(player_ids).map(|a| Box::new(a) as _),
+                |player_ids| {
+                    SubprocessMjaiAgent::new_batched(&SubprocessConfig::akochan(), player_ids)
+                        .map(|a| Box::new(a) as _)
+                },
+                seed_start,
+                seed_count,
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn cmd_vs_py(
+        &self,
+        config: SubprocessConfig,
+        engine: PyObject,
+        seed_start: (u64, u64),
+        seed_count: u64,
+        py: Python<'_>,
+    ) -> Result<()> {
+        py.allow_threads(move || {
+            self.run_batch(
+                |player_ids| {
+                    SubprocessMjaiAgent::new_batched(&config, player_ids).map(|a| Box::new(a) as _)
+                },
+                |player_ids| new_py_agent(engine, player_ids),
+                seed_start,
+                seed_count,
+            )?;
+            Ok(())
+        })
+    }
+
+    pub fn py_vs_cmd(
+        &self,
+        engine: PyObject,
+        config: SubprocessConfig,
+        seed_start: (u64, u64),
+        seed_count: u64,
+        py: Python<'_>,
+    ) -> Result<()> {
+        py.allow_threads(move || {
+            self.run_batch(
+                |player_ids| new_py_agent(engine, player_ids),
+                |player_ids| {
+                    SubprocessMjaiAgent::new_batched(&config, player_ids).map(|a| Box::new(a) as _)
+                },
                 seed_start,
                 seed_count,
             )?;
@@ -100,7 +178,10 @@ impl TwoVsTwo {
         py.allow_threads(move || {
             self.run_one(
                 |player_ids| new_py_agent(engine, player_ids),
-                |player_ids| AkochanAgent::new_batched(player_ids).map(|a| Box::new(a) as _),
+                |player_ids| {
+                    SubprocessMjaiAgent::new_batched(&SubprocessConfig::akochan(), player_ids)
+                        .map(|a| Box::new(a) as _)
+                },
                 seed,
                 split,
             )?;
@@ -110,6 +191,18 @@ impl TwoVsTwo {
 }
 
 impl TwoVsTwo {
+    fn batch_game(&self) -> BatchGame {
+        BatchGame {
+            rule: RuleSet {
+                game_length: self.game_length,
+                west_round: self.west_round,
+                ..RuleSet::tenhou()
+            },
+            init_scores: self.init_scores,
+            ..BatchGame::tenhou_hanchan(self.disable_progress_bar)
+        }
+    }
+
     pub fn run_batch<C, M>(
         &self,
         new_challenger_agent: C,
@@ -121,111 +214,23 @@ impl TwoVsTwo {
         C: FnOnce(&[u8]) -> Result<Box<dyn BatchAgent>>,
         M: FnOnce(&[u8]) -> Result<Box<dyn BatchAgent>>,
     {
-        if let Some(dir) = &self.log_dir {
-            fs::create_dir_all(dir)?;
-        }
-
-        log::info!(
-            "seed: [{}, {}) w/ {:#x}, start {} sets, {} hanchans",
-            seed_start.0,
-            seed_start.0 + seed_count,
-            seed_start.1,
+        let arena = Arena {
+            seating: Seating::Custom(vec![
+                [0, 1, 0, 1], // split A
+                [1, 0, 1, 0], // split B
+            ]),
+            disable_progress_bar: self.disable_progress_bar,
+            log_dir: self.log_dir.clone(),
+            game_length: self.game_length,
+            init_scores: self.init_scores,
+            west_round: self.west_round,
+            ledger: self.ledger.clone(),
+        };
+        arena.run_batch(
+            vec![Box::new(new_challenger_agent), Box::new(new_champion_agent)],
+            seed_start,
             seed_count,
-            seed_count * 2,
-        );
-
-        let seeds: Vec<_> = (seed_start.0..seed_start.0 + seed_count)
-            .flat_map(|seed| iter::repeat_n((seed, seed_start.1), 2))
-            .collect();
-
-        let challenger_player_ids_per_seed = [
-            0, 2, // split A
-            1, 3, // split B
-        ];
-        let challenger_player_ids: Vec<_> = challenger_player_ids_per_seed
-            .into_iter()
-            .cycle()
-            .take(seed_count as usize * challenger_player_ids_per_seed.len())
-            .collect();
-
-        let champion_player_ids_per_seed = [
-            1, 3, // split A
-            0, 2, // split B
-        ];
-        let champion_player_ids: Vec<_> = champion_player_ids_per_seed
-            .into_iter()
-            .cycle()
-            .take(seed_count as usize * champion_player_ids_per_seed.len())
-            .collect();
-
-        let mut agents = [
-            new_challenger_agent(&challenger_player_ids)?,
-            new_champion_agent(&champion_player_ids)?,
-        ];
-        let batch_game = BatchGame::tenhou_hanchan(self.disable_progress_bar);
-
-        let mut challenger_idx = 0;
-        let mut champion_idx = 0;
-        let agent_idxs_per_seed = [
-            [0, 1, 0, 1], // split A
-            [1, 0, 1, 0], // split B
-        ];
-        let indexes: Vec<_> = agent_idxs_per_seed
-            .into_iter()
-            .cycle()
-            .take(seed_count as usize * agent_idxs_per_seed.len())
-            .map(|agent_idxs_per_split| {
-                agent_idxs_per_split.map(|agent_idx| {
-                    let player_id_idx = if agent_idx == 0 {
-                        &mut challenger_idx
-                    } else {
-                        &mut champion_idx
-                    };
-                    let ret = Index {
-                        agent_idx,
-                        player_id_idx: *player_id_idx,
-                    };
-                    *player_id_idx += 1;
-                    ret
-                })
-            })
-            .collect();
-
-        let results = batch_game.run(&mut agents, &indexes, &seeds)?;
-
-        if let Some(dir) = &self.log_dir {
-            log::info!("dumping game logs");
-
-            let bar = if self.disable_progress_bar {
-                ProgressBar::hidden()
-            } else {
-                ProgressBar::new(seed_count * 2)
-            };
-            const TEMPLATE: &str = "[{elapsed_precise}] [{wide_bar}] {pos}/{len} {percent:>3}%";
-            bar.set_style(ProgressStyle::with_template(TEMPLATE)?.progress_chars("#-"));
-            bar.enable_steady_tick(Duration::from_millis(150));
-
-            results
-                .par_iter()
-                .progress_with(bar)
-                .enumerate()
-                .try_for_each(|(i, game_result)| {
-                    let split_name = ["a", "b"][i % 2];
-                    let (seed, key) = game_result.seed;
-                    let filename: PathBuf = [dir, &format!("{seed}_{key}_{split_name}.json.gz")]
-                        .iter()
-                        .collect();
-
-                    let log = game_result.dump_json_log()?;
-                    let mut comp = GzEncoder::new(log.as_bytes(), Compression::best());
-                    let mut f = File::create(filename)?;
-                    io::copy(&mut comp, &mut f)?;
-
-                    anyhow::Ok(())
-                })?;
-        }
-
-        Ok(results)
+        )
     }
 
     pub fn run_one<C, M>(
@@ -244,7 +249,7 @@ impl TwoVsTwo {
         }
 
         log::info!(
-            "seed: {} w/ {:#x}, split: {}, start 1 hanchan",
+            "seed: {} w/ {:#x}, split: {}, start 1 game",
             seed.0,
             seed.1,
             split
@@ -257,7 +262,7 @@ impl TwoVsTwo {
             new_challenger_agent(&challenger_player_ids)?,
             new_champion_agent(&champion_player_ids)?,
         ];
-        let batch_game = BatchGame::tenhou_hanchan(self.disable_progress_bar);
+        let batch_game = self.batch_game();
 
         let indexes = if split == 0 {
             [[
@@ -0,0 +1,108 @@
+use riichi::dataset::{GameplayLoader, LogFilter, ShardWriter};
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail, ensure};
+use glob::glob;
+use indicatif::{ProgressBar, ProgressStyle};
+
+const USAGE: &str = "Usage: build_shards [OPTIONS] <LOG_DIR> <OUT_DIR>
+
+Encodes the gameplays of every *.json.gz mjai log under LOG_DIR once into
+gzipped binary shards in OUT_DIR, along with an index.json, so that training
+can read them with libriichi.dataset.load_shard_files instead of parsing and
+encoding the logs again in every epoch.
+
+OPTIONS:
+    --version <N>            Version of the obs encoding [default: 4]
+    --oracle                 Also encode invisible_obs
+    --augmented              Encode the augmented logs
+    --sanma                  Load the logs as Tenhou's sanma
+    --player-names <FILE>    Only encode the players listed in FILE, one name
+                             per line
+    --filter <JSON>          Only encode the samples selected by the
+                             dataset filter, see filter_logs
+    --games-per-shard <N>    Number of gameplays in a shard [default: 256]";
+
+/// Number of log files loaded in parallel before their gameplays are written.
+const FILES_PER_CHUNK: usize = 64;
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut version = 4;
+    let mut oracle = false;
+    let mut augmented = false;
+    let mut sanma = false;
+    let mut player_names = None;
+    let mut filter = None;
+    let mut games_per_shard = 256;
+    let mut positional = vec![];
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().context(USAGE);
+        match arg.as_str() {
+            "--version" => version = value()?.parse()?,
+            "--oracle" => oracle = true,
+            "--augmented" => augmented = true,
+            "--sanma" => sanma = true,
+            "--player-names" => {
+                let names = fs::read_to_string(value()?)?
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .map(ToOwned::to_owned)
+                    .collect();
+                player_names = Some(names);
+            }
+            "--filter" => filter = Some(LogFilter::from_json(&value()?)?),
+            "--games-per-shard" => games_per_shard = value()?.parse()?,
+            v if v.starts_with('-') => bail!("unknown option {v}\n\n{USAGE}"),
+            _ => positional.push(arg),
+        }
+    }
+    let [log_dir, out_dir] = positional.as_slice() else {
+        bail!(USAGE);
+    };
+
+    let loader = GameplayLoader::new(
+        version,
+        oracle,
+        player_names,
+        None,
+        false,
+        true,
+        augmented,
+        None,
+        sanma,
+        filter,
+    );
+    let mut files = glob(&format!("{log_dir}/**/*.json.gz"))?
+        .map(|p| Ok(p?.to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(!files.is_empty(), "no logs found in {log_dir}");
+    files.sort();
+
+    let mut writer = ShardWriter::new(out_dir, version, oracle, augmented, sanma, games_per_shard)?;
+    const TEMPLATE: &str = "[{elapsed_precise}] [{wide_bar}] {pos}/{len} {percent:>3}%";
+    let bar = ProgressBar::new(files.len() as u64)
+        .with_style(ProgressStyle::with_template(TEMPLATE)?.progress_chars("#-"));
+    bar.enable_steady_tick(Duration::from_millis(150));
+    for chunk in files.chunks(FILES_PER_CHUNK) {
+        for game in loader.load_gz_log_files(chunk)?.iter().flatten() {
+            writer.write(game)?;
+        }
+        bar.inc(chunk.len() as u64);
+    }
+    bar.finish();
+
+    let index = writer.finish()?;
+    println!(
+        "{} gameplays with {} samples in {} shards",
+        index.games(),
+        index.samples(),
+        index.shards.len(),
+    );
+
+    Ok(())
+}
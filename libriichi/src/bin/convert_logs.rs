@@ -0,0 +1,96 @@
+use riichi::mjai::convert;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use glob::glob;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use serde_json as json;
+
+const USAGE: &str = "Usage: convert_logs <IN_DIR> <OUT_DIR>
+
+Converts every Tenhou mjlog (*.mjlog, *.xml), tenhou.net/6 log (*.json) and
+decoded Mahjong Soul record (*.json) under IN_DIR into mjai logs in OUT_DIR,
+keeping the relative paths, with the extension replaced by .json.gz. The
+inputs can be gzipped, with or without a .gz extension. Every converted log is
+validated with PlayerState under the rule of its source, and the ones that
+fail are reported and skipped.";
+
+fn main() -> Result<()> {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let [in_dir, out_dir] = args.as_slice() else {
+        bail!(USAGE);
+    };
+
+    const TEMPLATE: &str = "{spinner:.cyan} [{elapsed_precise}] {pos} ({per_sec})";
+    let bar = ProgressBar::new_spinner()
+        .with_style(ProgressStyle::with_template(TEMPLATE)?.tick_chars(".oO°Oo*"));
+    bar.enable_steady_tick(Duration::from_millis(150));
+
+    let failed = AtomicUsize::new(0);
+    let mut paths = vec![];
+    for ext in ["mjlog", "xml", "json", "mjlog.gz", "xml.gz", "json.gz"] {
+        paths.extend(glob(&format!("{in_dir}/**/*.{ext}"))?);
+    }
+    paths.into_par_iter().try_for_each(|path| {
+        bar.inc(1);
+        let path = path?;
+        let rel = path.strip_prefix(in_dir)?;
+        let result = convert_path(&path, &Path::new(out_dir).join(rel))
+            .with_context(|| format!("error in log {}", path.display()));
+        if let Err(err) = result {
+            failed.fetch_add(1, Ordering::Relaxed);
+            bar.suspend(|| println!("{err:?}"));
+        }
+        anyhow::Ok(())
+    })?;
+    bar.abandon();
+
+    let failed = failed.into_inner();
+    if failed > 0 {
+        println!("{failed} logs failed to convert");
+    }
+    Ok(())
+}
+
+fn convert_path(path: &Path, out_path: &Path) -> Result<()> {
+    let mut raw = vec![];
+    File::open(path)?.read_to_end(&mut raw)?;
+    // Tenhou's mjlog files are gzipped even without a .gz extension.
+    let raw = if raw.starts_with(&[0x1f, 0x8b]) {
+        io::read_to_string(GzDecoder::new(raw.as_slice()))?
+    } else {
+        String::from_utf8(raw)?
+    };
+
+    let (events, rule) = convert::from_any(&raw)?;
+    convert::validate(&events, rule)?;
+
+    let mut out_path = out_path.to_owned();
+    if out_path
+        .extension()
+        .is_some_and(|s| s.eq_ignore_ascii_case("gz"))
+    {
+        out_path.set_extension("");
+    }
+    out_path.set_extension("json.gz");
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut gz = GzEncoder::new(File::create(out_path)?, Compression::best());
+    for ev in &events {
+        json::to_writer(&mut gz, ev)?;
+        gz.write_all(b"\n")?;
+    }
+    gz.finish()?;
+    Ok(())
+}
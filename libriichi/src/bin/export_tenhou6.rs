@@ -0,0 +1,73 @@
+use riichi::mjai::{Event, convert};
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use flate2::read::GzDecoder;
+use glob::glob;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use serde_json as json;
+
+const USAGE: &str = "Usage: export_tenhou6 <LOG_DIR> <OUT_DIR>
+
+Exports every mjai log (*.json.gz) under LOG_DIR, such as the ones dumped by
+arena, into tenhou.net/6 logs in OUT_DIR, keeping the relative paths, with the
+extension replaced by .json. The exported logs can be opened in Tenhou's
+replay viewer with https://tenhou.net/6/#json=<LOG>.";
+
+fn main() -> Result<()> {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let [log_dir, out_dir] = args.as_slice() else {
+        bail!(USAGE);
+    };
+
+    const TEMPLATE: &str = "{spinner:.cyan} [{elapsed_precise}] {pos} ({per_sec})";
+    let bar = ProgressBar::new_spinner()
+        .with_style(ProgressStyle::with_template(TEMPLATE)?.tick_chars(".oO°Oo*"));
+    bar.enable_steady_tick(Duration::from_millis(150));
+
+    let failed = AtomicUsize::new(0);
+    glob(&format!("{log_dir}/**/*.json.gz"))?
+        .par_bridge()
+        .try_for_each(|path| {
+            bar.inc(1);
+            let path = path?;
+            let rel = path.strip_prefix(log_dir)?;
+            let result = export_path(&path, &Path::new(out_dir).join(rel))
+                .with_context(|| format!("error in log {}", path.display()));
+            if let Err(err) = result {
+                failed.fetch_add(1, Ordering::Relaxed);
+                bar.suspend(|| println!("{err:?}"));
+            }
+            anyhow::Ok(())
+        })?;
+    bar.abandon();
+
+    let failed = failed.into_inner();
+    if failed > 0 {
+        println!("{failed} logs failed to export");
+    }
+    Ok(())
+}
+
+fn export_path(path: &Path, out_path: &Path) -> Result<()> {
+    let reader = BufReader::new(GzDecoder::new(File::open(path)?));
+    let events = reader
+        .lines()
+        .map(|line| Ok(json::from_str::<Event>(&line?)?))
+        .collect::<Result<Vec<_>>>()?;
+    let log = convert::to_tenhou6(&events)?;
+
+    // a.json.gz -> a.json
+    let out_path = out_path.with_extension("");
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(out_path, log)?;
+    Ok(())
+}
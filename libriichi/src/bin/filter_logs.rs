@@ -0,0 +1,108 @@
+use riichi::dataset::LogFilter;
+use riichi::mjai::Event;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use flate2::read::GzDecoder;
+use glob::glob;
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use serde_json as json;
+
+const USAGE: &str = r#"Usage: filter_logs <LOG_DIR> <OUT_DIR> <FILTER>
+
+Copies every mjai log (*.json.gz) under LOG_DIR that has anything selected by
+FILTER into OUT_DIR, keeping the relative paths.
+
+FILTER is a dataset filter in JSON, for example
+    {"query": {"all": [{"tags": ["houou"]}, {"rank": {"max": 2}}]}}
+    {"query": {"any": ["yakuman", "all_last"]}, "riichi_decisions_only": true}
+    {"query": "yakuman", "rule": "mahjong-soul"}
+
+"rule" names the rule the logs are played under, one of tenhou,
+tenhou-tonpuu, tenhou-sanma, mahjong-soul, mahjong-soul-sanma and m-league.
+If omitted, it is Tenhou's yonma or sanma rule, told from each log.
+
+The logs are copied whole, so that the scores and rankings stay intact. Give
+the same filter to GameplayLoader or build_shards to only extract the selected
+kyokus and seats of them.
+
+Queries:
+    {"all": [Q, ...]}, {"any": [Q, ...]}, {"not": Q}
+    {"tags": [T, ...]}        the game has all of the tags: sanma, yonma, and
+                              for Tenhou logs, ippan, joukyuu, tokujou, houou,
+                              tonpuu, hanchan, soku, no_aka, no_kuitan
+    {"seats": [N, ...]}       the player sits at one of the seats
+    {"players": [S, ...]}     the player has one of the names
+    {"rank": {"min": N, "max": N}}
+                              the final rank of the player, 1-based
+    "game_yakuman"            some hora in the game is a yakuman
+    "yakuman"                 some hora in the kyoku is a yakuman
+    "all_last", "oya", "calls", "player_calls", "riichi_chance", "riichi",
+    "hora", "deal_in", "ryukyoku""#;
+
+fn main() -> Result<()> {
+    let args: Vec<_> = env::args().skip(1).collect();
+    let [log_dir, out_dir, filter] = args.as_slice() else {
+        bail!(USAGE);
+    };
+    let filter = LogFilter::from_json(filter)?;
+
+    const TEMPLATE: &str = "{spinner:.cyan} [{elapsed_precise}] {pos} ({per_sec})";
+    let bar = ProgressBar::new_spinner()
+        .with_style(ProgressStyle::with_template(TEMPLATE)?.tick_chars(".oO°Oo*"));
+    bar.enable_steady_tick(Duration::from_millis(150));
+
+    let kept = AtomicUsize::new(0);
+    let failed = AtomicUsize::new(0);
+    glob(&format!("{log_dir}/**/*.json.gz"))?
+        .par_bridge()
+        .try_for_each(|path| {
+            bar.inc(1);
+            let path = path?;
+            let rel = path.strip_prefix(log_dir)?;
+            let result = filter_path(&filter, &path, &Path::new(out_dir).join(rel))
+                .with_context(|| format!("error in log {}", path.display()));
+            match result {
+                Ok(true) => {
+                    kept.fetch_add(1, Ordering::Relaxed);
+                }
+                Ok(false) => (),
+                Err(err) => {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    bar.suspend(|| println!("{err:?}"));
+                }
+            }
+            anyhow::Ok(())
+        })?;
+    bar.abandon();
+
+    println!("{} logs kept", kept.into_inner());
+    let failed = failed.into_inner();
+    if failed > 0 {
+        println!("{failed} logs failed to filter");
+    }
+    Ok(())
+}
+
+fn filter_path(filter: &LogFilter, path: &Path, out_path: &Path) -> Result<bool> {
+    let raw = fs::read(path)?;
+    let events = io::read_to_string(GzDecoder::new(raw.as_slice()))?
+        .lines()
+        .map(json::from_str)
+        .collect::<Result<Vec<Event>, _>>()?;
+    if !filter.matches_events(&events, path.to_str())? {
+        return Ok(false);
+    }
+
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(out_path, raw)?;
+    Ok(true)
+}
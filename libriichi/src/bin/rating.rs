@@ -0,0 +1,45 @@
+use riichi::rating::{Ledger, RatingSystem, leaderboard};
+use std::env;
+
+use anyhow::{Context, Result, bail};
+
+const USAGE: &str = "Usage: rating [OPTIONS] <LEDGER> [DIR]...
+
+Records every mjai log under each DIR into LEDGER, skipping games already
+recorded, then prints the leaderboard.
+
+ARGS:
+    <LEDGER>    The ledger file, created if it does not exist.
+    [DIR]...    Directories of mjai logs (*.json or *.json.gz).
+
+OPTIONS:
+    --system <SYSTEM>    elo or trueskill [default: trueskill]
+    --k <K>              K-factor of Elo [default: 32]";
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut system = "trueskill".to_owned();
+    let mut k = 32.;
+    let mut positional = vec![];
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().context(USAGE);
+        match arg.as_str() {
+            "--system" => system = value()?,
+            "--k" => k = value()?.parse()?,
+            v if v.starts_with('-') => bail!("unknown option {v}\n\n{USAGE}"),
+            _ => positional.push(arg),
+        }
+    }
+    let system = RatingSystem::parse(&system, k)?;
+    let (ledger_path, dirs) = positional.split_first().context(USAGE)?;
+
+    let mut ledger = Ledger::open(ledger_path)?;
+    for dir in dirs {
+        let added = ledger.record_logs(dir)?;
+        eprintln!("recorded {added} games from {dir}");
+    }
+
+    print!("{}", leaderboard(&ledger.ratings(system)));
+    Ok(())
+}
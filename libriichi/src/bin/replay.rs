@@ -0,0 +1,107 @@
+use riichi::agent::{BatchAgent, SubprocessConfig, SubprocessMjaiAgent, Tsumogiri};
+use riichi::arena::Replay;
+use riichi::rule::RuleSet;
+use std::env;
+use std::io::{self, Write};
+
+use anyhow::{Context, Result, bail};
+use serde_json as json;
+
+const USAGE: &str = "Usage: replay [OPTIONS] <LOG>
+
+Checks that every tsumo, dora and ura in LOG matches the walls regenerated
+from the seed in its start_game. With --kyoku and --seat, the kyoku is re-run
+on the same wall with the seat swapped to another bot, and the new log is
+printed.
+
+ARGS:
+    <LOG>    An mjai log dumped by the arena (*.json or *.json.gz).
+
+OPTIONS:
+    --rule <RULE>      tenhou, tenhou-tonpuu, tenhou-sanma, mahjong-soul,
+                       mahjong-soul-sanma or m-league [default: tenhou]
+    --kyoku <N>        Index of the kyoku to re-run, counting from 0
+    --seat <SEAT>      Seat to swap
+    --cmd <CMD>        Command line of the mjai bot for the swapped seat,
+                       where {id} is replaced with the player ID
+                       [default: tsumogiri]
+    --others <CMD>     Command line of the mjai bot for the other seats after
+                       the kyoku diverges [default: tsumogiri]";
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut rule = RuleSet::tenhou();
+    let mut kyoku = None;
+    let mut seat = None;
+    let mut cmd = None;
+    let mut others_cmd = None;
+    let mut path = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().context(USAGE);
+        match arg.as_str() {
+            "--rule" => rule = RuleSet::from_name(&value()?)?,
+            "--kyoku" => kyoku = Some(value()?.parse()?),
+            "--seat" => seat = Some(value()?.parse()?),
+            "--cmd" => cmd = Some(value()?),
+            "--others" => others_cmd = Some(value()?),
+            v if v.starts_with('-') => bail!("unknown option {v}\n\n{USAGE}"),
+            _ => path = Some(arg),
+        }
+    }
+    let path = path.context(USAGE)?;
+
+    let replay = Replay::from_path(&path, rule)?;
+    replay.verify()?;
+    eprintln!(
+        "{path}: {} kyokus match seed {:?}",
+        replay.kyokus.len(),
+        replay.seed,
+    );
+
+    let (kyoku, seat) = match (kyoku, seat) {
+        (Some(kyoku), Some(seat)) => (kyoku, seat),
+        (None, None) => return Ok(()),
+        _ => bail!("--kyoku and --seat must be given together\n\n{USAGE}"),
+    };
+    let player_ids = [0, 1, 2, 3];
+    let mut agent = new_agent(cmd.as_deref(), &player_ids)?;
+    let mut others = match others_cmd {
+        Some(cmd) => Some(new_agent(Some(&cmd), &player_ids)?),
+        None => None,
+    };
+
+    let others = others.as_deref_mut().map(|a| a as &mut dyn BatchAgent);
+    let rerun = replay.rerun_kyoku(kyoku, seat, agent.as_mut(), others)?;
+    let mut stdout = io::stdout().lock();
+    for ev in &rerun.log {
+        json::to_writer(&mut stdout, ev)?;
+        writeln!(stdout)?;
+    }
+
+    let name = replay.kyoku_name(kyoku);
+    match rerun.diverged_at {
+        Some(idx) => eprintln!("{name}: diverged at event {idx}"),
+        None => eprintln!("{name}: identical to the log"),
+    }
+    eprintln!("original scores: {:?}", rerun.original_scores);
+    eprintln!("rerun scores:    {:?}", rerun.scores);
+    Ok(())
+}
+
+fn new_agent(cmd: Option<&str>, player_ids: &[u8]) -> Result<Box<dyn BatchAgent>> {
+    let Some(cmd) = cmd else {
+        return Ok(Box::new(Tsumogiri::new_batched(player_ids)?));
+    };
+    let mut words = cmd.split_whitespace().map(str::to_owned);
+    let program = words.next().context("empty command")?;
+    let config = SubprocessConfig {
+        name: program.clone(),
+        program: program.into(),
+        args: words.map(Into::into).collect(),
+        ..Default::default()
+    };
+    Ok(Box::new(SubprocessMjaiAgent::new_batched(
+        &config, player_ids,
+    )?))
+}
@@ -0,0 +1,99 @@
+use riichi::arena::Server;
+use riichi::rule::RuleSet;
+use std::env;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use log::{LevelFilter, Log, Metadata, Record};
+
+const USAGE: &str = "Usage: riichi-server [OPTIONS] <ADDR>
+
+ARGS:
+    <ADDR>    The address to listen on, for example 127.0.0.1:11600.
+
+OPTIONS:
+    --rule <RULE>        One of tenhou, tenhou-tonpuu, tenhou-sanma, mahjong-soul,
+                         mahjong-soul-sanma and m-league [default: tenhou]
+    --games <N>          The number of games to run [default: 1]
+    --seed <NONCE>       The seed nonce of the first game, incremented by one
+                         for each game [default: random]
+    --key <KEY>          The seed key [default: random]
+    --timeout <MS>       Time limit for each response in milliseconds
+                         [default: 10000]
+    --reply-all          Expect a response to every message, as the original
+                         mjai server does
+    --log-dir <DIR>      Dump game logs into <DIR>";
+
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        eprintln!("[{}] {}", record.level(), record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let mut addr = None;
+    let mut rule = RuleSet::tenhou();
+    let mut games: u64 = 1;
+    let mut nonce: u64 = rand::random();
+    let mut key = rand::random();
+    let mut timeout = Duration::from_secs(10);
+    let mut reply_all = false;
+    let mut log_dir = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().context(USAGE);
+        match arg.as_str() {
+            "--rule" => rule = RuleSet::from_name(&value()?)?,
+            "--games" => games = value()?.parse()?,
+            "--seed" => nonce = value()?.parse()?,
+            "--key" => key = value()?.parse()?,
+            "--timeout" => timeout = Duration::from_millis(value()?.parse()?),
+            "--reply-all" => reply_all = true,
+            "--log-dir" => log_dir = Some(value()?),
+            v if v.starts_with('-') => bail!("unknown option {v}\n\n{USAGE}"),
+            _ => addr = Some(arg),
+        }
+    }
+    let addr = addr.context(USAGE)?;
+
+    log::set_logger(&StderrLogger)?;
+    log::set_max_level(LevelFilter::Info);
+
+    let init_scores = if rule.sanma {
+        [35000, 35000, 35000, 0]
+    } else {
+        [25000; 4]
+    };
+    let server = Server {
+        rule,
+        init_scores,
+        timeout,
+        reply_all,
+        log_dir,
+    };
+
+    let listener = TcpListener::bind(&addr)?;
+    log::info!(
+        "listening on {addr}, waiting for {} players",
+        rule.player_count(),
+    );
+
+    let seeds: Vec<_> = (0..games).map(|i| (nonce.wrapping_add(i), key)).collect();
+    let results = server.run(&listener, &seeds)?;
+    for result in &results {
+        let (nonce, key) = result.seed;
+        println!("{nonce}_{key}: {:?} {:?}", result.names, result.scores);
+    }
+
+    Ok(())
+}
@@ -2,11 +2,11 @@ use crate::py_helper::add_submodule;
 
 use pyo3::prelude::*;
 
-pub const MAX_VERSION: u32 = 4;
+pub const MAX_VERSION: u32 = 5;
 
 pub const ACTION_SPACE: usize = 37 // discard | kan (choice)
                               + 1  // riichi
-                              + 3  // chi
+                              + 3  // chi (chi low is also nukidora in sanma)
                               + 1  // pon
                               + 1  // kan (decide)
                               + 1  // agari
@@ -23,6 +23,7 @@ pub const fn obs_shape(version: u32) -> (usize, usize) {
         2 => (942, 34),
         3 => (934, 34),
         4 => (1012, 34),
+        5 => (1017, 34),
         _ => unreachable!(),
     }
 }
@@ -32,7 +33,7 @@ pub const fn obs_shape(version: u32) -> (usize, usize) {
 pub const fn oracle_obs_shape(version: u32) -> (usize, usize) {
     match version {
         1 => (211, 34),
-        2 | 3 | 4 => (217, 34),
+        2..=5 => (217, 34),
         _ => unreachable!(),
     }
 }
@@ -0,0 +1,430 @@
+use crate::mjai::Event;
+use crate::tile::Tile;
+use crate::{must_tile, tu8};
+
+use pyo3::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use sha3::{Digest, Sha3_256};
+
+/// Configures the symmetries `GameplayLoader` augments yonma logs with.
+///
+/// Every game gets its own `Augmentation`, picked deterministically from
+/// `seed` and the deal of the game, so a different `seed` per epoch walks
+/// through the symmetry group without storing any augmented log.
+#[pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AugmentConfig {
+    #[pyo3(get)]
+    pub seed: u64,
+    /// Permutes the three suits.
+    #[pyo3(get)]
+    pub suits: bool,
+    /// Rotates the dragons.
+    #[pyo3(get)]
+    pub dragons: bool,
+    /// Rotates the seats.
+    #[pyo3(get)]
+    pub seats: bool,
+    /// Permutes the winds that are neither the round wind nor the seat wind
+    /// of the player, separately for every player and kyoku. The yakus of the
+    /// other players may change, so it is not supported with oracle.
+    #[pyo3(get)]
+    pub winds: bool,
+}
+
+/// An element of the symmetry group of yonma logs.
+///
+/// The suit permutation keeps the aka, and the dragons only rotate along the
+/// order of dora so that dora indicators stay valid. Neither changes the
+/// yakus of any hand except ryuuiisou, which `AugmentConfig::pick` avoids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Augmentation {
+    /// Suit `i` of m, p and s becomes `suits[i]`.
+    pub suits: [u8; 3],
+    /// P, F and C are shifted by this along P -> F -> C -> P.
+    pub dragon_shift: u8,
+    /// Seat `i` becomes seat `(i + seat_shift) % 4`.
+    pub seat_shift: u8,
+    /// Seeds the wind permutation of each player and kyoku, see
+    /// `AugmentConfig::winds`.
+    pub wind_key: Option<u64>,
+}
+
+#[pymethods]
+impl AugmentConfig {
+    #[new]
+    #[pyo3(signature = (seed, *, suits=true, dragons=true, seats=true, winds=false))]
+    const fn new(seed: u64, suits: bool, dragons: bool, seats: bool, winds: bool) -> Self {
+        Self {
+            seed,
+            suits,
+            dragons,
+            seats,
+            winds,
+        }
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl AugmentConfig {
+    /// Picks the augmentation of a game.
+    pub fn pick(&self, events: &[Event]) -> Augmentation {
+        let mut hasher = Sha3_256::new().chain_update(self.seed.to_le_bytes());
+        for ev in events {
+            if let Event::StartKyoku {
+                dora_marker,
+                tehais,
+                ..
+            } = ev
+            {
+                hasher.update([dora_marker.as_u8()]);
+                for tile in tehais.iter().flatten() {
+                    hasher.update([tile.as_u8()]);
+                }
+            }
+        }
+        let mut rng = ChaCha12Rng::from_seed(hasher.finalize().into());
+
+        // Ryuuiisou is made of s and F only. A yakuman wins at least 32000, so
+        // the games with such a hora keep both of them in place.
+        let maybe_ryuuiisou = events.iter().any(|ev| match ev {
+            Event::Hora {
+                deltas: Some(deltas),
+                ..
+            } => deltas.iter().any(|&d| d >= 32000),
+            _ => false,
+        });
+
+        let mut suits = [0, 1, 2];
+        if self.suits {
+            let n = if maybe_ryuuiisou { 2 } else { 3 };
+            suits[..n].shuffle(&mut rng);
+        }
+        let dragon_shift = if self.dragons && !maybe_ryuuiisou {
+            rng.random_range(0..3)
+        } else {
+            0
+        };
+        let seat_shift = if self.seats {
+            rng.random_range(0..4)
+        } else {
+            0
+        };
+        let wind_key = self.winds.then(|| rng.random());
+        Augmentation {
+            suits,
+            dragon_shift,
+            seat_shift,
+            wind_key,
+        }
+    }
+}
+
+impl Augmentation {
+    #[must_use]
+    pub fn tile(&self, tile: Tile) -> Tile {
+        if tile.is_unknown() {
+            return tile;
+        }
+        let id = tile.deaka().as_u8();
+        let ret = match id / 9 {
+            kind @ 0..3 => must_tile!(self.suits[kind as usize] * 9 + id % 9),
+            _ if id >= tu8!(P) => must_tile!(tu8!(P) + (id - tu8!(P) + self.dragon_shift) % 3),
+            _ => tile.deaka(),
+        };
+        if tile.is_aka() { ret.akaize() } else { ret }
+    }
+
+    #[must_use]
+    pub const fn seat(&self, player_id: u8) -> u8 {
+        (player_id + self.seat_shift) % 4
+    }
+
+    /// Applies the permutations of tiles and seats to a game. The winds are
+    /// left to `apply_winds`.
+    pub fn apply(&self, events: &mut [Event]) {
+        for ev in events {
+            ev.map_tiles(|t| self.tile(t));
+            self.rotate_seats(ev);
+        }
+    }
+
+    /// Permutes the winds that are neither the round wind nor the seat wind of
+    /// `player_id` in every kyoku, as long as the dora indicators of the kyoku
+    /// still point to the same tiles after that.
+    pub fn apply_winds(&self, events: &mut [Event], player_id: u8) {
+        let Some(key) = self.wind_key else {
+            return;
+        };
+
+        let mut kyoku_idx = 0_u8;
+        let mut start = 0;
+        while start < events.len() {
+            let len = events[start..]
+                .iter()
+                .position(|ev| matches!(ev, Event::EndKyoku))
+                .map_or(events.len() - start, |i| i + 1);
+            let kyoku = &mut events[start..start + len];
+            start += len;
+
+            let Some(&Event::StartKyoku { bakaze, oya, .. }) = kyoku
+                .iter()
+                .find(|ev| matches!(ev, Event::StartKyoku { .. }))
+            else {
+                continue;
+            };
+            let jikaze = tu8!(E) + (player_id + 4 - oya) % 4;
+            let guests: Vec<_> = (tu8!(E)..=tu8!(N))
+                .filter(|&w| w != bakaze.as_u8() && w != jikaze)
+                .collect();
+
+            let seed = Sha3_256::new()
+                .chain_update(key.to_le_bytes())
+                .chain_update([player_id, kyoku_idx])
+                .finalize()
+                .into();
+            kyoku_idx += 1;
+            let mut shuffled = guests.clone();
+            shuffled.shuffle(&mut ChaCha12Rng::from_seed(seed));
+            let mut winds = [tu8!(E), tu8!(S), tu8!(W), tu8!(N)];
+            for (from, to) in guests.into_iter().zip(shuffled) {
+                winds[(from - tu8!(E)) as usize] = to;
+            }
+
+            let map = |t: Tile| {
+                let id = t.as_u8();
+                if (tu8!(E)..=tu8!(N)).contains(&id) {
+                    must_tile!(winds[(id - tu8!(E)) as usize])
+                } else {
+                    t
+                }
+            };
+            let commutes = kyoku
+                .iter()
+                .flat_map(indicators)
+                .all(|t| map(t).next() == map(t.next()));
+            if commutes {
+                for ev in kyoku {
+                    ev.map_tiles(map);
+                }
+            }
+        }
+    }
+
+    fn rotate_seats(&self, ev: &mut Event) {
+        let shift = self.seat_shift as usize;
+        if shift == 0 {
+            return;
+        }
+        let seat = |s: &mut u8| *s = self.seat(*s);
+        match ev {
+            Event::StartGame { names, .. } => names.rotate_right(shift),
+            Event::StartKyoku {
+                oya,
+                scores,
+                tehais,
+                ..
+            } => {
+                seat(oya);
+                scores.rotate_right(shift);
+                tehais.rotate_right(shift);
+            }
+            Event::Tsumo { actor, .. }
+            | Event::Dahai { actor, .. }
+            | Event::Kakan { actor, .. }
+            | Event::Ankan { actor, .. }
+            | Event::Nukidora { actor, .. }
+            | Event::Reach { actor }
+            | Event::ReachAccepted { actor } => seat(actor),
+            Event::Chi { actor, target, .. }
+            | Event::Pon { actor, target, .. }
+            | Event::Daiminkan { actor, target, .. } => {
+                seat(actor);
+                seat(target);
+            }
+            Event::Hora {
+                actor,
+                target,
+                deltas,
+                ..
+            } => {
+                seat(actor);
+                seat(target);
+                if let Some(deltas) = deltas {
+                    deltas.rotate_right(shift);
+                }
+            }
+            Event::Ryukyoku {
+                deltas: Some(deltas),
+            } => deltas.rotate_right(shift),
+            _ => (),
+        }
+    }
+}
+
+/// The dora and ura indicators revealed by the event.
+fn indicators(ev: &Event) -> Vec<Tile> {
+    match ev {
+        Event::StartKyoku { dora_marker, .. } | Event::Dora { dora_marker } => vec![*dora_marker],
+        Event::Hora {
+            ura_markers: Some(ura),
+            ..
+        } => ura.clone(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::BatchGame;
+    use crate::dataset::{GameSummary, GameplayLoader};
+    use crate::rule::RuleSet;
+    use crate::t;
+    use crate::test_util::{events_of, run_arena};
+
+    use ndarray::s;
+
+    fn arena_events(seed: u64) -> Vec<Event> {
+        let g = BatchGame {
+            rule: RuleSet::tenhou_tonpuu(),
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        events_of(&run_arena(&g, (seed, seed)))
+    }
+
+    fn loader(oracle: bool, augment: Option<AugmentConfig>) -> GameplayLoader {
+        GameplayLoader::new(
+            1, oracle, None, None, true, true, false, augment, false, None,
+        )
+    }
+
+    #[test]
+    fn tile() {
+        let aug = Augmentation {
+            suits: [2, 0, 1],
+            dragon_shift: 1,
+            seat_shift: 0,
+            wind_key: None,
+        };
+        assert_eq!(aug.tile(t!(1m)), t!(1s));
+        assert_eq!(aug.tile(t!(5mr)), t!(5sr));
+        assert_eq!(aug.tile(t!(9p)), t!(9m));
+        assert_eq!(aug.tile(t!(3s)), t!(3p));
+        assert_eq!(aug.tile(t!(E)), t!(E));
+        assert_eq!(aug.tile(t!(P)), t!(F));
+        assert_eq!(aug.tile(t!(C)), t!(P));
+        assert_eq!(aug.tile(t!(?)), t!(?));
+
+        let mut seen = [false; 37];
+        for id in 0_u8..37 {
+            let tile = must_tile!(id);
+            let mapped = aug.tile(tile);
+            assert_eq!(mapped.is_aka(), tile.is_aka());
+            assert_eq!(mapped.deaka().next(), aug.tile(tile.deaka().next()));
+            seen[mapped.as_usize()] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+    }
+
+    #[test]
+    fn stays_valid() {
+        let events = arena_events(1);
+        let rule = RuleSet::tenhou_tonpuu();
+        let orig = GameSummary::new_with_rule(&events, None, rule).unwrap();
+        let config = AugmentConfig::new(0, true, true, true, false);
+        let mut shifts = vec![];
+        for seed in 0..8 {
+            let aug = AugmentConfig { seed, ..config }.pick(&events);
+            assert_eq!(aug, AugmentConfig { seed, ..config }.pick(&events));
+            shifts.push(aug.seat_shift);
+
+            let mut augmented = events.clone();
+            aug.apply(&mut augmented);
+            // Every hora is recalculated on the augmented log.
+            let game = GameSummary::new_with_rule(&augmented, None, rule).unwrap();
+            for (l, r) in orig.kyokus.iter().zip(&game.kyokus) {
+                assert_eq!(l.yakuman, r.yakuman);
+                for i in 0..4 {
+                    let j = aug.seat(i as u8) as usize;
+                    assert_eq!(l.horas[i], r.horas[j]);
+                    assert_eq!(l.riichi_chances[i], r.riichi_chances[j]);
+                }
+            }
+        }
+        assert!(shifts.iter().any(|&s| s != shifts[0]));
+    }
+
+    #[test]
+    fn loader_symmetry() {
+        let events = arena_events(2);
+        let orig = loader(false, None).load_events(&events).unwrap();
+
+        // The observations are relative to the player, so rotating the seats
+        // alone must not change anything.
+        let config = AugmentConfig::new(0, false, false, true, false);
+        let seed = (0..)
+            .find(|&seed| AugmentConfig { seed, ..config }.pick(&events).seat_shift != 0)
+            .unwrap();
+        let rotated = loader(false, Some(AugmentConfig { seed, ..config }))
+            .load_events(&events)
+            .unwrap();
+        assert_eq!(rotated.len(), 4);
+        for (l, r) in orig.iter().zip(&rotated) {
+            assert_eq!(l.player_id, r.player_id);
+            assert_eq!(l.player_name, r.player_name);
+            assert_eq!(l.obs, r.obs);
+            assert_eq!(l.actions, r.actions);
+            assert_eq!(l.masks, r.masks);
+            assert_eq!(l.grp.feature, r.grp.feature);
+            assert_eq!(l.grp.rank_by_player, r.grp.rank_by_player);
+        }
+
+        let config = AugmentConfig::new(3, true, true, true, false);
+        let aug = config.pick(&events);
+        let augmented = loader(false, Some(config)).load_events(&events).unwrap();
+        for (l, r) in orig.iter().zip(&augmented) {
+            assert_eq!(l.player_id, r.player_id);
+            assert_eq!(l.actions.len(), r.actions.len());
+            assert_eq!(l.at_kyoku, r.at_kyoku);
+            for (&la, &ra) in l.actions.iter().zip(&r.actions) {
+                if la < 37 {
+                    let tile = must_tile!(la as u8);
+                    assert_eq!(ra, aug.tile(tile).as_usize() as i64);
+                } else {
+                    assert_eq!(la, ra);
+                }
+            }
+        }
+
+        // The winds are permuted for the POV only, which keeps its decisions.
+        let config = AugmentConfig::new(4, false, false, false, true);
+        assert!(loader(true, Some(config)).load_events(&events).is_err());
+        let permuted = loader(false, Some(config)).load_events(&events).unwrap();
+        let mut changed = false;
+        for (l, r) in orig.iter().zip(&permuted) {
+            assert_eq!(l.actions.len(), r.actions.len());
+            for (lm, rm) in l.masks.iter().zip(&r.masks) {
+                assert_eq!(
+                    lm.iter().filter(|&&b| b).count(),
+                    rm.iter().filter(|&&b| b).count()
+                );
+                assert_eq!(lm.slice(s![37..]), rm.slice(s![37..]));
+            }
+            for (&la, &ra) in l.actions.iter().zip(&r.actions) {
+                if la >= 37 {
+                    assert_eq!(la, ra);
+                } else {
+                    assert_eq!(la < 27, ra < 27);
+                    changed |= la != ra;
+                }
+            }
+        }
+        assert!(changed);
+    }
+}
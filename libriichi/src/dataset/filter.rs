@@ -0,0 +1,610 @@
+use super::Grp;
+use crate::algo::agari::Agari;
+use crate::mjai::{Event, convert};
+use crate::rankings::Rankings;
+use crate::rule::RuleSet;
+use crate::state::PlayerState;
+use crate::tu8;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use ahash::AHashSet;
+use anyhow::{Context, Result, bail, ensure};
+use flate2::read::GzDecoder;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, de};
+use serde_json as json;
+
+/// A predicate on a kyoku seen from one seat, which is the unit of samples
+/// `GameplayLoader` extracts.
+///
+/// It is written in JSON, e.g. `{"all": ["all_last", {"rank": {"max": 2}}]}`
+/// selects the all-last kyokus of the players who finish first or second.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Query {
+    /// All of the queries hold, which is true for an empty list.
+    All(Vec<Self>),
+    /// Any of the queries holds, which is false for an empty list.
+    Any(Vec<Self>),
+    Not(Box<Self>),
+
+    /// The game has all of the tags, see `GameSummary::tags`.
+    Tags(Vec<String>),
+    /// The player sits at one of the seats.
+    Seats(Vec<u8>),
+    /// The player has one of the names.
+    Players(Vec<String>),
+    /// The final rank of the player is within `[min, max]`, 1-based.
+    Rank {
+        #[serde(default)]
+        min: Option<u8>,
+        #[serde(default)]
+        max: Option<u8>,
+    },
+    /// Some hora in the game is a yakuman, counting kazoe yakuman.
+    GameYakuman,
+
+    /// Some hora in the kyoku is a yakuman, counting kazoe yakuman.
+    Yakuman,
+    /// The kyoku is the last one of the last wind of the game, e.g. South 4
+    /// of a hanchan. The length of the game is told by the `tonpuu` and
+    /// `hanchan` tags, or by the rule of the game without them.
+    AllLast,
+    /// The player is the oya.
+    Oya,
+    /// Someone makes a chi, pon or daiminkan.
+    Calls,
+    /// The player makes a chi, pon or daiminkan.
+    PlayerCalls,
+    /// The player has the option to declare riichi at least once.
+    RiichiChance,
+    /// The player declares riichi.
+    Riichi,
+    /// The player wins.
+    Hora,
+    /// The player deals in.
+    DealIn,
+    /// The kyoku ends in a ryukyoku.
+    Ryukyoku,
+}
+
+/// Selects the samples to extract from logs by a `Query` and, optionally,
+/// only the samples where riichi can be declared.
+///
+/// A filter can be given to `GameplayLoader`, or used on its own to pick the
+/// logs that have anything selected.
+#[pyclass]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogFilter {
+    #[serde(default = "Query::always")]
+    pub query: Query,
+    /// Keeps only the decisions made where riichi can be declared, whether
+    /// riichi is declared or not.
+    #[pyo3(get)]
+    #[serde(default)]
+    pub riichi_decisions_only: bool,
+    /// The rule the logs are played under, either the name of a preset as in
+    /// `RuleSet::from_name` or a full `RuleSet`. If omitted, it is Tenhou's
+    /// yonma or sanma rule, told from each log.
+    #[serde(default, deserialize_with = "rule_by_name")]
+    pub rule: Option<RuleSet>,
+}
+
+/// What a `Query` needs to know about a game.
+#[derive(Debug, Clone)]
+pub struct GameSummary {
+    /// Room and rule tags of the game.
+    ///
+    /// `sanma` or `yonma` is always present. The name of a Tenhou log like
+    /// `2019010100gm-00a9-0000-0123abcd` gives the room (`ippan`, `joukyuu`,
+    /// `tokujou` or `houou`), `tonpuu` or `hanchan`, and `soku`, `no_aka` and
+    /// `no_kuitan` for the respective rules.
+    pub tags: AHashSet<String>,
+    pub names: [String; 4],
+    /// 0-based.
+    pub rank_by_player: [u8; 4],
+    pub kyokus: Vec<KyokuSummary>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KyokuSummary {
+    pub oya: u8,
+    pub all_last: bool,
+    pub yakuman: bool,
+    pub ryukyoku: bool,
+    pub calls: [bool; 4],
+    pub riichi_chances: [bool; 4],
+    pub riichis: [bool; 4],
+    pub horas: [bool; 4],
+    pub deal_ins: [bool; 4],
+}
+
+impl Query {
+    #[must_use]
+    pub const fn always() -> Self {
+        Self::All(vec![])
+    }
+
+    /// Tells if the query holds for the `kyoku_idx`-th kyoku of the game seen
+    /// from `player_id`.
+    pub fn matches(&self, game: &GameSummary, player_id: u8, kyoku_idx: usize) -> bool {
+        let seat = player_id as usize;
+        let kyoku = &game.kyokus[kyoku_idx];
+        match self {
+            Self::All(qs) => qs.iter().all(|q| q.matches(game, player_id, kyoku_idx)),
+            Self::Any(qs) => qs.iter().any(|q| q.matches(game, player_id, kyoku_idx)),
+            Self::Not(q) => !q.matches(game, player_id, kyoku_idx),
+
+            Self::Tags(tags) => tags.iter().all(|t| game.tags.contains(t)),
+            Self::Seats(seats) => seats.contains(&player_id),
+            Self::Players(names) => names.contains(&game.names[seat]),
+            &Self::Rank { min, max } => {
+                let rank = game.rank_by_player[seat] + 1;
+                min.is_none_or(|m| rank >= m) && max.is_none_or(|m| rank <= m)
+            }
+            Self::GameYakuman => game.kyokus.iter().any(|k| k.yakuman),
+
+            Self::Yakuman => kyoku.yakuman,
+            Self::AllLast => kyoku.all_last,
+            Self::Oya => kyoku.oya == player_id,
+            Self::Calls => kyoku.calls.contains(&true),
+            Self::PlayerCalls => kyoku.calls[seat],
+            Self::RiichiChance => kyoku.riichi_chances[seat],
+            Self::Riichi => kyoku.riichis[seat],
+            Self::Hora => kyoku.horas[seat],
+            Self::DealIn => kyoku.deal_ins[seat],
+            Self::Ryukyoku => kyoku.ryukyoku,
+        }
+    }
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            query: Query::always(),
+            riichi_decisions_only: false,
+            rule: None,
+        }
+    }
+}
+
+#[pymethods]
+impl LogFilter {
+    /// `query` is a `Query` in JSON, which selects everything if omitted.
+    /// `rule` is the name of a preset, see `RuleSet::from_name`.
+    #[new]
+    #[pyo3(signature = (query=None, *, riichi_decisions_only=false, rule=None))]
+    fn new_py(
+        query: Option<&str>,
+        riichi_decisions_only: bool,
+        rule: Option<&str>,
+    ) -> Result<Self> {
+        let query = match query {
+            Some(q) => json::from_str(q).context("invalid query")?,
+            None => Query::always(),
+        };
+        Ok(Self {
+            query,
+            riichi_decisions_only,
+            rule: rule.map(RuleSet::from_name).transpose()?,
+        })
+    }
+
+    #[getter]
+    fn query(&self) -> Result<String> {
+        Ok(json::to_string(&self.query)?)
+    }
+
+    /// Tells if anything of the log is selected. `filename` is only used for
+    /// tags.
+    #[pyo3(signature = (raw_log, filename=None))]
+    fn matches_log(&self, raw_log: &str, filename: Option<&str>) -> Result<bool> {
+        let events = raw_log
+            .lines()
+            .map(json::from_str)
+            .collect::<Result<Vec<Event>, _>>()
+            .context("failed to parse log")?;
+        self.matches_events(&events, filename)
+    }
+
+    /// Returns the filenames of the logs that have anything selected.
+    #[pyo3(name = "filter_gz_log_files")]
+    fn filter_gz_log_files_py(&self, gzip_filenames: Vec<String>) -> Result<Vec<String>> {
+        self.filter_gz_log_files(gzip_filenames)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl LogFilter {
+    /// Parses a filter in JSON, such as
+    /// `{"query": {"seats": [0]}, "riichi_decisions_only": true}`.
+    pub fn from_json(s: &str) -> Result<Self> {
+        json::from_str(s).context("invalid filter")
+    }
+
+    /// Returns whether each kyoku of the game is selected for `player_id`.
+    pub fn kyoku_mask(&self, game: &GameSummary, player_id: u8) -> Vec<bool> {
+        (0..game.kyokus.len())
+            .map(|i| {
+                self.query.matches(game, player_id, i)
+                    && (!self.riichi_decisions_only
+                        || game.kyokus[i].riichi_chances[player_id as usize])
+            })
+            .collect()
+    }
+
+    /// Summarizes the game under the rule of the filter, which must agree
+    /// with the log on sanma.
+    pub fn summarize(&self, events: &[Event], filename: Option<&str>) -> Result<GameSummary> {
+        let detected = convert::rule_of(events)?;
+        let rule = self.rule.unwrap_or(detected);
+        ensure!(
+            rule.sanma == detected.sanma,
+            "the rule of the filter does not match the number of players of the log",
+        );
+        GameSummary::new_with_rule(events, filename, rule)
+    }
+
+    pub fn matches_events(&self, events: &[Event], filename: Option<&str>) -> Result<bool> {
+        let game = self.summarize(events, filename)?;
+        let player_count = convert::rule_of(events)?.player_count();
+        let ret = (0..player_count).any(|i| self.kyoku_mask(&game, i).contains(&true));
+        Ok(ret)
+    }
+
+    pub fn filter_gz_log_files<V, S>(&self, gzip_filenames: V) -> Result<Vec<String>>
+    where
+        V: IntoParallelIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let kept: Vec<_> = gzip_filenames
+            .into_par_iter()
+            .map(|f| {
+                let filename = f.as_ref();
+                let inner = || {
+                    let file = File::open(filename)?;
+                    let gz = GzDecoder::new(file);
+                    let raw = io::read_to_string(gz)?;
+                    self.matches_log(&raw, Some(filename))
+                };
+                let matches = inner().with_context(|| format!("error when reading {filename}"))?;
+                anyhow::Ok(matches.then(|| filename.to_owned()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(kept.into_iter().flatten().collect())
+    }
+}
+
+impl GameSummary {
+    /// `filename` is the path of the log, which is only used for tags. The
+    /// rule is Tenhou's yonma or sanma rule, told from the log.
+    pub fn new(events: &[Event], filename: Option<&str>) -> Result<Self> {
+        Self::new_with_rule(events, filename, convert::rule_of(events)?)
+    }
+
+    /// Same as `new`, but judges the riichi chances and yakumans under
+    /// `rule`.
+    pub fn new_with_rule(events: &[Event], filename: Option<&str>, rule: RuleSet) -> Result<Self> {
+        let [Event::StartGame { names, .. }, ..] = events else {
+            bail!("empty or invalid game log");
+        };
+        let player_count = rule.player_count();
+        let sanma = player_count == 3;
+
+        let mut tags = filename.map(tenhou_tags).unwrap_or_default();
+        tags.insert(if sanma { "sanma" } else { "yonma" }.to_owned());
+
+        let grp = Grp::load_events(events)?;
+        let rank_by_player = if sanma {
+            Rankings::new_sanma(grp.final_scores).rank_by_player
+        } else {
+            grp.rank_by_player
+        };
+
+        let mut states: Vec<_> = (0..player_count)
+            .map(|i| PlayerState::new_with_rule(i, rule))
+            .collect();
+        let mut kyokus = vec![];
+        let mut rounds = vec![];
+        for (i, ev) in events.iter().enumerate() {
+            match *ev {
+                Event::StartKyoku {
+                    bakaze, kyoku, oya, ..
+                } => {
+                    rounds.push((bakaze.as_u8(), kyoku));
+                    kyokus.push(KyokuSummary {
+                        oya,
+                        ..Default::default()
+                    });
+                }
+                Event::Chi { actor, .. }
+                | Event::Pon { actor, .. }
+                | Event::Daiminkan { actor, .. } => {
+                    kyokus.last_mut().context("call outside kyoku")?.calls[actor as usize] = true;
+                }
+                Event::Reach { actor } => {
+                    kyokus.last_mut().context("reach outside kyoku")?.riichis[actor as usize] =
+                        true;
+                }
+                Event::Hora {
+                    actor,
+                    target,
+                    ref ura_markers,
+                    ..
+                } => {
+                    let is_ron = actor != target;
+                    let ura_markers = ura_markers.as_deref().unwrap_or_default();
+                    let detail = states
+                        .get(actor as usize)
+                        .context("hora by the vacant seat")?
+                        .agari_detail(is_ron, ura_markers)
+                        .with_context(|| format!("invalid hora at line {}", i + 1))?;
+                    let kyoku = kyokus.last_mut().context("hora outside kyoku")?;
+                    kyoku.horas[actor as usize] = true;
+                    if is_ron {
+                        kyoku.deal_ins[target as usize] = true;
+                    }
+                    kyoku.yakuman |= matches!(
+                        detail.agari,
+                        Agari::Yakuman(_) | Agari::Normal { han: 13.., .. }
+                    );
+                }
+                Event::Ryukyoku { .. } => {
+                    kyokus
+                        .last_mut()
+                        .context("ryukyoku outside kyoku")?
+                        .ryukyoku = true;
+                }
+                _ => (),
+            }
+
+            for (player_id, state) in states.iter_mut().enumerate() {
+                // Keep the cans on hora for the other winners of a multi-ron.
+                let cans = state
+                    .update_with_keep_cans(ev, true)
+                    .with_context(|| format!("player {player_id} at line {}", i + 1))?;
+                if cans.can_riichi {
+                    kyokus
+                        .last_mut()
+                        .context("riichi outside kyoku")?
+                        .riichi_chances[player_id] = true;
+                }
+            }
+        }
+
+        // The length of the game is not logged, so it is taken from the rule
+        // unless the filename tells otherwise. The kyokus of a sudden death
+        // after all-last are not counted.
+        let winds = if tags.contains("tonpuu") {
+            1
+        } else if tags.contains("hanchan") {
+            2
+        } else {
+            rule.game_length / player_count
+        };
+        let last_bakaze = tu8!(E) + winds.max(1) - 1;
+        for (kyoku, round) in kyokus.iter_mut().zip(rounds) {
+            kyoku.all_last = round == (last_bakaze, player_count);
+        }
+
+        Ok(Self {
+            tags,
+            names: names.clone(),
+            rank_by_player,
+            kyokus,
+        })
+    }
+}
+
+fn rule_by_name<'de, D>(deserializer: D) -> Result<Option<RuleSet>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Rule {
+        Name(String),
+        Full(RuleSet),
+    }
+    match Rule::deserialize(deserializer)? {
+        Rule::Name(name) => RuleSet::from_name(&name)
+            .map(Some)
+            .map_err(de::Error::custom),
+        Rule::Full(rule) => Ok(Some(rule)),
+    }
+}
+
+/// Decodes the type of a Tenhou game in the name of its log, e.g. `00a9` in
+/// `2019010100gm-00a9-0000-0123abcd`, into tags.
+fn tenhou_tags(filename: &str) -> AHashSet<String> {
+    let mut tags = AHashSet::new();
+    let name = Path::new(filename)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default();
+    let Some(ty) = name
+        .split_once("gm-")
+        .and_then(|(_, rest)| rest.get(..4))
+        .and_then(|hex| u16::from_str_radix(hex, 16).ok())
+    else {
+        return tags;
+    };
+
+    let room = match (ty & 0x80 != 0, ty & 0x20 != 0) {
+        (false, false) => "ippan",
+        (true, false) => "joukyuu",
+        (false, true) => "tokujou",
+        (true, true) => "houou",
+    };
+    let flags = [(0x02, "no_aka"), (0x04, "no_kuitan"), (0x40, "soku")];
+    tags.insert(room.to_owned());
+    tags.insert(if ty & 0x08 != 0 { "hanchan" } else { "tonpuu" }.to_owned());
+    tags.insert(if ty & 0x10 != 0 { "sanma" } else { "yonma" }.to_owned());
+    for (bit, tag) in flags {
+        if ty & bit != 0 {
+            tags.insert(tag.to_owned());
+        }
+    }
+    tags
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::BatchGame;
+    use crate::dataset::GameplayLoader;
+    use crate::rule::RuleSet;
+    use crate::t;
+    use crate::test_util::{events_of, run_arena};
+
+    fn tags(filename: &str) -> Vec<String> {
+        let mut ret: Vec<_> = tenhou_tags(filename).into_iter().collect();
+        ret.sort();
+        ret
+    }
+
+    #[test]
+    fn tenhou_tags_of_filename() {
+        assert_eq!(
+            tags("logs/2019/2019010100gm-00a9-0000-0123abcd.json.gz"),
+            ["hanchan", "houou", "yonma"],
+        );
+        assert_eq!(
+            tags("2019010100gm-00e1-0000-0123abcd"),
+            ["houou", "soku", "tonpuu", "yonma"],
+        );
+        assert_eq!(
+            tags("2019010100gm-0097-0000-0123abcd.json.gz"),
+            ["joukyuu", "no_aka", "no_kuitan", "sanma", "tonpuu"],
+        );
+        assert!(tags("2019010100gm-zzzz-0000-0123abcd").is_empty());
+        assert!(tags("game.json.gz").is_empty());
+    }
+
+    #[test]
+    fn query() {
+        let g = BatchGame {
+            rule: RuleSet::tenhou_tonpuu(),
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let result = run_arena(&g, (13, 13));
+        let events = events_of(&result);
+        let filename = "2019010100gm-00e1-0000-0123abcd.json.gz";
+        let game = GameSummary::new(&events, Some(filename)).unwrap();
+        let n = game.kyokus.len();
+        assert!(n >= 4);
+        let selected =
+            |q: &Query, player_id| (0..n).filter(|&i| q.matches(&game, player_id, i)).count();
+
+        let q: Query = json::from_str(r#"{"tags": ["houou", "tonpuu", "yonma"]}"#).unwrap();
+        assert_eq!(selected(&q, 0), n);
+        let q: Query = json::from_str(r#"{"tags": ["hanchan"]}"#).unwrap();
+        assert_eq!(selected(&q, 0), 0);
+
+        let q: Query = json::from_str(r#"{"rank": {"max": 1}}"#).unwrap();
+        let winners: Vec<_> = (0..4).filter(|&i| selected(&q, i) > 0).collect();
+        assert_eq!(winners, [result.rankings().player_by_rank[0]]);
+
+        let q: Query = json::from_str(r#""all_last""#).unwrap();
+        let rounds: Vec<_> = events
+            .iter()
+            .filter_map(|ev| match *ev {
+                Event::StartKyoku { bakaze, kyoku, .. } => Some((bakaze, kyoku)),
+                _ => None,
+            })
+            .collect();
+        for (kyoku, &(bakaze, k)) in game.kyokus.iter().zip(&rounds) {
+            assert_eq!(kyoku.all_last, bakaze == t!(E) && k == 4);
+        }
+        assert!(selected(&q, 0) > 0);
+        assert!(selected(&q, 0) < n);
+        // Without the tags, the length of the game is taken from the rule.
+        let untagged = GameSummary::new_with_rule(&events, None, g.rule).unwrap();
+        let all_last =
+            |game: &GameSummary| game.kyokus.iter().map(|k| k.all_last).collect::<Vec<_>>();
+        assert_eq!(all_last(&untagged), all_last(&game));
+        let hanchan = GameSummary::new(&events, None).unwrap();
+        for (kyoku, &(bakaze, k)) in hanchan.kyokus.iter().zip(&rounds) {
+            assert_eq!(kyoku.all_last, bakaze == t!(S) && k == 4);
+        }
+
+        let calls = Query::Calls;
+        let no_calls = Query::Not(Box::new(Query::Calls));
+        assert!(selected(&calls, 0) > 0);
+        assert_eq!(selected(&calls, 0) + selected(&no_calls, 0), n);
+        let q = Query::Any(vec![Query::Seats(vec![2]), Query::PlayerCalls]);
+        assert_eq!(selected(&q, 2), n);
+        assert!((0..4).all(|i| selected(&Query::PlayerCalls, i) <= selected(&calls, i)));
+
+        for kyoku in &game.kyokus {
+            assert_eq!(kyoku.ryukyoku, !kyoku.horas.contains(&true));
+            for i in 0..4 {
+                assert!(!kyoku.riichis[i] || kyoku.riichi_chances[i]);
+            }
+        }
+
+        let filter = LogFilter::from_json(
+            r#"{"query": {"not": {"seats": [0, 1, 2]}}, "riichi_decisions_only": true}"#,
+        )
+        .unwrap();
+        assert!(filter.matches_events(&events, None).unwrap());
+        let loader = GameplayLoader::new(
+            4,
+            false,
+            None,
+            None,
+            false,
+            true,
+            false,
+            None,
+            false,
+            Some(filter.clone()),
+        );
+        let games = loader.load_events(&events).unwrap();
+        assert_eq!(games.len(), 1);
+        let data = &games[0];
+        assert_eq!(data.player_id, 3);
+        assert!(!data.actions.is_empty());
+        assert_eq!(data.dones.len(), data.actions.len());
+        let mask = filter.kyoku_mask(&game, 3);
+        for (m, &kyoku) in data.masks.iter().zip(&data.at_kyoku) {
+            assert!(m[37]);
+            assert!(mask[kyoku as usize]);
+        }
+
+        let filter = LogFilter {
+            query: Query::Not(Box::new(Query::always())),
+            ..Default::default()
+        };
+        assert!(!filter.matches_events(&events, None).unwrap());
+        let loader = GameplayLoader::new(
+            4,
+            false,
+            None,
+            None,
+            false,
+            true,
+            false,
+            None,
+            false,
+            Some(filter),
+        );
+        assert!(loader.load_events(&events).unwrap().is_empty());
+
+        let filter = LogFilter::from_json(r#"{"rule": "mahjong-soul"}"#).unwrap();
+        assert_eq!(filter.rule, Some(RuleSet::mahjong_soul()));
+        assert!(filter.matches_events(&events, None).unwrap());
+        let json = json::to_string(&filter).unwrap();
+        assert_eq!(LogFilter::from_json(&json).unwrap(), filter);
+        let filter = LogFilter::from_json(r#"{"rule": "tenhou-sanma"}"#).unwrap();
+        filter.matches_events(&events, None).unwrap_err();
+        LogFilter::from_json(r#"{"rule": "riichi-city"}"#).unwrap_err();
+    }
+}
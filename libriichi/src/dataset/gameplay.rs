@@ -1,14 +1,17 @@
-use super::{Grp, Invisible};
+use super::{AugmentConfig, Grp, Invisible, LogFilter};
 use crate::chi_type::ChiType;
 use crate::mjai::Event;
+use crate::rankings::Rankings;
+use crate::rule::RuleSet;
 use crate::state::PlayerState;
 use std::array;
+use std::borrow::Cow;
 use std::fs::File;
 use std::io;
 use std::mem;
 
 use ahash::AHashSet;
-use anyhow::{Context, Result, bail};
+use anyhow::{Context, Result, bail, ensure};
 use derivative::Derivative;
 use flate2::read::GzDecoder;
 use ndarray::prelude::*;
@@ -34,8 +37,19 @@ pub struct GameplayLoader {
     trust_seed: bool,
     #[pyo3(get)]
     always_include_kan_select: bool,
+    /// Applies the fixed suit swap of `Tile::augment` to every log.
     #[pyo3(get)]
     augmented: bool,
+    /// Applies a symmetry picked per log instead, see `AugmentConfig`.
+    #[pyo3(get)]
+    augment: Option<AugmentConfig>,
+    /// Loads the logs as Tenhou's sanma, in which seat 3 is vacant. Oracle and
+    /// augmentation are not supported for sanma.
+    #[pyo3(get)]
+    sanma: bool,
+    /// Only extracts the samples selected by the filter.
+    #[pyo3(get)]
+    filter: Option<LogFilter>,
 
     #[derivative(Debug = "ignore")]
     player_names_set: AHashSet<String>,
@@ -69,6 +83,7 @@ struct LoaderContext<'a> {
 
     state: PlayerState,
     kyoku_idx: usize,
+    kyoku_mask: Option<Vec<bool>>,
 
     // fields below are only used for oracle
     opponent_states: [PlayerState; 3],
@@ -89,8 +104,12 @@ impl GameplayLoader {
         trust_seed = false,
         always_include_kan_select = true,
         augmented = false,
+        augment = None,
+        sanma = false,
+        filter = None,
     ))]
-    fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
         version: u32,
         oracle: bool,
         player_names: Option<Vec<String>>,
@@ -98,6 +117,9 @@ impl GameplayLoader {
         trust_seed: bool,
         always_include_kan_select: bool,
         augmented: bool,
+        augment: Option<AugmentConfig>,
+        sanma: bool,
+        filter: Option<LogFilter>,
     ) -> Self {
         let player_names = player_names.unwrap_or_default();
         let player_names_set = player_names.iter().cloned().collect();
@@ -111,6 +133,9 @@ impl GameplayLoader {
             trust_seed,
             always_include_kan_select,
             augmented,
+            augment,
+            sanma,
+            filter,
             player_names_set,
             excludes_set,
         }
@@ -118,15 +143,7 @@ impl GameplayLoader {
 
     // Nested result is too hard to handle...
     fn load_log(&self, raw_log: &str) -> Result<Vec<Gameplay>> {
-        let mut events = raw_log
-            .lines()
-            .map(json::from_str)
-            .collect::<Result<Vec<Event>, _>>()
-            .context("failed to parse log")?;
-        if self.augmented {
-            events.iter_mut().for_each(Event::augment);
-        }
-        self.load_events(&events)
+        self.load_log_with_filename(raw_log, None)
     }
 
     #[pyo3(name = "load_gz_log_files")]
@@ -140,6 +157,22 @@ impl GameplayLoader {
 }
 
 impl GameplayLoader {
+    fn load_log_with_filename(
+        &self,
+        raw_log: &str,
+        filename: Option<&str>,
+    ) -> Result<Vec<Gameplay>> {
+        let mut events = raw_log
+            .lines()
+            .map(json::from_str)
+            .collect::<Result<Vec<Event>, _>>()
+            .context("failed to parse log")?;
+        if self.augmented {
+            events.iter_mut().for_each(Event::augment);
+        }
+        self.load_events_with_filename(&events, filename)
+    }
+
     pub fn load_gz_log_files<V, S>(&self, gzip_filenames: V) -> Result<Vec<Vec<Gameplay>>>
     where
         V: IntoParallelIterator<Item = S>,
@@ -153,7 +186,7 @@ impl GameplayLoader {
                     let file = File::open(filename)?;
                     let gz = GzDecoder::new(file);
                     let raw = io::read_to_string(gz)?;
-                    self.load_log(&raw)
+                    self.load_log_with_filename(&raw, Some(filename))
                 };
                 inner().with_context(|| format!("error when reading {filename}"))
             })
@@ -161,7 +194,58 @@ impl GameplayLoader {
     }
 
     pub fn load_events(&self, events: &[Event]) -> Result<Vec<Gameplay>> {
-        let invisibles = self.oracle.then(|| Invisible::new(events, self.trust_seed));
+        self.load_events_with_filename(events, None)
+    }
+
+    /// `filename` is the path of the log, which is only used for the tags of
+    /// the filter.
+    pub fn load_events_with_filename(
+        &self,
+        events: &[Event],
+        filename: Option<&str>,
+    ) -> Result<Vec<Gameplay>> {
+        if self.sanma {
+            ensure!(!self.oracle, "oracle is not supported for sanma");
+            ensure!(
+                !self.augmented && self.augment.is_none(),
+                "augmentation is not supported for sanma",
+            );
+        }
+        ensure!(
+            !(self.augmented && self.augment.is_some()),
+            "augmented and augment are exclusive",
+        );
+        ensure!(
+            !(self.oracle && self.augment.is_some_and(|a| a.winds)),
+            "wind augmentation is not supported for oracle",
+        );
+        let player_count = self.rule().player_count() as usize;
+
+        let mut grp = Grp::load_events(events)?;
+        if self.sanma {
+            // The vacant seat must not be ranked above anyone.
+            grp.rank_by_player = Rankings::new_sanma(grp.final_scores).rank_by_player;
+        }
+
+        // Everything but the events fed to the states stays in the frame of
+        // the original log, including the player IDs of the gameplays.
+        let augmentation = self.augment.map(|a| a.pick(events));
+        let augmented_events = augmentation.map(|a| {
+            let mut events = events.to_vec();
+            a.apply(&mut events);
+            events
+        });
+        let mut invisibles = self.oracle.then(|| Invisible::new(events, self.trust_seed));
+        if let (Some(a), Some(invisibles)) = (augmentation, &mut invisibles) {
+            for inv in invisibles {
+                inv.map_tiles(|t| a.tile(t));
+            }
+        }
+        let summary = self
+            .filter
+            .as_ref()
+            .map(|f| f.summarize(events, filename))
+            .transpose()?;
 
         let [Event::StartGame { names, .. }, ..] = events else {
             bail!("empty or invalid game log");
@@ -169,6 +253,7 @@ impl GameplayLoader {
         names
             .iter()
             .enumerate()
+            .take(player_count)
             .filter(|&(_, name)| {
                 if !self.player_names_set.is_empty() {
                     return self.player_names_set.contains(name);
@@ -179,13 +264,58 @@ impl GameplayLoader {
                 true
             })
             .map(|(i, _)| i as u8)
+            .filter_map(|player_id| {
+                let kyoku_mask = self
+                    .filter
+                    .as_ref()
+                    .zip(summary.as_ref())
+                    .map(|(f, s)| f.kyoku_mask(s, player_id));
+                if kyoku_mask.as_ref().is_some_and(|m| !m.contains(&true)) {
+                    return None;
+                }
+                Some((player_id, kyoku_mask))
+            })
             .collect::<ArrayVec<[_; 4]>>()
             .into_par_iter()
-            .map(|&player_id| {
-                Gameplay::load_events_by_player(self, events, player_id, invisibles.as_deref())
+            .map(|(player_id, kyoku_mask)| {
+                let Some(a) = augmentation else {
+                    return Gameplay::load_events_by_player(
+                        self,
+                        events,
+                        *player_id,
+                        grp.clone(),
+                        kyoku_mask.clone(),
+                        invisibles.as_deref(),
+                    );
+                };
+                let seat = a.seat(*player_id);
+                let mut events = Cow::Borrowed(augmented_events.as_deref().unwrap_or(events));
+                if a.wind_key.is_some() {
+                    a.apply_winds(events.to_mut(), seat);
+                }
+                let mut data = Gameplay::load_events_by_player(
+                    self,
+                    &events,
+                    seat,
+                    grp.clone(),
+                    kyoku_mask.clone(),
+                    invisibles.as_deref(),
+                )?;
+                data.player_id = *player_id;
+                Ok(data)
             })
+            // Do not yield the gameplays left empty by the filter.
+            .filter(|data| !matches!(data, Ok(d) if d.actions.is_empty()))
             .collect()
     }
+
+    fn rule(&self) -> RuleSet {
+        if self.sanma {
+            RuleSet::tenhou_sanma()
+        } else {
+            RuleSet::default()
+        }
+    }
 }
 
 #[pymethods]
@@ -241,10 +371,10 @@ impl Gameplay {
         config: &GameplayLoader,
         events: &[Event],
         player_id: u8,
+        grp: Grp,
+        kyoku_mask: Option<Vec<bool>>,
         invisibles: Option<&[Invisible]>,
     ) -> Result<Self> {
-        let grp = Grp::load_events(events)?;
-
         let mut data = Self {
             grp,
             player_id,
@@ -254,8 +384,9 @@ impl Gameplay {
         let mut ctx = LoaderContext {
             config,
             invisibles,
-            state: PlayerState::new(player_id),
+            state: PlayerState::new_with_rule(player_id, config.rule()),
             kyoku_idx: 0,
+            kyoku_mask,
             // end_state: EndState::Passive,
             opponent_states: array::from_fn(|i| PlayerState::new((player_id + i as u8 + 1) % 4)),
             from_rinshan: false,
@@ -285,6 +416,7 @@ impl Gameplay {
             invisibles,
             state,
             kyoku_idx,
+            kyoku_mask,
             opponent_states,
             from_rinshan,
             yama_idx,
@@ -336,6 +468,14 @@ impl Gameplay {
         if !cans.can_act() {
             return Ok(());
         }
+        if kyoku_mask.as_ref().is_some_and(|m| !m[*kyoku_idx])
+            || config
+                .filter
+                .as_ref()
+                .is_some_and(|f| f.riichi_decisions_only && !cans.can_riichi)
+        {
+            return Ok(());
+        }
 
         let mut kan_select = None;
         let label_opt = match *next {
@@ -371,6 +511,7 @@ impl Gameplay {
                 Some(42)
             }
             Event::Ryukyoku { .. } if cans.can_ryukyoku => Some(44),
+            Event::Nukidora { .. } if config.version >= 5 => Some(38),
             _ => {
                 let mut ret = None;
 
@@ -95,18 +95,16 @@ impl Grp {
 
         for ev in events.iter().rev() {
             match *ev {
-                Event::Hora { deltas, .. } | Event::Ryukyoku { deltas, .. } => {
-                    if rank_by_player_opt.is_none() {
-                        let ds = deltas.context(
-                            "invalid log: field `deltas` is required for Hora and Ryukyoku of AL",
-                        )?;
-                        vec_add_assign(&mut final_deltas, &ds);
-                    }
+                Event::Hora { deltas, .. } | Event::Ryukyoku { deltas, .. }
+                    if rank_by_player_opt.is_none() =>
+                {
+                    let ds = deltas.context(
+                        "invalid log: field `deltas` is required for Hora and Ryukyoku of AL",
+                    )?;
+                    vec_add_assign(&mut final_deltas, &ds);
                 }
-                Event::ReachAccepted { actor } => {
-                    if rank_by_player_opt.is_none() {
-                        final_deltas[actor as usize] -= 1000;
-                    }
+                Event::ReachAccepted { actor } if rank_by_player_opt.is_none() => {
+                    final_deltas[actor as usize] -= 1000;
                 }
                 Event::StartKyoku {
                     bakaze,
@@ -147,6 +147,16 @@ impl Invisible {
         ret
     }
 
+    /// Replaces every tile with `f(tile)`.
+    pub fn map_tiles(&mut self, f: impl Fn(Tile) -> Tile) {
+        self.yama
+            .iter_mut()
+            .chain(&mut self.rinshan)
+            .chain(&mut self.dora_indicators)
+            .chain(&mut self.ura_indicators)
+            .for_each(|t| *t = f(*t));
+    }
+
     // TODO: merge this this arena::board::BoardState::encode_oracle_obs; they
     // should be identical.
     pub fn encode(
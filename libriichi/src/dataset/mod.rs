@@ -1,13 +1,19 @@
 //! Sample extractions.
 
+mod augment;
+mod filter;
 mod gameplay;
 mod grp;
 mod invisible;
+mod shard;
 
 use crate::py_helper::add_submodule;
+pub use augment::{AugmentConfig, Augmentation};
+pub use filter::{GameSummary, KyokuSummary, LogFilter, Query};
 pub use gameplay::{Gameplay, GameplayLoader};
 pub use grp::Grp;
 pub use invisible::Invisible;
+pub use shard::{ShardEntry, ShardIndex, ShardReader, ShardWriter, load_shard_files};
 
 use pyo3::prelude::*;
 
@@ -17,8 +23,11 @@ pub(crate) fn register_module(
     super_mod: &Bound<'_, PyModule>,
 ) -> PyResult<()> {
     let m = PyModule::new(py, "dataset")?;
+    m.add_class::<AugmentConfig>()?;
     m.add_class::<Gameplay>()?;
     m.add_class::<GameplayLoader>()?;
     m.add_class::<Grp>()?;
+    m.add_class::<LogFilter>()?;
+    m.add_function(wrap_pyfunction!(shard::load_shard_files_py, &m)?)?;
     add_submodule(py, prefix, super_mod, &m)
 }
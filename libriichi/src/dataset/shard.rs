@@ -0,0 +1,574 @@
+use super::{Gameplay, Grp};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use ndarray::prelude::*;
+use pyo3::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+const MAGIC: &[u8; 4] = b"MSHD";
+const FORMAT_VERSION: u32 = 1;
+const INDEX: &str = "index.json";
+
+// Every record starts with one of these, so that a truncated shard is told
+// apart from a complete one.
+const TAG_GAMEPLAY: u8 = 1;
+const TAG_END: u8 = 0;
+
+/// `index.json` of a shard directory.
+///
+/// The fields other than `shards` are the options of the `GameplayLoader`
+/// the shards were encoded with, which a consumer should check against its
+/// own config, as the encoded obs depend on them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardIndex {
+    pub format_version: u32,
+    pub version: u32,
+    pub oracle: bool,
+    pub augmented: bool,
+    pub sanma: bool,
+    pub shards: Vec<ShardEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardEntry {
+    /// Relative to the shard directory.
+    pub file: String,
+    /// Number of `Gameplay`s, that is, number of (game, player) pairs.
+    pub games: usize,
+    /// Total number of moves of the `Gameplay`s.
+    pub samples: usize,
+}
+
+/// Encodes `Gameplay`s into gzipped binary shards of at most
+/// `games_per_shard` each, named `shard-{n:05}.bin.gz`, along with an
+/// `index.json`.
+///
+/// Each shard is written to a temporary file first, and the index is only
+/// written by `finish`, so an interrupted run never leaves a directory that
+/// looks complete.
+pub struct ShardWriter {
+    dir: PathBuf,
+    games_per_shard: usize,
+    index: ShardIndex,
+    current: Option<(BufWriter<GzEncoder<File>>, ShardEntry)>,
+}
+
+/// Streams the `Gameplay`s in a shard one by one.
+pub struct ShardReader<R: Read> {
+    inner: BufReader<GzDecoder<R>>,
+    done: bool,
+}
+
+impl ShardIndex {
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self> {
+        let path = dir.as_ref().join(INDEX);
+        let raw = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let index: Self = json::from_str(&raw)?;
+        ensure!(
+            index.format_version == FORMAT_VERSION,
+            "unsupported shard format version {} in {}",
+            index.format_version,
+            path.display(),
+        );
+        Ok(index)
+    }
+
+    #[must_use]
+    pub fn games(&self) -> usize {
+        self.shards.iter().map(|s| s.games).sum()
+    }
+
+    #[must_use]
+    pub fn samples(&self) -> usize {
+        self.shards.iter().map(|s| s.samples).sum()
+    }
+}
+
+impl ShardWriter {
+    pub fn new(
+        dir: impl AsRef<Path>,
+        version: u32,
+        oracle: bool,
+        augmented: bool,
+        sanma: bool,
+        games_per_shard: usize,
+    ) -> Result<Self> {
+        ensure!(games_per_shard > 0, "games_per_shard must be positive");
+        let dir = dir.as_ref().to_owned();
+        fs::create_dir_all(&dir)?;
+        ensure!(
+            !dir.join(INDEX).exists(),
+            "{} already contains shards",
+            dir.display(),
+        );
+
+        Ok(Self {
+            dir,
+            games_per_shard,
+            index: ShardIndex {
+                format_version: FORMAT_VERSION,
+                version,
+                oracle,
+                augmented,
+                sanma,
+                shards: vec![],
+            },
+            current: None,
+        })
+    }
+
+    pub fn write(&mut self, game: &Gameplay) -> Result<()> {
+        if self.index.oracle {
+            ensure!(
+                game.invisible_obs.len() == game.obs.len(),
+                "oracle shards require invisible_obs",
+            );
+        }
+
+        let (w, entry) = match &mut self.current {
+            Some(current) => current,
+            None => {
+                let file = format!("shard-{:05}.bin.gz", self.index.shards.len());
+                let f = File::create(self.dir.join(format!("{file}.tmp")))?;
+                let mut w = BufWriter::new(GzEncoder::new(f, Compression::default()));
+                w.write_all(MAGIC)?;
+                w.write_u32::<LittleEndian>(FORMAT_VERSION)?;
+                let entry = ShardEntry {
+                    file,
+                    games: 0,
+                    samples: 0,
+                };
+                self.current.insert((w, entry))
+            }
+        };
+
+        w.write_u8(TAG_GAMEPLAY)?;
+        write_gameplay(w, game)?;
+        entry.games += 1;
+        entry.samples += game.obs.len();
+
+        if entry.games >= self.games_per_shard {
+            self.close_shard()?;
+        }
+        Ok(())
+    }
+
+    /// Closes the last shard and writes the index.
+    pub fn finish(mut self) -> Result<ShardIndex> {
+        self.close_shard()?;
+
+        let tmp = self.dir.join(format!("{INDEX}.tmp"));
+        fs::write(&tmp, json::to_string_pretty(&self.index)?)?;
+        fs::rename(&tmp, self.dir.join(INDEX))?;
+        Ok(self.index)
+    }
+
+    fn close_shard(&mut self) -> Result<()> {
+        let Some((mut w, entry)) = self.current.take() else {
+            return Ok(());
+        };
+        w.write_u8(TAG_END)?;
+        let f = w
+            .into_inner()
+            .map_err(io::IntoInnerError::into_error)?
+            .finish()?;
+        f.sync_all()?;
+
+        let tmp = self.dir.join(format!("{}.tmp", entry.file));
+        fs::rename(tmp, self.dir.join(&entry.file))?;
+        self.index.shards.push(entry);
+        Ok(())
+    }
+}
+
+impl ShardReader<File> {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<R: Read> ShardReader<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        let mut inner = BufReader::new(GzDecoder::new(reader));
+        let mut magic = [0; 4];
+        inner.read_exact(&mut magic)?;
+        ensure!(&magic == MAGIC, "not a shard");
+        let format_version = inner.read_u32::<LittleEndian>()?;
+        ensure!(
+            format_version == FORMAT_VERSION,
+            "unsupported shard format version {format_version}",
+        );
+        Ok(Self { inner, done: false })
+    }
+}
+
+impl<R: Read> Iterator for ShardReader<R> {
+    type Item = Result<Gameplay>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let ret = match self.inner.read_u8() {
+            Ok(TAG_GAMEPLAY) => read_gameplay(&mut self.inner),
+            Ok(TAG_END) => {
+                self.done = true;
+                return None;
+            }
+            Ok(tag) => Err(anyhow!("invalid record tag {tag}")),
+            Err(err) => Err(err).context("truncated shard"),
+        };
+        if ret.is_err() {
+            self.done = true;
+        }
+        Some(ret)
+    }
+}
+
+/// Reads the shard files in parallel, the counterpart of
+/// `GameplayLoader::load_gz_log_files`.
+pub fn load_shard_files<V, S>(filenames: V) -> Result<Vec<Vec<Gameplay>>>
+where
+    V: IntoParallelIterator<Item = S>,
+    S: AsRef<str>,
+{
+    filenames
+        .into_par_iter()
+        .map(|f| {
+            let filename = f.as_ref();
+            ShardReader::open(filename)
+                .and_then(|r| r.collect())
+                .with_context(|| format!("error when reading {filename}"))
+        })
+        .collect()
+}
+
+#[pyfunction]
+#[pyo3(name = "load_shard_files")]
+pub(super) fn load_shard_files_py(filenames: Vec<String>) -> Result<Vec<Vec<Gameplay>>> {
+    load_shard_files(filenames)
+}
+
+fn write_gameplay(w: &mut impl Write, game: &Gameplay) -> Result<()> {
+    let n = game.obs.len();
+    ensure!(
+        [
+            game.actions.len(),
+            game.masks.len(),
+            game.at_kyoku.len(),
+            game.dones.len(),
+            game.apply_gamma.len(),
+            game.at_turns.len(),
+            game.shantens.len(),
+        ]
+        .iter()
+        .all(|&l| l == n),
+        "per move fields of the gameplay have different lengths",
+    );
+    ensure!(
+        game.invisible_obs.is_empty() || game.invisible_obs.len() == n,
+        "invisible_obs has a different length from obs",
+    );
+
+    w.write_u8(game.player_id)?;
+    write_len(w, game.player_name.len())?;
+    w.write_all(game.player_name.as_bytes())?;
+    write_len(w, n)?;
+
+    write_arrays(w, &game.obs)?;
+    write_arrays(w, &game.invisible_obs)?;
+    for &v in &game.actions {
+        w.write_i64::<LittleEndian>(v)?;
+    }
+    let mask_len = game.masks.first().map_or(0, |m| m.len());
+    write_len(w, mask_len)?;
+    for mask in &game.masks {
+        ensure!(mask.len() == mask_len, "masks have different lengths");
+        for &v in mask {
+            w.write_u8(v as u8)?;
+        }
+    }
+    w.write_all(&game.at_kyoku)?;
+    for &v in game.dones.iter().chain(&game.apply_gamma) {
+        w.write_u8(v as u8)?;
+    }
+    w.write_all(&game.at_turns)?;
+    for &v in &game.shantens {
+        w.write_i8(v)?;
+    }
+
+    let (rows, cols) = game.grp.feature.dim();
+    write_len(w, rows)?;
+    write_len(w, cols)?;
+    for &v in &game.grp.feature {
+        w.write_f64::<LittleEndian>(v)?;
+    }
+    w.write_all(&game.grp.rank_by_player)?;
+    for &v in &game.grp.final_scores {
+        w.write_i32::<LittleEndian>(v)?;
+    }
+    Ok(())
+}
+
+fn read_gameplay(r: &mut impl Read) -> Result<Gameplay> {
+    let player_id = r.read_u8()?;
+    let len = read_len(r)?;
+    let player_name = String::from_utf8(read_bytes(r, len, 1)?)?;
+    let n = read_len(r)?;
+
+    let obs = read_arrays(r, n)?;
+    let invisible_obs = read_arrays(r, n)?;
+    let mut actions = vec![0; n];
+    LittleEndian::read_i64_into(&read_bytes(r, n, 8)?, &mut actions);
+    let mask_len = read_len(r)?;
+    let masks = if mask_len == 0 {
+        vec![Array1::default(0); n]
+    } else {
+        read_bytes(r, n, mask_len)?
+            .chunks(mask_len)
+            .map(|mask| mask.iter().map(|&v| v != 0).collect())
+            .collect()
+    };
+    let at_kyoku = read_bytes(r, n, 1)?;
+    let dones = read_bools(r, n)?;
+    let apply_gamma = read_bools(r, n)?;
+    let at_turns = read_bytes(r, n, 1)?;
+    let shantens = read_bytes(r, n, 1)?.into_iter().map(|v| v as i8).collect();
+
+    let rows = read_len(r)?;
+    let cols = read_len(r)?;
+    let bytes = read_bytes(r, rows.saturating_mul(cols), 8)?;
+    let mut feature = vec![0.; rows * cols];
+    LittleEndian::read_f64_into(&bytes, &mut feature);
+    let feature = Array2::from_shape_vec((rows, cols), feature)?;
+    let mut rank_by_player = [0; 4];
+    r.read_exact(&mut rank_by_player)?;
+    let mut final_scores = [0; 4];
+    r.read_i32_into::<LittleEndian>(&mut final_scores)?;
+
+    Ok(Gameplay {
+        obs,
+        invisible_obs,
+        actions,
+        masks,
+        at_kyoku,
+        dones,
+        apply_gamma,
+        at_turns,
+        shantens,
+        grp: Grp {
+            feature,
+            rank_by_player,
+            final_scores,
+        },
+        player_id,
+        player_name,
+    })
+}
+
+/// All the arrays of a gameplay share the same shape, so it is only written
+/// once.
+fn write_arrays(w: &mut impl Write, arrays: &[Array2<f32>]) -> Result<()> {
+    write_len(w, arrays.len())?;
+    let Some(first) = arrays.first() else {
+        return Ok(());
+    };
+    let (rows, cols) = first.dim();
+    write_len(w, rows)?;
+    write_len(w, cols)?;
+    for arr in arrays {
+        ensure!(arr.dim() == (rows, cols), "obs have different shapes");
+        for &v in arr {
+            w.write_f32::<LittleEndian>(v)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_arrays(r: &mut impl Read, n: usize) -> Result<Vec<Array2<f32>>> {
+    let len = read_len(r)?;
+    if len == 0 {
+        return Ok(vec![]);
+    }
+    if len != n {
+        bail!("expected {n} arrays, got {len}");
+    }
+    let rows = read_len(r)?;
+    let cols = read_len(r)?;
+    (0..len)
+        .map(|_| {
+            let bytes = read_bytes(r, rows.saturating_mul(cols), 4)?;
+            let mut data = vec![0.; rows * cols];
+            LittleEndian::read_f32_into(&bytes, &mut data);
+            Ok(Array2::from_shape_vec((rows, cols), data)?)
+        })
+        .collect()
+}
+
+fn read_bools(r: &mut impl Read, n: usize) -> Result<Vec<bool>> {
+    Ok(read_bytes(r, n, 1)?.into_iter().map(|v| v != 0).collect())
+}
+
+/// Reads `count` items of `size` bytes each.
+///
+/// The lengths in a shard are not trusted to allocate the buffer up front, as
+/// the decompressed size of what is left is unknown. The buffer grows as the
+/// bytes arrive instead, so a corrupted length fails as a truncated shard
+/// rather than allocating whatever it says.
+fn read_bytes(r: &mut impl Read, count: usize, size: usize) -> Result<Vec<u8>> {
+    let len = count
+        .checked_mul(size)
+        .with_context(|| format!("invalid length {count} * {size}"))?;
+    let mut buf = vec![];
+    r.take(len as u64).read_to_end(&mut buf)?;
+    ensure!(
+        buf.len() == len,
+        "truncated shard, expected {len} bytes, got {}",
+        buf.len(),
+    );
+    Ok(buf)
+}
+
+fn write_len(w: &mut impl Write, len: usize) -> Result<()> {
+    w.write_u32::<LittleEndian>(len.try_into()?)?;
+    Ok(())
+}
+
+fn read_len(r: &mut impl Read) -> Result<usize> {
+    Ok(r.read_u32::<LittleEndian>()? as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::{ActionWeights, BatchAgent, RandomAgent};
+    use crate::arena::{BatchGame, Index};
+    use crate::dataset::GameplayLoader;
+    use crate::mjai::Event;
+    use crate::rule::RuleSet;
+    use std::array;
+    use std::env;
+
+    fn assert_gameplay_eq(l: &Gameplay, r: &Gameplay) {
+        assert_eq!(l.obs, r.obs);
+        assert_eq!(l.invisible_obs, r.invisible_obs);
+        assert_eq!(l.actions, r.actions);
+        assert_eq!(l.masks, r.masks);
+        assert_eq!(l.at_kyoku, r.at_kyoku);
+        assert_eq!(l.dones, r.dones);
+        assert_eq!(l.apply_gamma, r.apply_gamma);
+        assert_eq!(l.at_turns, r.at_turns);
+        assert_eq!(l.shantens, r.shantens);
+        assert_eq!(l.grp.feature, r.grp.feature);
+        assert_eq!(l.grp.rank_by_player, r.grp.rank_by_player);
+        assert_eq!(l.grp.final_scores, r.grp.final_scores);
+        assert_eq!(l.player_id, r.player_id);
+        assert_eq!(l.player_name, r.player_name);
+    }
+
+    #[test]
+    fn round_trip() {
+        let g = BatchGame {
+            rule: RuleSet::tenhou_tonpuu(),
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let weights = ActionWeights {
+            chi: 5.,
+            pon: 5.,
+            ..Default::default()
+        };
+        let mut agents = [
+            Box::new(RandomAgent::new_batched(&[0, 1, 2, 3], 0, weights).unwrap())
+                as Box<dyn BatchAgent>,
+        ];
+        let indexes = [array::from_fn(|i| Index {
+            agent_idx: 0,
+            player_id_idx: i,
+        })];
+        let results = g.run(&mut agents, &indexes, &[(11, 11)]).unwrap();
+        let events: Vec<Event> = results[0]
+            .dump_json_log()
+            .unwrap()
+            .lines()
+            .map(|l| json::from_str(l).unwrap())
+            .collect();
+
+        let loader = GameplayLoader::new(4, true, None, None, true, true, false, None, false, None);
+        let games = loader.load_events(&events).unwrap();
+        assert_eq!(games.len(), 4);
+
+        let dir = env::temp_dir().join(format!("riichi-shards-{}", std::process::id()));
+        fs::remove_dir_all(&dir).ok();
+        let mut writer = ShardWriter::new(&dir, 4, true, false, false, 3).unwrap();
+        for game in &games {
+            writer.write(game).unwrap();
+        }
+        let index = writer.finish().unwrap();
+        assert_eq!(index.shards.len(), 2);
+        assert_eq!(index.games(), 4);
+        assert_eq!(
+            index.samples(),
+            games.iter().map(|g| g.obs.len()).sum::<usize>(),
+        );
+        assert!(ShardWriter::new(&dir, 4, true, false, false, 3).is_err());
+
+        let loaded = ShardIndex::load(&dir).unwrap();
+        assert_eq!(loaded.version, 4);
+        assert!(loaded.oracle);
+        let files: Vec<_> = loaded
+            .shards
+            .iter()
+            .map(|s| dir.join(&s.file).to_string_lossy().into_owned())
+            .collect();
+        let decoded: Vec<_> = load_shard_files(&files)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect();
+        assert_eq!(decoded.len(), games.len());
+        for (l, r) in games.iter().zip(&decoded) {
+            assert!(!l.obs.is_empty());
+            assert!(!l.invisible_obs.is_empty());
+            assert_gameplay_eq(l, r);
+        }
+
+        // A truncated shard must fail rather than end silently.
+        let raw = fs::read(&files[0]).unwrap();
+        let mut plain = vec![];
+        GzDecoder::new(raw.as_slice())
+            .read_to_end(&mut plain)
+            .unwrap();
+        plain.truncate(plain.len() - 1);
+        let mut truncated = GzEncoder::new(vec![], Compression::fast());
+        truncated.write_all(&plain).unwrap();
+        let truncated = truncated.finish().unwrap();
+        let results: Vec<_> = ShardReader::new(truncated.as_slice()).unwrap().collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[..3].iter().all(Result::is_ok));
+        assert!(results[3].is_err());
+
+        // So must a corrupted length, without allocating what it says.
+        // Magic, format version, tag and player ID come before the length of
+        // the first player name.
+        plain[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut corrupted = GzEncoder::new(vec![], Compression::fast());
+        corrupted.write_all(&plain).unwrap();
+        let corrupted = corrupted.finish().unwrap();
+        let mut reader = ShardReader::new(corrupted.as_slice()).unwrap();
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
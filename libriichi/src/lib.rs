@@ -71,13 +71,12 @@
     clippy::string_add,
     clippy::string_add_assign,
     clippy::string_lit_as_bytes,
-    clippy::string_to_string,
     clippy::suboptimal_flops,
     clippy::suspicious_to_owned,
     clippy::trait_duplication_in_bounds,
     clippy::trivially_copy_pass_by_ref,
     clippy::type_repetition_in_bounds,
-    clippy::unchecked_duration_subtraction,
+    clippy::unchecked_time_subtraction,
     clippy::undocumented_unsafe_blocks,
     clippy::unicode_not_nfc,
     clippy::uninlined_format_args,
@@ -93,23 +92,27 @@
     clippy::useless_let_if_seq
 )]
 
-mod arena;
 mod array;
 mod consts;
-mod dataset;
 mod macros;
 mod py_helper;
 mod rankings;
+#[cfg(test)]
+mod test_util;
 mod vec_ops;
 
 // pub for bins
 pub mod chi_type;
+pub mod dataset;
 pub mod mjai;
+pub mod rating;
+pub mod rule;
 pub mod stat;
 pub mod state;
 
 // pub for non-cfg(test) tests
 pub mod agent;
+pub mod arena;
 pub mod tile;
 
 // pub for benchmarks
@@ -129,9 +132,11 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 ///   `state.PlayerState`).
 /// - Read mjai logs and produce a batch of instances for training (via
 ///   `dataset`).
-/// - Self-play under standard Tenhou rules (via `arena`).
+/// - Self-play under Tenhou or other configurable rules (via `arena`).
 /// - Definitions of observation and action space for Mortal (via `consts`).
 /// - Statistical works on mjai logs (via `stat.Stat`).
+/// - Hand analysis such as shanten, ukeire, agari and single-player tables (via
+///   `algo`).
 /// - mjai interface (via `mjai.Bot`).
 #[pymodule]
 fn libriichi(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
@@ -154,7 +159,9 @@ fn libriichi(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     dataset::register_module(py, name, m)?;
     arena::register_module(py, name, m)?;
     stat::register_module(py, name, m)?;
+    rating::register_module(py, name, m)?;
     mjai::register_module(py, name, m)?;
+    algo::register_module(py, name, m)?;
 
     Ok(())
 }
@@ -0,0 +1,931 @@
+use super::{pad_scores, place_doras};
+use crate::mjai::Event;
+use crate::rule::RuleSet;
+use crate::tile::Tile;
+use crate::{must_tile, t, tu8};
+use std::mem;
+
+use anyhow::{Context, Result, bail, ensure};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self as json, Value};
+
+/// Converts a decoded Mahjong Soul game record into mjai events.
+///
+/// The record is the JSON form of the protobuf messages, with the `head`
+/// and the list of `.lq.Record*` messages, either as `records` or wrapped in
+/// `actions`, directly or under `data`. As protobuf omits the fields of
+/// default values, every missing field is taken as 0, false or empty.
+///
+/// Mahjong Soul records where the rule differs from Tenhou's:
+///
+/// 1. The deltas of a multi-ron are given as a whole, so they are split by
+///    `point_rong` of each winner, with the honba and kyotaku going to the
+///    first one in turn order. The points already account for double
+///    yakuman, so no rule is needed for them.
+/// 2. Abortive draws are `RecordLiuJu` with no deltas, where 四家立直 accepts
+///    the last riichi first.
+/// 3. The importer checks nothing against the rule, so a riichi at furiten
+///    or an ankan after riichi that changes the waits is imported as is, and
+///    left to `validate`.
+///
+/// The rule is `RuleSet::mahjong_soul` or `RuleSet::mahjong_soul_sanma`.
+///
+/// Mahjong Soul may reveal the dora of a minkan right away, which `arena`
+/// would not do before a rinshan kaihou, so such a dora is dropped.
+pub fn from_mahjong_soul(raw: &str) -> Result<(Vec<Event>, RuleSet)> {
+    let paipu: Value = json::from_str(raw).context("failed to parse Mahjong Soul record")?;
+    let head: Head = de(&paipu["head"]).context("invalid head")?;
+    let records = [
+        &paipu["data"]["records"],
+        &paipu["data"]["actions"],
+        &paipu["records"],
+        &paipu["actions"],
+    ]
+    .into_iter()
+    .find_map(Value::as_array)
+    .context("no records in the log")?;
+
+    let mut names: [String; 4] = Default::default();
+    for account in head.accounts {
+        let name = names
+            .get_mut(account.seat as usize)
+            .context("invalid seat of account")?;
+        *name = account.nickname;
+    }
+    let mut conv = Converter {
+        n: 4,
+        events: vec![Event::StartGame {
+            id: None,
+            names,
+            seed: None,
+        }],
+        kyoku: vec![],
+        hands: Default::default(),
+        pons: Default::default(),
+        dora_count: 0,
+        honba: 0,
+        kyotaku: 0,
+        last_discarder: None,
+        riichi_to_be_accepted: None,
+    };
+    for (i, record) in records.iter().enumerate() {
+        // An action is either a record or a user input.
+        let record = match record.get("result") {
+            Some(result) => result,
+            None if record.get("name").is_some() => record,
+            None => continue,
+        };
+        let name = record["name"]
+            .as_str()
+            .context("record without name")?
+            .trim_start_matches(".lq.");
+        conv.on_record(name, &record["data"])
+            .with_context(|| format!("on record #{i} {name}"))?;
+    }
+    ensure!(conv.kyoku.is_empty(), "incomplete kyoku at the end");
+    ensure!(conv.events.len() > 1, "no kyoku in the log");
+
+    conv.events.push(Event::EndGame);
+
+    let rule = if conv.n == 3 {
+        RuleSet::mahjong_soul_sanma()
+    } else {
+        RuleSet::mahjong_soul()
+    };
+    Ok((conv.events, rule))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Head {
+    accounts: Vec<Account>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Account {
+    seat: u8,
+    nickname: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct NewRound {
+    chang: u8,
+    ju: u8,
+    ben: u8,
+    liqibang: u8,
+    scores: Vec<i32>,
+    tiles0: Vec<String>,
+    tiles1: Vec<String>,
+    tiles2: Vec<String>,
+    tiles3: Vec<String>,
+    doras: Vec<String>,
+    /// The dora indicator of old records.
+    dora: String,
+}
+
+/// The common fields of `RecordDealTile`, `RecordDiscardTile`,
+/// `RecordChiPengGang`, `RecordAnGangAddGang` and `RecordBaBei`.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Move {
+    seat: u8,
+    #[serde(rename = "type")]
+    kind: u8,
+    tile: String,
+    /// A list for `RecordChiPengGang` and a single tile for
+    /// `RecordAnGangAddGang`.
+    tiles: Value,
+    froms: Vec<u8>,
+    moqie: bool,
+    is_liqi: bool,
+    is_wliqi: bool,
+    /// All the dora indicators revealed so far.
+    doras: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Hule {
+    hules: Vec<HuleInfo>,
+    delta_scores: Vec<i32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct HuleInfo {
+    seat: u8,
+    zimo: bool,
+    liqi: bool,
+    li_doras: Vec<String>,
+    point_rong: i32,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct NoTile {
+    /// One for each nagashi mangan, or a single one otherwise.
+    scores: Vec<NoTileScore>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct NoTileScore {
+    delta_scores: Vec<i32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LiuJu {
+    #[serde(rename = "type")]
+    kind: u8,
+}
+
+struct Converter {
+    n: u8,
+    events: Vec<Event>,
+    kyoku: Vec<Event>,
+    hands: [Vec<Tile>; 4],
+    /// The called tile and the consumed tiles of each pon, for kakan.
+    pons: [Vec<[Tile; 3]>; 4],
+    dora_count: usize,
+    honba: u8,
+    kyotaku: u8,
+    /// The target of a ron, which can also be a kakan or a nukidora.
+    last_discarder: Option<u8>,
+    riichi_to_be_accepted: Option<u8>,
+}
+
+/// Deserializes a field that protobuf may have omitted.
+fn de<T: DeserializeOwned + Default>(v: &Value) -> Result<T> {
+    if v.is_null() {
+        return Ok(T::default());
+    }
+    Ok(T::deserialize(v)?)
+}
+
+impl Converter {
+    fn on_record(&mut self, name: &str, data: &Value) -> Result<()> {
+        if name != "RecordNewRound" {
+            ensure!(!self.kyoku.is_empty(), "{name} outside of a kyoku");
+        }
+        match name {
+            "RecordNewRound" => self.start_kyoku(&de(data)?)?,
+            "RecordDealTile" => {
+                let mv: Move = de(data)?;
+                let actor = self.seat(mv.seat)?;
+                self.accept_riichi();
+                let pai = tile(&mv.tile)?;
+                self.hands[actor as usize].push(pai);
+                self.kyoku.push(Event::Tsumo { actor, pai });
+                self.add_doras(&mv.doras)?;
+            }
+            "RecordDiscardTile" => {
+                let mv: Move = de(data)?;
+                let actor = self.seat(mv.seat)?;
+                let pai = tile(&mv.tile)?;
+                let riichi = mv.is_liqi || mv.is_wliqi;
+                if riichi {
+                    self.kyoku.push(Event::Reach { actor });
+                }
+                self.take(actor, pai)?;
+                self.kyoku.push(Event::Dahai {
+                    actor,
+                    pai,
+                    tsumogiri: mv.moqie,
+                });
+                if riichi {
+                    self.riichi_to_be_accepted = Some(actor);
+                }
+                self.last_discarder = Some(actor);
+                self.add_doras(&mv.doras)?;
+            }
+            "RecordChiPengGang" => {
+                let mv: Move = de(data)?;
+                self.accept_riichi();
+                self.call(&mv)?;
+                self.add_doras(&mv.doras)?;
+            }
+            "RecordAnGangAddGang" => {
+                let mv: Move = de(data)?;
+                self.kan(&mv)?;
+                self.add_doras(&mv.doras)?;
+            }
+            "RecordBaBei" => {
+                let mv: Move = de(data)?;
+                let actor = self.seat(mv.seat)?;
+                self.take(actor, t!(N))?;
+                self.kyoku.push(Event::Nukidora { actor, pai: t!(N) });
+                self.last_discarder = Some(actor);
+                self.add_doras(&mv.doras)?;
+            }
+            "RecordHule" => {
+                self.hora(&de(data)?)?;
+                self.end_kyoku()?;
+            }
+            "RecordNoTile" => {
+                let no_tile: NoTile = de(data)?;
+                let mut deltas = [0; 4];
+                for score in &no_tile.scores {
+                    if score.delta_scores.is_empty() {
+                        continue;
+                    }
+                    for (d, s) in deltas.iter_mut().zip(pad_scores(&score.delta_scores)?) {
+                        *d += s;
+                    }
+                }
+                self.kyoku.push(Event::Ryukyoku {
+                    deltas: Some(deltas),
+                });
+                self.end_kyoku()?;
+            }
+            "RecordLiuJu" => {
+                let liuju: LiuJu = de(data)?;
+                // 四家立直
+                if liuju.kind == 4 {
+                    self.accept_riichi();
+                }
+                self.kyoku.push(Event::Ryukyoku {
+                    deltas: Some([0; 4]),
+                });
+                self.end_kyoku()?;
+            }
+            _ => bail!("unsupported record"),
+        }
+        Ok(())
+    }
+
+    fn start_kyoku(&mut self, round: &NewRound) -> Result<()> {
+        ensure!(self.kyoku.is_empty(), "new round before the end of a kyoku");
+        // The vacant seat of sanma has no haipai.
+        self.n = if round.tiles3.is_empty() { 3 } else { 4 };
+        ensure!(
+            round.chang < 4 && round.ju < self.n,
+            "invalid round {}-{}",
+            round.chang,
+            round.ju,
+        );
+        let oya = round.ju;
+        let dora_marker = match round.doras.first() {
+            Some(dora) => tile(dora)?,
+            None => tile(&round.dora)?,
+        };
+
+        let mut tehais = [[t!(?); 13]; 4];
+        let haipais = [&round.tiles0, &round.tiles1, &round.tiles2, &round.tiles3];
+        let mut first_tsumo = None;
+        for (i, haipai) in haipais.into_iter().enumerate().take(self.n as usize) {
+            let hand = haipai.iter().map(|s| tile(s)).collect::<Result<Vec<_>>>()?;
+            // The oya's first tsumo is included in the haipai.
+            let expected = if i as u8 == oya { 14 } else { 13 };
+            ensure!(
+                hand.len() == expected,
+                "expected {expected} tiles in the haipai of seat {i}, got {}",
+                hand.len(),
+            );
+            tehais[i].copy_from_slice(&hand[..13]);
+            if i as u8 == oya {
+                first_tsumo = Some(hand[13]);
+            }
+            self.hands[i] = hand;
+        }
+        self.pons = Default::default();
+        self.dora_count = 1;
+        self.honba = round.ben;
+        self.kyotaku = round.liqibang;
+        self.last_discarder = None;
+        self.riichi_to_be_accepted = None;
+
+        self.kyoku.push(Event::StartKyoku {
+            bakaze: must_tile!(tu8!(E) + round.chang),
+            dora_marker,
+            kyoku: oya + 1,
+            honba: round.ben,
+            kyotaku: round.liqibang,
+            oya,
+            scores: pad_scores(&round.scores)?,
+            tehais,
+        });
+        self.kyoku.push(Event::Tsumo {
+            actor: oya,
+            pai: first_tsumo.context("no haipai of oya")?,
+        });
+        Ok(())
+    }
+
+    fn call(&mut self, mv: &Move) -> Result<()> {
+        let actor = self.seat(mv.seat)?;
+        let tiles: Vec<String> = de(&mv.tiles)?;
+        ensure!(tiles.len() == mv.froms.len(), "tiles and froms mismatch");
+        let mut pai = None;
+        let mut consumed = vec![];
+        for (s, &from) in tiles.iter().zip(&mv.froms) {
+            let t = tile(s)?;
+            if from == actor {
+                consumed.push(t);
+            } else {
+                ensure!(pai.is_none(), "more than one called tile");
+                pai = Some((t, self.seat(from)?));
+            }
+        }
+        let (pai, target) = pai.context("no called tile")?;
+        for &t in &consumed {
+            self.take(actor, t)?;
+        }
+
+        let ev = match mv.kind {
+            0 => Event::Chi {
+                actor,
+                target,
+                pai,
+                consumed: consumed.as_slice().try_into()?,
+            },
+            1 => {
+                let consumed: [Tile; 2] = consumed.as_slice().try_into()?;
+                self.pons[actor as usize].push([pai, consumed[0], consumed[1]]);
+                Event::Pon {
+                    actor,
+                    target,
+                    pai,
+                    consumed,
+                }
+            }
+            2 => Event::Daiminkan {
+                actor,
+                target,
+                pai,
+                consumed: consumed.as_slice().try_into()?,
+            },
+            k => bail!("unknown call type {k}"),
+        };
+        self.kyoku.push(ev);
+        Ok(())
+    }
+
+    fn kan(&mut self, mv: &Move) -> Result<()> {
+        let actor = self.seat(mv.seat)?;
+        let kan_tile = tile(&de::<String>(&mv.tiles)?)?.deaka();
+        let hand = &mut self.hands[actor as usize];
+        match mv.kind {
+            // ankan
+            3 => {
+                let consumed: Vec<_> = hand
+                    .iter()
+                    .copied()
+                    .filter(|t| t.deaka() == kan_tile)
+                    .collect();
+                hand.retain(|t| t.deaka() != kan_tile);
+                self.kyoku.push(Event::Ankan {
+                    actor,
+                    consumed: consumed
+                        .as_slice()
+                        .try_into()
+                        .context("ankan without 4 tiles")?,
+                });
+            }
+            // kakan
+            2 => {
+                let idx = hand
+                    .iter()
+                    .position(|t| t.deaka() == kan_tile)
+                    .context("kakan tile not in hand")?;
+                let pai = hand.swap_remove(idx);
+                let consumed = *self.pons[actor as usize]
+                    .iter()
+                    .find(|pon| pon[0].deaka() == kan_tile)
+                    .context("kakan without pon")?;
+                self.kyoku.push(Event::Kakan {
+                    actor,
+                    pai,
+                    consumed,
+                });
+                self.last_discarder = Some(actor);
+            }
+            k => bail!("unknown kan type {k}"),
+        }
+        Ok(())
+    }
+
+    fn hora(&mut self, hule: &Hule) -> Result<()> {
+        ensure!(!hule.hules.is_empty(), "no hule");
+        let n = self.n;
+        let (first_actor, is_tsumo) = (hule.hules[0].seat, hule.hules[0].zimo);
+        let target = if is_tsumo {
+            ensure!(hule.hules.len() == 1, "multiple tsumo");
+            self.seat(first_actor)?
+        } else {
+            self.last_discarder.context("ron without discard")?
+        };
+
+        // In turn order starting from the target, as arena does.
+        let mut hules: Vec<_> = hule.hules.iter().collect();
+        hules.sort_by_key(|h| (h.seat + n - target) % n);
+        let total = pad_scores(&hule.delta_scores)?;
+        let mut horas = vec![];
+        let mut sum = [0; 4];
+        for (i, h) in hules.iter().enumerate() {
+            let actor = self.seat(h.seat)?;
+            let deltas = if hules.len() == 1 {
+                total
+            } else {
+                let mut deltas = [0; 4];
+                let mut point = h.point_rong;
+                if i == 0 {
+                    point += self.honba as i32 * 300;
+                    deltas[actor as usize] += self.kyotaku as i32 * 1000;
+                }
+                deltas[actor as usize] += point;
+                deltas[target as usize] -= point;
+                deltas
+            };
+            for (s, d) in sum.iter_mut().zip(deltas) {
+                *s += d;
+            }
+            let ura_markers = if h.liqi {
+                h.li_doras.iter().map(|s| tile(s)).collect::<Result<_>>()?
+            } else {
+                vec![]
+            };
+            horas.push(Event::Hora {
+                actor,
+                target,
+                deltas: Some(deltas),
+                ura_markers: Some(ura_markers),
+            });
+        }
+        ensure!(
+            sum == total,
+            "the deltas of the multi-ron do not add up to {total:?}",
+        );
+        self.kyoku.extend(horas);
+        Ok(())
+    }
+
+    fn end_kyoku(&mut self) -> Result<()> {
+        let (mut events, left) = place_doras(mem::take(&mut self.kyoku))?;
+        ensure!(left <= 1, "{left} dora indicators are never revealed");
+        events.push(Event::EndKyoku);
+        self.events.extend(events);
+        Ok(())
+    }
+
+    fn add_doras(&mut self, doras: &[String]) -> Result<()> {
+        for dora in doras.iter().skip(self.dora_count) {
+            self.kyoku.push(Event::Dora {
+                dora_marker: tile(dora)?,
+            });
+        }
+        self.dora_count = self.dora_count.max(doras.len());
+        Ok(())
+    }
+
+    fn accept_riichi(&mut self) {
+        if let Some(actor) = self.riichi_to_be_accepted.take() {
+            self.kyotaku += 1;
+            self.kyoku.push(Event::ReachAccepted { actor });
+        }
+    }
+
+    fn take(&mut self, actor: u8, tile: Tile) -> Result<()> {
+        let hand = &mut self.hands[actor as usize];
+        let idx = hand
+            .iter()
+            .position(|&t| t == tile)
+            .with_context(|| format!("{tile} not in the hand of seat {actor}"))?;
+        hand.swap_remove(idx);
+        Ok(())
+    }
+
+    fn seat(&self, seat: u8) -> Result<u8> {
+        ensure!(seat < self.n, "invalid seat {seat}");
+        Ok(seat)
+    }
+}
+
+/// Converts a tile like `1m`, `0p` for aka 5p, or `5z` for P.
+fn tile(s: &str) -> Result<Tile> {
+    let &[num, suit] = s.as_bytes() else {
+        bail!("invalid tile {s:?}");
+    };
+    let num = num.wrapping_sub(b'0');
+    let tile = match (suit, num) {
+        (b'm' | b'p' | b's', 0..=9) => {
+            let base = match suit {
+                b'm' => 0,
+                b'p' => 9,
+                _ => 18,
+            };
+            if num == 0 {
+                return Ok(must_tile!(base + 4).akaize());
+            }
+            base + num - 1
+        }
+        (b'z', 1..=7) => tu8!(E) + num - 1,
+        _ => bail!("invalid tile {s:?}"),
+    };
+    Ok(must_tile!(tile))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::BatchGame;
+    use crate::mjai::EventExt;
+    use crate::mjai::convert::test::normalized;
+    use crate::mjai::convert::validate;
+    use crate::rule::RuleSet;
+    use crate::test_util::run_arena;
+    use serde_json::json;
+
+    /// Encodes arena logs into Mahjong Soul records, wrapped in actions and
+    /// with the default values omitted like protobuf does.
+    #[derive(Default)]
+    struct Encoder {
+        actions: Vec<Value>,
+        names: Vec<Value>,
+        new_round: Option<Value>,
+        doras: Vec<String>,
+        new_doras: bool,
+        riichi: bool,
+        honba: i32,
+        kyotaku: i32,
+        horas: Vec<Value>,
+        hora_deltas: [i32; 4],
+        last: Event,
+    }
+
+    fn ms(tile: Tile) -> String {
+        let id = tile.deaka().as_u8();
+        let (num, suit) = match id {
+            0..=26 => (id % 9 + 1, ['m', 'p', 's'][id as usize / 9]),
+            _ => (id - tu8!(E) + 1, 'z'),
+        };
+        let num = if tile.is_aka() { 0 } else { num };
+        format!("{num}{suit}")
+    }
+
+    fn strip_defaults(v: &mut Value) {
+        match v {
+            Value::Object(map) => {
+                map.retain(|_, v| {
+                    !matches!(v, Value::Bool(false))
+                        && v.as_i64() != Some(0)
+                        && v.as_str() != Some("")
+                        && v.as_array().is_none_or(|a| !a.is_empty())
+                });
+                map.values_mut().for_each(strip_defaults);
+            }
+            Value::Array(list) => list.iter_mut().for_each(strip_defaults),
+            _ => (),
+        }
+    }
+
+    impl Encoder {
+        fn push(&mut self, name: &str, mut data: Value) {
+            if self.new_doras {
+                self.new_doras = false;
+                data["doras"] = json!(self.doras);
+            }
+            strip_defaults(&mut data);
+            self.actions.push(json!({
+                "type": 1,
+                "result": {"name": format!(".lq.{name}"), "data": data},
+            }));
+            // A user input, which is skipped.
+            self.actions
+                .push(json!({"type": 2, "user_input": {"seat": 1}}));
+        }
+
+        fn flush_horas(&mut self) {
+            if self.horas.is_empty() {
+                return;
+            }
+            let hules = json!(self.horas);
+            let deltas = self.hora_deltas;
+            self.push(
+                "RecordHule",
+                json!({"hules": hules, "delta_scores": deltas}),
+            );
+            self.horas.clear();
+            self.hora_deltas = [0; 4];
+        }
+
+        fn event(&mut self, ev: &Event) {
+            if !matches!(ev, Event::Hora { .. }) {
+                self.flush_horas();
+            }
+            match *ev {
+                Event::StartGame { ref names, .. } => {
+                    for (seat, name) in names.iter().enumerate() {
+                        self.names.push(json!({"seat": seat, "nickname": name}));
+                    }
+                }
+                Event::StartKyoku {
+                    bakaze,
+                    dora_marker,
+                    kyoku,
+                    honba,
+                    kyotaku,
+                    scores,
+                    ref tehais,
+                    ..
+                } => {
+                    let n = if tehais[3][0].is_unknown() { 3 } else { 4 };
+                    self.doras = vec![ms(dora_marker)];
+                    self.honba = honba as i32;
+                    self.kyotaku = kyotaku as i32;
+                    let mut round = json!({
+                        "chang": bakaze.as_u8() - tu8!(E),
+                        "ju": kyoku - 1,
+                        "ben": honba,
+                        "liqibang": kyotaku,
+                        "scores": scores[..n],
+                        "doras": self.doras,
+                    });
+                    for (i, tehai) in tehais.iter().enumerate().take(n) {
+                        round[format!("tiles{i}")] = json!(tehai.map(ms));
+                    }
+                    self.new_round = Some(round);
+                }
+                Event::Tsumo { actor, pai } => match self.new_round.take() {
+                    Some(mut round) => {
+                        round[format!("tiles{actor}")]
+                            .as_array_mut()
+                            .unwrap()
+                            .push(ms(pai).into());
+                        self.push("RecordNewRound", round);
+                    }
+                    None => self.push("RecordDealTile", json!({"seat": actor, "tile": ms(pai)})),
+                },
+                Event::Dahai {
+                    actor,
+                    pai,
+                    tsumogiri,
+                } => {
+                    let is_liqi = mem::take(&mut self.riichi);
+                    self.push(
+                        "RecordDiscardTile",
+                        json!({"seat": actor, "tile": ms(pai), "moqie": tsumogiri, "is_liqi": is_liqi}),
+                    );
+                }
+                Event::Reach { .. } => self.riichi = true,
+                Event::ReachAccepted { .. } => self.kyotaku += 1,
+                Event::Chi {
+                    actor,
+                    target,
+                    pai,
+                    consumed,
+                } => self.call(0, actor, target, pai, &consumed),
+                Event::Pon {
+                    actor,
+                    target,
+                    pai,
+                    consumed,
+                } => self.call(1, actor, target, pai, &consumed),
+                Event::Daiminkan {
+                    actor,
+                    target,
+                    pai,
+                    consumed,
+                } => self.call(2, actor, target, pai, &consumed),
+                Event::Ankan { actor, consumed } => self.push(
+                    "RecordAnGangAddGang",
+                    json!({"seat": actor, "type": 3, "tiles": ms(consumed[0].deaka())}),
+                ),
+                Event::Kakan { actor, pai, .. } => self.push(
+                    "RecordAnGangAddGang",
+                    json!({"seat": actor, "type": 2, "tiles": ms(pai)}),
+                ),
+                Event::Nukidora { actor, .. } => self.push("RecordBaBei", json!({"seat": actor})),
+                Event::Dora { dora_marker } => {
+                    self.doras.push(ms(dora_marker));
+                    self.new_doras = true;
+                }
+                Event::Hora {
+                    actor,
+                    target,
+                    deltas,
+                    ref ura_markers,
+                } => {
+                    let deltas = deltas.unwrap();
+                    let mut point_rong = deltas[actor as usize];
+                    if self.horas.is_empty() {
+                        point_rong -= self.honba * 300 + self.kyotaku * 1000;
+                    }
+                    let ura: Vec<_> = ura_markers.iter().flatten().map(|&t| ms(t)).collect();
+                    self.horas.push(json!({
+                        "seat": actor,
+                        "zimo": actor == target,
+                        "liqi": !ura.is_empty(),
+                        "li_doras": ura,
+                        "point_rong": point_rong,
+                    }));
+                    for (s, d) in self.hora_deltas.iter_mut().zip(deltas) {
+                        *s += d;
+                    }
+                }
+                Event::Ryukyoku { deltas } => {
+                    let deltas = deltas.unwrap();
+                    if deltas != [0; 4] {
+                        self.push(
+                            "RecordNoTile",
+                            json!({"scores": [{"delta_scores": deltas}]}),
+                        );
+                    } else if matches!(self.last, Event::Tsumo { .. }) {
+                        self.push("RecordLiuJu", json!({"type": 1}));
+                    } else if matches!(self.last, Event::ReachAccepted { .. }) {
+                        self.push("RecordLiuJu", json!({"type": 4}));
+                    } else {
+                        self.push("RecordNoTile", json!({}));
+                    }
+                }
+                _ => (),
+            }
+            self.last = ev.clone();
+        }
+
+        fn call(&mut self, kind: u8, actor: u8, target: u8, pai: Tile, consumed: &[Tile]) {
+            let mut tiles: Vec<_> = consumed.iter().map(|&t| ms(t)).collect();
+            let mut froms = vec![actor; consumed.len()];
+            tiles.push(ms(pai));
+            froms.push(target);
+            self.push(
+                "RecordChiPengGang",
+                json!({"seat": actor, "type": kind, "tiles": tiles, "froms": froms}),
+            );
+        }
+
+        fn finish(self) -> String {
+            json!({
+                "head": {"accounts": self.names},
+                "data": {"actions": self.actions},
+            })
+            .to_string()
+        }
+    }
+
+    fn round_trip(game: &BatchGame, seed: (u64, u64)) -> Vec<Event> {
+        let result = run_arena(game, seed);
+        let log: Vec<EventExt> = result.game_log.into_iter().flatten().collect();
+
+        let mut enc = Encoder::default();
+        enc.event(&Event::StartGame {
+            id: None,
+            names: result.names,
+            seed: None,
+        });
+        for ev in &log {
+            enc.event(&ev.event);
+        }
+        let raw = enc.finish();
+
+        let (events, rule) = from_mahjong_soul(&raw).unwrap();
+        assert_eq!(rule.sanma, game.rule.sanma);
+        assert!(rule.double_yakuman);
+        validate(&events, rule).unwrap();
+        assert_eq!(
+            normalized(
+                &events[1..events.len() - 1]
+                    .iter()
+                    .cloned()
+                    .map(EventExt::from)
+                    .collect::<Vec<_>>()
+            ),
+            normalized(&log),
+        );
+        events
+    }
+
+    #[test]
+    fn arena_round_trip() {
+        let game = BatchGame {
+            rule: RuleSet {
+                game_length: 4,
+                ..RuleSet::mahjong_soul()
+            },
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let events: Vec<_> = (0..12)
+            .flat_map(|seed| round_trip(&game, (seed, seed)))
+            .collect();
+        for kind in ["chi", "pon", "daiminkan", "kakan", "ankan", "reach", "hora"] {
+            assert!(
+                events
+                    .iter()
+                    .any(|ev| json::to_value(ev).unwrap()["type"] == kind),
+                "no {kind} in the games",
+            );
+        }
+
+        let sanma = BatchGame {
+            rule: RuleSet::tenhou_sanma(),
+            init_scores: [35000, 35000, 35000, 0],
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let events = round_trip(&sanma, (7, 7));
+        assert!(events.iter().any(|ev| matches!(ev, Event::Nukidora { .. })));
+    }
+
+    #[test]
+    fn multi_ron() {
+        let mut conv = Converter {
+            n: 4,
+            events: vec![],
+            kyoku: vec![Event::EndKyoku],
+            hands: Default::default(),
+            pons: Default::default(),
+            dora_count: 1,
+            honba: 1,
+            kyotaku: 1,
+            last_discarder: Some(0),
+            riichi_to_be_accepted: None,
+        };
+        let mut hule: Hule = de(&json!({
+            "hules": [
+                {"seat": 2, "point_rong": 8000},
+                {"seat": 1, "point_rong": 3900, "liqi": true, "li_doras": ["5z"]},
+            ],
+            "delta_scores": [-12200, 5200, 8000],
+        }))
+        .unwrap();
+        conv.hora(&hule).unwrap();
+        assert_eq!(
+            conv.kyoku[1..],
+            [
+                Event::Hora {
+                    actor: 1,
+                    target: 0,
+                    deltas: Some([-4200, 5200, 0, 0]),
+                    ura_markers: Some(vec![t!(P)]),
+                },
+                Event::Hora {
+                    actor: 2,
+                    target: 0,
+                    deltas: Some([-8000, 0, 8000, 0]),
+                    ura_markers: Some(vec![]),
+                },
+            ],
+        );
+
+        // Deltas giving the honba to seat 2 instead do not add up.
+        hule.delta_scores = vec![-12200, 4900, 8300, 0];
+        conv.hora(&hule).unwrap_err();
+    }
+
+    #[test]
+    fn tiles() {
+        assert_eq!(tile("1m").unwrap(), t!(1m));
+        assert_eq!(tile("0p").unwrap(), t!(5pr));
+        assert_eq!(tile("9s").unwrap(), t!(9s));
+        assert_eq!(tile("1z").unwrap(), t!(E));
+        assert_eq!(tile("7z").unwrap(), t!(C));
+        tile("0z").unwrap_err();
+        tile("8z").unwrap_err();
+        tile("5").unwrap_err();
+        tile("5mr").unwrap_err();
+    }
+}
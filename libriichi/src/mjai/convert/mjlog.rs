@@ -0,0 +1,903 @@
+use super::{pad_scores, reorder_doras};
+use crate::mjai::Event;
+use crate::rule::RuleSet;
+use crate::tile::Tile;
+use crate::{must_tile, t, tu8};
+use std::array;
+use std::mem;
+
+use anyhow::{Context, Result, anyhow, bail, ensure};
+
+/// Converts a Tenhou mjlog, the XML one, into mjai events.
+///
+/// It must already be decompressed. Tags that do not affect the game, such
+/// as `SHUFFLE` and `BYE`, are ignored.
+///
+/// The rule is Tenhou's, with the length, aka doras, kuitan and sanma taken
+/// from the type of the game in `GO`.
+pub fn from_mjlog(raw: &str) -> Result<(Vec<Event>, RuleSet)> {
+    let mut conv = Converter {
+        rule: RuleSet::tenhou(),
+        names: None,
+        events: vec![],
+        kyoku: vec![],
+        last_tsumo: [None; 4],
+    };
+    for tag in Tags(raw) {
+        let tag = tag?;
+        conv.on_tag(&tag)
+            .with_context(|| format!("on tag <{} {:?}>", tag.name, tag.attrs))?;
+    }
+    conv.finish()
+}
+
+struct Converter {
+    rule: RuleSet,
+    names: Option<[String; 4]>,
+    events: Vec<Event>,
+    kyoku: Vec<Event>,
+    /// The 136-tile ID of the last tsumo of each player, for `tsumogiri`.
+    last_tsumo: [Option<u32>; 4],
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    attrs: Vec<(&'a str, &'a str)>,
+}
+
+/// Iterates the tags of an XML document without nesting or text content,
+/// which is all mjlog needs.
+struct Tags<'a>(&'a str);
+
+impl Converter {
+    fn on_tag(&mut self, tag: &Tag<'_>) -> Result<()> {
+        let name = tag.name;
+        if let Some((actor, id)) = parse_move(name, "TUVW") {
+            self.last_tsumo[actor as usize] = Some(id);
+            self.kyoku.push(Event::Tsumo {
+                actor,
+                pai: self.tile(id)?,
+            });
+            return Ok(());
+        }
+        if let Some((actor, id)) = parse_move(name, "DEFG") {
+            let tsumogiri = self.last_tsumo[actor as usize].take() == Some(id);
+            self.kyoku.push(Event::Dahai {
+                actor,
+                pai: self.tile(id)?,
+                tsumogiri,
+            });
+            return Ok(());
+        }
+
+        match name {
+            "GO" => {
+                let typ: u32 = tag.attr("type")?.parse()?;
+                let base = if typ & 0x10 == 0 {
+                    RuleSet::tenhou()
+                } else {
+                    RuleSet::tenhou_sanma()
+                };
+                self.rule = RuleSet {
+                    game_length: if typ & 0x08 == 0 {
+                        base.player_count()
+                    } else {
+                        base.game_length
+                    },
+                    aka_count: if typ & 0x02 == 0 { base.aka_count } else { 0 },
+                    kuitan: typ & 0x04 == 0,
+                    ..base
+                };
+            }
+            // `UN` is sent again on reconnection with only the name of the
+            // one reconnecting.
+            "UN" if self.names.is_none() => {
+                let names = array::from_fn(|i| {
+                    tag.get(&format!("n{i}"))
+                        .map(percent_decode)
+                        .transpose()
+                        .map(Option::unwrap_or_default)
+                });
+                let [a, b, c, d] = names;
+                self.names = Some([a?, b?, c?, d?]);
+            }
+            "INIT" => self.start_kyoku(tag)?,
+            "N" => {
+                let actor = self.actor(tag)?;
+                let m = tag.attr("m")?.parse()?;
+                let ev = self.meld(actor, m)?;
+                self.kyoku.push(ev);
+            }
+            "REACH" => {
+                let actor = self.actor(tag)?;
+                match tag.attr("step")? {
+                    "1" => self.kyoku.push(Event::Reach { actor }),
+                    "2" => self.kyoku.push(Event::ReachAccepted { actor }),
+                    s => bail!("invalid step {s}"),
+                }
+            }
+            "DORA" => {
+                let dora_marker = self.tile(tag.attr("hai")?.parse()?)?;
+                self.kyoku.push(Event::Dora { dora_marker });
+            }
+            "AGARI" => {
+                let ura_markers = match tag.get("doraHaiUra") {
+                    Some(s) => parse_list(s)?
+                        .into_iter()
+                        .map(|id| self.tile(id))
+                        .collect::<Result<_>>()?,
+                    None => vec![],
+                };
+                self.kyoku.push(Event::Hora {
+                    actor: self.actor(tag)?,
+                    target: self.seat(tag.attr("fromWho")?)?,
+                    deltas: Some(parse_deltas(tag.attr("sc")?)?),
+                    ura_markers: Some(ura_markers),
+                });
+            }
+            "RYUUKYOKU" => {
+                self.kyoku.push(Event::Ryukyoku {
+                    deltas: Some(parse_deltas(tag.attr("sc")?)?),
+                });
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn start_kyoku(&mut self, tag: &Tag<'_>) -> Result<()> {
+        self.end_kyoku()?;
+
+        let seed = parse_list(tag.attr("seed")?)?;
+        let &[kyoku, honba, kyotaku, _, _, dora] = seed.as_slice() else {
+            bail!("invalid seed");
+        };
+        let scores: Vec<_> = parse_list(tag.attr("ten")?)?
+            .into_iter()
+            .map(|s| s as i32 * 100)
+            .collect();
+        let n = self.rule.player_count() as usize;
+        let mut tehais = [[t!(?); 13]; 4];
+        for (i, tehai) in tehais.iter_mut().enumerate().take(n) {
+            let tiles = parse_list(tag.attr(&format!("hai{i}"))?)?;
+            ensure!(tiles.len() == 13, "haipai of {i} is not 13 tiles");
+            for (t, id) in tehai.iter_mut().zip(tiles) {
+                *t = self.tile(id)?;
+            }
+        }
+        ensure!(kyoku < 16 && kyoku % 4 < n as u32, "invalid kyoku {kyoku}");
+
+        self.last_tsumo = [None; 4];
+        self.kyoku.push(Event::StartKyoku {
+            bakaze: must_tile!(tu8!(E) + (kyoku / 4) as u8),
+            dora_marker: self.tile(dora)?,
+            kyoku: (kyoku % 4 + 1) as u8,
+            honba: honba.try_into()?,
+            kyotaku: kyotaku.try_into()?,
+            oya: self.seat(tag.attr("oya")?)?,
+            scores: pad_scores(&scores)?,
+            tehais,
+        });
+        Ok(())
+    }
+
+    fn end_kyoku(&mut self) -> Result<()> {
+        if self.kyoku.is_empty() {
+            return Ok(());
+        }
+        let mut kyoku = reorder_doras(mem::take(&mut self.kyoku))?;
+        kyoku.push(Event::EndKyoku);
+        self.events.append(&mut kyoku);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(Vec<Event>, RuleSet)> {
+        self.end_kyoku()?;
+        ensure!(!self.events.is_empty(), "no kyoku in the log");
+
+        let mut ret = Vec::with_capacity(self.events.len() + 2);
+        ret.push(Event::StartGame {
+            id: None,
+            names: self.names.unwrap_or_default(),
+            seed: None,
+        });
+        ret.append(&mut self.events);
+        ret.push(Event::EndGame);
+        Ok((ret, self.rule))
+    }
+
+    /// Decodes a meld of the attribute `m` of a `N` tag, see
+    /// <http://tenhou.net/img/tehai.js>.
+    fn meld(&self, actor: u8, m: u32) -> Result<Event> {
+        let n = self.rule.player_count();
+        let target = (actor + (m & 3) as u8) % n;
+        let ev = if m & 0x4 != 0 {
+            let t = m >> 10;
+            let called = t % 3;
+            let t = t / 3;
+            let base = (t / 7 * 9 + t % 7) * 4;
+            let ids: [_; 3] = array::from_fn(|i| base + i as u32 * 4 + ((m >> (3 + 2 * i)) & 3));
+            let [a, b] = self.consumed(&ids, ids[called as usize])?;
+            Event::Chi {
+                actor,
+                target: (actor + n - 1) % n,
+                pai: self.tile(ids[called as usize])?,
+                consumed: [a, b],
+            }
+        } else if m & 0x18 != 0 {
+            let t = m >> 9;
+            let called = t % 3;
+            let base = t / 3 * 4;
+            let unused = base + ((m >> 5) & 3);
+            let ids: Vec<_> = (base..base + 4).filter(|&id| id != unused).collect();
+            let pai = ids[called as usize];
+            if m & 0x8 != 0 {
+                let [a, b] = self.consumed(&ids, pai)?;
+                Event::Pon {
+                    actor,
+                    target,
+                    pai: self.tile(pai)?,
+                    consumed: [a, b],
+                }
+            } else {
+                let [a, b, c] = self.consumed(&ids, u32::MAX)?;
+                Event::Kakan {
+                    actor,
+                    pai: self.tile(unused)?,
+                    consumed: [a, b, c],
+                }
+            }
+        } else if m & 0x20 != 0 {
+            Event::Nukidora {
+                actor,
+                pai: self.tile(m >> 8)?,
+            }
+        } else {
+            let pai = m >> 8;
+            let base = pai / 4 * 4;
+            let ids: Vec<_> = (base..base + 4).collect();
+            if m & 3 == 0 {
+                let [a, b, c, d] = self.consumed(&ids, u32::MAX)?;
+                Event::Ankan {
+                    actor,
+                    consumed: [a, b, c, d],
+                }
+            } else {
+                let [a, b, c] = self.consumed(&ids, pai)?;
+                Event::Daiminkan {
+                    actor,
+                    target,
+                    pai: self.tile(pai)?,
+                    consumed: [a, b, c],
+                }
+            }
+        };
+        Ok(ev)
+    }
+
+    /// The tiles of `ids` other than `pai`.
+    fn consumed<const N: usize>(&self, ids: &[u32], pai: u32) -> Result<[Tile; N]> {
+        let tiles = ids
+            .iter()
+            .filter(|&&id| id != pai)
+            .map(|&id| self.tile(id))
+            .collect::<Result<Vec<_>>>()?;
+        tiles.try_into().map_err(|_| anyhow!("invalid meld"))
+    }
+
+    /// Converts a 136-tile ID, where the first of the 5s is the aka.
+    fn tile(&self, id: u32) -> Result<Tile> {
+        ensure!(id < 136, "invalid tile {id}");
+        let tile = must_tile!((id / 4) as usize);
+        if self.rule.aka_count > 0 && id.is_multiple_of(4) {
+            Ok(tile.akaize())
+        } else {
+            Ok(tile)
+        }
+    }
+
+    fn actor(&self, tag: &Tag<'_>) -> Result<u8> {
+        self.seat(tag.attr("who")?)
+    }
+
+    fn seat(&self, s: &str) -> Result<u8> {
+        let seat = s.parse()?;
+        ensure!(seat < self.rule.player_count(), "invalid seat {seat}");
+        Ok(seat)
+    }
+}
+
+impl Tag<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.attrs.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    }
+
+    fn attr(&self, key: &str) -> Result<&str> {
+        self.get(key)
+            .with_context(|| format!("missing attribute {key}"))
+    }
+}
+
+impl<'a> Iterator for Tags<'a> {
+    type Item = Result<Tag<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.0.find('<')?;
+            let Some(len) = self.0[start..].find('>') else {
+                self.0 = "";
+                return Some(Err(anyhow!("unclosed tag")));
+            };
+            let inner = &self.0[start + 1..start + len];
+            self.0 = &self.0[start + len + 1..];
+            // Skips the declaration, comments and closing tags.
+            if inner.starts_with(['?', '!', '/']) {
+                continue;
+            }
+            return Some(parse_tag(inner.trim_end_matches('/')));
+        }
+    }
+}
+
+fn parse_tag(inner: &str) -> Result<Tag<'_>> {
+    let inner = inner.trim();
+    let (name, mut rest) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+    let mut attrs = vec![];
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let (key, after) = rest
+            .split_once("=\"")
+            .with_context(|| format!("invalid attribute in <{inner}>"))?;
+        let (value, after) = after
+            .split_once('"')
+            .with_context(|| format!("unclosed attribute in <{inner}>"))?;
+        attrs.push((key.trim(), value));
+        rest = after;
+    }
+    Ok(Tag { name, attrs })
+}
+
+/// Parses a tsumo tag like `T12` or a discard tag like `D12`, where the
+/// letter tells the actor.
+fn parse_move(name: &str, letters: &str) -> Option<(u8, u32)> {
+    let mut chars = name.chars();
+    let actor = letters.find(chars.next()?)? as u8;
+    let id = chars.as_str();
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((actor, id.parse().ok()?))
+}
+
+fn parse_list(s: &str) -> Result<Vec<u32>> {
+    if s.is_empty() {
+        return Ok(vec![]);
+    }
+    s.split(',')
+        .map(|v| {
+            v.trim()
+                .parse()
+                .with_context(|| format!("invalid list {s}"))
+        })
+        .collect()
+}
+
+/// `sc` is a list of (score, delta) pairs, in 100 points.
+fn parse_deltas(sc: &str) -> Result<[i32; 4]> {
+    let values = sc
+        .split(',')
+        .map(|v| v.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid sc {sc}"))?;
+    let deltas: Vec<_> = values.chunks(2).map(|c| c[c.len() - 1] * 100).collect();
+    pad_scores(&deltas)
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let [first, tail @ ..] = rest {
+        if *first == b'%' {
+            let hex = tail.get(..2).context("invalid percent encoding")?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex)?, 16)?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(*first);
+            rest = tail;
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::BatchGame;
+    use crate::mjai::EventExt;
+    use crate::mjai::convert::test::normalized;
+    use crate::mjai::convert::validate;
+    use crate::rule::RuleSet;
+    use crate::test_util::run_arena;
+    use serde_json as json;
+    use std::fmt::Write;
+
+    /// Encodes arena logs into mjlog, assigning a 136-tile ID to every tile.
+    struct Encoder {
+        n: u8,
+        xml: String,
+        free: Vec<u32>,
+        hands: [Vec<u32>; 4],
+        last_tsumo: [Option<u32>; 4],
+        last_discard: u32,
+        /// (actor, 136-tile IDs, called ID, `m & 3`) of every pon.
+        pons: Vec<(u8, Vec<u32>, u32, u32)>,
+    }
+
+    impl Encoder {
+        fn new(rule: &RuleSet) -> Self {
+            let n = rule.player_count();
+            let flags = [
+                (rule.aka_count == 0, 0x02),
+                (!rule.kuitan, 0x04),
+                (rule.game_length > n, 0x08),
+                (rule.sanma, 0x10),
+            ];
+            let typ = flags
+                .into_iter()
+                .filter(|&(on, _)| on)
+                .fold(0x01, |typ, (_, bit)| typ | bit);
+            Self {
+                n,
+                xml: format!(
+                    r#"<mjloggm ver="2.3"><SHUFFLE seed="" ref=""/><GO type="{typ}" lobby="0"/><UN n0="A" n1="B" n2="C" n3="{}"/><TAIKYOKU oya="0"/>"#,
+                    if n == 3 { "" } else { "D" },
+                ),
+                free: vec![],
+                hands: Default::default(),
+                last_tsumo: [None; 4],
+                last_discard: 0,
+                pons: vec![],
+            }
+        }
+
+        fn alloc(&mut self, tile: Tile) -> u32 {
+            let t = tile.deaka().as_u8() as u32;
+            let copies = if tile.is_aka() {
+                0..1
+            } else if matches!(t, 4 | 13 | 22) {
+                1..4
+            } else {
+                0..4
+            };
+            let idx = self
+                .free
+                .iter()
+                .position(|&id| id / 4 == t && copies.contains(&(id % 4)))
+                .unwrap();
+            self.free.swap_remove(idx)
+        }
+
+        fn take(&mut self, actor: u8, tile: Tile, prefer_not: Option<u32>) -> u32 {
+            let hand = &mut self.hands[actor as usize];
+            let matching = |&id: &u32| {
+                let t = must_tile!((id / 4) as usize);
+                (if id % 4 == 0 { t.akaize() } else { t }) == tile
+            };
+            let idx = hand
+                .iter()
+                .position(|id| matching(id) && Some(*id) != prefer_not)
+                .or_else(|| hand.iter().position(matching))
+                .unwrap();
+            hand.swap_remove(idx)
+        }
+
+        fn rel(&self, actor: u8, target: u8) -> u32 {
+            ((target + self.n - actor) % self.n) as u32
+        }
+
+        fn list(ids: &[u32]) -> String {
+            ids.iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+
+        fn sc(deltas: &[i32; 4]) -> String {
+            deltas
+                .iter()
+                .flat_map(|d| [0, d / 100])
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+
+        fn event(&mut self, ev: &Event) {
+            let x = &mut String::new();
+            match *ev {
+                Event::StartKyoku {
+                    bakaze,
+                    dora_marker,
+                    kyoku,
+                    honba,
+                    kyotaku,
+                    oya,
+                    scores,
+                    tehais,
+                } => {
+                    self.free = (0..136).collect();
+                    self.hands = Default::default();
+                    self.last_tsumo = [None; 4];
+                    self.pons.clear();
+                    let dora = self.alloc(dora_marker);
+                    let kyoku = (bakaze.as_u8() - tu8!(E)) * 4 + kyoku - 1;
+                    let ten = scores.map(|s| (s / 100).to_string()).join(",");
+                    write!(
+                        x,
+                        r#"<INIT seed="{kyoku},{honba},{kyotaku},0,0,{dora}" ten="{ten}" oya="{oya}""#,
+                    )
+                    .unwrap();
+                    for (i, tehai) in tehais.iter().enumerate() {
+                        if i < self.n as usize {
+                            let ids: Vec<_> = tehai.iter().map(|&t| self.alloc(t)).collect();
+                            self.hands[i].clone_from(&ids);
+                            write!(x, r#" hai{i}="{}""#, Self::list(&ids)).unwrap();
+                        } else {
+                            write!(x, r#" hai{i}="""#).unwrap();
+                        }
+                    }
+                    x.push_str("/>");
+                }
+                Event::Tsumo { actor, pai } => {
+                    let id = self.alloc(pai);
+                    self.hands[actor as usize].push(id);
+                    self.last_tsumo[actor as usize] = Some(id);
+                    write!(x, "<{}{id}/>", b"TUVW"[actor as usize] as char).unwrap();
+                }
+                Event::Dahai {
+                    actor,
+                    pai,
+                    tsumogiri,
+                } => {
+                    let last = self.last_tsumo[actor as usize].take();
+                    let id = if tsumogiri {
+                        let id = last.unwrap();
+                        self.take(actor, pai, None);
+                        self.hands[actor as usize].push(id);
+                        self.take(actor, pai, Some(u32::MAX));
+                        id
+                    } else {
+                        self.take(actor, pai, last)
+                    };
+                    self.last_discard = id;
+                    write!(x, "<{}{id}/>", b"DEFG"[actor as usize] as char).unwrap();
+                }
+                Event::Chi {
+                    actor,
+                    pai,
+                    consumed,
+                    ..
+                } => {
+                    let mut ids = vec![self.last_discard];
+                    ids.extend(consumed.map(|t| self.take(actor, t, None)));
+                    ids.sort_unstable();
+                    let called = ids.iter().position(|&id| id == self.last_discard).unwrap();
+                    let t = ids[0] / 4;
+                    let t = t / 9 * 7 + t % 9;
+                    let mut m = ((t * 3 + called as u32) << 10) | 0x4;
+                    for (i, id) in ids.iter().enumerate() {
+                        m |= (id % 4) << (3 + 2 * i);
+                    }
+                    let _ = pai;
+                    write!(x, r#"<N who="{actor}" m="{m}"/>"#).unwrap();
+                }
+                Event::Pon {
+                    actor,
+                    target,
+                    consumed,
+                    ..
+                } => {
+                    let mut ids = vec![self.last_discard];
+                    ids.extend(consumed.map(|t| self.take(actor, t, None)));
+                    ids.sort_unstable();
+                    let rel = self.rel(actor, target);
+                    self.pons.push((actor, ids.clone(), self.last_discard, rel));
+                    let m = Self::pon_m(&ids, self.last_discard, rel) | 0x8;
+                    write!(x, r#"<N who="{actor}" m="{m}"/>"#).unwrap();
+                }
+                Event::Kakan { actor, pai, .. } => {
+                    let added = self.take(actor, pai, None);
+                    let idx = self
+                        .pons
+                        .iter()
+                        .position(|(a, ids, ..)| *a == actor && ids[0] / 4 == added / 4)
+                        .unwrap();
+                    let (_, ids, called, rel) = self.pons.swap_remove(idx);
+                    let m = Self::pon_m(&ids, called, rel) | 0x10;
+                    write!(x, r#"<N who="{actor}" m="{m}"/>"#).unwrap();
+                }
+                Event::Daiminkan {
+                    actor,
+                    target,
+                    consumed,
+                    ..
+                } => {
+                    for t in consumed {
+                        self.take(actor, t, None);
+                    }
+                    let m = (self.last_discard << 8) | self.rel(actor, target);
+                    write!(x, r#"<N who="{actor}" m="{m}"/>"#).unwrap();
+                }
+                Event::Ankan { actor, consumed } => {
+                    let ids = consumed.map(|t| self.take(actor, t, None));
+                    let m = (ids[0] / 4 * 4) << 8;
+                    write!(x, r#"<N who="{actor}" m="{m}"/>"#).unwrap();
+                }
+                Event::Nukidora { actor, pai } => {
+                    let id = self.take(actor, pai, None);
+                    let m = (id << 8) | 0x20;
+                    write!(x, r#"<N who="{actor}" m="{m}"/>"#).unwrap();
+                }
+                Event::Dora { dora_marker } => {
+                    let id = self.alloc(dora_marker);
+                    write!(x, r#"<DORA hai="{id}"/>"#).unwrap();
+                }
+                Event::Reach { actor } => {
+                    write!(x, r#"<REACH who="{actor}" step="1"/>"#).unwrap();
+                }
+                Event::ReachAccepted { actor } => {
+                    write!(x, r#"<REACH who="{actor}" step="2"/>"#).unwrap();
+                }
+                Event::Hora {
+                    actor,
+                    target,
+                    deltas,
+                    ref ura_markers,
+                } => {
+                    let sc = Self::sc(&deltas.unwrap());
+                    write!(x, r#"<AGARI who="{actor}" fromWho="{target}" sc="{sc}""#).unwrap();
+                    let ura = ura_markers.as_deref().unwrap_or_default();
+                    if !ura.is_empty() {
+                        let ids: Vec<_> = ura.iter().map(|&t| self.alloc(t)).collect();
+                        write!(x, r#" doraHaiUra="{}""#, Self::list(&ids)).unwrap();
+                    }
+                    x.push_str("/>");
+                }
+                Event::Ryukyoku { deltas } => {
+                    let sc = Self::sc(&deltas.unwrap());
+                    write!(x, r#"<RYUUKYOKU sc="{sc}"/>"#).unwrap();
+                }
+                _ => (),
+            }
+            self.xml.push_str(x);
+        }
+
+        fn pon_m(ids: &[u32], called: u32, rel: u32) -> u32 {
+            let t = ids[0] / 4;
+            let unused = (0..4).find(|c| ids.iter().all(|id| id % 4 != *c)).unwrap();
+            let called = ids.iter().position(|&id| id == called).unwrap() as u32;
+            ((t * 3 + called) << 9) | (unused << 5) | rel
+        }
+    }
+
+    fn round_trip(game: &BatchGame, seed: (u64, u64)) -> Vec<Event> {
+        let result = run_arena(game, seed);
+        let log: Vec<EventExt> = result.game_log.into_iter().flatten().collect();
+
+        let mut enc = Encoder::new(&game.rule);
+        for ev in &log {
+            enc.event(&ev.event);
+        }
+        enc.xml.push_str("</mjloggm>");
+
+        let (events, rule) = from_mjlog(&enc.xml).unwrap();
+        assert_eq!(rule, game.rule);
+        validate(&events, rule).unwrap();
+        assert_eq!(
+            normalized(
+                &events[1..events.len() - 1]
+                    .iter()
+                    .cloned()
+                    .map(EventExt::from)
+                    .collect::<Vec<_>>()
+            ),
+            normalized(&log),
+        );
+        events
+    }
+
+    #[test]
+    fn arena_round_trip() {
+        let game = BatchGame {
+            rule: RuleSet::tenhou_tonpuu(),
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let events: Vec<_> = (0..12)
+            .flat_map(|seed| round_trip(&game, (seed, seed)))
+            .collect();
+        for kind in ["chi", "pon", "daiminkan", "kakan", "ankan", "reach", "hora"] {
+            assert!(
+                events
+                    .iter()
+                    .any(|ev| json::to_value(ev).unwrap()["type"] == kind),
+                "no {kind} in the games",
+            );
+        }
+        let sanma = BatchGame {
+            rule: RuleSet::tenhou_sanma(),
+            init_scores: [35000, 35000, 35000, 0],
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let events = round_trip(&sanma, (7, 7));
+        assert!(events.iter().any(|ev| matches!(ev, Event::Nukidora { .. })));
+    }
+
+    #[test]
+    fn tenhou_tags() {
+        // Reconnection, an aka tsumogiri, pao and a second INIT with a
+        // kyushukyuhai.
+        let xml = r#"<mjloggm ver="2.3"><SHUFFLE seed="mt19937ar-sha512-n288-base64,AAAA" ref=""/><GO type="169" lobby="0"/><UN n0="%E3%81%82%E3%81%84" n1="B" n2="C" n3="D" dan="9,9,9,9" rate="1500.00,1500.00,1500.00,1500.00" sx="M,M,M,M"/><TAIKYOKU oya="0"/><INIT seed="4,1,1,2,3,8" ten="240,250,250,250" oya="0" hai0="0,4,8,12,36,40,44,48,72,76,80,108,112" hai1="1,5,9,13,37,41,45,49,73,77,81,109,113" hai2="2,6,10,14,38,42,46,50,74,78,82,110,114" hai3="3,7,11,15,39,43,47,51,75,79,83,111,115"/><T16/><D16/><BYE who="1"/><UN n1="B"/><U100/><E100/><AGARI ba="1,1" hai="1,5,9" ten="30,32000,5" yaku="" doraHai="8" who="2" fromWho="1" paoWho="3" sc="240,0,250,-160,250,330,250,-160" owari="240,-26.0,250,-15.0,580,63.0,90,-41.0"/><INIT seed="5,0,0,2,3,9" ten="240,90,580,90" oya="1" hai0="0,4,8,12,36,40,44,48,72,76,80,108,112" hai1="1,5,9,13,37,41,45,49,73,77,81,109,113" hai2="2,6,10,14,38,42,46,50,74,78,82,110,114" hai3="3,7,11,15,39,43,47,51,75,79,83,111,115"/><U17/><RYUUKYOKU type="yao9" ba="0,0" sc="240,0,90,0,580,0,90,0" hai1="1,5"/></mjloggm>"#;
+        let (events, rule) = from_mjlog(xml).unwrap();
+        validate(&events, rule).unwrap();
+        assert_eq!(rule, RuleSet::tenhou());
+
+        let Event::StartGame { names, .. } = &events[0] else {
+            panic!();
+        };
+        assert_eq!(names, &["あい", "B", "C", "D"].map(ToOwned::to_owned));
+        let Event::StartKyoku {
+            bakaze,
+            kyoku,
+            honba,
+            kyotaku,
+            ..
+        } = events[1]
+        else {
+            panic!();
+        };
+        assert_eq!((bakaze, kyoku, honba, kyotaku), (t!(S), 1, 1, 1));
+        assert_eq!(
+            events[2..],
+            [
+                Event::Tsumo {
+                    actor: 0,
+                    pai: t!(5mr),
+                },
+                Event::Dahai {
+                    actor: 0,
+                    pai: t!(5mr),
+                    tsumogiri: true,
+                },
+                Event::Tsumo {
+                    actor: 1,
+                    pai: t!(8s),
+                },
+                Event::Dahai {
+                    actor: 1,
+                    pai: t!(8s),
+                    tsumogiri: true,
+                },
+                Event::Hora {
+                    actor: 2,
+                    target: 1,
+                    deltas: Some([0, -16000, 33000, -16000]),
+                    ura_markers: Some(vec![]),
+                },
+                Event::EndKyoku,
+                events[8].clone(),
+                Event::Tsumo {
+                    actor: 1,
+                    pai: t!(5m),
+                },
+                Event::Ryukyoku {
+                    deltas: Some([0; 4]),
+                },
+                Event::EndKyoku,
+                Event::EndGame,
+            ],
+        );
+    }
+
+    #[test]
+    fn sanma_kans() {
+        // A sanma kyoku written by hand after the tags of Tenhou, not through
+        // `Encoder`, with the `m` of the melds worked out from tehai.js: A
+        // pons 1p from B, B daiminkans 9m from C, A kakans the 1p, and C rons
+        // A. The dora of both kans are revealed after the rinshan discard.
+        let xml = r#"<mjloggm ver="2.3"><SHUFFLE seed="mt19937ar-sha512-n288-base64,AAAA" ref=""/><GO type="185" lobby="0"/><UN n0="A" n1="B" n2="C" n3="" dan="18,18,18,0" rate="2200.00,2200.00,2200.00,1500.00" sx="M,M,M,C"/><TAIKYOKU oya="0"/><INIT seed="0,0,0,3,2,116" ten="350,350,350,0" oya="0" hai0="0,1,36,37,68,69,72,73,104,105,112,113,128" hai1="33,34,35,108,109,110,56,60,64,76,80,84,120" hai2="40,44,48,53,57,61,81,85,89,91,92,96,100" hai3=""/><T133/><D133/><U38/><E38/><N who="0" m="14953"/><D128/><U134/><E134/><V32/><F32/><N who="1" m="8193"/><U124/><E124/><DORA hai="132"/><V125/><F125/><T39/><N who="0" m="14961"/><T74/><D74/><DORA hai="129"/><U117/><E117/><V121/><F121/><T90/><D90/><AGARI ba="0,0" hai="40,44,48,53,57,61,81,85,89,90,91,92,96,100" machi="90" ten="40,1300,0" yaku="8,1" doraHai="116,132,129" who="2" fromWho="0" sc="350,-13,350,0,350,13,0,0" owari="337,-16.3,350,0.0,363,16.3,0,0.0"/></mjloggm>"#;
+        let (events, rule) = from_mjlog(xml).unwrap();
+        validate(&events, rule).unwrap();
+        assert_eq!(rule, RuleSet::tenhou_sanma());
+
+        let Event::StartKyoku { tehais, .. } = &events[1] else {
+            panic!();
+        };
+        assert!(tehais[3].iter().all(|t| t.is_unknown()));
+        let calls: Vec<_> = events
+            .iter()
+            .filter(|ev| {
+                matches!(
+                    ev,
+                    Event::Pon { .. }
+                        | Event::Daiminkan { .. }
+                        | Event::Kakan { .. }
+                        | Event::Dora { .. }
+                        | Event::Hora { .. }
+                )
+            })
+            .cloned()
+            .collect();
+        assert_eq!(
+            calls,
+            [
+                Event::Pon {
+                    actor: 0,
+                    target: 1,
+                    pai: t!(1p),
+                    consumed: t![1p, 1p],
+                },
+                Event::Daiminkan {
+                    actor: 1,
+                    target: 2,
+                    pai: t!(9m),
+                    consumed: t![9m, 9m, 9m],
+                },
+                Event::Dora { dora_marker: t!(C) },
+                Event::Kakan {
+                    actor: 0,
+                    pai: t!(1p),
+                    consumed: t![1p, 1p, 1p],
+                },
+                Event::Dora { dora_marker: t!(F) },
+                Event::Hora {
+                    actor: 2,
+                    target: 0,
+                    deltas: Some([-1300, 0, 1300, 0]),
+                    ura_markers: Some(vec![]),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn melds() {
+        let conv = Converter {
+            rule: RuleSet::tenhou(),
+            names: None,
+            events: vec![],
+            kyoku: vec![],
+            last_tsumo: [None; 4],
+        };
+        // 3p (called), 4p and aka 5p, with the copies 1, 2 and 0.
+        assert_eq!(
+            conv.meld(0, (27 << 10) | 0x4 | (1 << 3) | (2 << 5))
+                .unwrap(),
+            Event::Chi {
+                actor: 0,
+                target: 3,
+                pai: t!(3p),
+                consumed: t![4p, 5pr],
+            },
+        );
+        assert_eq!(
+            conv.meld(0, 0x4a01).unwrap(),
+            Event::Daiminkan {
+                actor: 0,
+                target: 1,
+                pai: t!(1s),
+                consumed: t![1s, 1s, 1s],
+            },
+        );
+        assert_eq!(
+            conv.meld(0, 0x3c00).unwrap(),
+            Event::Ankan {
+                actor: 0,
+                consumed: t![7p, 7p, 7p, 7p],
+            },
+        );
+    }
+}
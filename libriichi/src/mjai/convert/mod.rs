@@ -0,0 +1,251 @@
+//! Conversions between mjai events and other log formats.
+//!
+//! The output of every importer, and the input expected by every exporter,
+//! follows the conventions of the logs dumped by
+//! `arena`:
+//!
+//! 1. A game is `start_game`, the kyokus and `end_game`, where a kyoku is
+//!    `start_kyoku`, the events, one `hora` per winner or a single
+//!    `ryukyoku`, and `end_kyoku`.
+//! 2. The dora of an ankan is revealed right after it, and that of a
+//!    daiminkan or kakan right before the next discard, or before the next
+//!    tsumo for a continuous kan.
+//! 3. `deltas` of `hora` and `ryukyoku` are taken from the source as is, so
+//!    pao and abortive draws need no special treatment.
+//! 4. In sanma, seat 3 is vacant, with all unknown tiles and a score of 0.
+
+mod majsoul;
+mod mjlog;
+mod tenhou6;
+mod tenhou6_export;
+
+pub use majsoul::from_mahjong_soul;
+pub use mjlog::from_mjlog;
+pub use tenhou6::from_tenhou6;
+pub use tenhou6_export::{to_tenhou6, to_tenhou6_with_rule};
+
+use crate::mjai::Event;
+use crate::rule::RuleSet;
+use crate::state::PlayerState;
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result, bail, ensure};
+use serde_json::{self as json, Value};
+
+/// Detects the format of `raw` and converts it, returning the events along
+/// with the rule of the game.
+pub fn from_any(raw: &str) -> Result<(Vec<Event>, RuleSet)> {
+    match raw.trim_start().chars().next() {
+        Some('<') => from_mjlog(raw),
+        Some('{') => {
+            let value: Value = json::from_str(raw).context("failed to parse JSON log")?;
+            if value.get("head").is_some() {
+                from_mahjong_soul(raw)
+            } else {
+                from_tenhou6(raw)
+            }
+        }
+        _ => bail!("unknown log format"),
+    }
+}
+
+/// Feeds `events` to the `PlayerState` of every seat under `rule`, which
+/// catches most inconsistencies of a conversion.
+pub fn validate(events: &[Event], rule: RuleSet) -> Result<()> {
+    ensure!(
+        matches!(events, [Event::StartGame { .. }, .., Event::EndGame]),
+        "not a complete game",
+    );
+    ensure!(
+        rule.sanma == rule_of(events)?.sanma,
+        "the rule does not match the number of players of the log",
+    );
+
+    for player_id in 0..rule.player_count() {
+        let mut state = PlayerState::new_with_rule(player_id, rule);
+        for (i, ev) in events.iter().enumerate() {
+            state
+                .update(ev)
+                .with_context(|| format!("player {player_id} at line {}", i + 1))?;
+        }
+    }
+    Ok(())
+}
+
+/// Tells Tenhou's sanma from yonma by the vacant seat 3 in the first kyoku,
+/// for the logs whose rule is not known otherwise.
+pub(crate) fn rule_of(events: &[Event]) -> Result<RuleSet> {
+    let sanma = events
+        .iter()
+        .find_map(|ev| match ev {
+            Event::StartKyoku { tehais, .. } => Some(tehais[3][0].is_unknown()),
+            _ => None,
+        })
+        .context("no kyoku in the log")?;
+    let rule = if sanma {
+        RuleSet::tenhou_sanma()
+    } else {
+        RuleSet::default()
+    };
+    Ok(rule)
+}
+
+/// Moves the `dora` events of a kyoku to where `arena` would have logged
+/// them, keeping their order.
+///
+/// The sources do not agree with each other on this: Tenhou's mjlog reveals
+/// the dora of a minkan after the discard, and tenhou.net/6 does not record
+/// the timing at all.
+fn reorder_doras(kyoku: Vec<Event>) -> Result<Vec<Event>> {
+    let (ret, left) = place_doras(kyoku)?;
+    ensure!(left == 0, "{left} dora indicators are never revealed");
+    Ok(ret)
+}
+
+/// Same as `reorder_doras`, but drops the markers left unrevealed instead,
+/// returning how many they are.
+fn place_doras(kyoku: Vec<Event>) -> Result<(Vec<Event>, usize)> {
+    let mut markers: VecDeque<_> = kyoku
+        .iter()
+        .filter_map(|ev| match *ev {
+            Event::Dora { dora_marker } => Some(dora_marker),
+            _ => None,
+        })
+        .collect();
+    let mut pop = || {
+        markers
+            .pop_front()
+            .map(|dora_marker| Event::Dora { dora_marker })
+            .context("missing dora indicator of a kan")
+    };
+
+    let mut ret = Vec::with_capacity(kyoku.len());
+    let mut need_new_dora_at_discard = false;
+    let mut need_new_dora_at_tsumo = false;
+    for ev in kyoku {
+        match ev {
+            Event::Dora { .. } => continue,
+            Event::Tsumo { .. } if need_new_dora_at_tsumo => {
+                need_new_dora_at_tsumo = false;
+                ret.push(pop()?);
+            }
+            Event::Dahai { .. } if need_new_dora_at_discard => {
+                need_new_dora_at_discard = false;
+                ret.push(pop()?);
+            }
+            Event::Ankan { .. } => {
+                if need_new_dora_at_discard {
+                    need_new_dora_at_discard = false;
+                    ret.push(pop()?);
+                }
+                ret.push(ev);
+                ret.push(pop()?);
+                continue;
+            }
+            Event::Daiminkan { .. } | Event::Kakan { .. } => {
+                need_new_dora_at_tsumo |= need_new_dora_at_discard;
+                need_new_dora_at_discard = true;
+            }
+            _ => (),
+        }
+        ret.push(ev);
+    }
+    Ok((ret, markers.len()))
+}
+
+/// Pads the scores of sanma to 4 players.
+fn pad_scores(scores: &[i32]) -> Result<[i32; 4]> {
+    ensure!(
+        (3..=4).contains(&scores.len()),
+        "expected 3 or 4 scores, got {}",
+        scores.len(),
+    );
+    let mut ret = [0; 4];
+    ret[..scores.len()].copy_from_slice(scores);
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mjai::EventExt;
+    use crate::t;
+
+    /// Makes consumed tiles comparable regardless of their order.
+    pub(super) fn normalized(events: &[EventExt]) -> Vec<Event> {
+        events
+            .iter()
+            .map(|ev| {
+                let mut ev = ev.event.clone();
+                match &mut ev {
+                    Event::Chi { consumed, .. } | Event::Pon { consumed, .. } => {
+                        consumed.sort_by_key(|t| t.as_u8());
+                    }
+                    Event::Daiminkan { consumed, .. } | Event::Kakan { consumed, .. } => {
+                        consumed.sort_by_key(|t| t.as_u8());
+                    }
+                    Event::Ankan { consumed, .. } => consumed.sort_by_key(|t| t.as_u8()),
+                    _ => (),
+                }
+                ev
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dora_order() {
+        let dora = |s: &str| Event::Dora {
+            dora_marker: s.parse().unwrap(),
+        };
+        let tsumo = Event::Tsumo {
+            actor: 0,
+            pai: t!(1m),
+        };
+        let dahai = Event::Dahai {
+            actor: 0,
+            pai: t!(1m),
+            tsumogiri: true,
+        };
+        let kakan = Event::Kakan {
+            actor: 0,
+            pai: t!(E),
+            consumed: t![E, E, E],
+        };
+        let ankan = Event::Ankan {
+            actor: 0,
+            consumed: t![S, S, S, S],
+        };
+
+        // kakan, kakan, ankan, with the doras logged at the end, the way
+        // mjlog does for minkans.
+        let kyoku = vec![
+            kakan.clone(),
+            tsumo.clone(),
+            kakan.clone(),
+            tsumo.clone(),
+            ankan.clone(),
+            tsumo.clone(),
+            dahai.clone(),
+            dora("1p"),
+            dora("2p"),
+            dora("3p"),
+        ];
+        let expected = vec![
+            kakan.clone(),
+            tsumo.clone(),
+            kakan,
+            dora("1p"),
+            tsumo.clone(),
+            dora("2p"),
+            ankan,
+            dora("3p"),
+            tsumo,
+            dahai,
+        ];
+        assert_eq!(reorder_doras(kyoku.clone()).unwrap(), expected);
+        reorder_doras(kyoku[..9].to_vec()).unwrap_err();
+        reorder_doras([kyoku.clone(), vec![dora("4p")]].concat()).unwrap_err();
+        let (placed, left) = place_doras([kyoku, vec![dora("4p")]].concat()).unwrap();
+        assert_eq!((placed, left), (expected, 1));
+    }
+}
@@ -0,0 +1,598 @@
+use super::{pad_scores, reorder_doras, rule_of};
+use crate::mjai::Event;
+use crate::rule::RuleSet;
+use crate::tile::Tile;
+use crate::{must_tile, t, tu8};
+use std::collections::VecDeque;
+
+use anyhow::{Context, Result, bail, ensure};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self as json, Value};
+
+/// Converts a log in the JSON format of tenhou.net/6 into mjai events.
+///
+/// As the format does not record the order of the moves across players, it
+/// is reconstructed from the calls: a discard is called by the player whose
+/// next draw is a call of the same tile from the discarder, pon and kan
+/// first, or the turn passes to the next player otherwise.
+///
+/// This cannot tell when a player passes a discard and calls the same tile
+/// from the same discarder later, without a draw in between as their turns
+/// are skipped by the calls of others. The call is then put on the first
+/// discard, and the kyoku fails to convert.
+///
+/// The rule is Tenhou's, with the length, kuitan and aka doras taken from
+/// `rule` of the log if present.
+pub fn from_tenhou6(raw: &str) -> Result<(Vec<Event>, RuleSet)> {
+    let game: Game = json::from_str(raw).context("failed to parse tenhou.net/6 log")?;
+    ensure!(!game.log.is_empty(), "no kyoku in the log");
+
+    let mut names: [String; 4] = Default::default();
+    for (name, n) in names.iter_mut().zip(game.name) {
+        *name = n;
+    }
+    let mut ret = vec![Event::StartGame {
+        id: None,
+        names,
+        seed: None,
+    }];
+    for (i, kyoku) in game.log.iter().enumerate() {
+        let events = convert_kyoku(kyoku).with_context(|| format!("in kyoku #{i}"))?;
+        ret.extend(events);
+    }
+    ret.push(Event::EndGame);
+
+    let base = rule_of(&ret)?;
+    let rule = match game.rule {
+        Some(GameRule { disp, aka }) => RuleSet {
+            game_length: if disp.contains('東') {
+                base.player_count()
+            } else {
+                base.game_length
+            },
+            aka_count: if aka == 0 { 0 } else { base.aka_count },
+            kuitan: disp.contains('喰'),
+            ..base
+        },
+        None => base,
+    };
+    Ok((ret, rule))
+}
+
+#[derive(Deserialize)]
+struct Game {
+    #[serde(default)]
+    name: Vec<String>,
+    #[serde(default)]
+    rule: Option<GameRule>,
+    log: Vec<Vec<Value>>,
+}
+
+/// `disp` is the name of the game type like `般南喰赤`, and `aka` is 1 if aka
+/// doras are used.
+#[derive(Deserialize)]
+struct GameRule {
+    disp: String,
+    aka: u8,
+}
+
+/// An entry of the draws or discards of a player.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Tile(u8),
+    Meld(String),
+}
+
+/// A parsed meld string like `p252525`, where the position of the letter
+/// tells the target and the tile right after the letter is the called one.
+struct Meld {
+    letter: char,
+    /// Position of the letter in the string.
+    pos: usize,
+    called: Tile,
+    others: Vec<Tile>,
+}
+
+struct KyokuConverter {
+    n: u8,
+    draws: [VecDeque<Entry>; 4],
+    discards: [VecDeque<Entry>; 4],
+    events: Vec<Event>,
+    last_tsumo: [Option<Tile>; 4],
+    riichi_to_be_accepted: Option<u8>,
+    riichi_accepted: [bool; 4],
+}
+
+fn field<T: DeserializeOwned>(kyoku: &[Value], idx: usize) -> Result<T> {
+    let v = kyoku
+        .get(idx)
+        .with_context(|| format!("missing field #{idx}"))?;
+    T::deserialize(v).with_context(|| format!("invalid field #{idx}"))
+}
+
+fn convert_kyoku(kyoku: &[Value]) -> Result<Vec<Event>> {
+    ensure!(kyoku.len() == 17, "expected 17 fields, got {}", kyoku.len());
+    let [kyoku_idx, honba, kyotaku]: [u8; 3] = field(kyoku, 0)?;
+    let scores: Vec<i32> = field(kyoku, 1)?;
+    let doras: Vec<u8> = field(kyoku, 2)?;
+    let uras: Vec<u8> = field(kyoku, 3)?;
+    let result: Vec<Value> = field(kyoku, 16)?;
+
+    let mut haipais = vec![];
+    let mut draws: [VecDeque<Entry>; 4] = Default::default();
+    let mut discards: [VecDeque<Entry>; 4] = Default::default();
+    for i in 0..4 {
+        haipais.push(field::<Vec<u8>>(kyoku, 4 + i * 3)?);
+        draws[i] = field(kyoku, 5 + i * 3)?;
+        discards[i] = field(kyoku, 6 + i * 3)?;
+    }
+    // The vacant seat of sanma has no haipai.
+    let n = if haipais[3].is_empty() { 3 } else { 4 };
+    ensure!(
+        kyoku_idx < 16 && kyoku_idx % 4 < n,
+        "invalid kyoku {kyoku_idx}",
+    );
+
+    let mut tehais = [[t!(?); 13]; 4];
+    for (tehai, haipai) in tehais.iter_mut().zip(&haipais).take(n as usize) {
+        ensure!(haipai.len() == 13, "haipai is not 13 tiles");
+        for (t, &id) in tehai.iter_mut().zip(haipai) {
+            *t = tile(id)?;
+        }
+    }
+    let oya = kyoku_idx % 4;
+    let mut conv = KyokuConverter {
+        n,
+        draws,
+        discards,
+        events: vec![Event::StartKyoku {
+            bakaze: must_tile!(tu8!(E) + kyoku_idx / 4),
+            dora_marker: tile(*doras.first().context("no dora indicator")?)?,
+            kyoku: oya + 1,
+            honba,
+            kyotaku,
+            oya,
+            scores: pad_scores(&scores)?,
+            tehais,
+        }],
+        last_tsumo: [None; 4],
+        riichi_to_be_accepted: None,
+        riichi_accepted: [false; 4],
+    };
+
+    conv.play(oya)?;
+    for (i, (draws, discards)) in conv.draws.iter().zip(&conv.discards).enumerate() {
+        ensure!(
+            draws.is_empty() && discards.is_empty(),
+            "moves of player {i} left after the end of the kyoku",
+        );
+    }
+    conv.result(&result, &uras)?;
+
+    for &id in &doras[1..] {
+        conv.events.push(Event::Dora {
+            dora_marker: tile(id)?,
+        });
+    }
+    let mut events = reorder_doras(conv.events)?;
+    events.push(Event::EndKyoku);
+    Ok(events)
+}
+
+impl KyokuConverter {
+    /// Replays the draws and discards until they are used up.
+    fn play(&mut self, oya: u8) -> Result<()> {
+        let mut actor = oya;
+        'tsumo: while let Some(draw) = self.draws[actor as usize].pop_front() {
+            let Entry::Tile(id) = draw else {
+                bail!("unexpected call {draw:?} of player {actor}");
+            };
+            let pai = tile(id)?;
+            self.accept_riichi();
+            self.events.push(Event::Tsumo { actor, pai });
+            self.last_tsumo[actor as usize] = Some(pai);
+
+            loop {
+                let Some(discard) = self.discards[actor as usize].pop_front() else {
+                    // Tsumo agari, kyushukyuhai or the end of the wall.
+                    break 'tsumo;
+                };
+                let last_tsumo = self.last_tsumo[actor as usize].take();
+                let (riichi, id) = match discard {
+                    Entry::Tile(id) => (false, id),
+                    Entry::Meld(s) if s.starts_with('r') => {
+                        let id = s[1..]
+                            .parse()
+                            .with_context(|| format!("invalid riichi {s}"))?;
+                        (true, id)
+                    }
+                    Entry::Meld(s) => {
+                        let meld = parse_meld(&s)?;
+                        match meld.letter {
+                            'a' => {
+                                let mut consumed = meld.others;
+                                consumed.push(meld.called);
+                                self.events.push(Event::Ankan {
+                                    actor,
+                                    consumed: consumed.as_slice().try_into()?,
+                                });
+                                continue 'tsumo;
+                            }
+                            'k' => {
+                                let consumed = meld.others.as_slice().try_into()?;
+                                self.events.push(Event::Kakan {
+                                    actor,
+                                    pai: meld.called,
+                                    consumed,
+                                });
+                                continue 'tsumo;
+                            }
+                            'f' => {
+                                self.events.push(Event::Nukidora {
+                                    actor,
+                                    pai: meld.called,
+                                });
+                                continue 'tsumo;
+                            }
+                            c => bail!("unexpected meld {c} in discards of player {actor}"),
+                        }
+                    }
+                };
+
+                if riichi {
+                    self.events.push(Event::Reach { actor });
+                }
+                let (pai, tsumogiri) = if id == 60 {
+                    (last_tsumo.context("tsumogiri without tsumo")?, true)
+                } else {
+                    (tile(id)?, false)
+                };
+                self.events.push(Event::Dahai {
+                    actor,
+                    pai,
+                    tsumogiri,
+                });
+                if riichi {
+                    self.riichi_to_be_accepted = Some(actor);
+                }
+
+                let Some((caller, ev)) = self.find_call(actor, pai)? else {
+                    actor = (actor + 1) % self.n;
+                    continue 'tsumo;
+                };
+                self.accept_riichi();
+                actor = caller;
+                if matches!(ev, Event::Daiminkan { .. }) {
+                    self.events.push(ev);
+                    // The discard slot of a daiminkan is a placeholder.
+                    if self.discards[actor as usize].front() == Some(&Entry::Tile(0)) {
+                        self.discards[actor as usize].pop_front();
+                    }
+                    continue 'tsumo;
+                }
+                self.events.push(ev);
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds the call of the discard `pai` of `discarder`, if any.
+    ///
+    /// The caller is whoever has a matching call at the front of their draws,
+    /// even if it is actually of a later discard of the same tile, see
+    /// `from_tenhou6`.
+    fn find_call(&mut self, discarder: u8, pai: Tile) -> Result<Option<(u8, Event)>> {
+        let mut found = None;
+        for caller in (1..self.n).map(|i| (discarder + i) % self.n) {
+            let Some(Entry::Meld(s)) = self.draws[caller as usize].front() else {
+                continue;
+            };
+            let meld = parse_meld(s)?;
+            let target = meld.target(caller, self.n);
+            if target != discarder || meld.called != pai {
+                continue;
+            }
+            let ev = match meld.letter {
+                'c' => Event::Chi {
+                    actor: caller,
+                    target,
+                    pai,
+                    consumed: meld.others.as_slice().try_into()?,
+                },
+                'p' => Event::Pon {
+                    actor: caller,
+                    target,
+                    pai,
+                    consumed: meld.others.as_slice().try_into()?,
+                },
+                'm' => Event::Daiminkan {
+                    actor: caller,
+                    target,
+                    pai,
+                    consumed: meld.others.as_slice().try_into()?,
+                },
+                c => bail!("unexpected meld {c} in draws of player {caller}"),
+            };
+            // Pon and kan take precedence over chi.
+            let is_chi = matches!(ev, Event::Chi { .. });
+            if found.is_none() || !is_chi {
+                found = Some((caller, ev));
+            }
+        }
+        if let Some((caller, _)) = found {
+            self.draws[caller as usize].pop_front();
+        }
+        Ok(found)
+    }
+
+    fn accept_riichi(&mut self) {
+        if let Some(actor) = self.riichi_to_be_accepted.take() {
+            self.riichi_accepted[actor as usize] = true;
+            self.events.push(Event::ReachAccepted { actor });
+        }
+    }
+
+    /// Converts the last field, like `["和了", deltas, [who, from, pao,
+    /// ...], ...]` or `["流局", deltas]`.
+    fn result(&mut self, result: &[Value], uras: &[u8]) -> Result<()> {
+        let [Value::String(name), rest @ ..] = result else {
+            bail!("invalid result");
+        };
+        match name.as_str() {
+            "和了" => {
+                ensure!(!rest.is_empty() && rest.len() % 2 == 0, "invalid agari",);
+                for agari in rest.chunks(2) {
+                    let deltas: Vec<i32> = field(agari, 0)?;
+                    let info: Vec<Value> = field(agari, 1)?;
+                    let actor: u8 = field(&info, 0)?;
+                    let target: u8 = field(&info, 1)?;
+                    ensure!(actor < self.n && target < self.n, "invalid agari");
+                    let ura_markers = if self.riichi_accepted[actor as usize] {
+                        uras.iter().map(|&id| tile(id)).collect::<Result<_>>()?
+                    } else {
+                        vec![]
+                    };
+                    self.events.push(Event::Hora {
+                        actor,
+                        target,
+                        deltas: Some(pad_scores(&deltas)?),
+                        ura_markers: Some(ura_markers),
+                    });
+                }
+            }
+            _ => {
+                // The 4th riichi is accepted before the abortive draw.
+                if name == "四家立直" {
+                    self.accept_riichi();
+                }
+                let deltas = match rest.first() {
+                    Some(v) => pad_scores(&Vec::<i32>::deserialize(v)?)?,
+                    None => [0; 4],
+                };
+                self.events.push(Event::Ryukyoku {
+                    deltas: Some(deltas),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Meld {
+    /// The letter is at the start for kamicha, in the middle for toimen and
+    /// at the end for shimocha.
+    const fn target(&self, actor: u8, n: u8) -> u8 {
+        let rel = match self.pos {
+            0 => n - 1,
+            2 => 2,
+            _ => 1,
+        };
+        (actor + rel) % n
+    }
+}
+
+fn parse_meld(s: &str) -> Result<Meld> {
+    let idx = s
+        .find(|c: char| c.is_ascii_alphabetic())
+        .with_context(|| format!("invalid meld {s}"))?;
+    let letter = s[idx..].chars().next().unwrap();
+    let digits = format!("{}{}", &s[..idx], &s[idx + 1..]);
+    ensure!(digits.len() % 2 == 0 && idx % 2 == 0, "invalid meld {s}",);
+    let mut tiles = (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            let id = digits[i..i + 2]
+                .parse()
+                .with_context(|| format!("invalid meld {s}"))?;
+            tile(id)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(idx / 2 < tiles.len(), "invalid meld {s}");
+    let called = tiles.remove(idx / 2);
+    Ok(Meld {
+        letter,
+        pos: idx,
+        called,
+        others: tiles,
+    })
+}
+
+/// Converts a tile like `11` for 1m, `47` for C, or `51` for aka 5m.
+fn tile(id: u8) -> Result<Tile> {
+    let tile = match id {
+        11..=19 => id - 11,
+        21..=29 => id - 21 + 9,
+        31..=39 => id - 31 + 18,
+        41..=47 => id - 41 + 27,
+        51..=53 => return Ok(must_tile!(tu8!(5m) + (id - 51) * 9).akaize()),
+        _ => bail!("invalid tile {id}"),
+    };
+    Ok(must_tile!(tile))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mjai::convert::validate;
+
+    #[test]
+    fn calls_and_kans() {
+        // Chi, pon, daiminkan, ankan, riichi, kakan and a ron on the riichi,
+        // followed by a kyushukyuhai.
+        let raw = r#"{
+            "title": ["", ""],
+            "name": ["A", "B", "C", "D"],
+            "rule": {"disp": "般東喰赤", "aka": 1},
+            "log": [
+                [
+                    [0, 0, 0], [25000, 25000, 25000, 25000], [11, 12, 21, 22], [13, 14, 15, 16],
+                    [21, 42, 42, 42, 13, 14, 15, 16, 17, 18, 33, 34, 35], [19, 42, 51], [21, "424242a42", "r60"],
+                    [22, 23, 39, 31, 31, 32, 32, 36, 36, 41, 41, 44, 44], ["c212223", 29], [39, 60],
+                    [39, 39, 47, 24, 24, 25, 25, 26, 26, 27, 27, 43, 43], ["p393939", 39, 28], [47, "k39393939", 60],
+                    [47, 47, 47, 37, 37, 38, 38, 45, 45, 45, 46, 46, 46], ["m47474747", 11, 19], [0, 60, 60],
+                    ["和了", [13000, 0, 0, -12000], [0, 3, 0, "30符3飜3900点"]]
+                ],
+                [
+                    [1, 0, 0], [38000, 25000, 25000, 12000], [23], [],
+                    [11, 12, 13, 14, 15, 16, 17, 18, 19, 21, 22, 23, 24], [], [],
+                    [11, 19, 21, 29, 31, 39, 41, 42, 43, 44, 45, 46, 12], [47], [],
+                    [31, 32, 33, 34, 35, 36, 37, 38, 25, 26, 27, 28, 32], [], [],
+                    [14, 15, 16, 17, 18, 24, 25, 26, 27, 28, 33, 34, 35], [], [],
+                    ["九種九牌"]
+                ]
+            ]
+        }"#;
+        let (events, rule) = from_tenhou6(raw).unwrap();
+        assert_eq!(rule, RuleSet::tenhou_tonpuu());
+        validate(&events, rule).unwrap();
+
+        let kinds: Vec<_> = events
+            .iter()
+            .map(|ev| match ev {
+                Event::Tsumo { actor, .. } => format!("tsumo{actor}"),
+                Event::Dahai { actor, .. } => format!("dahai{actor}"),
+                Event::Dora { dora_marker } => format!("dora{dora_marker}"),
+                ev => {
+                    let json = json::to_value(ev).unwrap();
+                    let typ = json["type"].as_str().unwrap().to_owned();
+                    match ev.actor() {
+                        Some(actor) => format!("{typ}{actor}"),
+                        None => typ,
+                    }
+                }
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                "start_game",
+                "start_kyoku",
+                "tsumo0",
+                "dahai0",
+                "chi1",
+                "dahai1",
+                "pon2",
+                "dahai2",
+                "daiminkan3",
+                "tsumo3",
+                "dora2m",
+                "dahai3",
+                "tsumo0",
+                "ankan0",
+                "dora1p",
+                "tsumo0",
+                "reach0",
+                "dahai0",
+                "reach_accepted0",
+                "tsumo1",
+                "dahai1",
+                "tsumo2",
+                "kakan2",
+                "tsumo2",
+                "dora2p",
+                "dahai2",
+                "tsumo3",
+                "dahai3",
+                "hora0",
+                "end_kyoku",
+                "start_kyoku",
+                "tsumo1",
+                "ryukyoku",
+                "end_kyoku",
+                "end_game",
+            ],
+        );
+
+        assert_eq!(
+            events[17],
+            Event::Dahai {
+                actor: 0,
+                pai: t!(5mr),
+                tsumogiri: true,
+            },
+        );
+        assert_eq!(
+            events[22],
+            Event::Kakan {
+                actor: 2,
+                pai: t!(9s),
+                consumed: t![9s, 9s, 9s],
+            },
+        );
+        assert_eq!(
+            events[28],
+            Event::Hora {
+                actor: 0,
+                target: 3,
+                deltas: Some([13000, 0, 0, -12000]),
+                ura_markers: Some(t![3m, 4m, 5m, 6m].to_vec()),
+            },
+        );
+        let Event::StartKyoku { bakaze, oya, .. } = events[30] else {
+            panic!();
+        };
+        assert_eq!((bakaze, oya), (t!(E), 1));
+    }
+
+    #[test]
+    fn passed_then_called() {
+        // C passes the first 5m of A, but pons the second one after their
+        // turn is skipped by the pon of D. The pon is taken as of the first
+        // 5m, so D ends up with a call where a draw is expected.
+        let raw = r#"{
+            "name": ["A", "B", "C", "D"],
+            "log": [
+                [
+                    [0, 0, 0], [25000, 25000, 25000, 25000], [11], [],
+                    [15, 15, 11, 12, 13, 21, 22, 23, 31, 32, 33, 41, 41], [45, 46], [15, 15],
+                    [29, 12, 13, 14, 24, 25, 26, 34, 35, 36, 42, 42, 43], [47], [29],
+                    [15, 15, 17, 18, 19, 27, 28, 37, 38, 39, 43, 44, 44], ["15p1515"], [19],
+                    [29, 29, 16, 17, 18, 26, 27, 28, 36, 37, 38, 44, 47], ["29p2929"], [44],
+                    ["流局", [0, 0, 0, 0]]
+                ]
+            ]
+        }"#;
+        let err = from_tenhou6(raw).unwrap_err();
+        assert!(format!("{err:#}").contains("unexpected call"));
+    }
+
+    #[test]
+    fn meld_strings() {
+        let meld = parse_meld("25p2525").unwrap();
+        assert_eq!((meld.letter, meld.called), ('p', t!(5p)));
+        assert_eq!(meld.target(0, 4), 2);
+        let meld = parse_meld("393939m39").unwrap();
+        assert_eq!(meld.letter, 'm');
+        assert_eq!(meld.target(0, 4), 1);
+        assert_eq!(meld.others, t![9s, 9s, 9s]);
+        let meld = parse_meld("c525153").unwrap();
+        assert_eq!(meld.called, t!(5pr));
+        assert_eq!(meld.target(1, 3), 0);
+        assert_eq!(meld.others, t![5mr, 5sr]);
+        assert!(parse_meld("p25252").is_err());
+        assert!(parse_meld("p252560").is_err());
+        assert!(parse_meld("2525").is_err());
+    }
+}
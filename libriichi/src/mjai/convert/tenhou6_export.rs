@@ -0,0 +1,671 @@
+use super::rule_of;
+use crate::algo::agari::{Agari, Yaku};
+use crate::algo::point::Point;
+use crate::mjai::Event;
+use crate::rankings::Rankings;
+use crate::rule::RuleSet;
+use crate::state::PlayerState;
+use crate::tile::Tile;
+use crate::tu8;
+use std::mem;
+
+use anyhow::{Context, Result, bail, ensure};
+use serde_json::{self as json, Value, json};
+
+/// Converts the events of a game into a log in the JSON format of
+/// tenhou.net/6, which can be opened in Tenhou's replay viewer.
+///
+/// mjai does not record the yakus and points of a hora, nor the kind of a
+/// ryukyoku, so they are recalculated by the `PlayerState` of every seat.
+/// The final points in `sc` follow Tenhou's oka and the uma of 20-10, with
+/// no uma for sanma.
+pub fn to_tenhou6(events: &[Event]) -> Result<String> {
+    to_tenhou6_with_rule(events, rule_of(events)?)
+}
+
+/// Same as `to_tenhou6`, but recalculates the horas and ryukyokus under
+/// `rule` instead of the Tenhou rule.
+pub fn to_tenhou6_with_rule(events: &[Event], rule: RuleSet) -> Result<String> {
+    let [Event::StartGame { names, .. }, rest @ .., Event::EndGame] = events else {
+        bail!("not a complete game");
+    };
+    ensure!(
+        rule.sanma == rule_of(events)?.sanma,
+        "the rule does not match the number of players of the log",
+    );
+    let n = rule.player_count();
+    let mut states: Vec<_> = (0..n)
+        .map(|i| PlayerState::new_with_rule(i, rule))
+        .collect();
+
+    let mut log = vec![];
+    let mut start = 0;
+    for (i, ev) in rest.iter().enumerate() {
+        if matches!(ev, Event::EndKyoku) {
+            let kyoku = &rest[start..=i];
+            let kyoku_log = export_kyoku(kyoku, &mut states)
+                .with_context(|| format!("in kyoku #{}", log.len()))?;
+            log.push(kyoku_log);
+            start = i + 1;
+        }
+    }
+    ensure!(start == rest.len(), "incomplete kyoku at the end");
+
+    let long_game = rest.iter().any(|ev| match ev {
+        Event::StartKyoku { bakaze, .. } => bakaze.as_u8() != tu8!(E),
+        _ => false,
+    });
+    let disp = format!(
+        "般{}{}{}{}",
+        if rule.sanma { "三" } else { "" },
+        if long_game { "南" } else { "東" },
+        if rule.kuitan { "喰" } else { "" },
+        if rule.aka_count > 0 { "赤" } else { "" },
+    );
+    let game = json!({
+        "title": ["", ""],
+        "name": names,
+        "rule": {"disp": disp, "aka": u8::from(rule.aka_count > 0)},
+        "log": log,
+        "sc": final_points(rest, n)?,
+    });
+    Ok(json::to_string(&game)?)
+}
+
+fn export_kyoku(kyoku: &[Event], states: &mut [PlayerState]) -> Result<Value> {
+    let Some(&Event::StartKyoku {
+        bakaze,
+        dora_marker,
+        kyoku: kyoku_num,
+        honba,
+        kyotaku,
+        scores,
+        ref tehais,
+        ..
+    }) = kyoku.first()
+    else {
+        bail!("kyoku without start_kyoku");
+    };
+    let n = states.len() as u8;
+
+    let mut draws: [Vec<Value>; 4] = Default::default();
+    let mut discards: [Vec<Value>; 4] = Default::default();
+    let mut doras = vec![tile_id(dora_marker)?];
+    let mut uras = vec![];
+    // Where the letter of each pon is, so that its kakan is put there too.
+    let mut pons: [Vec<(Tile, usize)>; 4] = Default::default();
+    let mut riichi_declared = [false; 4];
+    let mut riichi_accepted = 0;
+    let mut kans = 0;
+    let mut has_call = false;
+    let mut result = None;
+
+    for (i, ev) in kyoku.iter().enumerate() {
+        match *ev {
+            Event::Tsumo { actor, pai } => draws[actor as usize].push(tile_id(pai)?.into()),
+            Event::Dahai {
+                actor,
+                pai,
+                tsumogiri,
+            } => {
+                let id = if tsumogiri { 60 } else { tile_id(pai)? };
+                let entry = if mem::take(&mut riichi_declared[actor as usize]) {
+                    format!("r{id}").into()
+                } else {
+                    id.into()
+                };
+                discards[actor as usize].push(entry);
+            }
+            Event::Reach { actor } => riichi_declared[actor as usize] = true,
+            Event::ReachAccepted { .. } => riichi_accepted += 1,
+            Event::Chi {
+                actor,
+                pai,
+                consumed,
+                ..
+            } => {
+                has_call = true;
+                draws[actor as usize].push(meld('c', pai, &consumed, 0)?);
+            }
+            Event::Pon {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                has_call = true;
+                let pos = call_pos(actor, target, n, consumed.len());
+                pons[actor as usize].push((pai.deaka(), pos));
+                draws[actor as usize].push(meld('p', pai, &consumed, pos)?);
+            }
+            Event::Daiminkan {
+                actor,
+                target,
+                pai,
+                consumed,
+            } => {
+                has_call = true;
+                kans += 1;
+                let pos = call_pos(actor, target, n, consumed.len());
+                draws[actor as usize].push(meld('m', pai, &consumed, pos)?);
+                // Tenhou puts a placeholder in the discards for a daiminkan.
+                discards[actor as usize].push(0.into());
+            }
+            Event::Kakan {
+                actor,
+                pai,
+                consumed,
+            } => {
+                has_call = true;
+                kans += 1;
+                let pos = pons[actor as usize]
+                    .iter()
+                    .find(|&&(tile, _)| tile == pai.deaka())
+                    .map(|&(_, pos)| pos)
+                    .context("kakan without pon")?;
+                discards[actor as usize].push(meld('k', pai, &consumed, pos)?);
+            }
+            Event::Ankan { actor, consumed } => {
+                has_call = true;
+                kans += 1;
+                discards[actor as usize].push(meld('a', consumed[3], &consumed[..3], 3)?);
+            }
+            Event::Nukidora { actor, pai } => {
+                discards[actor as usize].push(meld('f', pai, &[], 0)?);
+            }
+            Event::Dora { dora_marker } => doras.push(tile_id(dora_marker)?),
+            // All the horas of a multi-ron are calculated at once before any
+            // of them is fed to the states.
+            Event::Hora { .. } if result.is_none() => {
+                let mut agaris = vec![Value::from("和了")];
+                for ev in kyoku[i..]
+                    .iter()
+                    .take_while(|ev| matches!(ev, Event::Hora { .. }))
+                {
+                    let Event::Hora {
+                        actor,
+                        target,
+                        deltas,
+                        ref ura_markers,
+                    } = *ev
+                    else {
+                        unreachable!();
+                    };
+                    let deltas = deltas.context("deltas is required")?;
+                    let ura_markers = ura_markers.as_deref().unwrap_or_default();
+                    if uras.is_empty() {
+                        uras = ura_markers
+                            .iter()
+                            .map(|&t| tile_id(t))
+                            .collect::<Result<_>>()?;
+                    }
+                    let info =
+                        agari_info(&states[actor as usize], actor, target, deltas, ura_markers)?;
+                    agaris.push(json!(deltas));
+                    agaris.push(info.into());
+                }
+                result = Some(agaris);
+            }
+            Event::Ryukyoku { deltas } => {
+                let deltas = deltas.unwrap_or_default();
+                let name = if matches!(kyoku[i - 1], Event::Tsumo { .. }) {
+                    "九種九牌"
+                } else if states[0].tiles_left() == 0 {
+                    if deltas.iter().any(|&d| d != 0) {
+                        "流局"
+                    } else if states.iter().all(|s| s.shanten() == 0) {
+                        "全員聴牌"
+                    } else {
+                        "全員不聴"
+                    }
+                } else if kans >= 4 {
+                    "四槓散了"
+                } else if riichi_accepted == 4 {
+                    "四家立直"
+                } else if !has_call && discards.iter().map(Vec::len).sum::<usize>() == 4 {
+                    "四風連打"
+                } else {
+                    "三家和了"
+                };
+                result = Some(vec![name.into(), json!(deltas)]);
+            }
+            _ => (),
+        }
+
+        for state in states.iter_mut() {
+            // Keep the cans on hora for the other winners of a multi-ron.
+            state.update_with_keep_cans(ev, true)?;
+        }
+    }
+
+    let mut ret = vec![
+        json!([
+            (bakaze.as_u8() - tu8!(E)) * 4 + kyoku_num - 1,
+            honba,
+            kyotaku
+        ]),
+        json!(scores),
+        json!(doras),
+        json!(uras),
+    ];
+    for ((tehai, draws), discards) in tehais.iter().zip(draws).zip(discards) {
+        let haipai = if tehai[0].is_unknown() {
+            vec![]
+        } else {
+            tehai.iter().map(|&t| tile_id(t)).collect::<Result<_>>()?
+        };
+        ret.extend([json!(haipai), draws.into(), discards.into()]);
+    }
+    ret.push(result.context("kyoku without hora or ryukyoku")?.into());
+    Ok(ret.into())
+}
+
+/// Makes `[who, from, pao, points, yakus...]` of a hora.
+fn agari_info(
+    state: &PlayerState,
+    actor: u8,
+    target: u8,
+    deltas: [i32; 4],
+    ura_markers: &[Tile],
+) -> Result<Vec<Value>> {
+    let is_ron = actor != target;
+    let detail = state.agari_detail(is_ron, ura_markers)?;
+    let point = state.agari_points(is_ron, ura_markers)?;
+
+    // pao is not recorded either, but it is the only way another player
+    // pays for a ron, or a single player pays for a tsumo.
+    let n = state.rule().player_count();
+    let payers: Vec<_> = (0..n).filter(|&i| deltas[i as usize] < 0).collect();
+    let pao = match *payers.as_slice() {
+        [p] if !is_ron => p,
+        _ => payers
+            .into_iter()
+            .find(|&i| is_ron && i != target)
+            .unwrap_or(actor),
+    };
+
+    let mut info = vec![
+        actor.into(),
+        target.into(),
+        pao.into(),
+        point_str(detail.agari, point, is_ron, state.is_oya()).into(),
+    ];
+    let is_yakuman = matches!(detail.agari, Agari::Yakuman(_));
+    for &(yaku, han) in &detail.yakus {
+        let name = yaku_name(yaku, state.bakaze(), state.jikaze());
+        let s = if is_yakuman {
+            format!("{name}(役満)")
+        } else {
+            format!("{name}({han}飜)")
+        };
+        info.push(s.into());
+    }
+    if !is_yakuman {
+        let doras = [
+            ("ドラ", detail.dora, detail.dora > 0),
+            ("赤ドラ", detail.aka_dora, detail.aka_dora > 0),
+            ("裏ドラ", detail.ura_dora, state.self_riichi_accepted()),
+        ];
+        for (name, han, shown) in doras {
+            if shown {
+                info.push(format!("{name}({han}飜)").into());
+            }
+        }
+    }
+    Ok(info)
+}
+
+/// Formats the points like `30符2飜2000点`, `満貫2000-4000点` or
+/// `跳満6000点∀`, without honba.
+fn point_str(agari: Agari, point: Point, is_ron: bool, is_oya: bool) -> String {
+    let payment = if is_ron {
+        format!("{}点", point.ron)
+    } else if is_oya {
+        format!("{}点∀", point.tsumo_ko)
+    } else {
+        format!("{}-{}点", point.tsumo_ko, point.tsumo_oya)
+    };
+    let limit = match agari {
+        Agari::Yakuman(_) | Agari::Normal { han: 13.., .. } => "役満",
+        Agari::Normal { han: 11..=12, .. } => "三倍満",
+        Agari::Normal { han: 8..=10, .. } => "倍満",
+        Agari::Normal { han: 6..=7, .. } => "跳満",
+        Agari::Normal { han: 5, .. }
+        | Agari::Normal { fu: 40.., han: 4 }
+        | Agari::Normal { fu: 70.., han: 3 } => "満貫",
+        Agari::Normal { fu, han } => return format!("{fu}符{han}飜{payment}"),
+    };
+    format!("{limit}{payment}")
+}
+
+fn yaku_name(yaku: Yaku, bakaze: Tile, jikaze: Tile) -> String {
+    const WINDS: [&str; 4] = ["東", "南", "西", "北"];
+    let name = match yaku {
+        Yaku::Riichi => "立直",
+        Yaku::DoubleRiichi => "両立直",
+        Yaku::Ippatsu => "一発",
+        Yaku::MenzenTsumo => "門前清自摸和",
+        Yaku::Haitei => "海底摸月",
+        Yaku::Houtei => "河底撈魚",
+        Yaku::Rinshan => "嶺上開花",
+        Yaku::Chankan => "槍槓",
+        Yaku::Pinfu => "平和",
+        Yaku::Chiitoitsu => "七対子",
+        Yaku::Tanyao => "断幺九",
+        Yaku::Iipeikou => "一盃口",
+        Yaku::Ryanpeikou => "二盃口",
+        Yaku::Toitoi => "対々和",
+        Yaku::Sanankou => "三暗刻",
+        Yaku::Sankantsu => "三槓子",
+        Yaku::SanshokuDoujun => "三色同順",
+        Yaku::SanshokuDoukou => "三色同刻",
+        Yaku::Ittsuu => "一気通貫",
+        Yaku::Chanta => "混全帯幺九",
+        Yaku::Junchan => "純全帯幺九",
+        Yaku::Honroutou => "混老頭",
+        Yaku::Shousangen => "小三元",
+        Yaku::Honitsu => "混一色",
+        Yaku::Chinitsu => "清一色",
+        Yaku::Bakaze => {
+            return format!("場風 {}", WINDS[(bakaze.as_u8() - tu8!(E)) as usize]);
+        }
+        Yaku::Jikaze => {
+            return format!("自風 {}", WINDS[(jikaze.as_u8() - tu8!(E)) as usize]);
+        }
+        Yaku::Haku => "役牌 白",
+        Yaku::Hatsu => "役牌 發",
+        Yaku::Chun => "役牌 中",
+        Yaku::Tenhou => "天和",
+        Yaku::Chihou => "地和",
+        Yaku::KokushiMusou => "国士無双",
+        Yaku::ChuurenPoutou => "九蓮宝燈",
+        Yaku::Suuankou => "四暗刻",
+        Yaku::Suukantsu => "四槓子",
+        Yaku::Daisangen => "大三元",
+        Yaku::Shousuushii => "小四喜",
+        Yaku::Daisuushii => "大四喜",
+        Yaku::Tsuuiisou => "字一色",
+        Yaku::Ryuuiisou => "緑一色",
+        Yaku::Chinroutou => "清老頭",
+        Yaku::Renhou => "人和",
+        Yaku::Shiisanpuutaa => "十三不塔",
+        Yaku::KokushiMusouJuusanmen => "国士無双１３面",
+        Yaku::JunseiChuurenPoutou => "純正九蓮宝燈",
+        Yaku::SuuankouTanki => "四暗刻単騎",
+        Yaku::Sanrenkou => "三連刻",
+    };
+    name.to_owned()
+}
+
+/// Returns `[score0 / 100, point0, score1 / 100, point1, ...]` of the first
+/// `n` seats, after the remaining kyotaku goes to the top.
+fn final_points(events: &[Event], n: u8) -> Result<Vec<Value>> {
+    let n = n as usize;
+    let mut init_scores = None;
+    let mut scores = [0; 4];
+    let mut kyotaku = 0;
+    for ev in events {
+        match *ev {
+            Event::StartKyoku {
+                scores: s,
+                kyotaku: k,
+                ..
+            } => {
+                init_scores.get_or_insert(s);
+                scores = s;
+                kyotaku = k as i32;
+            }
+            Event::ReachAccepted { actor } => {
+                scores[actor as usize] -= 1000;
+                kyotaku += 1;
+            }
+            Event::Hora { deltas, .. } | Event::Ryukyoku { deltas } => {
+                let deltas = deltas.context("deltas is required")?;
+                for (s, d) in scores.iter_mut().zip(deltas) {
+                    *s += d;
+                }
+                if matches!(ev, Event::Hora { .. }) {
+                    kyotaku = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+    let init_scores = init_scores.context("no kyoku in the log")?;
+    *scores[..n].iter_mut().min_by_key(|s| -**s).unwrap() += kyotaku * 1000;
+
+    // 30000 返し for 25000 持ち and 40000 返し for 35000 持ち.
+    let start = init_scores[..n].iter().sum::<i32>() / n as i32;
+    let target = start + 5000;
+    let uma: &[i32] = if n == 4 { &[20, 10, -10, -20] } else { &[0; 3] };
+    let rankings = if n == 4 {
+        Rankings::new(scores)
+    } else {
+        Rankings::new_sanma(scores)
+    };
+    let mut ret = vec![];
+    for (i, &score) in scores[..n].iter().enumerate() {
+        let rank = rankings.rank_by_player[i] as usize;
+        let oka = if rank == 0 {
+            (target - start) * n as i32
+        } else {
+            0
+        };
+        // In tenths to get rid of the float errors.
+        let point = (score - target + oka) / 100 + uma[rank] * 10;
+        ret.push((score / 100).into());
+        ret.push((point as f64 / 10.).into());
+    }
+    Ok(ret)
+}
+
+/// The letter of a call is put at the start for kamicha, after the first tile
+/// for toimen and at the end for shimocha.
+const fn call_pos(actor: u8, target: u8, n: u8, consumed: usize) -> usize {
+    let rel = (target + n - actor) % n;
+    if rel == n - 1 {
+        0
+    } else if rel == 1 {
+        consumed
+    } else {
+        1
+    }
+}
+
+/// Makes a meld string like `39p3939`, with the letter and `called` put
+/// before `others[pos]`.
+fn meld(letter: char, called: Tile, others: &[Tile], pos: usize) -> Result<Value> {
+    let mut s = String::new();
+    for (i, &tile) in others.iter().enumerate() {
+        if i == pos {
+            s.push(letter);
+            s.push_str(&tile_id(called)?.to_string());
+        }
+        s.push_str(&tile_id(tile)?.to_string());
+    }
+    if pos == others.len() {
+        s.push(letter);
+        s.push_str(&tile_id(called)?.to_string());
+    }
+    Ok(s.into())
+}
+
+/// The inverse of `tile` of the importer.
+fn tile_id(tile: Tile) -> Result<u8> {
+    let id = tile.as_u8();
+    let ret = match id {
+        0..=8 => 11 + id,
+        9..=17 => 21 + id - 9,
+        18..=26 => 31 + id - 18,
+        27..=33 => 41 + id - 27,
+        34..=36 => 51 + id - 34,
+        _ => bail!("unexpected unknown tile"),
+    };
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::arena::BatchGame;
+    use crate::mjai::EventExt;
+    use crate::mjai::convert::test::normalized;
+    use crate::mjai::convert::{from_tenhou6, validate};
+    use crate::rule::RuleSet;
+    use crate::t;
+    use crate::test_util::run_arena;
+
+    /// Exports an arena game, imports it back, and checks the rons against
+    /// their deltas.
+    fn round_trip(game: &BatchGame, seed: (u64, u64)) -> Value {
+        let result = run_arena(game, seed);
+        let raw = result.dump_tenhou6_log().unwrap();
+        let log: Vec<EventExt> = result.game_log.into_iter().flatten().collect();
+
+        let (events, rule) = from_tenhou6(&raw).unwrap();
+        assert_eq!(rule.kuitan, game.rule.kuitan);
+        validate(&events, rule).unwrap();
+        assert_eq!(
+            normalized(
+                &events[1..events.len() - 1]
+                    .iter()
+                    .cloned()
+                    .map(EventExt::from)
+                    .collect::<Vec<_>>()
+            ),
+            normalized(&log),
+        );
+
+        let game: Value = json::from_str(&raw).unwrap();
+        for kyoku in game["log"].as_array().unwrap() {
+            let honba = kyoku[0][1].as_i64().unwrap();
+            let result = kyoku[16].as_array().unwrap();
+            let [name, deltas, info] = result.as_slice() else {
+                continue;
+            };
+            let (actor, target, pao) = (&info[0], &info[1], &info[2]);
+            if name != "和了" || actor == target || actor != pao {
+                continue;
+            }
+            let points = info[3].as_str().unwrap();
+            let ron: i64 = points
+                .trim_end_matches('点')
+                .rsplit(|c: char| !c.is_ascii_digit())
+                .next()
+                .unwrap()
+                .parse()
+                .unwrap();
+            let target = target.as_u64().unwrap() as usize;
+            assert_eq!(
+                deltas[target].as_i64().unwrap(),
+                -ron - honba * 300,
+                "{points}"
+            );
+        }
+        game
+    }
+
+    #[test]
+    fn arena_round_trip() {
+        let game = BatchGame {
+            rule: RuleSet::tenhou_tonpuu(),
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let games: Vec<_> = (0..12)
+            .map(|seed| round_trip(&game, (seed, seed)))
+            .collect();
+        let mut letters = vec![];
+        for kyoku in games.iter().flat_map(|g| g["log"].as_array().unwrap()) {
+            let moves = kyoku.as_array().unwrap()[5..16].iter();
+            for s in moves
+                .flat_map(|v| v.as_array().unwrap())
+                .filter_map(Value::as_str)
+            {
+                letters.extend(s.chars().find(char::is_ascii_alphabetic));
+            }
+        }
+        for letter in ['c', 'p', 'm', 'k', 'a', 'r'] {
+            assert!(letters.contains(&letter), "no {letter} in the games");
+        }
+        let raw = json::to_string(&games).unwrap();
+        assert!(raw.contains("立直(1飜)") && raw.contains("裏ドラ("));
+
+        let sanma = BatchGame {
+            rule: RuleSet::tenhou_sanma(),
+            init_scores: [35000, 35000, 35000, 0],
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let game = round_trip(&sanma, (7, 7));
+        assert_eq!(game["rule"]["disp"], "般三南喰赤");
+        assert!(game.to_string().contains("\"f44\""));
+        let sc = game["sc"].as_array().unwrap();
+        assert_eq!(sc.len(), 6);
+        let total: f64 = sc
+            .iter()
+            .skip(1)
+            .step_by(2)
+            .map(|p| p.as_f64().unwrap())
+            .sum();
+        assert!(total.abs() < 1e-6, "{sc:?}");
+
+        // The rule of the game is followed rather than Tenhou's.
+        let game = BatchGame {
+            rule: RuleSet {
+                kuitan: false,
+                aka_count: 0,
+                ..RuleSet::tenhou_tonpuu()
+            },
+            disable_progress_bar: true,
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let result = run_arena(&game, (3, 3));
+        let raw: Value = json::from_str(&result.dump_tenhou6_log().unwrap()).unwrap();
+        let disp = raw["rule"]["disp"].as_str().unwrap();
+        assert!(!disp.contains(['喰', '赤']), "{disp}");
+        assert_eq!(raw["rule"]["aka"], 0);
+        let mut events: Vec<_> = result
+            .game_log
+            .iter()
+            .flatten()
+            .map(|ev| ev.event.clone())
+            .collect();
+        events.insert(
+            0,
+            Event::StartGame {
+                id: None,
+                names: result.names.clone(),
+                seed: None,
+            },
+        );
+        events.push(Event::EndGame);
+        to_tenhou6_with_rule(&events, RuleSet::tenhou_sanma()).unwrap_err();
+    }
+
+    #[test]
+    fn formats() {
+        let point = Point::calc(false, 30, 3);
+        let agari = Agari::Normal { fu: 30, han: 3 };
+        assert_eq!(point_str(agari, point, true, false), "30符3飜3900点");
+        assert_eq!(point_str(agari, point, false, false), "30符3飜1000-2000点");
+        let point = Point::calc(true, 40, 4);
+        let agari = Agari::Normal { fu: 40, han: 4 };
+        assert_eq!(point_str(agari, point, false, true), "満貫4000点∀");
+        let point = Point::yakuman(false, 2);
+        assert_eq!(
+            point_str(Agari::Yakuman(2), point, true, false),
+            "役満64000点",
+        );
+        assert_eq!(yaku_name(Yaku::Jikaze, t!(E), t!(W)), "自風 西");
+
+        // pon from toimen, daiminkan from shimocha, and chi.
+        assert_eq!(call_pos(0, 2, 4, 2), 1);
+        assert_eq!(call_pos(1, 2, 3, 3), 3);
+        assert_eq!(call_pos(1, 0, 3, 2), 0);
+        assert_eq!(meld('p', t!(5p), &t![5pr, 5p], 1).unwrap(), "52p2525",);
+        assert_eq!(meld('m', t!(9s), &t![9s, 9s, 9s], 3).unwrap(), "393939m39",);
+        assert_eq!(meld('f', t!(N), &[], 0).unwrap(), "f44");
+        tile_id(t!(?)).unwrap_err();
+    }
+}
@@ -1,6 +1,7 @@
+use crate::t;
 use crate::tile::Tile;
 use std::error::Error;
-use std::fmt;
+use std::{array, fmt};
 
 use derivative::Derivative;
 use serde::{Deserialize, Serialize};
@@ -22,6 +23,10 @@ pub enum Event {
     None,
 
     StartGame {
+        /// The player ID of the receiver, only present when sent by a server.
+        #[serde(default)]
+        #[serde_as(deserialize_as = "Option<TryFromInto<Actor>>")]
+        id: Option<u8>,
         #[serde(default)]
         names: [String; 4],
 
@@ -89,6 +94,13 @@ pub enum Event {
         actor: u8,
         consumed: [Tile; 4],
     },
+    /// Sets aside a N as a dora in sanma (抜きドラ), which is then followed by
+    /// a rinshan tsumo. This is an extension.
+    Nukidora {
+        #[serde_as(deserialize_as = "TryFromInto<Actor>")]
+        actor: u8,
+        pai: Tile,
+    },
     Dora {
         dora_marker: Tile,
     },
@@ -146,7 +158,7 @@ pub struct Metadata {
     pub eval_time_ns: Option<u64>,
     pub shanten: Option<i8>,
     pub at_furiten: Option<bool>,
-    pub kan_select: Option<Box<Metadata>>,
+    pub kan_select: Option<Box<Self>>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -168,6 +180,7 @@ impl Event {
             | Self::Daiminkan { actor, .. }
             | Self::Kakan { actor, .. }
             | Self::Ankan { actor, .. }
+            | Self::Nukidora { actor, .. }
             | Self::Reach { actor, .. }
             | Self::ReachAccepted { actor, .. }
             | Self::Hora { actor, .. } => Some(actor),
@@ -184,10 +197,48 @@ impl Event {
         )
     }
 
-    pub fn augment(&mut self) {
-        const fn swap_tile(t: &mut Tile) {
-            *t = t.augment();
+    /// Returns the event as seen by `player_id`, where the tiles hidden from
+    /// it are replaced by unknown tiles.
+    #[must_use]
+    pub fn masked(&self, player_id: u8) -> Self {
+        match *self {
+            Self::StartKyoku {
+                bakaze,
+                dora_marker,
+                kyoku,
+                honba,
+                kyotaku,
+                oya,
+                scores,
+                tehais,
+            } => Self::StartKyoku {
+                bakaze,
+                dora_marker,
+                kyoku,
+                honba,
+                kyotaku,
+                oya,
+                scores,
+                tehais: array::from_fn(|i| {
+                    if i == player_id as usize {
+                        tehais[i]
+                    } else {
+                        [t!(?); 13]
+                    }
+                }),
+            },
+            Self::Tsumo { actor, .. } if actor != player_id => Self::Tsumo { actor, pai: t!(?) },
+            _ => self.clone(),
         }
+    }
+
+    pub fn augment(&mut self) {
+        self.map_tiles(Tile::augment);
+    }
+
+    /// Replaces every tile in the event with `f(tile)`.
+    pub fn map_tiles(&mut self, f: impl Fn(Tile) -> Tile) {
+        let swap_tile = |t: &mut Tile| *t = f(*t);
 
         match self {
             Self::StartKyoku {
@@ -200,7 +251,9 @@ impl Event {
                 swap_tile(dora_marker);
                 tehais.iter_mut().flatten().for_each(swap_tile);
             }
-            Self::Tsumo { pai, .. } | Self::Dahai { pai, .. } => swap_tile(pai),
+            Self::Tsumo { pai, .. } | Self::Dahai { pai, .. } | Self::Nukidora { pai, .. } => {
+                swap_tile(pai);
+            }
             Self::Chi { pai, consumed, .. } | Self::Pon { pai, consumed, .. } => {
                 swap_tile(pai);
                 consumed.iter_mut().for_each(swap_tile);
@@ -262,6 +315,7 @@ mod test {
         let lines = r#"
             {"type":"none"}
             {"type":"start_game","names":["Equim","Mortal","akochan","NoName"],"seed":[123,456]}
+            {"type":"start_game","id":1,"names":["Equim","Mortal","akochan","NoName"]}
             {"type":"start_kyoku","bakaze":"E","dora_marker":"5s","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["N","3p","W","W","7m","N","S","C","7m","P","8p","2m","5m"],["7p","1p","2m","3m","4m","C","7s","7s","9s","9p","1m","C","1s"],["3s","E","5m","P","5m","F","7p","6m","5s","9p","1s","S","N"],["2p","4s","4p","E","5p","F","3p","1s","8p","6s","8s","7s","5p"]]}
             {"type":"tsumo","actor":0,"pai":"1m"}
             {"type":"dahai","actor":0,"pai":"2m","tsumogiri":true}
@@ -270,6 +324,7 @@ mod test {
             {"type":"daiminkan","actor":2,"target":0,"pai":"5p","consumed":["5pr","5p","5p"]}
             {"type":"kakan","actor":3,"pai":"S","consumed":["S","S","S"]}
             {"type":"ankan","actor":0,"consumed":["9m","9m","9m","9m"]}
+            {"type":"nukidora","actor":2,"pai":"N"}
             {"type":"dora","dora_marker":"3s"}
             {"type":"reach","actor":1}
             {"type":"reach_accepted","actor":2}
@@ -1,4 +1,5 @@
 mod bot;
+pub mod convert;
 mod event;
 
 pub use event::{Event, EventExt, EventWithCanAct, Metadata, OutOfBoundError};
@@ -6,8 +6,18 @@ pub struct Rankings {
 
 impl Rankings {
     pub fn new(scores: [i32; 4]) -> Self {
+        Self::new_inner(scores, false)
+    }
+
+    /// Ranks the scores of sanma, where the vacant seat 3 is always ranked
+    /// the last regardless of its score.
+    pub fn new_sanma(scores: [i32; 4]) -> Self {
+        Self::new_inner(scores, true)
+    }
+
+    fn new_inner(scores: [i32; 4], sanma: bool) -> Self {
         let mut player_by_rank = [0, 1, 2, 3];
-        player_by_rank.sort_by_key(|&i| -scores[i as usize]);
+        player_by_rank.sort_by_key(|&i| (sanma && i == 3, -scores[i as usize]));
 
         let mut rank_by_player = [0; 4];
         for (rank, id) in player_by_rank.into_iter().enumerate() {
@@ -63,4 +73,14 @@ mod test {
         *scores.iter_mut().min_by_key(|s| -**s).unwrap() = 0;
         assert_eq!(scores, [0; 4]);
     }
+
+    #[test]
+    fn rankings_sanma() {
+        let rk = Rankings::new_sanma([-1000, 50000, 56000, 0]);
+        assert_eq!(rk.player_by_rank, [2, 1, 0, 3]);
+        assert_eq!(rk.rank_by_player, [2, 1, 0, 3]);
+
+        let rk = Rankings::new_sanma([35000, 35000, 35000, 0]);
+        assert_eq!(rk.player_by_rank, [0, 1, 2, 3]);
+    }
 }
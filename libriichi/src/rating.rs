@@ -0,0 +1,597 @@
+//! Ratings of agents across many games, kept in a flat-file ledger.
+//!
+//! The ledger is a plain text file with one finished game per line, holding
+//! a game ID, the 4 player names and their final scores, separated by tabs.
+//! A vacant seat in sanma has an empty name. Lines starting with `#` are
+//! comments. Ratings are not stored but replayed from the games in the order
+//! they were recorded, so the same ledger can be rated with any system.
+
+use crate::arena::GameResult;
+use crate::mjai::Event;
+use crate::py_helper::add_submodule;
+use crate::rankings::Rankings;
+use crate::vec_ops::vec_add_assign;
+use std::array;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write as _};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+use flate2::read::GzDecoder;
+use glob::glob;
+use pyo3::prelude::*;
+use serde_json as json;
+
+const HEADER: &str = "# id\tname0\tname1\tname2\tname3\tscore0\tscore1\tscore2\tscore3";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RatingSystem {
+    /// Multi-player Elo, where a game counts as a match between every pair
+    /// of players, scaled by `k / (n - 1)`.
+    Elo { k: f64 },
+    /// The Weng-Lin Bayesian approximation of TrueSkill with full pairing
+    /// under the Bradley-Terry model. The rating is the conservative
+    /// estimate `mu - 3 * sigma`.
+    TrueSkill,
+}
+
+/// A finished game in the ledger.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub id: String,
+    pub names: [String; 4],
+    pub scores: [i32; 4],
+}
+
+#[pyclass]
+#[derive(Debug, Clone)]
+pub struct Rating {
+    #[pyo3(get)]
+    pub name: String,
+    /// The single comparable strength number.
+    #[pyo3(get)]
+    pub rating: f64,
+    /// Only meaningful for TrueSkill.
+    #[pyo3(get)]
+    pub mu: f64,
+    /// Only meaningful for TrueSkill.
+    #[pyo3(get)]
+    pub sigma: f64,
+    #[pyo3(get)]
+    pub games: u32,
+    /// Counted once per seat, so that a player taking 3 seats in a 1v3 game
+    /// has 3 ranks.
+    #[pyo3(get)]
+    pub avg_rank: f64,
+}
+
+#[pyclass]
+pub struct Ledger {
+    path: PathBuf,
+    records: Vec<Record>,
+    /// Game IDs along with player names, as the same seed can be played by
+    /// different agents.
+    seen: HashSet<(String, [String; 4])>,
+}
+
+impl Record {
+    const fn player_count(&self) -> usize {
+        if self.names[3].is_empty() { 3 } else { 4 }
+    }
+
+    fn rankings(&self) -> Rankings {
+        if self.player_count() == 3 {
+            Rankings::new_sanma(self.scores)
+        } else {
+            Rankings::new(self.scores)
+        }
+    }
+
+    fn parse(line: &str) -> Result<Self> {
+        let fields: Vec<_> = line.split('\t').collect();
+        ensure!(fields.len() == 9, "expected 9 fields, got {}", fields.len());
+        let scores = fields[5..]
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            id: fields[0].to_owned(),
+            names: array::from_fn(|i| fields[1 + i].to_owned()),
+            scores: scores.try_into().unwrap(),
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.id)?;
+        for name in &self.names {
+            write!(f, "\t{name}")?;
+        }
+        for score in self.scores {
+            write!(f, "\t{score}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Ledger {
+    /// Opens the ledger at `path`, which is created on the first record if it
+    /// does not exist.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let mut records = vec![];
+        if path.exists() {
+            let reader = BufReader::new(File::open(&path)?);
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let record = Record::parse(&line)
+                    .with_context(|| format!("invalid line {} in {}", i + 1, path.display()))?;
+                records.push(record);
+            }
+        }
+        let seen = records
+            .iter()
+            .map(|r| (r.id.clone(), r.names.clone()))
+            .collect();
+        Ok(Self {
+            path,
+            records,
+            seen,
+        })
+    }
+
+    #[must_use]
+    pub fn records(&self) -> &[Record] {
+        &self.records
+    }
+
+    /// Appends the games to the ledger, skipping those already recorded.
+    /// Returns the number of games actually added.
+    pub fn record<I>(&mut self, records: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = Record>,
+    {
+        let mut lines = String::new();
+        let mut added = vec![];
+        for record in records {
+            for field in iter_fields(&record) {
+                ensure!(
+                    !field.contains(['\t', '\n', '\r']),
+                    "game ID and names must not contain tabs or newlines, got {field:?}",
+                );
+            }
+            if self.seen.insert((record.id.clone(), record.names.clone())) {
+                lines.push_str(&record.to_string());
+                lines.push('\n');
+                added.push(record);
+            }
+        }
+        if added.is_empty() {
+            return Ok(0);
+        }
+
+        let is_new = !self.path.exists();
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        if is_new {
+            writeln!(f, "{HEADER}")?;
+        }
+        f.write_all(lines.as_bytes())?;
+
+        let count = added.len();
+        self.records.extend(added);
+        Ok(count)
+    }
+
+    /// Records arena results, each with its game ID.
+    pub fn record_results<'a, I>(&mut self, results: I) -> Result<usize>
+    where
+        I: IntoIterator<Item = (String, &'a GameResult)>,
+    {
+        let records = results.into_iter().map(|(id, result)| Record {
+            id,
+            names: result.names.clone(),
+            scores: result.scores,
+        });
+        self.record(records)
+    }
+
+    /// Records every mjai log (`*.json` or `*.json.gz`) under `dir`. The game
+    /// ID of a log is its path relative to `dir` without the extension, which
+    /// matches the game ID of arena results with the same logs.
+    pub fn record_logs(&mut self, dir: &str) -> Result<usize> {
+        let mut paths = glob(&format!("{dir}/**/*.json"))?
+            .chain(glob(&format!("{dir}/**/*.json.gz"))?)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Keep the ledger order deterministic.
+        paths.sort();
+
+        let records = paths
+            .iter()
+            .map(|path| {
+                let raw_log = if path
+                    .extension()
+                    .is_some_and(|s| s.eq_ignore_ascii_case("gz"))
+                {
+                    io::read_to_string(GzDecoder::new(File::open(path)?))?
+                } else {
+                    io::read_to_string(File::open(path)?)?
+                };
+                let (names, scores) = final_scores(&raw_log)
+                    .with_context(|| format!("failed to parse {}", path.display()))?;
+
+                let rel = path.strip_prefix(dir).unwrap_or(path).to_string_lossy();
+                let id = rel
+                    .trim_end_matches(".gz")
+                    .trim_end_matches(".json")
+                    .to_owned();
+                Ok(Record { id, names, scores })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.record(records)
+    }
+
+    /// Replays every game in the ledger and returns the ratings, from the
+    /// highest to the lowest.
+    #[must_use]
+    pub fn ratings(&self, system: RatingSystem) -> Vec<Rating> {
+        #[derive(Clone, Copy)]
+        struct Entry {
+            mu: f64,
+            sigma: f64,
+            games: u32,
+            rank_sum: u32,
+            seats: u32,
+        }
+
+        let (mu, sigma) = match system {
+            RatingSystem::Elo { .. } => (1500., 0.),
+            RatingSystem::TrueSkill => (TS_MU, TS_SIGMA),
+        };
+        let mut entries: HashMap<&str, Entry> = HashMap::new();
+
+        for record in &self.records {
+            let n = record.player_count();
+            let names = &record.names[..n];
+            let rank_by_player = record.rankings().rank_by_player;
+
+            let seats: Vec<_> = names
+                .iter()
+                .map(|name| {
+                    let entry = entries.entry(name).or_insert(Entry {
+                        mu,
+                        sigma,
+                        games: 0,
+                        rank_sum: 0,
+                        seats: 0,
+                    });
+                    (entry.mu, entry.sigma)
+                })
+                .collect();
+            let ranks = &rank_by_player[..n];
+            let (mu_deltas, var_factors) = match system {
+                RatingSystem::Elo { k } => elo_update(names, ranks, &seats, k),
+                RatingSystem::TrueSkill => trueskill_update(names, ranks, &seats),
+            };
+
+            let mut counted = HashSet::new();
+            for (i, name) in names.iter().enumerate() {
+                let entry = entries.get_mut(name.as_str()).unwrap();
+                entry.mu += mu_deltas[i];
+                entry.sigma *= var_factors[i].sqrt();
+                entry.rank_sum += ranks[i] as u32 + 1;
+                entry.seats += 1;
+                if counted.insert(name) {
+                    entry.games += 1;
+                }
+            }
+        }
+
+        let mut ratings: Vec<_> = entries
+            .into_iter()
+            .map(|(name, entry)| Rating {
+                name: name.to_owned(),
+                rating: match system {
+                    RatingSystem::Elo { .. } => entry.mu,
+                    RatingSystem::TrueSkill => 3_f64.mul_add(-entry.sigma, entry.mu),
+                },
+                mu: entry.mu,
+                sigma: entry.sigma,
+                games: entry.games,
+                avg_rank: entry.rank_sum as f64 / entry.seats as f64,
+            })
+            .collect();
+        ratings.sort_by(|a, b| b.rating.total_cmp(&a.rating).then(a.name.cmp(&b.name)));
+        ratings
+    }
+}
+
+#[pymethods]
+impl Ledger {
+    #[new]
+    fn new_py(path: &str) -> Result<Self> {
+        Self::open(path)
+    }
+
+    /// Records every mjai log under `dir`, returning the number of games
+    /// added.
+    #[pyo3(name = "record_logs")]
+    fn record_logs_py(&mut self, dir: &str) -> Result<usize> {
+        self.record_logs(dir)
+    }
+
+    /// `system` is either `"elo"` or `"trueskill"`.
+    #[pyo3(name = "ratings", signature = (system = "trueskill", k = 32.))]
+    fn ratings_py(&self, system: &str, k: f64) -> Result<Vec<Rating>> {
+        Ok(self.ratings(RatingSystem::parse(system, k)?))
+    }
+
+    const fn __len__(&self) -> usize {
+        self.records.len()
+    }
+}
+
+#[pymethods]
+impl Rating {
+    fn __repr__(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+impl RatingSystem {
+    /// Parses `"elo"` or `"trueskill"`, where `k` is only used by Elo.
+    pub fn parse(name: &str, k: f64) -> Result<Self> {
+        match name {
+            "elo" => Ok(Self::Elo { k }),
+            "trueskill" => Ok(Self::TrueSkill),
+            _ => bail!("unknown rating system {name}, expected elo or trueskill"),
+        }
+    }
+}
+
+fn iter_fields(record: &Record) -> impl Iterator<Item = &str> {
+    iter::once(record.id.as_str()).chain(record.names.iter().map(String::as_str))
+}
+
+/// Win/tie/loss score of `a` against `b`.
+fn pair_score(rank_a: u8, rank_b: u8) -> f64 {
+    match rank_a.cmp(&rank_b) {
+        std::cmp::Ordering::Less => 1.,
+        std::cmp::Ordering::Equal => 0.5,
+        std::cmp::Ordering::Greater => 0.,
+    }
+}
+
+/// Returns the rating delta and the variance factor of each seat, given the
+/// `(mu, sigma)` of each seat before the game. Seats of the same name are not
+/// compared against each other, and their updates add up.
+fn elo_update(
+    names: &[String],
+    ranks: &[u8],
+    seats: &[(f64, f64)],
+    k: f64,
+) -> (Vec<f64>, Vec<f64>) {
+    let n = names.len();
+    let deltas = (0..n)
+        .map(|i| {
+            let (r_i, _) = seats[i];
+            let sum: f64 = (0..n)
+                .filter(|&j| names[j] != names[i])
+                .map(|j| {
+                    let (r_j, _) = seats[j];
+                    let expected = 1. / (1. + 10_f64.powf((r_j - r_i) / 400.));
+                    pair_score(ranks[i], ranks[j]) - expected
+                })
+                .sum();
+            k / (n - 1) as f64 * sum
+        })
+        .collect();
+    (deltas, vec![1.; n])
+}
+
+const TS_MU: f64 = 25.;
+const TS_SIGMA: f64 = TS_MU / 3.;
+const TS_BETA: f64 = TS_SIGMA / 2.;
+const TS_KAPPA: f64 = 1e-4;
+
+fn trueskill_update(names: &[String], ranks: &[u8], seats: &[(f64, f64)]) -> (Vec<f64>, Vec<f64>) {
+    let n = names.len();
+    (0..n)
+        .map(|i| {
+            let (mu_i, sigma_i) = seats[i];
+            let var_i = sigma_i * sigma_i;
+            let (omega, delta) = (0..n)
+                .filter(|&q| names[q] != names[i])
+                .map(|q| {
+                    let (mu_q, sigma_q) = seats[q];
+                    let c = 2_f64
+                        .mul_add(TS_BETA * TS_BETA, sigma_q.mul_add(sigma_q, var_i))
+                        .sqrt();
+                    let e_i = (mu_i / c).exp();
+                    let e_q = (mu_q / c).exp();
+                    let p_iq = e_i / (e_i + e_q);
+                    let omega = var_i / c * (pair_score(ranks[i], ranks[q]) - p_iq);
+                    let gamma = sigma_i / c;
+                    let delta = gamma * var_i / (c * c) * p_iq * (1. - p_iq);
+                    (omega, delta)
+                })
+                .fold((0., 0.), |(o, d), (omega, delta)| (o + omega, d + delta));
+            (omega, (1. - delta).max(TS_KAPPA))
+        })
+        .unzip()
+}
+
+/// Reads the player names and final scores from an mjai log. The remaining
+/// kyotaku are left out, which never changes the rankings.
+fn final_scores(raw_log: &str) -> Result<([String; 4], [i32; 4])> {
+    let mut names = None;
+    let mut scores = None;
+    for line in raw_log.lines() {
+        match json::from_str(line)? {
+            Event::StartGame { names: n, .. } => names = Some(n),
+            Event::StartKyoku { scores: s, .. } => scores = Some(s),
+            Event::ReachAccepted { actor } => {
+                if let Some(s) = &mut scores {
+                    s[actor as usize] -= 1000;
+                }
+            }
+            Event::Hora { deltas, .. } | Event::Ryukyoku { deltas } => {
+                let deltas = deltas.context("deltas is required for rating")?;
+                if let Some(s) = &mut scores {
+                    vec_add_assign(s, &deltas);
+                }
+            }
+            _ => (),
+        }
+    }
+    let Some(names) = names else {
+        bail!("start_game is missing");
+    };
+    let Some(scores) = scores else {
+        bail!("start_kyoku is missing");
+    };
+    Ok((names, scores))
+}
+
+/// Formats the ratings as a leaderboard table.
+#[must_use]
+pub fn leaderboard(ratings: &[Rating]) -> String {
+    let width = ratings
+        .iter()
+        .map(|r| r.name.chars().count())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let mut out = format!(
+        "{:>4}  {:<width$}  {:>9}  {:>8}  {:>7}  {:>6}  {:>8}\n",
+        "#", "name", "rating", "mu", "sigma", "games", "avg rank",
+    );
+    for (i, r) in ratings.iter().enumerate() {
+        writeln!(
+            out,
+            "{:>4}  {:<width$}  {:>9.2}  {:>8.2}  {:>7.3}  {:>6}  {:>8.4}",
+            i + 1,
+            r.name,
+            r.rating,
+            r.mu,
+            r.sigma,
+            r.games,
+            r.avg_rank,
+        )
+        .unwrap();
+    }
+    out
+}
+
+pub(crate) fn register_module(
+    py: Python<'_>,
+    prefix: &str,
+    super_mod: &Bound<'_, PyModule>,
+) -> PyResult<()> {
+    let m = PyModule::new(py, "rating")?;
+    m.add_class::<Ledger>()?;
+    m.add_class::<Rating>()?;
+    add_submodule(py, prefix, super_mod, &m)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agent::Tsumogiri;
+    use crate::arena::{BatchGame, Index};
+    use crate::rule::RuleSet;
+    use std::{env, fs};
+
+    fn record(id: usize, names: [&str; 4], scores: [i32; 4]) -> Record {
+        Record {
+            id: id.to_string(),
+            names: names.map(str::to_owned),
+            scores,
+        }
+    }
+
+    #[test]
+    fn ledger() {
+        let path = env::temp_dir().join(format!("riichi-ledger-{}.tsv", std::process::id()));
+        fs::remove_file(&path).ok();
+
+        let mut ledger = Ledger::open(&path).unwrap();
+        let records: Vec<_> = (0..100)
+            .flat_map(|i| {
+                [
+                    record(i, ["a", "b", "c", "d"], [40000, 30000, 20000, 10000]),
+                    // 1v3, where b takes 3 seats.
+                    record(i, ["b", "a", "b", "b"], [10000, 40000, 30000, 20000]),
+                    // sanma
+                    record(i, ["c", "d", "a", ""], [30000, 20000, 55000, 0]),
+                ]
+            })
+            .collect();
+        assert_eq!(ledger.record(records.clone()).unwrap(), 300);
+        assert_eq!(ledger.record(records).unwrap(), 0);
+        ledger
+            .record([record(0, ["a\tb", "b", "c", "d"], [0; 4])])
+            .unwrap_err();
+
+        let ledger = Ledger::open(&path).unwrap();
+        assert_eq!(ledger.records().len(), 300);
+
+        for system in [RatingSystem::Elo { k: 32. }, RatingSystem::TrueSkill] {
+            let ratings = ledger.ratings(system);
+            let names: Vec<_> = ratings.iter().map(|r| r.name.as_str()).collect();
+            assert_eq!(names[0], "a");
+            assert_eq!(ratings[0].games, 300);
+            assert!((ratings[0].avg_rank - 1.).abs() < 1e-9);
+            assert_eq!(ratings.iter().find(|r| r.name == "b").unwrap().games, 200);
+        }
+        let ratings = ledger.ratings(RatingSystem::TrueSkill);
+        assert!(ratings[0].sigma < TS_SIGMA / 2.);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn arena_logs() {
+        let log_dir = env::temp_dir().join(format!("riichi-rating-{}", std::process::id()));
+        fs::remove_dir_all(&log_dir).ok();
+
+        let g = BatchGame {
+            rule: RuleSet {
+                game_length: 4,
+                ..RuleSet::tenhou()
+            },
+            log_dir: Some(log_dir.to_string_lossy().into_owned()),
+            ..BatchGame::tenhou_hanchan(true)
+        };
+        let mut agents = [Box::new(Tsumogiri::new_batched(&[0, 1, 2, 3]).unwrap()) as _];
+        let indexes = &[array::from_fn(|i| Index {
+            agent_idx: 0,
+            player_id_idx: i,
+        })];
+        let results = g.run(&mut agents, indexes, &[(1009, 0)]).unwrap();
+
+        let (_, scores) = final_scores(&results[0].dump_json_log().unwrap()).unwrap();
+        assert_eq!(
+            Rankings::new(scores).rank_by_player,
+            results[0].rankings().rank_by_player,
+        );
+
+        let path = log_dir.join("ledger.tsv");
+        let mut ledger = Ledger::open(&path).unwrap();
+        let games = [("1009_0_a".to_owned(), &results[0])];
+        assert_eq!(ledger.record_results(games).unwrap(), 1);
+        // The same game read back from its log is not recorded twice.
+        assert_eq!(ledger.record_logs(&log_dir.to_string_lossy()).unwrap(), 0);
+
+        fs::remove_dir_all(&log_dir).unwrap();
+    }
+}
@@ -0,0 +1,306 @@
+//! Rule variations shared by the arena and `PlayerState`.
+
+use crate::tile::Tile;
+use crate::{matches_tu8, t, tu8};
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+/// A set of switches for the rule differences commonly seen among popular
+/// riichi mahjong platforms.
+///
+/// `Default` gives Tenhou's rule, which is what the rest of libriichi assumes
+/// when no `RuleSet` is specified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSet {
+    /// Number of kyokus before the extension, 8 for hanchan and 4 for tonpuu.
+    pub game_length: u8,
+    /// Number of aka doras, at most one per suit. They are assigned in the
+    /// order of 5mr, 5pr and 5sr. In sanma, 5mr is removed along with 5m
+    /// regardless.
+    pub aka_count: u8,
+    /// 喰断, whether 断幺九 is valid for an open hand.
+    pub kuitan: bool,
+    /// 後付け, whether a hand can win when its yaku is not guaranteed by every
+    /// one of its waits (片和了り). Incidental yakus like 門前清自摸和, 立直 or
+    /// 海底摸月 are not affected.
+    pub atozuke: bool,
+    /// Whether 国士無双十三面, 四暗刻単騎, 純正九蓮宝燈 and 大四喜 count as
+    /// double yakuman.
+    pub double_yakuman: bool,
+    /// 数え役満, whether 13 han or more counts as yakuman. If not, it is
+    /// capped at 三倍満.
+    pub kazoe_yakuman: bool,
+    /// 人和, whether a ron before one's first draw without any call in
+    /// between counts as yakuman. Like 天和 and 地和, it does not accumulate
+    /// with other yakus.
+    pub renhou: bool,
+    /// 三連刻, a local yaku of 2 han for three kotsus of consecutive numbers in
+    /// the same suit.
+    pub sanrenkou: bool,
+    /// 十三不塔, a local yakuman for a hand at the first draw without any call
+    /// in between that has exactly one pair and no other mentsu or taatsu.
+    pub shiisanpuutaa: bool,
+    /// 三家和, whether three players ron-ing the same tile aborts the kyoku.
+    pub triple_ron_abort: bool,
+    /// Whether two players can ron the same tile at once. If not, only the one
+    /// closest to the target in turn order wins (頭ハネ).
+    pub double_ron: bool,
+    /// Ditto, but for three players. It is ignored if `triple_ron_abort`
+    /// holds.
+    pub triple_ron: bool,
+    /// The game ends immediately after a kyoku once anyone's score goes below
+    /// this value (飛び). `None` disables it.
+    pub tobi_threshold: Option<i32>,
+    /// 和了止め, whether the game ends at all-last if the oya wins and becomes
+    /// the top with at least `target_score`.
+    pub agari_yame: bool,
+    /// 聴牌止め, the same as `agari_yame`, but for the oya's tenpai at an
+    /// exhaustive ryukyoku.
+    pub tenpai_yame: bool,
+    /// 西入 (南入 for tonpuu), whether the game extends into the next round
+    /// when no one has reached `target_score` after all-last. The extension
+    /// round is a sudden death of at most 4 kyokus.
+    pub west_round: bool,
+    /// 返し点, the score required to end the game at all-last.
+    pub target_score: i32,
+    /// 三人麻雀, which removes 2m to 8m and lets N be set aside as a dora (抜
+    /// きドラ). Seat 3 is always vacant, and its score is always 0.
+    pub sanma: bool,
+    /// ツモ損, whether the payment of the absent player is simply lost for a
+    /// tsumo agari in sanma. If not, it is split between the other two
+    /// players instead (北家折半).
+    pub tsumo_son: bool,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::tenhou()
+    }
+}
+
+impl RuleSet {
+    /// As per [Tenhou's rule](https://tenhou.net/man/#RULE).
+    #[must_use]
+    pub const fn tenhou() -> Self {
+        Self {
+            game_length: 8,
+            aka_count: 3,
+            kuitan: true,
+            atozuke: true,
+            double_yakuman: false,
+            kazoe_yakuman: true,
+            renhou: false,
+            sanrenkou: false,
+            shiisanpuutaa: false,
+            triple_ron_abort: true,
+            double_ron: true,
+            triple_ron: false,
+            tobi_threshold: Some(0),
+            agari_yame: true,
+            tenpai_yame: true,
+            west_round: true,
+            target_score: 30000,
+            sanma: false,
+            tsumo_son: true,
+        }
+    }
+
+    /// Tenhou's rule for 東風戦.
+    #[must_use]
+    pub const fn tenhou_tonpuu() -> Self {
+        Self {
+            game_length: 4,
+            ..Self::tenhou()
+        }
+    }
+
+    /// Tenhou's rule for 三人打ち. Note that the initial score of 35000 is not
+    /// part of the `RuleSet`.
+    #[must_use]
+    pub const fn tenhou_sanma() -> Self {
+        Self {
+            game_length: 6,
+            target_score: 40000,
+            sanma: true,
+            ..Self::tenhou()
+        }
+    }
+
+    /// Mahjong Soul's ranked match rule.
+    #[must_use]
+    pub const fn mahjong_soul() -> Self {
+        Self {
+            double_yakuman: true,
+            tenpai_yame: false,
+            ..Self::tenhou()
+        }
+    }
+
+    /// Mahjong Soul's ranked match rule for 三人麻雀, with the same length
+    /// and target score as Tenhou's.
+    #[must_use]
+    pub const fn mahjong_soul_sanma() -> Self {
+        Self {
+            double_yakuman: true,
+            tenpai_yame: false,
+            ..Self::tenhou_sanma()
+        }
+    }
+
+    /// M-League's rule, which has no 飛び, no 西入, no 和了止め and uses 頭ハネ
+    /// instead of multi-ron.
+    #[must_use]
+    pub const fn m_league() -> Self {
+        Self {
+            triple_ron_abort: false,
+            double_ron: false,
+            tobi_threshold: None,
+            agari_yame: false,
+            tenpai_yame: false,
+            west_round: false,
+            ..Self::tenhou()
+        }
+    }
+
+    /// Looks up a preset by its name, which is the name of the constructor
+    /// in kebab case, e.g. `tenhou-sanma`.
+    pub fn from_name(name: &str) -> Result<Self> {
+        let rule = match name {
+            "tenhou" => Self::tenhou(),
+            "tenhou-tonpuu" => Self::tenhou_tonpuu(),
+            "tenhou-sanma" => Self::tenhou_sanma(),
+            "mahjong-soul" => Self::mahjong_soul(),
+            "mahjong-soul-sanma" => Self::mahjong_soul_sanma(),
+            "m-league" => Self::m_league(),
+            _ => bail!(
+                "unknown rule {name}, expected one of tenhou, tenhou-tonpuu, tenhou-sanma, \
+                mahjong-soul, mahjong-soul-sanma and m-league"
+            ),
+        };
+        Ok(rule)
+    }
+
+    /// Returns how many of the `rons` simultaneous rons on the same tile are
+    /// accepted, taken in turn order starting from the target. The case of
+    /// `triple_ron_abort` is not considered here.
+    #[must_use]
+    pub const fn accepted_rons(&self, rons: usize) -> usize {
+        match rons {
+            0 | 1 => rons,
+            _ if !self.double_ron => 1,
+            2 => 2,
+            _ if self.triple_ron => 3,
+            _ => 2,
+        }
+    }
+
+    /// Returns the number of kyokus the game can last at most, not counting
+    /// renchans.
+    #[must_use]
+    pub const fn max_kyoku(&self) -> u8 {
+        if self.west_round {
+            self.game_length + self.player_count()
+        } else {
+            self.game_length
+        }
+    }
+
+    #[must_use]
+    pub const fn player_count(&self) -> u8 {
+        if self.sanma { 3 } else { 4 }
+    }
+
+    /// Number of tiles left in the live wall right after haipai.
+    #[must_use]
+    pub const fn initial_tiles_left(&self) -> u8 {
+        if self.sanma { 55 } else { 70 }
+    }
+
+    /// Returns whether `tile` is in the tile set of this rule.
+    #[must_use]
+    pub const fn has_tile(&self, tile: Tile) -> bool {
+        !self.sanma || !matches_tu8!(tile.deaka().as_u8(), 2m | 3m | 4m | 5m | 6m | 7m | 8m)
+    }
+
+    /// Returns the dora indicated by `indicator`, which in sanma is 9m for
+    /// 1m.
+    #[must_use]
+    pub const fn dora_of(&self, indicator: Tile) -> Tile {
+        if self.sanma && indicator.as_u8() == tu8!(1m) {
+            t!(9m)
+        } else {
+            indicator.next()
+        }
+    }
+
+    #[must_use]
+    pub fn is_tobi(&self, scores: &[i32; 4]) -> bool {
+        self.tobi_threshold.is_some_and(|threshold| {
+            scores[..self.player_count() as usize]
+                .iter()
+                .any(|&s| s < threshold)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn accepted_rons() {
+        let tenhou = RuleSet::tenhou();
+        assert_eq!(tenhou.accepted_rons(1), 1);
+        assert_eq!(tenhou.accepted_rons(2), 2);
+        assert_eq!(tenhou.accepted_rons(3), 2);
+
+        let m_league = RuleSet::m_league();
+        assert_eq!(m_league.accepted_rons(2), 1);
+        assert_eq!(m_league.accepted_rons(3), 1);
+
+        let all = RuleSet {
+            triple_ron: true,
+            ..RuleSet::m_league()
+        };
+        assert_eq!(all.accepted_rons(3), 1);
+        let all = RuleSet {
+            double_ron: true,
+            ..all
+        };
+        assert_eq!(all.accepted_rons(3), 3);
+    }
+
+    #[test]
+    fn tobi() {
+        let tenhou = RuleSet::tenhou();
+        assert!(!tenhou.is_tobi(&[0, 50000, 25000, 25000]));
+        assert!(tenhou.is_tobi(&[-100, 50100, 25000, 25000]));
+        assert!(!RuleSet::m_league().is_tobi(&[-100, 50100, 25000, 25000]));
+        assert!(!RuleSet::tenhou_sanma().is_tobi(&[35000, 35000, 35000, 0]));
+    }
+
+    #[test]
+    fn sanma() {
+        let sanma = RuleSet::tenhou_sanma();
+        assert_eq!(sanma.player_count(), 3);
+        assert_eq!(sanma.max_kyoku(), 9);
+        assert!(!sanma.has_tile(t!(5mr)));
+        assert!(sanma.has_tile(t!(1m)));
+        assert!(sanma.has_tile(t!(5p)));
+        assert_eq!(sanma.dora_of(t!(1m)), t!(9m));
+        assert_eq!(sanma.dora_of(t!(9m)), t!(1m));
+        assert_eq!(sanma.dora_of(t!(N)), t!(E));
+        assert_eq!(RuleSet::tenhou().dora_of(t!(1m)), t!(2m));
+    }
+
+    #[test]
+    fn from_name() {
+        assert_eq!(
+            RuleSet::from_name("mahjong-soul-sanma").unwrap(),
+            RuleSet::mahjong_soul_sanma(),
+        );
+        assert!(RuleSet::from_name("tenhou-sanma").unwrap().sanma);
+        RuleSet::from_name("tenhou_sanma").unwrap_err();
+    }
+}
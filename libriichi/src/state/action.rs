@@ -2,7 +2,7 @@ use super::PlayerState;
 use crate::chi_type::ChiType;
 use crate::mjai::Event;
 use crate::tile::Tile;
-use crate::tuz;
+use crate::{must_tile, t, tuz};
 
 use anyhow::{Result, bail, ensure};
 use pyo3::prelude::*;
@@ -35,6 +35,9 @@ pub struct ActionCandidate {
     pub can_ron_agari: bool,
     #[pyo3(get)]
     pub can_ryukyoku: bool,
+    /// Sanma only.
+    #[pyo3(get)]
+    pub can_nukidora: bool,
 
     #[pyo3(get)]
     pub target_actor: u8,
@@ -81,6 +84,7 @@ impl ActionCandidate {
             || self.can_riichi
             || self.can_agari()
             || self.can_ryukyoku
+            || self.can_nukidora
     }
 
     fn __repr__(&self) -> String {
@@ -89,6 +93,106 @@ impl ActionCandidate {
 }
 
 impl PlayerState {
+    /// Returns every valid reaction to the current state, including
+    /// `Event::None` if it can pass. Calls consume the aka dora whenever
+    /// possible.
+    #[must_use]
+    pub fn legal_reactions(&self) -> Vec<Event> {
+        let actor = self.player_id;
+        let cans = self.last_cans;
+        let mut ret = vec![];
+
+        if cans.can_discard {
+            let candidates = self.discard_candidates_aka();
+            for (tid, _) in candidates.iter().enumerate().filter(|&(_, &b)| b) {
+                let pai = must_tile!(tid);
+                let tsumogiri = self.last_self_tsumo.is_some_and(|t| t == pai);
+                ret.push(Event::Dahai {
+                    actor,
+                    pai,
+                    tsumogiri,
+                });
+            }
+        }
+        if cans.can_riichi {
+            ret.push(Event::Reach { actor });
+        }
+        if cans.can_nukidora {
+            ret.push(Event::Nukidora { actor, pai: t!(N) });
+        }
+        if cans.can_ankan {
+            for &tile in &self.ankan_candidates {
+                let consumed = [self.akaize_if_in_hand(tile), tile, tile, tile];
+                ret.push(Event::Ankan { actor, consumed });
+            }
+        }
+        if cans.can_kakan {
+            for &tile in &self.kakan_candidates {
+                let pai = self.akaize_if_in_hand(tile);
+                let consumed = if pai.is_aka() {
+                    [tile; 3]
+                } else {
+                    [tile.akaize(), tile, tile]
+                };
+                ret.push(Event::Kakan {
+                    actor,
+                    pai,
+                    consumed,
+                });
+            }
+        }
+
+        if let Some(pai) = self.last_kawa_tile {
+            let target = cans.target_actor;
+            let tile = pai.deaka();
+            let chis = [
+                (cans.can_chi_low, [tile.next(), tile.next().next()]),
+                (cans.can_chi_mid, [tile.prev(), tile.next()]),
+                (cans.can_chi_high, [tile.prev().prev(), tile.prev()]),
+            ];
+            for (_, consumed) in chis.into_iter().filter(|&(can, _)| can) {
+                ret.push(Event::Chi {
+                    actor,
+                    target,
+                    pai,
+                    consumed: consumed.map(|t| self.akaize_if_in_hand(t)),
+                });
+            }
+            if cans.can_pon {
+                ret.push(Event::Pon {
+                    actor,
+                    target,
+                    pai,
+                    consumed: [self.akaize_if_in_hand(tile), tile],
+                });
+            }
+            if cans.can_daiminkan {
+                ret.push(Event::Daiminkan {
+                    actor,
+                    target,
+                    pai,
+                    consumed: [self.akaize_if_in_hand(tile), tile, tile],
+                });
+            }
+        }
+
+        if cans.can_agari() {
+            ret.push(Event::Hora {
+                actor,
+                target: cans.target_actor,
+                deltas: None,
+                ura_markers: None,
+            });
+        }
+        if cans.can_ryukyoku {
+            ret.push(Event::Ryukyoku { deltas: None });
+        }
+        if cans.can_pass() {
+            ret.push(Event::None);
+        }
+        ret
+    }
+
     /// Check if `action` is a valid reaction to the current state.
     pub fn validate_reaction(&self, action: &Event) -> Result<()> {
         let cans = self.last_cans;
@@ -137,7 +241,10 @@ impl PlayerState {
                 pai,
                 consumed,
             } => {
-                ensure!((target + 1) % 4 == actor, "chi from non-kamicha");
+                ensure!(
+                    (target + 1) % self.rule.player_count() == actor,
+                    "chi from non-kamicha",
+                );
                 ensure!(
                     matches!(self.last_kawa_tile, Some(tile) if tile == pai),
                     "chi target is not the last kawa tile",
@@ -193,6 +300,11 @@ impl PlayerState {
                 ensure!(self.ankan_candidates.contains(&tile), "cannot ankan {tile}");
                 self.ensure_tiles_in_hand(&consumed)?;
             }
+            Event::Nukidora { pai, .. } => {
+                ensure!(cans.can_nukidora, "cannot nukidora");
+                ensure!(pai == t!(N), "cannot nukidora {pai}");
+                self.ensure_tiles_in_hand(&[pai])?;
+            }
 
             Event::Hora { target, .. } => {
                 if target == self.player_id {
@@ -1,5 +1,6 @@
 use super::{PlayerState, SinglePlayerTables};
-use crate::algo::agari::AgariCalculator;
+use crate::algo::agari::{Agari, AgariCalculator, AgariDetail, Yaku};
+use crate::algo::danger::{Danger, Opponent, WaitWeights};
 use crate::algo::point::Point;
 use crate::algo::shanten;
 use crate::algo::sp::{InitState, SPCalculator};
@@ -8,7 +9,7 @@ use crate::vec_ops::vec_add_assign;
 use crate::{must_tile, t, tu8, tuz};
 
 use anyhow::{Context, Result, ensure};
-use tinyvec::array_vec;
+use tinyvec::{ArrayVec, array_vec};
 
 impl PlayerState {
     /// Used by `BoardState` to check if a player is making 4 kans on his own.
@@ -164,19 +165,7 @@ impl PlayerState {
                         continue;
                     }
 
-                    let agari_calc = AgariCalculator {
-                        tehai: &tehai_3n2,
-                        is_menzen: self.is_menzen,
-                        chis: &self.chis,
-                        pons: &self.pons,
-                        minkans: &self.minkans,
-                        ankans: &self.ankans,
-                        bakaze: self.bakaze.as_u8(),
-                        jikaze: self.jikaze.as_u8(),
-                        winning_tile: tsumo as u8,
-                        is_ron: true,
-                    };
-                    ret[discard] = agari_calc.has_yaku();
+                    ret[discard] = self.has_yaku(&tehai_3n2, tsumo as u8, true);
                 }
             });
 
@@ -196,6 +185,21 @@ impl PlayerState {
         ret
     }
 
+    /// Returns the aka version of `tile` if it is a 5 and the aka of it is in
+    /// hand, otherwise `tile` itself. Used by `Agent` impls to build the
+    /// consumed tiles of calls.
+    #[inline]
+    #[must_use]
+    pub const fn akaize_if_in_hand(&self, tile: Tile) -> Tile {
+        let in_hand = match tile.as_u8() {
+            tu8!(5m) => self.akas_in_hand[0],
+            tu8!(5p) => self.akas_in_hand[1],
+            tu8!(5s) => self.akas_in_hand[2],
+            _ => false,
+        };
+        if in_hand { tile.akaize() } else { tile }
+    }
+
     #[inline]
     #[must_use]
     pub fn yaokyuu_kind_count(&self) -> u8 {
@@ -222,7 +226,7 @@ impl PlayerState {
 
         // Ryukyoku if we are in the west round, because we usually don't need a
         // big hand to win.
-        if self.bakaze == t!(W) {
+        if self.kyoku_in_game() >= self.rule.game_length {
             return true;
         }
 
@@ -230,7 +234,7 @@ impl PlayerState {
             // Ryukyoku if it is all-last and we are oya or we are not the last,
             // because it is hard to decide whether it is appropriate to not
             // ryukyoku.
-            if self.oya == 0 || self.rank < 3 {
+            if self.oya == 0 || self.rank < self.last_rank() {
                 return true;
             }
 
@@ -240,7 +244,7 @@ impl PlayerState {
             scores[0] = 12000 + self.kyotaku as i32 * 1000 + self.honba as i32 * 300;
             scores[self.oya as usize] = -6000 - self.honba as i32 * 300;
             vec_add_assign(&mut scores, &self.scores);
-            return self.get_rank(scores) < 3;
+            return self.get_rank(scores) < self.last_rank();
         }
 
         // Do not ryukyoku if we have >= 10 yaokyuu tiles.
@@ -272,17 +276,17 @@ impl PlayerState {
     fn rule_based_agari_slow(&self, is_ron: bool, target_rel: usize) -> bool {
         // Agari if it is not yet all-last, or we are oya ourselves, or we are
         // not the last place at all.
-        if !self.is_all_last || self.oya == 0 || self.rank < 3 {
+        if !self.is_all_last || self.oya == 0 || self.rank < self.last_rank() {
             return true;
         }
 
-        if self.bakaze == t!(W) {
+        if self.kyoku_in_game() >= self.rule.game_length {
             // Agari if we are in the west round but it is not yet the real
             // all-last (W4).
-            if self.kyoku < 3 {
+            if self.kyoku_in_game() + 1 < self.rule.max_kyoku() {
                 return true;
             }
-        } else if self.scores.iter().all(|&s| s < 30000) {
+        } else if self.rule.west_round && self.scores.iter().all(|&s| s < self.rule.target_score) {
             // Agari if 西入 is possible. Note that this condition is sound but
             // not complete.
             return true;
@@ -306,7 +310,11 @@ impl PlayerState {
             let mut tiles_seen = self.tiles_seen;
             let mut ura_indicators = array_vec!([_; 5]);
             'outer: for (t, _) in tehai_ordered_by_count {
-                let ura_ind = must_tile!(t).prev();
+                let ura_ind = if self.rule.sanma && t == tuz!(9m) {
+                    t!(1m)
+                } else {
+                    must_tile!(t).prev()
+                };
                 loop {
                     if ura_indicators.len() >= self.dora_indicators.len() {
                         // Break out of all loops.
@@ -330,6 +338,7 @@ impl PlayerState {
         };
 
         // Calculate the best post-hora situation for us.
+        let n = self.rule.player_count() as usize;
         let mut exp_scores = self.scores;
         if is_ron {
             exp_scores[0] +=
@@ -337,12 +346,17 @@ impl PlayerState {
             exp_scores[target_rel] -= max_win_point.ron + self.honba as i32 * 300;
         } else {
             // The player must be ko here.
-            exp_scores[0] += max_win_point.tsumo_total(false)
-                + self.kyotaku as i32 * 1000
-                + self.honba as i32 * 300;
+            let tsumo_total = if self.rule.sanma {
+                max_win_point.tsumo_total_sanma(false)
+            } else {
+                max_win_point.tsumo_total(false)
+            };
+            exp_scores[0] +=
+                tsumo_total + self.kyotaku as i32 * 1000 + self.honba as i32 * 100 * (n as i32 - 1);
             exp_scores
                 .iter_mut()
                 .enumerate()
+                .take(n)
                 .skip(1)
                 .for_each(|(idx, s)| {
                     if idx as u8 == self.oya {
@@ -353,18 +367,22 @@ impl PlayerState {
                 });
         }
 
-        // The prerequisite `!(self.bakaze == t!(W) && self.kyoku == 3)` has
-        // already been checked at the beginning.
+        // The prerequisite that it is not the real all-last (W4) has already
+        // been checked at the beginning.
         //
         // Agari if 西入 or keeping 西入 is possible. This condition is sound
         // and complete.
-        if exp_scores.iter().all(|&s| s < 30000) {
+        if self.rule.west_round && exp_scores.iter().all(|&s| s < self.rule.target_score) {
             return true;
         }
 
         // Agari if the best post-hora situation in theory will make us avoid
         // taking the last place.
-        self.get_rank(exp_scores) < 3
+        self.get_rank(exp_scores) < self.last_rank()
+    }
+
+    const fn last_rank(&self) -> u8 {
+        self.rule.player_count() - 1
     }
 
     /// Err is returned if the hand cannot agari, or cannot retrieve the winning
@@ -380,12 +398,65 @@ impl PlayerState {
             "cannot agari"
         );
 
-        // Here, 天和 and 地和 are handled individually as special cases, and
-        // there is no multi yakuman for these two.
-        if !is_ron && self.can_w_riichi {
-            return Ok(Point::yakuman(self.oya == 0, 1));
+        // Here, 天和, 地和, 十三不塔 and 人和 are handled individually as special
+        // cases, and there is no multi yakuman for these.
+        if self.can_w_riichi && (!is_ron || self.rule.renhou) {
+            return Ok(self.sanma_point(Point::yakuman(self.oya == 0, 1)));
         }
 
+        let (tehai, winning_tile, [dora, aka_dora, ura_dora]) =
+            self.agari_hand(is_ron, ura_indicators)?;
+        let additional_hans = self.situational_yakus(is_ron).iter().map(|&(_, n)| n).sum();
+        let agari = self
+            .agari_calc(&tehai, winning_tile, is_ron)
+            .agari(additional_hans, dora + aka_dora + ura_dora)
+            .context("not a hora hand")?;
+
+        Ok(self.sanma_point(agari.point(self.oya == 0)))
+    }
+
+    /// Same as `agari_points`, but returns the yakus, fu and doras that make
+    /// up the points instead.
+    pub fn agari_detail(&self, is_ron: bool, ura_indicators: &[Tile]) -> Result<AgariDetail> {
+        ensure!(
+            is_ron && self.last_cans.can_ron_agari || self.last_cans.can_tsumo_agari,
+            "cannot agari"
+        );
+
+        let (tehai, winning_tile, [dora, aka_dora, ura_dora]) =
+            self.agari_hand(is_ron, ura_indicators)?;
+        if self.can_w_riichi && (!is_ron || self.rule.renhou) {
+            let yaku = if is_ron {
+                Yaku::Renhou
+            } else if shanten::calc_all(&tehai, self.tehai_len_div3) > -1 {
+                Yaku::Shiisanpuutaa
+            } else if self.oya == 0 {
+                Yaku::Tenhou
+            } else {
+                Yaku::Chihou
+            };
+            return Ok(AgariDetail {
+                agari: Agari::Yakuman(1),
+                yakus: vec![(yaku, 1)],
+                fu: vec![],
+                dora,
+                aka_dora,
+                ura_dora,
+            });
+        }
+
+        self.agari_calc(&tehai, winning_tile, is_ron)
+            .agari_detail(&self.situational_yakus(is_ron), dora, aka_dora, ura_dora)
+            .context("not a hora hand")
+    }
+
+    /// Returns the tehai including the winning tile, the winning tile, and the
+    /// number of doras, akas and uras.
+    fn agari_hand(
+        &self,
+        is_ron: bool,
+        ura_indicators: &[Tile],
+    ) -> Result<([u8; 34], Tile, [u8; 3])> {
         let winning_tile = if is_ron {
             self.last_kawa_tile
         } else {
@@ -393,57 +464,101 @@ impl PlayerState {
         }
         .context("cannot find the winning tile")?;
 
-        let additional_hans = if is_ron {
-            [
-                self.riichi_accepted[0],       // 立直
-                self.is_w_riichi,              // 両立直
-                self.at_ippatsu,               // 一发
-                self.tiles_left == 0,          // 河底撈魚
-                self.chankan_chance.is_some(), // 槍槓
-            ]
-            .iter()
-            .filter(|&&b| b)
-            .count() as u8
-        } else {
-            [
-                self.riichi_accepted[0],                  // 立直
-                self.is_w_riichi,                         // 両立直
-                self.at_ippatsu,                          // 一发
-                self.is_menzen,                           // 門前清自摸和
-                self.tiles_left == 0 && !self.at_rinshan, // 海底摸月
-                self.at_rinshan,                          // 嶺上開花
-            ]
-            .iter()
-            .filter(|&&b| b)
-            .count() as u8
-        };
-
         let mut tehai = self.tehai;
-        let mut final_doras_owned = self.doras_owned[0];
+        let mut doras_owned = self.doras_owned[0];
         if is_ron {
             let tid = winning_tile.deaka().as_usize();
             tehai[tid] += 1;
-            final_doras_owned += self.dora_factor[tid];
+            doras_owned += self.dora_factor[tid];
             if winning_tile.is_aka() {
-                final_doras_owned += 1;
+                doras_owned += 1;
             };
         }
-        if self.riichi_accepted[0] {
-            final_doras_owned += ura_indicators
+
+        // `doras_owned` includes akas, so count the regular ones to tell them
+        // apart.
+        let dora = tehai
+            .iter()
+            .zip(&self.dora_factor)
+            .map(|(&count, &f)| count * f)
+            .chain(
+                self.fuuro_overview[0]
+                    .iter()
+                    .flatten()
+                    .map(|t| self.dora_factor[t.deaka().as_usize()]),
+            )
+            .chain(
+                self.ankan_overview[0]
+                    .iter()
+                    .map(|t| self.dora_factor[t.deaka().as_usize()] * 4),
+            )
+            .sum::<u8>()
+            + self.nukidoras[0] * (1 + self.dora_factor[tuz!(N)]);
+        let aka_dora = doras_owned - dora;
+
+        let ura_dora = if self.riichi_accepted[0] {
+            ura_indicators
                 .iter()
                 .map(|&ura| {
-                    let next = ura.next();
+                    let next = self.rule.dora_of(ura);
                     let mut count = tehai[next.as_usize()];
                     if self.ankan_overview[0].contains(&next) {
                         count += 4;
                     }
+                    if next.as_u8() == tu8!(N) {
+                        count += self.nukidoras[0];
+                    }
                     count
                 })
-                .sum::<u8>();
-        }
+                .sum::<u8>()
+        } else {
+            0
+        };
+
+        Ok((tehai, winning_tile, [dora, aka_dora, ura_dora]))
+    }
 
-        let agari_calc = AgariCalculator {
-            tehai: &tehai,
+    /// Yakus other than those determined by the shape of the hand, except for
+    /// 天和 and 地和.
+    fn situational_yakus(&self, is_ron: bool) -> Vec<(Yaku, u8)> {
+        let riichi = if self.is_w_riichi {
+            (Yaku::DoubleRiichi, 2)
+        } else {
+            (Yaku::Riichi, 1)
+        };
+        let yakus = if is_ron {
+            [
+                (self.riichi_accepted[0], riichi),
+                (self.at_ippatsu, (Yaku::Ippatsu, 1)),
+                (self.tiles_left == 0, (Yaku::Houtei, 1)),
+                (self.chankan_chance.is_some(), (Yaku::Chankan, 1)),
+            ]
+            .to_vec()
+        } else {
+            [
+                (self.riichi_accepted[0], riichi),
+                (self.at_ippatsu, (Yaku::Ippatsu, 1)),
+                (self.is_menzen, (Yaku::MenzenTsumo, 1)),
+                (self.tiles_left == 0 && !self.at_rinshan, (Yaku::Haitei, 1)),
+                (self.at_rinshan, (Yaku::Rinshan, 1)),
+            ]
+            .to_vec()
+        };
+        yakus
+            .into_iter()
+            .filter(|&(b, _)| b)
+            .map(|(_, yaku)| yaku)
+            .collect()
+    }
+
+    fn agari_calc<'a>(
+        &'a self,
+        tehai: &'a [u8; 34],
+        winning_tile: Tile,
+        is_ron: bool,
+    ) -> AgariCalculator<'a> {
+        AgariCalculator {
+            tehai,
             is_menzen: self.is_menzen,
             chis: &self.chis,
             pons: &self.pons,
@@ -453,12 +568,17 @@ impl PlayerState {
             jikaze: self.jikaze.as_u8(),
             winning_tile: winning_tile.deaka().as_u8(),
             is_ron,
-        };
-        let agari = agari_calc
-            .agari(additional_hans, final_doras_owned)
-            .context("not a hora hand")?;
+            rule: self.rule,
+        }
+    }
 
-        Ok(agari.point(self.oya == 0))
+    /// Converts `point` with the sanma scoring if the rule is sanma.
+    const fn sanma_point(&self, point: Point) -> Point {
+        if self.rule.sanma {
+            point.sanma(self.oya == 0, self.rule.tsumo_son)
+        } else {
+            point
+        }
     }
 
     /// Calculate the actual shanten at this point. Unlike `self.shanten`, this
@@ -502,41 +622,67 @@ impl PlayerState {
         shanten::calc_all(&self.tehai, self.tehai_len_div3)
     }
 
+    /// Estimate the danger of each tile against `opponent`, which is relative
+    /// to `player_id`, using the tiles seen by this player.
+    pub fn danger(&self, opponent: u8) -> Result<Danger> {
+        ensure!(
+            (1..self.rule.player_count()).contains(&opponent),
+            "invalid opponent {opponent}",
+        );
+        let opponent = opponent as usize;
+        let riichi_idx = self.kawa[opponent]
+            .iter()
+            .flatten()
+            .position(|k| k.sutehai.is_riichi);
+        let info = Opponent {
+            discards: &self.kawa_overview[opponent],
+            riichi_idx,
+            genbutsu: &self.genbutsu[opponent],
+        };
+        Ok(Danger::new(
+            &info,
+            &self.tiles_seen,
+            &WaitWeights::default(),
+        ))
+    }
+
     /// Can be called at both 3n+1 and 3n+2, but `self.real_time_shanten` must
-    /// be >= 0 and `self.tiles_left` must be >= 4.
+    /// be >= 0 and `self.tiles_left` must be >= the number of players.
     ///
-    /// This function is currently highly internal.
-    pub(super) fn single_player_tables(&self) -> Result<SinglePlayerTables> {
-        ensure!(self.tiles_left >= 4, "need at least one more tsumo");
+    /// In sanma, the point calculation still follows four-player mahjong.
+    pub fn single_player_tables(&self) -> Result<SinglePlayerTables> {
+        let n = self.rule.player_count();
+        ensure!(self.tiles_left >= n, "need at least one more tsumo");
 
         let cur_shanten = self.real_time_shanten();
         ensure!(cur_shanten >= 0, "can't calculate an agari hand");
 
         let mut can_discard = self.last_cans.can_discard;
         let (tsumos_left, calc_haitei) = if can_discard {
-            (self.tiles_left / 4, self.tiles_left % 4 == 0)
+            (self.tiles_left / n, self.tiles_left.is_multiple_of(n))
         } else {
             let target = self.rel(self.last_cans.target_actor) as u8;
             // Let's just ignore chankan here.
-            let tiles_left_at_next_tsumo = self.tiles_left.saturating_sub(4 - target);
+            let tiles_left_at_next_tsumo = self.tiles_left.saturating_sub(n - target);
             (
-                tiles_left_at_next_tsumo / 4,
-                tiles_left_at_next_tsumo % 4 == 0,
+                tiles_left_at_next_tsumo / n,
+                tiles_left_at_next_tsumo.is_multiple_of(n),
             )
         };
         ensure!(tsumos_left >= 1, "need at least one more tsumo");
 
-        let num_doras_in_fuuro = if self.is_menzen && self.ankan_overview[0].is_empty() {
-            0
-        } else {
-            let num_doras_in_tehai: u8 = self
-                .dora_indicators
-                .iter()
-                .map(|ind| self.tehai[ind.next().as_usize()])
-                .sum();
-            let num_akas = self.akas_in_hand.iter().filter(|&&b| b).count() as u8;
-            self.doras_owned[0] - num_doras_in_tehai - num_akas
-        };
+        let num_doras_in_fuuro =
+            if self.is_menzen && self.ankan_overview[0].is_empty() && self.nukidoras[0] == 0 {
+                0
+            } else {
+                let num_doras_in_tehai: u8 = self
+                    .dora_indicators
+                    .iter()
+                    .map(|&ind| self.tehai[self.rule.dora_of(ind).as_usize()])
+                    .sum();
+                let num_akas = self.akas_in_hand.iter().filter(|&&b| b).count() as u8;
+                self.doras_owned[0] - num_doras_in_tehai - num_akas
+            };
         let prefer_riichi = self.scores[0] >= 1000;
         let calc_double_riichi = can_discard && self.can_w_riichi;
 
@@ -558,11 +704,34 @@ impl PlayerState {
             can_discard = false;
         }
 
+        // Tiles that do not exist in the rule are treated as all seen. The
+        // dora indicators are mapped so that `SPCalculator` can keep using
+        // `Tile::next`.
+        let mut tiles_seen = self.tiles_seen;
+        let mut akas_seen = self.akas_seen;
+        for (tid, seen) in tiles_seen.iter_mut().enumerate() {
+            if !self.rule.has_tile(must_tile!(tid)) {
+                *seen = 4;
+            }
+        }
+        for (aka_id, seen) in akas_seen.iter_mut().enumerate() {
+            if aka_id as u8 >= self.rule.aka_count
+                || !self.rule.has_tile(must_tile!(tuz!(5mr) + aka_id))
+            {
+                *seen = true;
+            }
+        }
+        let dora_indicators: ArrayVec<[Tile; 5]> = self
+            .dora_indicators
+            .iter()
+            .map(|&ind| self.rule.dora_of(ind).prev())
+            .collect();
+
         let init_state = InitState {
             tehai,
             akas_in_hand,
-            tiles_seen: self.tiles_seen,
-            akas_seen: self.akas_seen,
+            tiles_seen,
+            akas_seen,
         };
         let sp_calc = SPCalculator {
             tehai_len_div3: self.tehai_len_div3,
@@ -575,7 +744,8 @@ impl PlayerState {
             jikaze: self.jikaze.as_u8(),
             num_doras_in_fuuro,
             prefer_riichi,
-            dora_indicators: &self.dora_indicators,
+            dora_indicators: &dora_indicators,
+            rule: self.rule,
             calc_double_riichi,
             calc_haitei,
             sort_result: true,
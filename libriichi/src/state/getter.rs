@@ -1,4 +1,5 @@
 use super::{ActionCandidate, PlayerState};
+use crate::rule::RuleSet;
 use crate::tile::Tile;
 
 use pyo3::prelude::*;
@@ -156,6 +157,38 @@ impl PlayerState {
 }
 
 impl PlayerState {
+    #[inline]
+    #[must_use]
+    pub const fn rule(&self) -> RuleSet {
+        self.rule
+    }
+    #[inline]
+    #[must_use]
+    pub const fn bakaze(&self) -> Tile {
+        self.bakaze
+    }
+    #[inline]
+    #[must_use]
+    pub const fn jikaze(&self) -> Tile {
+        self.jikaze
+    }
+    #[inline]
+    #[must_use]
+    pub const fn tiles_left(&self) -> u8 {
+        self.tiles_left
+    }
+    #[inline]
+    #[must_use]
+    pub const fn tiles_seen(&self) -> &[u8; 34] {
+        &self.tiles_seen
+    }
+    /// Relative to `player_id`.
+    #[inline]
+    #[must_use]
+    pub const fn riichi_accepted(&self) -> [bool; 4] {
+        self.riichi_accepted
+    }
+
     #[inline]
     #[must_use]
     pub const fn last_self_tsumo(&self) -> Option<Tile> {
@@ -89,7 +89,7 @@ impl IntegerEncoder {
                     ctx.idx += intervals - 1;
                 }
             }
-            4 => {
+            4 | 5 => {
                 debug_assert!(self.one_hot || self.rescale);
 
                 if self.one_hot {
@@ -155,7 +155,7 @@ impl<'a> ObsEncoderContext<'a> {
                 2 | 3 => IntegerEncoder::new(score as usize / 100, 500)
                     .rbf_intervals(10)
                     .encode(&mut self),
-                4 => {
+                4 | 5 => {
                     let v = score.clamp(0, 30_000) as f32 / 30_000.;
                     self.arr.fill(self.idx, v);
                     self.idx += 1;
@@ -172,24 +172,24 @@ impl<'a> ObsEncoderContext<'a> {
         match self.version {
             // for v1, this was a mistake, it actually only uses 3 channels.
             1 => self.arr.fill_rows(self.idx, n, 1.),
-            2 | 3 | 4 => self.arr.fill(self.idx + n, 1.),
+            2..=5 => self.arr.fill(self.idx + n, 1.),
             _ => unreachable!(),
         }
         self.idx += 4;
 
         let cap = match self.version {
-            1 | 4 => 10,
+            1 | 4 | 5 => 10,
             2 | 3 => 6,
             _ => unreachable!(),
         };
         let n = state.honba as usize;
         IntegerEncoder::new(n, cap)
-            .rescale(self.version == 4)
+            .rescale(self.version >= 4)
             .rbf_intervals(3)
             .encode(&mut self);
         let n = state.kyotaku as usize;
         IntegerEncoder::new(n, cap)
-            .rescale(self.version == 4)
+            .rescale(self.version >= 4)
             .rbf_intervals(3)
             .encode(&mut self);
 
@@ -197,8 +197,13 @@ impl<'a> ObsEncoderContext<'a> {
         self.arr.assign(self.idx + 1, state.jikaze.as_usize(), 1.);
         self.idx += 2;
 
-        if matches!(self.version, 2 | 3 | 4) {
-            let n = (state.bakaze.as_u8() - tu8!(E)).min(1) * 4 + state.kyoku;
+        if matches!(self.version, 2..=5) {
+            let kyokus_per_round = if self.version >= 5 {
+                state.rule.player_count()
+            } else {
+                4
+            };
+            let n = (state.bakaze.as_u8() - tu8!(E)).min(1) * kyokus_per_round + state.kyoku;
             IntegerEncoder::new(n as usize, 7)
                 .rescale(true)
                 .encode(&mut self);
@@ -220,7 +225,7 @@ impl<'a> ObsEncoderContext<'a> {
         self.idx += (18 - state.kawa[0].len().min(18)) * SELF_KAWA_ITEM_CHANNELS;
 
         let max_kawa_len = state.kawa.iter().map(|k| k.len()).max().unwrap();
-        if matches!(self.version, 3 | 4) {
+        if matches!(self.version, 3..=5) {
             for (turn, kawa_item) in state.kawa[0].iter().enumerate() {
                 if let Some(kawa_item) = kawa_item {
                     let sutehai = kawa_item.sutehai;
@@ -258,7 +263,7 @@ impl<'a> ObsEncoderContext<'a> {
                     }
                     self.idx += 6;
                 }
-                3 | 4 => {
+                3..=5 => {
                     for (turn, kawa_item) in player_kawa.iter().enumerate() {
                         if let Some(kawa_item) = kawa_item {
                             let sutehai = kawa_item.sutehai;
@@ -328,7 +333,7 @@ impl<'a> ObsEncoderContext<'a> {
             self.idx += 1;
         }
 
-        if matches!(self.version, 2 | 3 | 4) {
+        if matches!(self.version, 2..=5) {
             for (tid, count) in state.tiles_seen.iter().copied().enumerate() {
                 self.arr.assign(self.idx, tid, count as f32 / 4.);
             }
@@ -561,7 +566,7 @@ impl<'a> ObsEncoderContext<'a> {
         }
         self.idx += 1;
 
-        if self.version == 4 {
+        if self.version >= 4 {
             if let Ok(SinglePlayerTables { max_ev_table }) = state.single_player_tables() {
                 // Get the max EV from the table that maximizes EV, which should
                 // be the global max EV.
@@ -614,7 +619,13 @@ impl<'a> ObsEncoderContext<'a> {
                 // minimal because we assume no uradora.
                 let min_tsumo_agari = state
                     .agari_points(cans.can_ron_agari, &[])
-                    .map(|p| p.tsumo_total(state.is_oya()) as f32)
+                    .map(|p| {
+                        if state.rule.sanma {
+                            p.tsumo_total_sanma(state.is_oya()) as f32
+                        } else {
+                            p.tsumo_total(state.is_oya()) as f32
+                        }
+                    })
                     .unwrap_or_default();
                 self.encode_ev(min_tsumo_agari);
 
@@ -623,6 +634,24 @@ impl<'a> ObsEncoderContext<'a> {
             }
         }
 
+        if self.version >= 5 {
+            for count in state.nukidoras {
+                IntegerEncoder::new(count as usize, 4)
+                    .rescale(true)
+                    .encode(&mut self);
+            }
+
+            // Nukidora shares the action of chi low, as they can never happen
+            // at the same time.
+            if cans.can_nukidora {
+                self.arr.fill(self.idx, 1.);
+                if !self.at_kan_select {
+                    self.mask[38] = true;
+                }
+            }
+            self.idx += 1;
+        }
+
         assert_eq!(self.idx, self.arr.rows());
         let arr = self.arr.build();
         debug_assert!(arr.iter().all(|&v| (0. ..=1.).contains(&v)));
@@ -2,8 +2,9 @@ use super::action::ActionCandidate;
 use super::item::{ChiPon, KawaItem, Sutehai};
 use crate::algo::sp::Candidate;
 use crate::hand::tiles_to_string;
-use crate::must_tile;
+use crate::rule::RuleSet;
 use crate::tile::Tile;
+use crate::{must_tile, tu8};
 use std::iter;
 
 use anyhow::Result;
@@ -23,6 +24,7 @@ use tinyvec::{ArrayVec, TinyVec};
 #[derivative(Default)]
 pub struct PlayerState {
     pub(super) player_id: u8,
+    pub(super) rule: RuleSet,
 
     /// Does not include aka.
     #[derivative(Default(value = "[0; 34]"))]
@@ -55,6 +57,10 @@ pub struct PlayerState {
     /// Used for furiten check.
     #[derivative(Default(value = "[false; 34]"))]
     pub(super) discarded_tiles: [bool; 34],
+    /// 現物 of each player, which are the tiles discarded by themselves, plus
+    /// the ones discarded by anyone after their riichi is accepted.
+    #[derivative(Default(value = "[[false; 34]; 4]"))]
+    pub(super) genbutsu: [[bool; 34]; 4],
 
     pub(super) bakaze: Tile,
     pub(super) jikaze: Tile,
@@ -67,7 +73,10 @@ pub struct PlayerState {
     pub(super) rank: u8,
     /// Relative to `player_id`.
     pub(super) oya: u8,
-    /// Including 西入 sudden death.
+    /// The absolute seat of the first oya (起家), from which ties in ranks
+    /// are broken.
+    pub(super) chiicha: u8,
+    /// Including 西入 (南入 for tonpuu) sudden death.
     pub(super) is_all_last: bool,
     pub(super) dora_indicators: ArrayVec<[Tile; 5]>,
 
@@ -128,6 +137,9 @@ pub struct PlayerState {
     /// feature to the obs.
     pub(super) doras_owned: [u8; 4],
     pub(super) doras_seen: u8,
+    /// Number of N set aside as nukidora in sanma, which are also counted in
+    /// `doras_owned`.
+    pub(super) nukidoras: [u8; 4],
 
     pub(super) akas_in_hand: [bool; 3],
 
@@ -139,19 +151,37 @@ pub struct PlayerState {
     pub(super) has_next_shanten_discard: bool,
 }
 
-#[pymethods]
 impl PlayerState {
-    /// Panics if `player_id` is outside of range [0, 3].
-    #[new]
+    /// Panics if `player_id` is not a valid seat under `rule`, which in sanma
+    /// is in range [0, 2].
     #[must_use]
-    pub fn new(player_id: u8) -> Self {
-        assert!(player_id < 4, "{player_id} is not in range [0, 3]");
+    pub fn new_with_rule(player_id: u8, rule: RuleSet) -> Self {
+        let n = rule.player_count();
+        assert!(player_id < n, "{player_id} is not in range [0, {}]", n - 1);
         Self {
             player_id,
+            rule,
             ..Default::default()
         }
     }
 
+    /// Returns the number of kyokus since E1, not counting renchans, e.g. 4 for
+    /// S1.
+    #[inline]
+    pub(super) const fn kyoku_in_game(&self) -> u8 {
+        (self.bakaze.as_u8() - tu8!(E)) * self.rule.player_count() + self.kyoku
+    }
+}
+
+#[pymethods]
+impl PlayerState {
+    /// Panics if `player_id` is outside of range [0, 3].
+    #[new]
+    #[must_use]
+    pub fn new(player_id: u8) -> Self {
+        Self::new_with_rule(player_id, RuleSet::default())
+    }
+
     /// Returns an `ActionCandidate`.
     #[pyo3(name = "update")]
     pub(super) fn update_json(&mut self, mjai_json: &str) -> Result<ActionCandidate> {
@@ -228,6 +258,7 @@ furiten: {}
 waits: {waits:?}
 dora indicators: {:?}
 doras owned: {:?}
+nukidoras: {:?}
 doras seen: {}
 action candidates: {:#?}
 last self tsumo: {:?}
@@ -254,6 +285,7 @@ single player table (max EV):
             self.at_furiten,
             self.dora_indicators,
             self.doras_owned,
+            self.nukidoras,
             self.doras_seen,
             self.last_cans,
             self.last_self_tsumo,
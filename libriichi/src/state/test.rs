@@ -1,10 +1,12 @@
 use super::{ActionCandidate, PlayerState};
+use crate::algo::agari::{Agari, Fu, Yaku};
 use crate::algo::shanten;
 use crate::consts::MAX_VERSION;
 use crate::hand::{hand, hand_with_aka, tile37_to_vec};
 use crate::mjai::Event;
+use crate::rule::RuleSet;
 use crate::{matches_tu8, must_tile, t, tuz};
-use std::mem;
+use std::{iter, mem};
 
 impl PlayerState {
     fn test_update(&mut self, event: &Event) -> ActionCandidate {
@@ -43,6 +45,9 @@ impl PlayerState {
                 self.dora_factor[t.deaka().as_usize()] * 4
                     + matches_tu8!(t.as_u8(), 5m | 5p | 5s) as u8
             }))
+            .chain(iter::once(
+                self.nukidoras[0] * (1 + self.dora_factor[tuz!(N)]),
+            ))
             .sum()
     }
 
@@ -474,6 +479,14 @@ fn furiten() {
     assert!(ps.at_furiten);
     assert!(cans.can_tsumo_agari);
     assert_eq!(ps.agari_points(false, &[t!(3m)]).unwrap().tsumo_ko, 6000);
+    let detail = ps.agari_detail(false, &[t!(3m)]).unwrap();
+    assert_eq!(detail.agari, Agari::Normal { fu: 20, han: 7 });
+    assert_eq!(
+        detail.yakus,
+        [(Yaku::Riichi, 1), (Yaku::MenzenTsumo, 1), (Yaku::Pinfu, 1)],
+    );
+    assert_eq!(detail.fu, [(Fu::Futei, 20)]);
+    assert_eq!((detail.dora, detail.aka_dora, detail.ura_dora), (1, 1, 2));
 }
 
 #[test]
@@ -823,6 +836,16 @@ fn get_rank() {
     let ps = PlayerState::new(2);
     let rank = ps.get_rank([5, 2, 5, 3]);
     assert_eq!(rank, 1);
+
+    // Ties are broken from chiicha, which is seat 1 here.
+    let mut ps = PlayerState::new(0);
+    ps.chiicha = 1;
+    let rank = ps.get_rank([25000, 25000, 25000, 25000]);
+    assert_eq!(rank, 3);
+    let mut ps = PlayerState::new(2);
+    ps.chiicha = 1;
+    let rank = ps.get_rank([25000, 25000, 25000, 25000]);
+    assert_eq!(rank, 1);
 }
 
 #[test]
@@ -1416,3 +1439,102 @@ fn chi_at_0_shanten() {
     assert!(ps.at_furiten);
     assert!(!ps.has_next_shanten_discard);
 }
+
+#[test]
+fn nukidora() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"W","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[35000,35000,35000,0],"tehais":[["E","E","E","1m","1m","1m","9m","9m","9m","1p","2p","3p","N"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"N"}
+    "#;
+    let mut ps = PlayerState::new_with_rule(0, RuleSet::tenhou_sanma());
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert!(ps.last_cans.can_nukidora);
+    assert_eq!(ps.jikaze, t!(E));
+    assert_eq!(ps.tiles_left, 54);
+
+    let log = r#"
+        {"type":"nukidora","actor":0,"pai":"N"}
+        {"type":"tsumo","actor":0,"pai":"5s"}
+        {"type":"dahai","actor":0,"pai":"5s","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+    "#;
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert_eq!(ps.nukidoras[0], 1);
+    // One in hand, plus the nukidora which is also a dora itself.
+    assert_eq!(ps.doras_owned[0], 3);
+    assert!(ps.waits[tuz!(N)]);
+
+    let cans = ps.test_update_json(r#"{"type":"nukidora","actor":1,"pai":"N"}"#);
+    assert!(cans.can_ron_agari);
+    assert!(!cans.can_chi());
+    assert_eq!(ps.nukidoras, [1, 1, 0, 0]);
+    // ダブ東 2, 混全帯幺九 2, dora 4
+    let point = ps.agari_points(true, &[]).unwrap();
+    assert_eq!(point.ron, 24000);
+}
+
+#[test]
+fn renhou() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","2m","3m","4p","5p","6p","7s","8s","9s","1s","1s","2m","4m"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+    "#;
+    let discard = r#"{"type":"dahai","actor":0,"pai":"3m","tsumogiri":true}"#;
+
+    let mut ps = PlayerState::new(1);
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert!(!ps.test_update_json(discard).can_ron_agari);
+
+    let rule = RuleSet {
+        renhou: true,
+        ..RuleSet::tenhou()
+    };
+    let mut ps = PlayerState::new_with_rule(1, rule);
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+    assert!(ps.test_update_json(discard).can_ron_agari);
+    assert_eq!(ps.agari_points(true, &[]).unwrap().ron, 32000);
+    let detail = ps.agari_detail(true, &[]).unwrap();
+    assert_eq!(detail.agari, Agari::Yakuman(1));
+    assert_eq!(detail.yakus, [(Yaku::Renhou, 1)]);
+}
+
+#[test]
+fn genbutsu() {
+    let log = r#"
+        {"type":"start_kyoku","bakaze":"E","dora_marker":"1p","kyoku":1,"honba":0,"kyotaku":0,"oya":0,"scores":[25000,25000,25000,25000],"tehais":[["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["?","?","?","?","?","?","?","?","?","?","?","?","?"],["1m","2m","3m","4p","5p","6p","7s","8s","9s","1s","1s","E","S"]]}
+        {"type":"tsumo","actor":0,"pai":"?"}
+        {"type":"dahai","actor":0,"pai":"5m","tsumogiri":true}
+        {"type":"tsumo","actor":1,"pai":"?"}
+        {"type":"reach","actor":1}
+        {"type":"dahai","actor":1,"pai":"6p","tsumogiri":false}
+        {"type":"reach_accepted","actor":1}
+        {"type":"tsumo","actor":2,"pai":"?"}
+        {"type":"dahai","actor":2,"pai":"9p","tsumogiri":true}
+    "#;
+    let mut ps = PlayerState::new(3);
+    for line in log.trim().lines() {
+        ps.test_update_json(line);
+    }
+
+    // Relative to player 3.
+    let danger = ps.danger(2).unwrap();
+    assert!(danger.genbutsu[tuz!(6p)] && danger.genbutsu[tuz!(9p)]);
+    assert!(!danger.genbutsu[tuz!(5m)]);
+    assert!(danger.suji[tuz!(3p)] && !danger.suji[tuz!(2m)]);
+    assert!(danger.wait_probs[tuz!(9p)] < f32::EPSILON);
+    assert!(danger.wait_probs[tuz!(8m)] > 0.);
+
+    // Only their own discards for the others.
+    assert!(!ps.danger(3).unwrap().genbutsu[tuz!(6p)]);
+    assert!(ps.danger(1).unwrap().genbutsu[tuz!(5m)]);
+    ps.danger(0).unwrap_err();
+    ps.danger(4).unwrap_err();
+}
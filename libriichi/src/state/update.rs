@@ -110,6 +110,7 @@ impl PlayerState {
 
             Event::Kakan { actor, pai, .. } => self.kakan(actor, pai)?,
             Event::Ankan { actor, consumed } => self.ankan(actor, consumed)?,
+            Event::Nukidora { actor, pai } => self.nukidora(actor, pai)?,
             Event::Dora { dora_marker } => self.add_dora_indicator(dora_marker)?,
 
             Event::Reach { actor } => self.reach(actor),
@@ -142,25 +143,26 @@ impl PlayerState {
         self.next_shanten_discards.fill(false);
         self.forbidden_tiles.fill(false);
         self.discarded_tiles.fill(false);
+        self.genbutsu.iter_mut().for_each(|g| g.fill(false));
 
         self.bakaze = bakaze;
         self.honba = honba;
         self.kyotaku = kyotaku;
         self.oya = self.rel(oya) as u8;
-        self.jikaze = must_tile!(tu8!(E) + (4 - self.oya) % 4);
+        let n = self.rule.player_count();
+        self.jikaze = must_tile!(tu8!(E) + (n - self.oya) % n);
         self.kyoku = kyoku - 1;
-        self.is_all_last = match self.bakaze.as_u8() {
-            tu8!(E) => false,
-            tu8!(S) => self.kyoku == 3,
-            _ => true,
-        };
+        self.chiicha = (oya + n - self.kyoku) % n;
+        self.is_all_last = self.kyoku_in_game() + 1 >= self.rule.game_length;
 
+        // In sanma the vacant seat is always kept at the end.
         self.scores = scores;
-        self.scores.rotate_left(self.player_id as usize);
+        self.scores[..n as usize].rotate_left(self.player_id as usize);
 
         self.dora_indicators.clear();
         self.doras_owned.fill(0);
         self.doras_seen = 0;
+        self.nukidoras.fill(0);
         self.akas_in_hand.fill(false);
 
         self.ankan_candidates.clear();
@@ -183,7 +185,7 @@ impl PlayerState {
         self.kans_on_board = 0;
         self.tehai_len_div3 = 4;
         self.has_next_shanten_discard = false;
-        self.tiles_left = 70;
+        self.tiles_left = self.rule.initial_tiles_left();
         self.at_turn = 0;
 
         self.kawa.iter_mut().for_each(|k| k.clear());
@@ -250,27 +252,28 @@ impl PlayerState {
             {
                 self.last_cans.can_tsumo_agari = true;
             } else {
-                let agari_calc = AgariCalculator {
-                    tehai: &self.tehai,
-                    is_menzen: self.is_menzen,
-                    chis: &self.chis,
-                    pons: &self.pons,
-                    minkans: &self.minkans,
-                    ankans: &self.ankans,
-                    bakaze: self.bakaze.as_u8(),
-                    jikaze: self.jikaze.as_u8(),
-                    winning_tile: pai.deaka().as_u8(),
-                    is_ron: false,
-                };
-                self.last_cans.can_tsumo_agari = agari_calc.has_yaku();
-            }
-        }
-
-        // haitei tile cannot be used for kakan or ankan
+                self.last_cans.can_tsumo_agari =
+                    self.has_yaku(&self.tehai, pai.deaka().as_u8(), false);
+            }
+        } else if self.rule.shiisanpuutaa
+            && self.can_w_riichi
+            && agari::is_shiisanpuutaa(&self.tehai)
+        {
+            // 十三不塔
+            self.last_cans.can_tsumo_agari = true;
+        }
+
+        // haitei tile cannot be used for kakan, ankan or nukidora
         if self.tiles_left == 0 {
             return Ok(());
         }
 
+        if self.rule.sanma && self.tehai[tuz!(N)] > 0 {
+            // After riichi, only the N just drawn can be set aside, so that
+            // the waits are kept unchanged.
+            self.last_cans.can_nukidora = !self.riichi_accepted[0] || pai.as_u8() == tu8!(N);
+        }
+
         if self.riichi_accepted[0] {
             if self.kans_on_board < 4 {
                 // Using Tenhou rule here.
@@ -301,7 +304,7 @@ impl PlayerState {
         }
 
         self.last_cans.can_riichi = self.is_menzen
-            && self.tiles_left >= 4
+            && self.tiles_left >= self.rule.player_count()
             && self.scores[0] >= 1000
             && (self.shanten == 0 || self.shanten == 1 && self.has_next_shanten_discard);
 
@@ -332,6 +335,13 @@ impl PlayerState {
         self.kawa_overview[actor_rel].push(pai);
         self.last_kawa_tile = Some(pai);
 
+        let tid = pai.deaka().as_usize();
+        for (seat, genbutsu) in self.genbutsu.iter_mut().enumerate() {
+            if seat == actor_rel || self.riichi_accepted[seat] {
+                genbutsu[tid] = true;
+            }
+        }
+
         if !tsumogiri {
             self.last_tedashis[actor_rel] = Some(sutehai);
         }
@@ -369,26 +379,18 @@ impl PlayerState {
         }
 
         if !self.at_furiten && self.waits[pai.deaka().as_usize()] {
-            if self.riichi_accepted[0] || self.tiles_left == 0 {
-                // 立直 or 河底撈魚
+            if self.riichi_accepted[0]
+                || self.tiles_left == 0
+                || self.rule.renhou && self.can_w_riichi
+            {
+                // 立直, 河底撈魚 or 人和
                 self.last_cans.can_ron_agari = true;
             } else {
                 let mut tehai_with_winning_tile = self.tehai;
                 tehai_with_winning_tile[pai.deaka().as_usize()] += 1;
 
-                let agari_calc = AgariCalculator {
-                    tehai: &tehai_with_winning_tile,
-                    is_menzen: self.is_menzen,
-                    chis: &self.chis,
-                    pons: &self.pons,
-                    minkans: &self.minkans,
-                    ankans: &self.ankans,
-                    bakaze: self.bakaze.as_u8(),
-                    jikaze: self.jikaze.as_u8(),
-                    winning_tile: pai.deaka().as_u8(),
-                    is_ron: true,
-                };
-                self.last_cans.can_ron_agari = agari_calc.has_yaku();
+                self.last_cans.can_ron_agari =
+                    self.has_yaku(&tehai_with_winning_tile, pai.deaka().as_u8(), true);
             }
 
             // Track same-cycle furiten
@@ -416,7 +418,7 @@ impl PlayerState {
             return Ok(());
         }
 
-        if actor_rel == 3 && !pai.is_jihai() && self.tehai_len_div3 > 0 {
+        if !self.rule.sanma && actor_rel == 3 && !pai.is_jihai() && self.tehai_len_div3 > 0 {
             self.set_can_chi_from_tile(pai);
         }
         self.last_cans.can_pon = self.tehai[pai.deaka().as_usize()] >= 2;
@@ -478,7 +480,7 @@ impl PlayerState {
                     self.forbidden_tiles[bigger] = true;
                 }
             }
-        } else if deaka_tile_id > max && min % 9 > 0 {
+        } else if deaka_tile_id > max && !min.is_multiple_of(9) {
             // Like 56s chi 7s, then 4s is not allowed to discard
             let smaller = min - 1;
             if self.tehai[smaller] > 0 {
@@ -662,6 +664,47 @@ impl PlayerState {
         Ok(())
     }
 
+    fn nukidora(&mut self, actor: u8, pai: Tile) -> Result<()> {
+        let actor_rel = self.rel(actor);
+        self.nukidoras[actor_rel] += 1;
+        self.doras_owned[actor_rel] += 1;
+
+        if actor_rel != 0 {
+            self.witness_tile(pai)?;
+            self.update_doras_owned(actor_rel, pai);
+            self.last_kawa_tile = Some(pai); // for getting winning tile in self.agari
+
+            // A nukidora can be ron'd just like a discard, but it is not 槍槓.
+            if !self.at_furiten && self.waits[tuz!(N)] {
+                let mut tehai_with_winning_tile = self.tehai;
+                tehai_with_winning_tile[tuz!(N)] += 1;
+                self.last_cans.can_ron_agari = self.riichi_accepted[0]
+                    || self.has_yaku(&tehai_with_winning_tile, tu8!(N), true);
+
+                // Same as in dahai.
+                if self.last_cans.can_ron_agari {
+                    self.to_mark_same_cycle_furiten = Some(());
+                } else {
+                    self.at_furiten = true;
+                }
+            }
+
+            return Ok(());
+        }
+
+        // Following Tenhou, nukidora does not break ippatsu or the chance of
+        // double riichi, and the tsumo after it counts as rinshan.
+        self.at_rinshan = true;
+        self.move_tile(pai, MoveType::FuuroConsume)?;
+
+        if !self.riichi_accepted[0] {
+            self.update_shanten();
+            self.update_waits_and_furiten();
+        }
+
+        Ok(())
+    }
+
     const fn reach(&mut self, actor: u8) {
         let actor_rel = self.rel(actor);
         self.riichi_declared[actor_rel] = true;
@@ -685,8 +728,10 @@ impl PlayerState {
         }
     }
 
+    /// In sanma, the relative seat of the vacant seat is always 3.
     pub(super) const fn rel(&self, actor: u8) -> usize {
-        ((actor + 4 - self.player_id) % 4) as usize
+        let n = self.rule.player_count();
+        ((actor + n - self.player_id) % n) as usize
     }
 
     /// Updates `tiles_seen`, `doras_seen` and `akas_seen`.
@@ -784,7 +829,7 @@ impl PlayerState {
         // `doras_seen`. This must be done before adding `dora_factor`.
         self.witness_tile(tile)?;
 
-        let next = tile.next();
+        let next = self.rule.dora_of(tile);
         self.dora_factor[next.as_usize()] += 1;
 
         // Count new dora in my tehai
@@ -800,6 +845,9 @@ impl PlayerState {
             if self.ankan_overview[i].contains(&next) {
                 self.doras_owned[i] += 4;
             }
+            if next.as_u8() == tu8!(N) {
+                self.doras_owned[i] += self.nukidoras[i];
+            }
         }
 
         // Add `doras_seen` based on `tiles_seen`
@@ -808,11 +856,12 @@ impl PlayerState {
     }
 
     pub(super) fn pad_kawa_for_pon_or_daiminkan(&mut self, abs_actor: u8, abs_target: u8) {
-        let mut i = (abs_target + 1) % 4;
+        let n = self.rule.player_count();
+        let mut i = (abs_target + 1) % n;
         while i != abs_actor {
             let rel = self.rel(i);
             self.kawa[rel].push(None);
-            i = (i + 1) % 4;
+            i = (i + 1) % n;
         }
     }
 
@@ -952,6 +1001,48 @@ impl PlayerState {
         }
     }
 
+    /// `tehai` must be 3n+2 and include `winning_tile`, which must be
+    /// deaka'd. Incidental yakus are not considered here.
+    ///
+    /// If 後付け is disallowed by the rule, every other wait of the hand is
+    /// also required to have a yaku.
+    pub(super) fn has_yaku(&self, tehai: &[u8; 34], winning_tile: u8, is_ron: bool) -> bool {
+        let check = |tehai: &[u8; 34], winning_tile| {
+            let agari_calc = AgariCalculator {
+                tehai,
+                is_menzen: self.is_menzen,
+                chis: &self.chis,
+                pons: &self.pons,
+                minkans: &self.minkans,
+                ankans: &self.ankans,
+                bakaze: self.bakaze.as_u8(),
+                jikaze: self.jikaze.as_u8(),
+                winning_tile,
+                is_ron,
+                rule: self.rule,
+            };
+            agari_calc.has_yaku()
+        };
+
+        if !check(tehai, winning_tile) {
+            return false;
+        }
+        if self.rule.atozuke {
+            return true;
+        }
+
+        let mut tehai_3n1 = *tehai;
+        tehai_3n1[winning_tile as usize] -= 1;
+        (0..34).all(|t| {
+            if t == winning_tile as usize || tehai_3n1[t] == 4 {
+                return true;
+            }
+            let mut tehai_3n2 = tehai_3n1;
+            tehai_3n2[t] += 1;
+            shanten::calc_all(&tehai_3n2, self.tehai_len_div3) > -1 || check(&tehai_3n2, t as u8)
+        })
+    }
+
     pub(super) const fn update_doras_owned(&mut self, actor_rel: usize, tile: Tile) {
         self.doras_owned[actor_rel] += self.dora_factor[tile.deaka().as_usize()];
         if tile.is_aka() {
@@ -963,11 +1054,20 @@ impl PlayerState {
         self.rank = self.get_rank(self.scores);
     }
 
-    pub(super) fn get_rank(&self, mut scores_rel: [i32; 4]) -> u8 {
-        let scores_abs = {
-            scores_rel.rotate_right(self.player_id as usize);
+    pub(crate) fn get_rank(&self, mut scores_rel: [i32; 4]) -> u8 {
+        let n = self.rule.player_count() as usize;
+        let chiicha = self.chiicha as usize;
+        // Rotated so that the ties are broken from chiicha.
+        let scores_from_chiicha = {
+            scores_rel[..n].rotate_right(self.player_id as usize);
+            scores_rel[..n].rotate_left(chiicha);
             scores_rel
         };
-        Rankings::new(scores_abs).rank_by_player[self.player_id as usize]
+        let rankings = if self.rule.sanma {
+            Rankings::new_sanma(scores_from_chiicha)
+        } else {
+            Rankings::new(scores_from_chiicha)
+        };
+        rankings.rank_by_player[(self.player_id as usize + n - chiicha) % n]
     }
 }
@@ -0,0 +1,41 @@
+//! Fixtures shared by the tests of several modules.
+
+use crate::agent::{ActionWeights, BatchAgent, RandomAgent};
+use crate::arena::{BatchGame, GameResult, Index};
+use crate::mjai::Event;
+use std::array;
+
+use serde_json as json;
+
+/// Plays a game of random agents that riichi and kan a lot.
+pub(crate) fn run_arena(game: &BatchGame, seed: (u64, u64)) -> GameResult {
+    let n = game.rule.player_count();
+    let weights = ActionWeights {
+        riichi: 1000.,
+        kan: 20.,
+        ..Default::default()
+    };
+    let player_ids: Vec<_> = (0..n).collect();
+    let mut agents = [
+        Box::new(RandomAgent::new_batched(&player_ids, seed.0, weights).unwrap())
+            as Box<dyn BatchAgent>,
+    ];
+    let indexes = [array::from_fn(|i| Index {
+        agent_idx: 0,
+        player_id_idx: i % n as usize,
+    })];
+    game.run(&mut agents, &indexes, &[seed])
+        .unwrap()
+        .pop()
+        .unwrap()
+}
+
+/// Reads back the events of the log dumped by `result`.
+pub(crate) fn events_of(result: &GameResult) -> Vec<Event> {
+    result
+        .dump_json_log()
+        .unwrap()
+        .lines()
+        .map(|l| json::from_str(l).unwrap())
+        .collect()
+}
@@ -4,7 +4,7 @@ import numpy as np
 from torch.utils.data import IterableDataset
 from model import GRP
 from reward_calculator import RewardCalculator
-from libriichi.dataset import GameplayLoader
+from libriichi.dataset import AugmentConfig, GameplayLoader, LogFilter, load_shard_files
 from config import config
 
 class FileDatasetsIter(IterableDataset):
@@ -21,6 +21,12 @@ class FileDatasetsIter(IterableDataset):
         num_epochs = 1,
         enable_augmentation = False,
         augmented_first = False,
+        symmetry_augmentation = False,
+        augment_seed = 0,
+        sanma = False,
+        filter_query = '',
+        riichi_decisions_only = False,
+        from_shards = False,
     ):
         super().__init__()
         self.version = version
@@ -34,6 +40,12 @@ class FileDatasetsIter(IterableDataset):
         self.num_epochs = num_epochs
         self.enable_augmentation = enable_augmentation
         self.augmented_first = augmented_first
+        self.symmetry_augmentation = symmetry_augmentation
+        self.augment_seed = augment_seed
+        self.sanma = sanma
+        self.filter_query = filter_query
+        self.riichi_decisions_only = riichi_decisions_only
+        self.from_shards = from_shards
         self.iterator = None
 
     def build_iter(self):
@@ -43,21 +55,31 @@ class FileDatasetsIter(IterableDataset):
         self.grp.load_state_dict(grp_state['model'])
         self.reward_calc = RewardCalculator(self.grp, self.pts)
 
-        for _ in range(self.num_epochs):
-            yield from self.load_files(self.augmented_first)
+        for epoch in range(self.num_epochs):
+            yield from self.load_files(self.augmented_first, epoch)
             if self.enable_augmentation:
-                yield from self.load_files(not self.augmented_first)
+                yield from self.load_files(not self.augmented_first, epoch)
 
-    def load_files(self, augmented):
+    def load_files(self, augmented, epoch):
         # shuffle the file list for each epoch
         random.shuffle(self.file_list)
 
+        log_filter = None
+        if self.filter_query or self.riichi_decisions_only:
+            log_filter = LogFilter(
+                self.filter_query or None,
+                riichi_decisions_only = self.riichi_decisions_only,
+            )
         self.loader = GameplayLoader(
             version = self.version,
             oracle = self.oracle,
             player_names = self.player_names,
             excludes = self.excludes,
             augmented = augmented,
+            # the run and the epoch pick another symmetry for every game
+            augment = AugmentConfig(self.augment_seed * self.num_epochs + epoch) if self.symmetry_augmentation else None,
+            sanma = self.sanma,
+            filter = log_filter,
         )
         self.buffer = []
 
@@ -78,7 +100,10 @@ class FileDatasetsIter(IterableDataset):
         self.buffer.clear()
 
     def populate_buffer(self, file_list):
-        data = self.loader.load_gz_log_files(file_list)
+        if self.from_shards:
+            data = load_shard_files(file_list)
+        else:
+            data = self.loader.load_gz_log_files(file_list)
         for file in data:
             for game in file:
                 # per move
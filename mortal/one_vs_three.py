@@ -6,7 +6,7 @@ import secrets
 import os
 from model import Brain, DQN
 from engine import MortalEngine
-from libriichi.arena import OneVsThree
+from libriichi.arena import OneVsThree, ArenaReport
 from config import config
 
 def main():
@@ -16,6 +16,9 @@ def main():
     iters = cfg['iters']
     log_dir = cfg['log_dir']
     use_akochan = cfg['akochan']['enabled']
+    game_length = cfg.get('game_length', 8)
+    west_round = cfg.get('west_round', True)
+    early_stop = cfg.get('early_stop', False)
 
     if (key := cfg.get('seed_key', -1)) == -1:
         key = secrets.randbits(64)
@@ -77,6 +80,8 @@ def main():
         env = OneVsThree(
             disable_progress_bar = False,
             log_dir = log_dir,
+            game_length = game_length,
+            west_round = west_round,
         )
         if use_akochan:
             rankings = env.ako_vs_py(
@@ -96,6 +101,25 @@ def main():
         avg_pt = rankings @ np.array([90, 45, 0, -135]) / rankings.sum()
         print(f'challenger rankings: {rankings} ({avg_rank}, {avg_pt}pt)')
 
+        if (i + 1) * seeds_per_iter < 2:
+            continue
+        # `ako_vs_py` seats akochan as the challenger of each split.
+        if use_akochan:
+            challenger, champion = 'akochan', engine_chal.name
+        else:
+            challenger, champion = engine_chal.name, engine_cham.name
+        report = ArenaReport.from_log_dir(
+            log_dir,
+            challenger,
+            champion,
+            key = key,
+            delta = cfg.get('sprt_delta', 0.02),
+        )
+        print(report)
+        if early_stop and report.sprt.decision != 'continue':
+            print(f'stopping early, SPRT accepted {report.sprt.decision}')
+            break
+
 if __name__ == '__main__':
     try:
         main()
@@ -54,6 +54,14 @@ def train():
     num_epochs = config['dataset']['num_epochs']
     enable_augmentation = config['dataset']['enable_augmentation']
     augmented_first = config['dataset']['augmented_first']
+    symmetry_augmentation = config['dataset'].get('symmetry_augmentation', False)
+    assert not (symmetry_augmentation and enable_augmentation), 'symmetry_augmentation and enable_augmentation are exclusive'
+    sanma = config['dataset'].get('sanma', False)
+    filter_query = config['dataset'].get('filter_query', '')
+    riichi_decisions_only = config['dataset'].get('riichi_decisions_only', False)
+    shard_dirs = config['dataset'].get('shard_dirs', [])
+    assert not (shard_dirs and enable_augmentation), 'augmentation is baked into shards, list an augmented shard dir instead'
+    assert not (shard_dirs and symmetry_augmentation), 'symmetry_augmentation needs raw logs, shards are already encoded'
     eps = config['optim']['eps']
     betas = config['optim']['betas']
     weight_decay = config['optim']['weight_decay']
@@ -145,10 +153,22 @@ def train():
         nonlocal idx
 
         player_names = []
+        from_shards = False
         if online:
             player_names = ['trainee']
             dirname = drain()
             file_list = list(map(lambda p: path.join(dirname, p), os.listdir(dirname)))
+        elif shard_dirs:
+            # shards are built by the `build_shards` bin, with the player
+            # names and augmentation already applied
+            from_shards = True
+            file_list = []
+            for dirname in shard_dirs:
+                with open(path.join(dirname, 'index.json')) as f:
+                    index = json.load(f)
+                assert index['version'] == version, f'{dirname} is built for version {index["version"]}'
+                assert index['sanma'] == sanma, f'{dirname} does not match sanma = {sanma}'
+                file_list.extend(path.join(dirname, s['file']) for s in index['shards'])
         else:
             player_names_set = set()
             for filename in config['dataset']['player_names_files']:
@@ -193,6 +213,13 @@ def train():
             num_epochs = num_epochs,
             enable_augmentation = enable_augmentation,
             augmented_first = augmented_first,
+            symmetry_augmentation = symmetry_augmentation,
+            # resumed runs must not see the symmetries of the previous ones
+            augment_seed = steps,
+            sanma = sanma,
+            filter_query = filter_query,
+            riichi_decisions_only = riichi_decisions_only,
+            from_shards = from_shards,
         )
         data_loader = iter(DataLoader(
             dataset = file_data,